@@ -1,18 +1,46 @@
-//! Module containing declaration related to [Action] struct
+//! Module containing declaration related to [Action] struct and [ActionHandler] trait
 
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use chrono::NaiveDateTime;
+use clap::ValueEnum;
+use itertools::Itertools;
+use rand::RngCore;
+use regex_macro::regex;
+
+use crate::conflict::{ConflictPolicy, ConflictResolver, Resolution};
+use crate::dedupe::{find_duplicates, DedupeMode};
+use crate::keepfile::NumberPattern;
+use crate::output::Terminator;
+use crate::permissions::ResolvedPermissions;
+use crate::script::{comment, quote};
+use crate::session::CopySession;
+use crate::throttle::RateLimiter;
+use crate::ExecutionOptions;
 
 /// The action to perform on matching files
 ///
 /// # Variants
 /// - `MoveOrCopyTo` - Move or copy matching files to the specified directory
 /// - `Delete` - Delete non-matching files
+/// - `List` - Print matching files
+/// - `Custom` - Apply a user provided [ActionHandler]
 #[derive(Debug, Clone)]
 pub enum Action {
     /// Copy or move matching files to the specified directory
-    MoveOrCopyTo(MoveOrCopy, PathBuf),
+    MoveOrCopyTo(MoveOrCopyHandler),
     /// Delete non-matching files
-    Delete,
+    Delete(DeleteStrategy),
+    /// Print matching files, without acting on them
+    List(ListHandler),
+    /// Apply a custom handler, provided by the library user
+    Custom(Rc<dyn ActionHandler>),
 }
 
 impl Action {
@@ -23,20 +51,261 @@ impl Action {
     /// The actions are prioritized as follows:
     /// - If `copy_to` is specified, the action is `CopyTo`.
     /// - If `move_to` is specified, the action is `MoveTo`.
+    /// - If `quarantine` is specified, the action is `MoveTo`, applied to the non-matching files.
+    /// - If `list` is specified, the action is `List`.
     /// - If no action is specified, the action is `CopyTo`, with the default directory being `./selected`.
     /// - If `delete` is specified, the action is `Delete`.
-    pub fn new(copy_to: Option<String>, move_to: Option<String>, delete: bool) -> Action {
+    ///   If `shred` is specified too, files are overwritten the given number of times before deletion.
+    ///
+    /// Moved or copied files are placed in the destination directory according to the `layout`.
+    pub fn new(
+        copy_to: Option<String>,
+        move_to: Option<String>,
+        quarantine: Option<String>,
+        delete: bool,
+        shred: Option<u32>,
+        list: bool,
+        layout: DestinationLayout,
+    ) -> Action {
         use Action::*;
         use MoveOrCopy::*;
-        match (move_to, copy_to, delete) {
-            (_, Some(path), _) => MoveOrCopyTo(Copy, PathBuf::from(path)),
-            (Some(path), _, _) => MoveOrCopyTo(Move, PathBuf::from(path)),
-            (None, None, false) => MoveOrCopyTo(Copy, PathBuf::from("selected")),
-            (_, _, true) => Delete,
+        let handler = |op, path| MoveOrCopyHandler::new(op, PathBuf::from(path), layout);
+        match (move_to, copy_to, quarantine, delete) {
+            (_, Some(path), _, _) => MoveOrCopyTo(handler(Copy, path)),
+            (Some(path), _, _, _) => MoveOrCopyTo(handler(Move, path)),
+            (None, None, Some(path), _) => MoveOrCopyTo(handler(Move, path).rejecting()),
+            (None, None, None, false) if list => List(ListHandler),
+            (None, None, None, false) => MoveOrCopyTo(handler(Copy, "selected".to_owned())),
+            (_, _, _, true) => Delete(shred.map_or(DeleteStrategy::Unlink, DeleteStrategy::Shred)),
+        }
+    }
+
+    /// Get the handler that executes the action
+    pub fn handler(&self) -> Rc<dyn ActionHandler> {
+        match self {
+            Action::MoveOrCopyTo(handler) => Rc::new(handler.clone()),
+            Action::Delete(strategy) => Rc::new(strategy.clone()),
+            Action::List(handler) => Rc::new(handler.clone()),
+            Action::Custom(handler) => handler.clone(),
+        }
+    }
+}
+
+/// Files an action is applied to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+    /// Files that were found in the keep file
+    Kept,
+    /// Files that were **not** found in the keep file
+    Rejected,
+}
+
+/// Information shared by all the files an action is applied to
+#[derive(Debug, Clone)]
+pub struct ExecutionContext<'a> {
+    /// Directory the files were selected from
+    pub source_dir: &'a Path,
+    /// Paths of the files relative to the source directory, as they were found during the traversal
+    pub walk_paths: Option<&'a HashMap<PathBuf, PathBuf>>,
+    /// Options the action is executed with
+    pub options: &'a ExecutionOptions,
+}
+
+impl ExecutionContext<'_> {
+    /// Get the path of the file relative to the source directory
+    ///
+    /// The path the file was found at during the traversal is preferred,
+    /// so files reached through symbolic links are mapped too.
+    ///
+    /// # Errors
+    /// - If the file was not found during the traversal, and is not inside the source directory
+    pub fn relative_path<'p>(&'p self, src: &'p Path) -> Result<&'p Path, UnmappablePathError> {
+        self.walk_paths
+            .and_then(|paths| paths.get(src))
+            .map(PathBuf::as_path)
+            .or_else(|| src.strip_prefix(self.source_dir).ok())
+            .ok_or_else(|| UnmappablePathError {
+                path: src.to_path_buf(),
+                source_dir: self.source_dir.to_path_buf(),
+            })
+    }
+
+    /// Display the path in descriptions
+    ///
+    /// If `options.relative` is true, paths inside the source directory are shown relative to it.
+    pub fn display<'p>(&self, path: &'p Path) -> std::path::Display<'p> {
+        match self.options.relative {
+            true => path.strip_prefix(self.source_dir).unwrap_or(path).display(),
+            false => path.display(),
         }
     }
 }
 
+/// The file can't be mapped to a destination, as its path relative to the source directory is unknown
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("\"{}\" can't be mapped to a destination, as it is not inside \"{}\"", .path.display(), .source_dir.display())]
+pub struct UnmappablePathError {
+    /// Path of the file
+    pub path: PathBuf,
+    /// The source directory
+    pub source_dir: PathBuf,
+}
+
+impl From<UnmappablePathError> for std::io::Error {
+    fn from(error: UnmappablePathError) -> Self {
+        std::io::Error::other(error)
+    }
+}
+
+/// Result of applying an action to a single file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The action was applied to the file, with a description of what was done
+    Applied(String),
+    /// The file was skipped, with the reason why
+    Skipped(String),
+    /// The action printed the file itself, so it isn't described again
+    Printed,
+}
+
+/// Execution logic of an action
+///
+/// Implement this trait to provide custom actions (upload, tag, index, ...)
+/// and use them through [Action::Custom].
+///
+/// Implementations must respect [ExecutionOptions::dry_run] and only describe
+/// what would be done when it is set.
+pub trait ActionHandler: Debug {
+    /// Which files the action should be applied to
+    ///
+    /// Defaults to the files that were found in the keep file.
+    fn selection(&self) -> Selection {
+        Selection::Kept
+    }
+
+    /// Apply the action to a single file
+    ///
+    /// # Arguments
+    /// - `src` - the file to apply the action to
+    /// - `ctx` - the execution context
+    fn apply(&self, src: &Path, ctx: &ExecutionContext) -> Result<Outcome, std::io::Error>;
+
+    /// Apply the action to a single file of an archive, reading its content from `content`
+    ///
+    /// The `src` path is the path of the archive joined with the path of the file inside it,
+    /// see [crate::archive::Archive]. Defaults to [ActionHandler::apply], for the actions
+    /// that only need the path of the file.
+    fn extract(&self, src: &Path, _content: &mut dyn Read, ctx: &ExecutionContext) -> Result<Outcome, std::io::Error> {
+        self.apply(src, ctx)
+    }
+
+    /// Describe the action applied to a single file as a POSIX shell command
+    ///
+    /// Returns `None` if the action has no shell equivalent, which is the default.
+    fn shell_command(&self, _src: &Path, _ctx: &ExecutionContext) -> Option<String> {
+        None
+    }
+}
+
+/// The way files are deleted
+#[derive(Debug, Clone)]
+pub enum DeleteStrategy {
+    /// Files are simply removed
+    Unlink,
+    /// File contents are overwritten with random data the given number of times before removal
+    Shred(u32),
+}
+
+impl DeleteStrategy {
+    /// Get a description of the operation
+    pub fn description(&self) -> &str {
+        match self {
+            DeleteStrategy::Unlink => "Deleted",
+            DeleteStrategy::Shred(_) => "Shredded",
+        }
+    }
+
+    /// Delete the file at the provided path
+    ///
+    /// # Errors
+    /// Possible errors include:
+    /// - If the file does not exist
+    /// - If the file is not writable (when shredding)
+    pub fn delete<P: AsRef<Path>>(&self, path: P) -> Result<(), std::io::Error> {
+        if let DeleteStrategy::Shred(passes) = self {
+            Self::overwrite(path.as_ref(), *passes)?;
+        }
+        std::fs::remove_file(path)
+    }
+
+    /// Overwrite the contents of the file with random data
+    fn overwrite(path: &Path, passes: u32) -> Result<(), std::io::Error> {
+        let len = std::fs::metadata(path)?.len();
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        let mut rng = rand::thread_rng();
+        let mut buffer = vec![0u8; 64 * 1024];
+
+        for _ in 0..passes {
+            file.seek(SeekFrom::Start(0))?;
+            let mut remaining = len;
+            while remaining > 0 {
+                let chunk = remaining.min(buffer.len() as u64) as usize;
+                rng.fill_bytes(&mut buffer[..chunk]);
+                file.write_all(&buffer[..chunk])?;
+                remaining -= chunk as u64;
+            }
+            // Make sure the data actually reaches the disk before the next pass
+            file.sync_all()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ActionHandler for DeleteStrategy {
+    fn selection(&self) -> Selection {
+        Selection::Rejected
+    }
+
+    fn apply(&self, src: &Path, ctx: &ExecutionContext) -> Result<Outcome, std::io::Error> {
+        if !ctx.options.dry_run {
+            self.delete(src)?;
+        }
+        Ok(Outcome::Applied(format!(
+            "{}: {}",
+            self.description(),
+            ctx.display(src)
+        )))
+    }
+
+    fn shell_command(&self, src: &Path, _ctx: &ExecutionContext) -> Option<String> {
+        let src = quote(src)?;
+        match self {
+            DeleteStrategy::Unlink => Some(format!("rm -f -- {src}")),
+            DeleteStrategy::Shred(passes) => Some(format!("shred -u -n {passes} -- {src}")),
+        }
+    }
+}
+
+/// Handler that prints the matching files to the standard output, each one ended by the [Terminator]
+#[derive(Debug, Clone, Default)]
+pub struct ListHandler;
+
+impl ActionHandler for ListHandler {
+    fn apply(&self, src: &Path, ctx: &ExecutionContext) -> Result<Outcome, std::io::Error> {
+        ctx.options.terminator.print(ctx.display(src))?;
+        Ok(Outcome::Printed)
+    }
+
+    fn shell_command(&self, src: &Path, ctx: &ExecutionContext) -> Option<String> {
+        let terminator = match ctx.options.terminator {
+            Terminator::Newline => "\\n",
+            Terminator::Nul => "\\0",
+        };
+        Some(format!("printf '%s{terminator}' {}", quote(src)?))
+    }
+}
+
 /// The action to perform on matching files, as a move or copy operation
 #[derive(Debug, Clone)]
 pub enum MoveOrCopy {
@@ -68,19 +337,1075 @@ impl MoveOrCopy {
     /// - If the parent directory of the destination path does not exist
     /// - If the parent directory of the destination path is not writable
     pub fn move_or_copy<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<(), std::io::Error> {
+        self.move_or_copy_throttled(from, to, None)
+    }
+
+    /// Perform the move or copy operation, limiting the copy throughput with the provided rate limiter
+    ///
+    /// Moves are renames and don't transfer any data, so they are never throttled.
+    pub fn move_or_copy_throttled<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        from: P,
+        to: Q,
+        limiter: Option<&RateLimiter>,
+    ) -> Result<(), std::io::Error> {
         match to.as_ref().parent() {
             Some(parent) => {
                 // Create the parent directories if they don't exist
                 std::fs::create_dir_all(parent)?;
-                match self {
-                    MoveOrCopy::Move => std::fs::rename(from, to),
-                    MoveOrCopy::Copy => std::fs::copy(from, to).map(|_| ()),
+                match (self, limiter) {
+                    (MoveOrCopy::Move, _) => std::fs::rename(from, to),
+                    (MoveOrCopy::Copy, Some(limiter)) => limiter.copy(from, to).map(|_| ()),
+                    (MoveOrCopy::Copy, None) => std::fs::copy(from, to).map(|_| ()),
+                }
+            }
+            None => Err(std::io::Error::other("Failed to get parent directory")),
+        }
+    }
+}
+
+/// Copy a file to several destinations, reading it only once
+///
+/// Each chunk read from the source is written to all the destinations,
+/// limiting the total written throughput with the provided rate limiter.
+/// Like [std::fs::copy], the permissions of the file are copied too.
+///
+/// # Errors
+/// - If the source can't be read, or any of the destinations can't be written
+pub fn copy_to_all(from: &Path, to: &[PathBuf], limiter: Option<&RateLimiter>) -> std::io::Result<()> {
+    let mut reader = File::open(from)?;
+    write_to_all(&mut reader, to, limiter)?;
+
+    let permissions = reader.metadata()?.permissions();
+    for dest in to {
+        std::fs::set_permissions(dest, permissions.clone())?;
+    }
+    Ok(())
+}
+
+/// Write the content to several destinations, reading it only once
+///
+/// Each chunk read is written to all the destinations, creating their parent directories,
+/// and limiting the total written throughput with the provided rate limiter.
+///
+/// # Errors
+/// - If the content can't be read, or any of the destinations can't be written
+pub fn write_to_all(reader: &mut dyn Read, to: &[PathBuf], limiter: Option<&RateLimiter>) -> std::io::Result<()> {
+    let mut writers = Vec::with_capacity(to.len());
+    for dest in to {
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        writers.push(File::create(dest)?);
+    }
+
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for writer in writers.iter_mut() {
+            writer.write_all(&buffer[..read])?;
+        }
+        if let Some(limiter) = limiter {
+            limiter.consume(read * writers.len());
+        }
+    }
+    Ok(())
+}
+
+/// The way files are placed in the destination directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DestinationLayout {
+    /// The directory structure relative to the source directory is preserved
+    #[default]
+    Mirror,
+    /// Files are placed in subdirectories named after their lowercase extension
+    ByExtension,
+}
+
+/// Render the name of the per-run destination subdirectory
+///
+/// Supported placeholders are `{date}` (`2024-06-01`), `{time}` (`153000`)
+/// and `{name}`, the name of the source directory.
+///
+/// # Errors
+/// - If the template contains an unknown placeholder
+pub fn render_subdir_template(template: &str, name: &str, now: NaiveDateTime) -> std::io::Result<String> {
+    let mut unknown = vec![];
+    let rendered = regex!(r"\{(\w*)\}").replace_all(template, |caps: &regex::Captures| match &caps[1] {
+        "date" => now.format("%Y-%m-%d").to_string(),
+        "time" => now.format("%H%M%S").to_string(),
+        "name" => name.to_owned(),
+        other => {
+            unknown.push(other.to_owned());
+            String::new()
+        }
+    });
+    match unknown.is_empty() {
+        true => Ok(rendered.into_owned()),
+        false => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "Unknown placeholders in the subdirectory template: {{{}}}",
+                unknown.join("}, {")
+            ),
+        )),
+    }
+}
+
+impl DestinationLayout {
+    /// Get the destination path of a file
+    ///
+    /// # Arguments
+    /// - `relative` - path of the file, relative to the source directory
+    /// - `dest_dir` - the destination directory
+    pub fn destination(&self, relative: &Path, dest_dir: &Path) -> PathBuf {
+        match self {
+            DestinationLayout::Mirror => dest_dir.join(relative),
+            DestinationLayout::ByExtension => {
+                let file_name = relative.file_name().unwrap_or(relative.as_os_str());
+                match relative.extension().and_then(|ext| ext.to_str()) {
+                    Some(ext) => dest_dir.join(ext.to_ascii_lowercase()).join(file_name),
+                    None => dest_dir.join(file_name),
                 }
             }
-            None => Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to get parent directory",
+        }
+    }
+}
+
+/// Order of sequentially renumbered files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RenumberOrder {
+    /// Files are numbered in the order their numbers appear in the keep file
+    KeepFile,
+    /// Files are numbered in the ascending order of their original numbers
+    Number,
+}
+
+/// Handler that moves or copies files to a destination directory
+///
+/// Files are placed in the destination directory according to the [DestinationLayout].
+#[derive(Debug, Clone)]
+pub struct MoveOrCopyHandler {
+    /// Whether files are moved or copied
+    pub op: MoveOrCopy,
+    /// Directory files are moved or copied to
+    pub dest_dir: PathBuf,
+    /// Files the handler is applied to
+    pub selection: Selection,
+    /// Additional directories files are copied to, in the same pass over the source
+    pub mirrors: Vec<PathBuf>,
+    /// The way files are placed in the destination directory
+    pub layout: DestinationLayout,
+    /// Order to sequentially renumber the files in, if they should be renumbered
+    pub renumber: Option<RenumberOrder>,
+    /// Rate limiter shared by all the copies, if the throughput should be limited
+    pub throttle: Option<Rc<RateLimiter>>,
+    /// Decides what to do when a destination already exists
+    pub conflicts: ConflictResolver,
+    /// Permissions and ownership applied to the moved or copied files
+    pub permissions: Option<ResolvedPermissions>,
+    /// What to do with byte-identical duplicates, if they should be deduplicated
+    pub dedupe: Option<DedupeMode>,
+    /// Duplicates, mapped to the file they are identical to
+    duplicates: HashMap<PathBuf, PathBuf>,
+    /// Destinations written by this run, which the duplicates can be linked to
+    transferred: Rc<RefCell<HashSet<PathBuf>>>,
+    /// Destinations given to the files by this run, mapped to the file each one was given to
+    claimed: Rc<RefCell<HashMap<PathBuf, PathBuf>>>,
+    /// New names of the renumbered files
+    new_names: HashMap<PathBuf, PathBuf>,
+}
+
+impl MoveOrCopyHandler {
+    /// Construct a new move or copy handler
+    pub fn new(op: MoveOrCopy, dest_dir: PathBuf, layout: DestinationLayout) -> Self {
+        MoveOrCopyHandler {
+            op,
+            dest_dir,
+            selection: Selection::Kept,
+            mirrors: vec![],
+            layout,
+            renumber: None,
+            throttle: None,
+            conflicts: ConflictResolver::default(),
+            permissions: None,
+            dedupe: None,
+            duplicates: HashMap::new(),
+            transferred: Rc::default(),
+            claimed: Rc::default(),
+            new_names: HashMap::new(),
+        }
+    }
+
+    /// Place the files into a subdirectory of the destination directories, unique to this run
+    ///
+    /// If the subdirectory already exists in any of the destinations, a number is appended to its name (`_2`, `_3`, ...).
+    /// If `resume` is true, the subdirectory the [CopySession] of an interrupted run recorded is used instead,
+    /// so that run can be resumed, even if the subdirectory was named after another time.
+    ///
+    /// Returns the name of the subdirectory used.
+    pub fn use_subdir(&mut self, subdir: &str, resume: bool) -> String {
+        let exists = |name: &str| {
+            std::iter::once(&self.dest_dir)
+                .chain(&self.mirrors)
+                .any(|dir| dir.join(name).exists())
+        };
+        let interrupted = Some(&self.dest_dir)
+            .filter(|_| resume)
+            .and_then(|dest_dir| CopySession::interrupted_subdir(dest_dir));
+        let unique = interrupted.unwrap_or_else(|| {
+            (2..)
+                .map(|n| format!("{subdir}_{n}"))
+                .find(|name| !exists(name))
+                .filter(|_| exists(subdir))
+                .unwrap_or_else(|| subdir.to_owned())
+        });
+
+        self.dest_dir.push(&unique);
+        self.mirrors.iter_mut().for_each(|mirror| mirror.push(&unique));
+        unique
+    }
+
+    /// Apply the handler to the files that don't match the keep file, like [DeleteStrategy] does
+    pub fn rejecting(mut self) -> Self {
+        self.selection = Selection::Rejected;
+        self
+    }
+
+    /// Assign sequential names (`001.jpg`, `002.jpg`, ...) to the files
+    ///
+    /// Files sharing the same original number (e.g. `IMG_0001.jpg` and `IMG_0001.cr2`) get the same new number.
+    /// Numbering continues after the highest number already present in the destination directory,
+    /// so existing files are never overwritten.
+    ///
+    /// Does nothing if renumbering wasn't requested.
+    ///
+    /// # Arguments
+    /// - `files` - the files the action will be applied to
+    /// - `keep_order` - the numbers in the order they appear in the keep file
+    /// - `number_pattern` - the pattern extracting the numbers of the files
+    ///
+    /// # Errors
+    /// - If the destination directory can't be read
+    pub fn with_numbering(
+        mut self,
+        files: &[PathBuf],
+        keep_order: &[u32],
+        number_pattern: &NumberPattern,
+    ) -> std::io::Result<Self> {
+        let Some(order) = self.renumber else {
+            return Ok(self);
+        };
+
+        let number_of = |file: &PathBuf| {
+            file.file_name()
+                .and_then(|f| f.to_str())
+                .and_then(|f| number_pattern.extract(f))
+        };
+        let mut numbers: Vec<u32> = files.iter().filter_map(number_of).collect();
+        match order {
+            RenumberOrder::Number => numbers.sort_unstable(),
+            RenumberOrder::KeepFile => {
+                numbers.sort_by_key(|num| keep_order.iter().position(|n| n == num).unwrap_or(usize::MAX))
+            }
+        }
+        numbers.dedup();
+
+        let offset = Self::highest_sequence_number(&self.dest_dir)?;
+        let width = (offset + numbers.len()).to_string().len().max(3);
+        let sequence: HashMap<u32, usize> = numbers
+            .into_iter()
+            .enumerate()
+            .map(|(index, num)| (num, offset + index + 1))
+            .collect();
+
+        self.new_names = files
+            .iter()
+            .filter_map(|file| {
+                let index = sequence.get(&number_of(file)?)?;
+                let name = match file.extension() {
+                    Some(ext) => format!("{index:0width$}.{}", ext.to_string_lossy()),
+                    None => format!("{index:0width$}"),
+                };
+                Some((file.clone(), PathBuf::from(name)))
+            })
+            .collect();
+        Ok(self)
+    }
+
+    /// Find the byte-identical files, so only the first of them is transferred
+    ///
+    /// Does nothing if deduplication wasn't requested.
+    ///
+    /// # Errors
+    /// - If any of the files can't be read
+    pub fn with_deduplication(mut self, files: &[PathBuf]) -> std::io::Result<Self> {
+        if self.dedupe.is_some() {
+            self.duplicates = find_duplicates(files)?;
+        }
+        Ok(self)
+    }
+
+    /// Get the highest sequence number of the files in the directory and its subdirectories
+    ///
+    /// Files whose names (without the extension) are made of digits only are considered sequentially numbered.
+    fn highest_sequence_number(dir: &Path) -> std::io::Result<usize> {
+        if !dir.exists() {
+            return Ok(0);
+        }
+        let mut highest = 0;
+        let mut stack = vec![dir.to_path_buf()];
+        while let Some(dir) = stack.pop() {
+            for entry in dir.read_dir()?.flat_map(Result::ok) {
+                let path = entry.path();
+                if path.is_dir() {
+                    stack.push(path);
+                } else if let Some(num) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .filter(|stem| stem.bytes().all(|b| b.is_ascii_digit()))
+                    .and_then(|stem| stem.parse().ok())
+                {
+                    highest = highest.max(num);
+                }
+            }
+        }
+        Ok(highest)
+    }
+}
+
+impl MoveOrCopyHandler {
+    /// Get the destination path of the file
+    ///
+    /// If another file of this run was given the same destination, a number is appended to the name
+    /// of the file (`_2`, `_3`, ...), see [MoveOrCopyHandler::claim].
+    ///
+    /// # Errors
+    /// - If the file can't be mapped to a path relative to the source directory
+    pub fn destination(&self, src: &Path, ctx: &ExecutionContext) -> Result<PathBuf, UnmappablePathError> {
+        let relative = match self.new_names.get(src) {
+            Some(name) => name.as_path(),
+            None => ctx.relative_path(src)?,
+        };
+        Ok(self.claim(src, self.layout.destination(relative, &self.dest_dir)))
+    }
+
+    /// Claim the destination for the file, numbering it if another file of this run already claimed it
+    ///
+    /// Layouts that don't keep the directories of the files, like [DestinationLayout::ByExtension],
+    /// give the same destination to same named files of different directories, and the later ones
+    /// would overwrite the earlier ones. A file keeps the destination it claimed first.
+    fn claim(&self, src: &Path, dest: PathBuf) -> PathBuf {
+        let mut claimed = self.claimed.borrow_mut();
+        let stem = dest.file_stem().unwrap_or_default().to_os_string();
+        let numbered = (2..).map(|n| {
+            let mut name = stem.clone();
+            name.push(format!("_{n}"));
+            if let Some(ext) = dest.extension() {
+                name.push(".");
+                name.push(ext);
+            }
+            dest.with_file_name(name)
+        });
+        let dest = std::iter::once(dest.clone())
+            .chain(numbered)
+            .find(|dest| claimed.get(dest).is_none_or(|owner| owner == src))
+            .unwrap_or(dest);
+        claimed.insert(dest.clone(), src.to_path_buf());
+        dest
+    }
+
+    /// Get the destination paths of the file in the destination directory and all the mirrors
+    ///
+    /// # Errors
+    /// - If the file can't be mapped to a path relative to the source directory
+    pub fn destinations(&self, src: &Path, ctx: &ExecutionContext) -> Result<Vec<PathBuf>, UnmappablePathError> {
+        let dest = self.destination(src, ctx)?;
+        let relative = dest.strip_prefix(&self.dest_dir).unwrap_or(&dest);
+        let mirrors = self.mirrors.iter().map(|mirror| mirror.join(relative));
+        Ok(std::iter::once(dest.clone()).chain(mirrors).collect())
+    }
+
+    /// Get the destinations the file should be written to, leaving out the existing ones
+    /// the conflicts are resolved to skip for
+    ///
+    /// If `overwrite` is true, all the destinations are written, whatever the conflict policy.
+    fn targets(
+        &self,
+        src: &Path,
+        dests: Vec<PathBuf>,
+        overwrite: bool,
+        ctx: &ExecutionContext,
+    ) -> std::io::Result<Vec<PathBuf>> {
+        let mut targets = Vec::with_capacity(dests.len());
+        for dest in dests {
+            if overwrite || self.should_write(src, &dest, ctx)? {
+                targets.push(dest);
+            }
+        }
+        Ok(targets)
+    }
+
+    /// Get the deduplication mode and the file the duplicate is identical to
+    ///
+    /// Returns `None` if the file isn't a duplicate, or deduplication wasn't requested.
+    fn original_of(&self, src: &Path) -> Option<(DedupeMode, &PathBuf)> {
+        Some((self.dedupe?, self.duplicates.get(src)?))
+    }
+
+    /// Remember that the file is at its destinations, like a file transferred by an interrupted run,
+    /// so its duplicates can be linked to it
+    pub fn mark_transferred(&self, src: &Path, ctx: &ExecutionContext) {
+        if let Ok(dests) = self.destinations(src, ctx) {
+            self.transferred.borrow_mut().extend(dests);
+        }
+    }
+
+    /// Check whether the destination should be written, asking the conflict resolver if it already exists
+    ///
+    /// Conflicts aren't prompted for in dry-run mode, so the existing destinations aren't written then.
+    fn should_write(&self, src: &Path, dest: &Path, ctx: &ExecutionContext) -> std::io::Result<bool> {
+        Ok(!dest.exists() || (!self.would_prompt(ctx) && self.conflicts.resolve(src, dest)? == Resolution::Overwrite))
+    }
+
+    /// Check if the conflicts would be prompted for, but aren't because this is a dry run
+    fn would_prompt(&self, ctx: &ExecutionContext) -> bool {
+        ctx.options.dry_run && self.conflicts.policy == ConflictPolicy::Prompt
+    }
+
+    /// Describe the file as skipped because its destinations already exist
+    fn skip_existing(&self, src: &Path, ctx: &ExecutionContext) -> Outcome {
+        match self.would_prompt(ctx) {
+            true => Outcome::Skipped(format!(
+                "Would prompt for \"{}\", the destination already exists",
+                ctx.display(src)
+            )),
+            false => Outcome::Skipped(format!(
+                "Skipped \"{}\", the destination already exists",
+                ctx.display(src)
             )),
         }
     }
+
+    /// Skip the duplicate, or hard link it to the transferred original
+    ///
+    /// The duplicate is only linked to the destinations the original was transferred to by this run,
+    /// so it's never linked to an unrelated file already at the destination.
+    fn apply_duplicate(
+        &self,
+        mode: DedupeMode,
+        src: &Path,
+        original: &Path,
+        ctx: &ExecutionContext,
+    ) -> Result<Outcome, std::io::Error> {
+        let dests = self.destinations(src, ctx)?;
+        let original_dests = self.destinations(original, ctx)?;
+        if mode == DedupeMode::Skip {
+            return Ok(Outcome::Skipped(format!(
+                "Skipped \"{}\", identical to \"{}\"",
+                ctx.display(src),
+                ctx.display(original)
+            )));
+        }
+        let mut links = Vec::with_capacity(dests.len());
+        for (dest, original_dest) in dests.into_iter().zip(original_dests) {
+            let transferred = self.transferred.borrow().contains(&original_dest);
+            if transferred && self.should_write(src, &dest, ctx)? {
+                links.push((dest, original_dest));
+            }
+        }
+        let Some((dest, original_dest)) = links.first() else {
+            return Ok(Outcome::Skipped(format!(
+                "Skipped \"{}\", identical to \"{}\", which wasn't transferred, or the destination already exists",
+                ctx.display(src),
+                ctx.display(original)
+            )));
+        };
+        if !ctx.options.dry_run {
+            for (dest, original_dest) in links.iter() {
+                if let Some(parent) = dest.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                // Linking fails if the destination exists, so the overwritten file is removed first
+                if dest.exists() {
+                    std::fs::remove_file(dest)?;
+                }
+                std::fs::hard_link(original_dest, dest)?;
+            }
+        }
+        Ok(Outcome::Applied(format!(
+            "Linked \"{}\" to \"{}\", identical to \"{}\"",
+            ctx.display(dest),
+            ctx.display(original_dest),
+            ctx.display(src)
+        )))
+    }
+
+    /// Move or copy the file an interrupted run didn't finish transferring
+    ///
+    /// The destinations may hold partial copies of the file, so they are all overwritten,
+    /// whatever the conflict policy.
+    pub fn resume(&self, src: &Path, ctx: &ExecutionContext) -> Result<Outcome, std::io::Error> {
+        self.transfer(src, true, ctx)
+    }
+
+    /// Move or copy the file to all of its destinations, see [MoveOrCopyHandler::targets]
+    fn transfer(&self, src: &Path, overwrite: bool, ctx: &ExecutionContext) -> Result<Outcome, std::io::Error> {
+        if let Some((mode, original)) = self.original_of(src) {
+            return self.apply_duplicate(mode, src, original, ctx);
+        }
+        let dests = self.targets(src, self.destinations(src, ctx)?, overwrite, ctx)?;
+        if dests.is_empty() {
+            return Ok(self.skip_existing(src, ctx));
+        }
+
+        if !ctx.options.dry_run {
+            match dests.as_slice() {
+                [dest] => self.op.move_or_copy_throttled(src, dest, self.throttle.as_deref())?,
+                dests => copy_to_all(src, dests, self.throttle.as_deref())?,
+            }
+            if let Some(permissions) = &self.permissions {
+                for dest in dests.iter() {
+                    permissions.apply(dest)?;
+                }
+            }
+        }
+        self.transferred.borrow_mut().extend(dests.iter().cloned());
+        Ok(Outcome::Applied(format!(
+            "{} \"{}\" to {}",
+            self.op.description(),
+            ctx.display(src),
+            dests.iter().map(|dest| format!("\"{}\"", ctx.display(dest))).join(", ")
+        )))
+    }
+}
+
+impl ActionHandler for MoveOrCopyHandler {
+    fn selection(&self) -> Selection {
+        self.selection
+    }
+
+    fn apply(&self, src: &Path, ctx: &ExecutionContext) -> Result<Outcome, std::io::Error> {
+        self.transfer(src, false, ctx)
+    }
+
+    fn extract(&self, src: &Path, content: &mut dyn Read, ctx: &ExecutionContext) -> Result<Outcome, std::io::Error> {
+        let dests = self.targets(src, self.destinations(src, ctx)?, false, ctx)?;
+        if dests.is_empty() {
+            return Ok(self.skip_existing(src, ctx));
+        }
+
+        if !ctx.options.dry_run {
+            write_to_all(content, &dests, self.throttle.as_deref())?;
+            if let Some(permissions) = &self.permissions {
+                for dest in dests.iter() {
+                    permissions.apply(dest)?;
+                }
+            }
+        }
+        Ok(Outcome::Applied(format!(
+            "Extracted \"{}\" to {}",
+            ctx.display(src),
+            dests.iter().map(|dest| format!("\"{}\"", ctx.display(dest))).join(", ")
+        )))
+    }
+
+    fn shell_command(&self, src: &Path, ctx: &ExecutionContext) -> Option<String> {
+        if let Some((mode, original)) = self.original_of(src) {
+            return match mode {
+                DedupeMode::Skip => Some(format!("# {} is identical to {}", comment(src), comment(original))),
+                DedupeMode::Hardlink => {
+                    let dests = self.destinations(src, ctx).ok()?.into_iter();
+                    let links: Option<Vec<_>> = dests
+                        .zip(self.destinations(original, ctx).ok()?)
+                        .map(|(dest, original_dest)| {
+                            Some(format!(
+                                "mkdir -p -- {} && ln -- {} {}",
+                                quote(dest.parent()?)?,
+                                quote(&original_dest)?,
+                                quote(&dest)?
+                            ))
+                        })
+                        .collect();
+                    Some(links?.join(" && "))
+                }
+            };
+        }
+        let command = match self.op {
+            MoveOrCopy::Move => "mv",
+            MoveOrCopy::Copy => "cp",
+        };
+        let commands: Option<Vec<_>> = self
+            .destinations(src, ctx)
+            .ok()?
+            .iter()
+            .map(|dest| {
+                let mut commands = vec![
+                    format!("mkdir -p -- {}", quote(dest.parent()?)?),
+                    format!("{command} -- {} {}", quote(src)?, quote(dest)?),
+                ];
+                if let Some(permissions) = &self.permissions {
+                    commands.extend(permissions.shell_commands(&quote(dest)?));
+                }
+                Some(commands.join(" && "))
+            })
+            .collect();
+        Some(commands?.join(" && "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    fn options(dry_run: bool) -> ExecutionOptions {
+        ExecutionOptions {
+            dry_run,
+            protect: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn shred_removes_file() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("IMG_0001.jpg");
+        std::fs::write(&file, b"sensitive content")?;
+        // The link keeps the content reachable after the file is removed
+        let link = dir.path().join("link");
+        std::fs::hard_link(&file, &link)?;
+
+        DeleteStrategy::Shred(2).delete(&file)?;
+        assert!(!file.exists());
+        let content = std::fs::read(&link)?;
+        assert_eq!(content.len(), b"sensitive content".len());
+        assert_ne!(content, b"sensitive content");
+
+        Ok(())
+    }
+
+    #[test]
+    fn delete_missing_file_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("missing.jpg");
+
+        assert!(DeleteStrategy::Unlink.delete(&file).is_err());
+        assert!(DeleteStrategy::Shred(1).delete(&file).is_err());
+    }
+
+    #[test]
+    fn copy_handler_preserves_structure() -> TestResult {
+        let src_dir = tempfile::tempdir()?;
+        let dest_dir = tempfile::tempdir()?;
+        let file = src_dir.path().join("inner").join("IMG_0001.jpg");
+        std::fs::create_dir_all(file.parent().unwrap())?;
+        std::fs::write(&file, b"content")?;
+
+        let handler = Action::MoveOrCopyTo(MoveOrCopyHandler::new(
+            MoveOrCopy::Copy,
+            dest_dir.path().to_path_buf(),
+            DestinationLayout::Mirror,
+        ))
+        .handler();
+        let options = options(false);
+        let ctx = ExecutionContext {
+            source_dir: src_dir.path(),
+            walk_paths: None,
+            options: &options,
+        };
+
+        assert_eq!(handler.selection(), Selection::Kept);
+        assert!(matches!(handler.apply(&file, &ctx)?, Outcome::Applied(_)));
+        assert!(file.exists());
+        assert!(dest_dir.path().join("inner").join("IMG_0001.jpg").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn copy_handler_fans_out_to_mirrors() -> TestResult {
+        let src_dir = tempfile::tempdir()?;
+        let dest_dir = tempfile::tempdir()?;
+        let backup_dir = tempfile::tempdir()?;
+        let file = src_dir.path().join("inner").join("IMG_0001.jpg");
+        std::fs::create_dir_all(file.parent().unwrap())?;
+        std::fs::write(&file, b"content")?;
+
+        let mut handler = MoveOrCopyHandler::new(
+            MoveOrCopy::Copy,
+            dest_dir.path().to_path_buf(),
+            DestinationLayout::Mirror,
+        );
+        handler.mirrors = vec![backup_dir.path().to_path_buf()];
+        let options = options(false);
+        let ctx = ExecutionContext {
+            source_dir: src_dir.path(),
+            walk_paths: None,
+            options: &options,
+        };
+
+        assert!(matches!(handler.apply(&file, &ctx)?, Outcome::Applied(_)));
+        for dir in [dest_dir.path(), backup_dir.path()] {
+            assert_eq!(std::fs::read(dir.join("inner").join("IMG_0001.jpg"))?, b"content");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn copy_handler_skips_conflicts() -> TestResult {
+        let src_dir = tempfile::tempdir()?;
+        let dest_dir = tempfile::tempdir()?;
+        let file = src_dir.path().join("IMG_0001.jpg");
+        std::fs::write(&file, b"new")?;
+        std::fs::write(dest_dir.path().join("IMG_0001.jpg"), b"old")?;
+
+        let mut handler = MoveOrCopyHandler::new(
+            MoveOrCopy::Copy,
+            dest_dir.path().to_path_buf(),
+            DestinationLayout::Mirror,
+        );
+        handler.conflicts = ConflictResolver::new(ConflictPolicy::Skip);
+        let options = options(false);
+        let ctx = ExecutionContext {
+            source_dir: src_dir.path(),
+            walk_paths: None,
+            options: &options,
+        };
+
+        assert!(matches!(handler.apply(&file, &ctx)?, Outcome::Skipped(_)));
+        assert_eq!(std::fs::read(dest_dir.path().join("IMG_0001.jpg"))?, b"old");
+
+        Ok(())
+    }
+
+    #[test]
+    fn dry_run_reports_prompted_conflicts() -> TestResult {
+        let src_dir = tempfile::tempdir()?;
+        let dest_dir = tempfile::tempdir()?;
+        let file = src_dir.path().join("IMG_0001.jpg");
+        std::fs::write(&file, b"new")?;
+        std::fs::write(dest_dir.path().join("IMG_0001.jpg"), b"old")?;
+
+        let mut handler = MoveOrCopyHandler::new(
+            MoveOrCopy::Copy,
+            dest_dir.path().to_path_buf(),
+            DestinationLayout::Mirror,
+        );
+        handler.conflicts = ConflictResolver::new(ConflictPolicy::Prompt);
+        let options = options(true);
+        let ctx = ExecutionContext {
+            source_dir: src_dir.path(),
+            walk_paths: None,
+            options: &options,
+        };
+
+        let outcome = handler.apply(&file, &ctx)?;
+        assert!(matches!(outcome, Outcome::Skipped(ref message) if message.starts_with("Would prompt")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn duplicates_are_linked_to_transferred_originals() -> TestResult {
+        let src_dir = tempfile::tempdir()?;
+        let dest_dir = tempfile::tempdir()?;
+        let files = [src_dir.path().join("IMG_0001.jpg"), src_dir.path().join("IMG_0002.jpg")];
+        for file in files.iter() {
+            std::fs::write(file, b"same")?;
+        }
+        std::fs::write(dest_dir.path().join("IMG_0001.jpg"), b"old")?;
+
+        let mut handler = MoveOrCopyHandler::new(
+            MoveOrCopy::Copy,
+            dest_dir.path().to_path_buf(),
+            DestinationLayout::Mirror,
+        );
+        handler.dedupe = Some(DedupeMode::Hardlink);
+        handler.conflicts = ConflictResolver::new(ConflictPolicy::Skip);
+        let handler = handler.with_deduplication(&files)?;
+        let options = options(false);
+        let ctx = ExecutionContext {
+            source_dir: src_dir.path(),
+            walk_paths: None,
+            options: &options,
+        };
+
+        // The original was skipped, so the duplicate isn't linked to the unrelated file
+        assert!(matches!(handler.apply(&files[0], &ctx)?, Outcome::Skipped(_)));
+        assert!(matches!(handler.apply(&files[1], &ctx)?, Outcome::Skipped(_)));
+        assert!(!dest_dir.path().join("IMG_0002.jpg").exists());
+
+        let mut handler = handler.clone();
+        handler.conflicts = ConflictResolver::new(ConflictPolicy::Overwrite);
+        std::fs::write(dest_dir.path().join("IMG_0002.jpg"), b"other")?;
+        assert!(matches!(handler.apply(&files[0], &ctx)?, Outcome::Applied(_)));
+        assert!(matches!(handler.apply(&files[1], &ctx)?, Outcome::Applied(_)));
+        assert_eq!(std::fs::read(dest_dir.path().join("IMG_0002.jpg"))?, b"same");
+
+        Ok(())
+    }
+
+    #[test]
+    fn delete_handler_respects_dry_run() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("IMG_0001.jpg");
+        std::fs::write(&file, b"content")?;
+
+        let handler = Action::Delete(DeleteStrategy::Unlink).handler();
+        let options = options(true);
+        let ctx = ExecutionContext {
+            source_dir: dir.path(),
+            walk_paths: None,
+            options: &options,
+        };
+
+        assert_eq!(handler.selection(), Selection::Rejected);
+        assert!(matches!(handler.apply(&file, &ctx)?, Outcome::Applied(_)));
+        assert!(file.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn by_extension_layout() {
+        let layout = DestinationLayout::ByExtension;
+        let dest = Path::new("dest");
+
+        assert_eq!(
+            layout.destination(Path::new("inner/IMG_0001.CR2"), dest),
+            Path::new("dest/cr2/IMG_0001.CR2")
+        );
+        assert_eq!(
+            layout.destination(Path::new("IMG_0001.jpg"), dest),
+            Path::new("dest/jpg/IMG_0001.jpg")
+        );
+        assert_eq!(
+            layout.destination(Path::new("inner/IMG_0001"), dest),
+            Path::new("dest/IMG_0001")
+        );
+    }
+
+    #[test]
+    fn by_extension_layout_numbers_same_names() -> TestResult {
+        let source_dir = tempfile::tempdir()?;
+        let dest_dir = tempfile::tempdir()?;
+        let files = ["a/IMG_0001.CR2", "b/IMG_0001.CR2"].map(|file| source_dir.path().join(file));
+        for (file, content) in files.iter().zip([b"first", b"other"]) {
+            std::fs::create_dir_all(file.parent().unwrap())?;
+            std::fs::write(file, content)?;
+        }
+        let handler = MoveOrCopyHandler::new(
+            MoveOrCopy::Move,
+            dest_dir.path().to_path_buf(),
+            DestinationLayout::ByExtension,
+        );
+        let ctx = ExecutionContext {
+            source_dir: source_dir.path(),
+            walk_paths: None,
+            options: &ExecutionOptions::default(),
+        };
+        for file in &files {
+            handler.apply(file, &ctx)?;
+        }
+        // Neither file overwrites the other, and asking again gives the same destination
+        assert_eq!(std::fs::read(dest_dir.path().join("cr2/IMG_0001.CR2"))?, b"first");
+        assert_eq!(std::fs::read(dest_dir.path().join("cr2/IMG_0001_2.CR2"))?, b"other");
+        assert_eq!(
+            handler.destination(&files[1], &ctx)?,
+            dest_dir.path().join("cr2/IMG_0001_2.CR2")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn renumbering_follows_keep_order() -> TestResult {
+        let dest_dir = tempfile::tempdir()?;
+        std::fs::write(dest_dir.path().join("002.jpg"), b"")?;
+
+        let files: Vec<_> = ["IMG_0007.jpg", "IMG_0003.jpg", "IMG_0003.cr2", "IMG_0005.jpg"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let mut handler = MoveOrCopyHandler::new(MoveOrCopy::Copy, dest_dir.path().to_path_buf(), Default::default());
+        handler.renumber = Some(RenumberOrder::KeepFile);
+        let mut handler = handler.with_numbering(&files, &[5, 7, 3], &NumberPattern::default())?;
+
+        let name = |file: &str| handler.new_names[Path::new(file)].clone();
+        assert_eq!(name("IMG_0005.jpg"), Path::new("003.jpg"));
+        assert_eq!(name("IMG_0007.jpg"), Path::new("004.jpg"));
+        assert_eq!(name("IMG_0003.jpg"), Path::new("005.jpg"));
+        assert_eq!(name("IMG_0003.cr2"), Path::new("005.cr2"));
+
+        handler.renumber = Some(RenumberOrder::Number);
+        let handler = handler.with_numbering(&files, &[], &NumberPattern::default())?;
+        let name = |file: &str| handler.new_names[Path::new(file)].clone();
+        assert_eq!(name("IMG_0003.cr2"), Path::new("003.cr2"));
+        assert_eq!(name("IMG_0007.jpg"), Path::new("005.jpg"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn quarantine_moves_rejected_files() {
+        let action = Action::new(
+            None,
+            None,
+            Some("rejects".to_owned()),
+            false,
+            None,
+            false,
+            DestinationLayout::Mirror,
+        );
+        let Action::MoveOrCopyTo(handler) = &action else {
+            panic!("Unexpected action: {action:?}");
+        };
+        assert!(matches!(handler.op, MoveOrCopy::Move));
+        assert_eq!(handler.dest_dir, PathBuf::from("rejects"));
+        assert_eq!(action.handler().selection(), Selection::Rejected);
+    }
+
+    #[test]
+    fn copy_handler_maps_walk_paths() -> TestResult {
+        let src_dir = tempfile::tempdir()?;
+        let dest_dir = tempfile::tempdir()?;
+        let handler = MoveOrCopyHandler::new(
+            MoveOrCopy::Copy,
+            dest_dir.path().to_path_buf(),
+            DestinationLayout::Mirror,
+        );
+        let options = options(true);
+
+        // A file reached through a symbolic link, whose canonical path is outside the source directory
+        let linked = PathBuf::from("/elsewhere/IMG_0001.jpg");
+        let walk_paths = HashMap::from([(linked.clone(), PathBuf::from("linked/IMG_0001.jpg"))]);
+        let ctx = ExecutionContext {
+            source_dir: src_dir.path(),
+            walk_paths: Some(&walk_paths),
+            options: &options,
+        };
+        assert_eq!(
+            handler.destination(&linked, &ctx)?,
+            dest_dir.path().join("linked").join("IMG_0001.jpg")
+        );
+
+        let unknown = Path::new("/elsewhere/IMG_0002.jpg");
+        let error = handler.apply(unknown, &ctx).unwrap_err();
+        assert!(error.get_ref().is_some_and(|inner| inner.is::<UnmappablePathError>()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unique_run_subdirectory() -> TestResult {
+        let now = chrono::NaiveDate::from_ymd_opt(2024, 6, 1)
+            .unwrap()
+            .and_hms_opt(15, 30, 0)
+            .unwrap();
+        let subdir = render_subdir_template("{date}_{name}", "wedding", now)?;
+        assert_eq!(subdir, "2024-06-01_wedding");
+        assert!(render_subdir_template("{day}", "wedding", now).is_err());
+
+        let dest_dir = tempfile::tempdir()?;
+        std::fs::create_dir(dest_dir.path().join(&subdir))?;
+        let mut handler = MoveOrCopyHandler::new(
+            MoveOrCopy::Copy,
+            dest_dir.path().to_path_buf(),
+            DestinationLayout::Mirror,
+        );
+        let used = handler.use_subdir(&subdir, true);
+        assert_eq!(handler.dest_dir, dest_dir.path().join("2024-06-01_wedding_2"));
+
+        // The subdirectory of an interrupted run is resumed, instead of starting a new one,
+        // even if the template renders another name by then
+        let session = CopySession::start(&handler.dest_dir, false, &[])?.with_subdir(Some(&used))?;
+        let resumed = |resume| {
+            let mut handler = MoveOrCopyHandler::new(
+                MoveOrCopy::Copy,
+                dest_dir.path().to_path_buf(),
+                DestinationLayout::Mirror,
+            );
+            handler.use_subdir("2024-06-01_1530", resume);
+            handler.dest_dir
+        };
+        assert_eq!(resumed(true), dest_dir.path().join("2024-06-01_wedding_2"));
+        assert_eq!(resumed(false), dest_dir.path().join("2024-06-01_1530"));
+
+        session.finish()?;
+        assert!(!dest_dir.path().join(CopySession::FILE_NAME).exists());
+        assert_eq!(resumed(true), dest_dir.path().join("2024-06-01_1530"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn relative_descriptions() -> TestResult {
+        let options = ExecutionOptions {
+            dry_run: true,
+            relative: true,
+            ..Default::default()
+        };
+        let ctx = ExecutionContext {
+            source_dir: Path::new("/home/user/shoot"),
+            walk_paths: None,
+            options: &options,
+        };
+        let src = Path::new("/home/user/shoot/inner/IMG_0001.jpg");
+
+        let outcome = DeleteStrategy::Unlink.apply(src, &ctx)?;
+        assert_eq!(outcome, Outcome::Applied("Deleted: inner/IMG_0001.jpg".to_owned()));
+        assert_eq!(
+            ctx.display(Path::new("/elsewhere/IMG_0001.jpg")).to_string(),
+            "/elsewhere/IMG_0001.jpg"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn shell_commands() {
+        let options = options(true);
+        let ctx = ExecutionContext {
+            source_dir: Path::new("/src"),
+            walk_paths: None,
+            options: &options,
+        };
+        let src = Path::new("/src/inner/IMG 0001.jpg");
+
+        let copy = MoveOrCopyHandler::new(MoveOrCopy::Copy, PathBuf::from("/dest"), DestinationLayout::Mirror);
+        assert_eq!(
+            copy.shell_command(src, &ctx).unwrap(),
+            "mkdir -p -- '/dest/inner' && cp -- '/src/inner/IMG 0001.jpg' '/dest/inner/IMG 0001.jpg'"
+        );
+        assert_eq!(
+            DeleteStrategy::Shred(3).shell_command(src, &ctx).unwrap(),
+            "shred -u -n 3 -- '/src/inner/IMG 0001.jpg'"
+        );
+        assert_eq!(
+            ListHandler.shell_command(src, &ctx).unwrap(),
+            r"printf '%s\n' '/src/inner/IMG 0001.jpg'"
+        );
+        assert!(copy.shell_command(Path::new("/elsewhere/IMG_0001.jpg"), &ctx).is_none());
+
+        let options = ExecutionOptions {
+            terminator: Terminator::Nul,
+            ..options.clone()
+        };
+        let ctx = ExecutionContext {
+            options: &options,
+            ..ctx
+        };
+        assert_eq!(
+            ListHandler.shell_command(src, &ctx).unwrap(),
+            r"printf '%s\0' '/src/inner/IMG 0001.jpg'"
+        );
+    }
 }
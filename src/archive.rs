@@ -0,0 +1,268 @@
+//! Module containing declarations related to [Archive] struct
+//!
+//! A zip or tar archive can be searched instead of a directory. Its files are filtered by their paths
+//! inside the archive, and only the selected ones are extracted, one entry at a time, without unpacking
+//! the rest of the archive. Reading archives is only available when the crate is built with the `archive` feature.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::file_source::{ScanLimits, SelectedFiles};
+use crate::SelectedDirectory;
+
+/// Format of an archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// A zip archive, `.zip`
+    Zip,
+    /// A tar archive, `.tar`
+    Tar,
+    /// A gzip compressed tar archive, `.tar.gz` or `.tgz`
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// Detect the format of the archive from its extension, returning `None` if the file isn't an archive
+    pub fn detect(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Archive searched instead of a directory
+///
+/// Each file of the archive is represented by the path of the archive joined with its path inside the archive,
+/// like `photos.zip/2024/IMG_0001.jpg`, so the files are filtered and mapped to their destinations
+/// like the files of a directory, with the archive as the source directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Archive {
+    /// Canonical path of the archive
+    pub path: PathBuf,
+    /// Format of the archive
+    pub format: ArchiveFormat,
+}
+
+impl Archive {
+    /// Open the archive at the `path`, detecting its format from the extension
+    ///
+    /// # Errors
+    /// - If the file isn't a zip or tar archive
+    /// - If the path can't be canonicalized
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let format = ArchiveFormat::detect(path).ok_or_else(|| {
+            let message = format!("\"{}\" isn't a zip or tar archive", path.display());
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+        })?;
+        Ok(Archive {
+            path: path.canonicalize()?,
+            format,
+        })
+    }
+
+    /// List the files of the archive, without extracting them
+    ///
+    /// Only the regular files are listed. The files whose paths would leave the destination,
+    /// being absolute or containing `..`, are skipped, and so are the files outside the `limits`.
+    ///
+    /// # Errors
+    /// - If the crate was built without the `archive` feature
+    /// - If the archive can't be read
+    pub fn list(&self, limits: &ScanLimits) -> std::io::Result<SelectedFiles> {
+        let mut files = vec![];
+        read_entries(&self.path, self.format, &mut |file, _| {
+            if file.strip_prefix(&self.path).is_ok_and(|relative| limits.allows_path(relative)) {
+                files.push(file.to_path_buf());
+            }
+        })?;
+        Ok(SelectedFiles {
+            dir: SelectedDirectory(self.path.clone()),
+            files,
+            walk_paths: HashMap::new(),
+            scan_errors: vec![],
+        })
+    }
+
+    /// Read the archive one entry at a time, passing the content of the `selected` files to `extract`
+    ///
+    /// The archive is read once, in the order of its entries. The files that aren't selected are skipped
+    /// without being extracted, so the archive is never unpacked as a whole.
+    ///
+    /// # Errors
+    /// - If the crate was built without the `archive` feature
+    /// - If the archive can't be read
+    pub fn extract(
+        &self,
+        selected: impl Fn(&Path) -> bool,
+        mut extract: impl FnMut(&Path, &mut dyn Read),
+    ) -> std::io::Result<()> {
+        read_entries(&self.path, self.format, &mut |file, content| {
+            if selected(file) {
+                extract(file, content)
+            }
+        })
+    }
+}
+
+/// Read the regular files of the archive in the order of their entries, passing each one to `visit`
+/// with its path joined to the path of the archive
+#[cfg(feature = "archive")]
+fn read_entries(
+    archive: &Path,
+    format: ArchiveFormat,
+    visit: &mut dyn FnMut(&Path, &mut dyn Read),
+) -> std::io::Result<()> {
+    let file = std::io::BufReader::new(std::fs::File::open(archive)?);
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(file).map_err(std::io::Error::other)?;
+            for index in 0..zip.len() {
+                let mut entry = zip.by_index(index).map_err(std::io::Error::other)?;
+                let path = enclosed(Path::new(entry.name()));
+                if let Some(path) = path.filter(|_| entry.is_file()) {
+                    visit(&archive.join(path), &mut entry);
+                }
+            }
+            Ok(())
+        }
+        ArchiveFormat::Tar => read_tar_entries(archive, tar::Archive::new(file), visit),
+        ArchiveFormat::TarGz => read_tar_entries(archive, tar::Archive::new(flate2::read::GzDecoder::new(file)), visit),
+    }
+}
+
+/// Read the regular files of the tar archive, see [read_entries]
+#[cfg(feature = "archive")]
+fn read_tar_entries<R: Read>(
+    archive: &Path,
+    mut tar: tar::Archive<R>,
+    visit: &mut dyn FnMut(&Path, &mut dyn Read),
+) -> std::io::Result<()> {
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = enclosed(&entry.path()?);
+        if let Some(path) = path.filter(|_| entry.header().entry_type().is_file()) {
+            visit(&archive.join(path), &mut entry);
+        }
+    }
+    Ok(())
+}
+
+/// Get the path of the entry without its `.` components, if it is relative and doesn't contain `..`
+#[cfg(feature = "archive")]
+fn enclosed(path: &Path) -> Option<PathBuf> {
+    use std::path::Component;
+
+    let mut enclosed = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => enclosed.push(name),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(enclosed).filter(|enclosed| !enclosed.as_os_str().is_empty())
+}
+
+/// Read the regular files of the archive
+///
+/// Always fails, as the crate was built without the `archive` feature.
+#[cfg(not(feature = "archive"))]
+fn read_entries(
+    _archive: &Path,
+    _format: ArchiveFormat,
+    _visit: &mut dyn FnMut(&Path, &mut dyn Read),
+) -> std::io::Result<()> {
+    let message = "built without the `archive` feature";
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, message))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect_format() {
+        assert_eq!(ArchiveFormat::detect(Path::new("photos.zip")), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::detect(Path::new("photos.TAR")), Some(ArchiveFormat::Tar));
+        assert_eq!(
+            ArchiveFormat::detect(Path::new("photos.tar.gz")),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::detect(Path::new("photos.tgz")),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(ArchiveFormat::detect(Path::new("photos")), None);
+        assert_eq!(ArchiveFormat::detect(Path::new("IMG_0001.jpg")), None);
+    }
+
+    #[test]
+    #[cfg(feature = "archive")]
+    fn extract_selected_files() -> crate::test_utils::TestResult {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir()?;
+        let names = ["IMG_0001.jpg", "2024/IMG_0002.jpg", "2024/.thumbnails/IMG_0003.jpg"];
+
+        let zip_path = dir.path().join("photos.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path)?);
+        zip.add_directory("2024/", zip::write::SimpleFileOptions::default())?;
+        for name in names {
+            zip.start_file(name, zip::write::SimpleFileOptions::default())?;
+            zip.write_all(name.as_bytes())?;
+        }
+        zip.finish()?;
+
+        let tar_path = dir.path().join("photos.tar");
+        let mut tar = tar::Builder::new(std::fs::File::create(&tar_path)?);
+        for name in names.iter().chain(["../IMG_0004.jpg"].iter()) {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(name.len() as u64);
+            header.set_mode(0o644);
+            // The path is written as is, so the entry leaving the archive isn't rejected while it's written
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_cksum();
+            tar.append(&header, name.as_bytes())?;
+        }
+        tar.finish()?;
+
+        for path in [zip_path, tar_path] {
+            let archive = Archive::open(&path)?;
+            let limits = ScanLimits {
+                exclude_dirs: vec![".thumbnails".parse()?],
+                ..ScanLimits::default()
+            };
+            let files = archive.list(&limits)?;
+            let relative: Vec<_> = files
+                .files
+                .iter()
+                .map(|file| file.strip_prefix(&archive.path).unwrap())
+                .collect();
+            assert_eq!(relative, [Path::new("IMG_0001.jpg"), Path::new("2024/IMG_0002.jpg")]);
+
+            let mut extracted = vec![];
+            archive.extract(
+                |file| file.ends_with("IMG_0002.jpg"),
+                |file, content| {
+                    let mut text = String::new();
+                    content.read_to_string(&mut text).unwrap();
+                    extracted.push((file.to_path_buf(), text));
+                },
+            )?;
+            assert_eq!(
+                extracted,
+                [(archive.path.join("2024/IMG_0002.jpg"), "2024/IMG_0002.jpg".to_owned())]
+            );
+        }
+
+        Ok(())
+    }
+}
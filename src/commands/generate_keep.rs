@@ -0,0 +1,131 @@
+//! Module containing the keep file generation subcommand ([GenerateKeepArgs])
+//!
+//! When the selection already exists as a set of files (e.g. the folder of JPEGs the client picked),
+//! the keep file is generated from their numbers, so the matching RAW files can be extracted.
+
+use std::path::PathBuf;
+
+use clap::Args;
+use itertools::Itertools;
+
+use crate::config::{ConfigFile, ConfigFileError};
+use crate::file_source::{FileSource, SelectedFiles};
+use crate::keepfile::{KeepFile, KeepFileLine, KeepTarget, NumberPattern};
+use crate::SelectedDirectory;
+
+/// Arguments of the keep file generation subcommand
+#[derive(Args, Debug, Clone)]
+pub struct GenerateKeepArgs {
+    /// Directory with the already selected files
+    #[clap(value_name = "DIR")]
+    dir: PathBuf,
+
+    /// The keep file to write, or `-` to print the numbers
+    #[clap(short, long, value_name = "FILE", default_value = "keep.txt")]
+    output: PathBuf,
+
+    /// Only use the files matching the configuration file
+    #[clap(long, visible_alias = "cfg", value_name = "FILE")]
+    config: Option<PathBuf>,
+}
+
+/// Error type for the keep file generation
+#[derive(thiserror::Error, Debug)]
+pub enum GenerateKeepError {
+    /// An I/O error occurred while scanning the directory or writing the keep file
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    /// The configuration file couldn't be loaded
+    #[error("{0}")]
+    Config(#[from] ConfigFileError),
+}
+
+impl GenerateKeepArgs {
+    /// Collect the numbers of the files in the directory, sorted and without duplicates
+    ///
+    /// Numbers are extracted the same way files are matched against the keep file,
+    /// with the number pattern and position of the configuration file, if any.
+    ///
+    /// # Errors
+    /// - If the directory can't be scanned
+    /// - If the configuration file can't be loaded
+    pub fn numbers(&self) -> Result<Vec<u32>, GenerateKeepError> {
+        let dir = SelectedDirectory::try_from(self.dir.clone())?;
+        let files = SelectedFiles::try_from(dir.clone())?;
+        let (files, number_pattern) = match &self.config {
+            Some(config) => {
+                let config = ConfigFile::try_load(config)?.with_root(&dir);
+                let number_pattern = config.number_pattern(None, None, None);
+                let files = files.filter_by(config.into_filter()).iter().cloned().collect();
+                (files, number_pattern)
+            }
+            None => (files.iter().cloned().collect::<Vec<_>>(), NumberPattern::default()),
+        };
+        Ok(files
+            .iter()
+            .filter_map(|file| file.file_name()?.to_str())
+            .filter_map(|name| number_pattern.extract(name))
+            .sorted_unstable()
+            .dedup()
+            .collect())
+    }
+
+    /// Generate the keep file
+    ///
+    /// # Errors
+    /// - If the numbers can't be collected
+    /// - If the keep file can't be written
+    pub fn run(&self) -> Result<(), GenerateKeepError> {
+        let mut keepfile = KeepFile::default();
+        for num in self.numbers()? {
+            keepfile.push(KeepFileLine::new(KeepTarget::Number(num)));
+        }
+        if self.output.as_os_str() == "-" {
+            keepfile.write(std::io::stdout().lock())?;
+        } else {
+            keepfile.save(&self.output)?;
+            println!(
+                "Wrote {} numbers to \"{}\"",
+                keepfile.lines.len(),
+                self.output.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    #[test]
+    fn generates_keep_file_from_selected_files() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let selected = dir.path().join("selected");
+        std::fs::create_dir_all(selected.join("inner"))?;
+        for file in [
+            "IMG_0012.jpg",
+            "IMG_0003.jpg",
+            "IMG_0012.cr2",
+            "inner/IMG_0007.jpg",
+            "notes.txt",
+        ] {
+            std::fs::write(selected.join(file), b"content")?;
+        }
+
+        let output = dir.path().join("keep.txt");
+        let args = GenerateKeepArgs {
+            dir: selected,
+            output: output.clone(),
+            config: None,
+        };
+        args.run()?;
+
+        assert_eq!(std::fs::read_to_string(&output)?, "3\n7\n12\n");
+        assert_eq!(KeepFile::try_load(&output)?.numbers(), vec![3, 7, 12]);
+
+        Ok(())
+    }
+}
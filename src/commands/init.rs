@@ -0,0 +1,172 @@
+//! Module containing the scaffolding subcommand ([InitArgs])
+//!
+//! A commented configuration template shows the available keys next to working values,
+//! so a first configuration is edited instead of written from scratch.
+
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use itertools::Itertools;
+
+use crate::file_source::{FileSource, SelectedFiles};
+use crate::SelectedDirectory;
+
+/// Extensions written into the template when they aren't detected
+const DEFAULT_EXTENSIONS: [&str; 3] = ["jpg", "png", "cr2"];
+
+/// Content of the written keep file
+const KEEP_TEMPLATE: &str = "# Numbers, ranges (120-145) or file names to keep, one per line\n";
+
+/// Arguments of the scaffolding subcommand
+#[derive(Args, Debug, Clone)]
+pub struct InitArgs {
+    /// The directory to write the configuration file into
+    #[clap(value_name = "DIR", default_value = ".")]
+    dir: PathBuf,
+
+    /// Also write an empty `keep.txt`
+    #[clap(short, long)]
+    keep: bool,
+
+    /// Fill in the extensions of the files in the directory, instead of the default ones
+    #[clap(long)]
+    detect: bool,
+
+    /// Overwrite the existing files
+    #[clap(short, long)]
+    force: bool,
+}
+
+/// Error type for the scaffolding
+#[derive(thiserror::Error, Debug)]
+pub enum InitError {
+    /// An I/O error occurred while scanning the directory or writing the files
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    /// The file already exists, and overwriting it wasn't allowed
+    #[error("{0:?} already exists, pass --force to overwrite it")]
+    Exists(PathBuf),
+}
+
+impl InitArgs {
+    /// Collect the lowercase extensions of the files in the directory, sorted and without duplicates
+    ///
+    /// # Errors
+    /// - If the directory can't be scanned
+    pub fn extensions(&self) -> Result<Vec<String>, InitError> {
+        let files = SelectedFiles::try_from(SelectedDirectory::try_from(self.dir.clone())?)?;
+        Ok(files
+            .iter()
+            .filter_map(|file| file.extension()?.to_str())
+            .map(str::to_ascii_lowercase)
+            // Numeric extensions, like the parts of split archives, would be read as numbers
+            .filter(|ext| ext.chars().all(|c| c.is_ascii_alphanumeric()) && !ext.chars().all(|c| c.is_ascii_digit()))
+            .sorted_unstable()
+            .dedup()
+            .collect())
+    }
+
+    /// Build the commented configuration template with the extensions to match
+    pub fn template(extensions: &[String]) -> String {
+        format!(
+            "\
+# Configuration of delete-rest, see the README for the details of every key
+# name: my-shoot
+
+# Extensions of the files to match
+extensions: [{}]
+# Regular expressions the names of the files to match have to match
+formats:
+  - IMG_\\d{{4}}\\.\\w+
+
+# Files and directories never matched, even if the rules above match them
+# exclude_extensions: [xmp]
+# exclude_formats: ['^Screen']
+# exclude_dirs: [exports, .thumbnails]
+
+# Limits of the matched files, by size, modification time and depth in the directory
+# min_size: 50KB
+# newer_than: 30d
+# max_depth: 2
+
+# Pattern extracting the number a file name is compared by, the last number in the name by default
+# number_pattern: 'IMG_(?P<number>\\d+)'
+",
+            extensions.join(", ")
+        )
+    }
+
+    /// Write `content` to `path`
+    fn write(path: &Path, content: &str) -> Result<(), InitError> {
+        std::fs::write(path, content)?;
+        println!("Wrote \"{}\"", path.display());
+        Ok(())
+    }
+
+    /// Write the configuration template, and the keep file if requested
+    ///
+    /// # Errors
+    /// - If the directory can't be scanned for the extensions
+    /// - If one of the files exists and `force` isn't set
+    /// - If the files can't be written
+    pub fn run(&self) -> Result<(), InitError> {
+        let extensions = match self.detect {
+            true => self.extensions()?,
+            false => vec![],
+        };
+        let extensions = match extensions.is_empty() {
+            true => DEFAULT_EXTENSIONS.map(str::to_owned).to_vec(),
+            false => extensions,
+        };
+        let mut files = vec![(self.dir.join("config.yaml"), Self::template(&extensions))];
+        if self.keep {
+            files.push((self.dir.join("keep.txt"), KEEP_TEMPLATE.to_owned()));
+        }
+        // Nothing is written if any of the files would be overwritten
+        if let Some((existing, _)) = files.iter().find(|(path, _)| path.exists() && !self.force) {
+            return Err(InitError::Exists(existing.clone()));
+        }
+        for (path, content) in files {
+            Self::write(&path, &content)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::ConfigFile;
+    use crate::keepfile::KeepFile;
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    #[test]
+    fn writes_config_template() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        for file in ["IMG_0001.CR3", "IMG_0001.jpg", "IMG_0002.cr3", "backup.001"] {
+            std::fs::write(dir.path().join(file), b"")?;
+        }
+        let mut args = InitArgs {
+            dir: dir.path().to_path_buf(),
+            keep: true,
+            detect: true,
+            force: false,
+        };
+        assert_eq!(args.extensions()?, ["cr3", "jpg"]);
+        args.run()?;
+
+        let config = ConfigFile::try_load(dir.path().join("config.yaml"))?;
+        assert!(config.matches("IMG_0001.CR3"));
+        assert!(!config.matches("IMG_0001.png"));
+        assert!(KeepFile::try_load(dir.path().join("keep.txt"))?.lines.is_empty());
+
+        assert!(matches!(args.run(), Err(InitError::Exists(_))));
+        args.force = true;
+        args.detect = false;
+        args.run()?;
+        assert!(ConfigFile::try_load(dir.path().join("config.yaml"))?.matches("IMG_0001.png"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+//! Module containing the configuration linting subcommand ([LintArgs])
+//!
+//! Besides the problems [validation](super::validate) reports, linting warns about valid options
+//! that are likely mistakes, like formats that can never match, so they are caught before a destructive run.
+
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::commands::validate::Report;
+use crate::config::{ConfigFile, ConfigFormat};
+
+/// Arguments of the linting subcommand
+#[derive(Args, Debug, Clone)]
+pub struct LintArgs {
+    /// The directory the default configuration file is looked up in
+    #[clap(short, long, default_value = ".", value_name = "DIR")]
+    path: PathBuf,
+
+    /// The configuration file to lint, instead of `config.yaml` or `config.toml` in the directory or its parents
+    #[clap(short = 'Y', long, visible_alias = "cfg", value_name = "FILE")]
+    config: Option<PathBuf>,
+}
+
+/// Error type for the linting, reporting how many problems and warnings were found
+#[derive(thiserror::Error, Debug)]
+#[error("Found {0} problem(s)")]
+pub struct LintError(pub usize);
+
+impl LintArgs {
+    /// Check the configuration file, and look for the likely mistakes if it is valid
+    pub fn check(&self) -> Report {
+        let config = self.config.clone().or_else(|| ConfigFile::discover(&self.path));
+        let config = config.unwrap_or_else(|| ConfigFile::locate(&self.path));
+        let format = ConfigFormat::detect(&config);
+        let problems = match std::fs::read(&config) {
+            Ok(content) => ConfigFile::check(&content, format),
+            Err(e) => vec![e.to_string()],
+        };
+        let warnings = match problems.is_empty() {
+            true => ConfigFile::try_load_as(&config, format)
+                .map(|config| config.lint())
+                .unwrap_or_default(),
+            false => vec![],
+        };
+        Report {
+            file: config,
+            problems,
+            warnings,
+        }
+    }
+
+    /// Lint the configuration file, and print the problems and the warnings
+    ///
+    /// # Errors
+    /// - If the configuration has problems, or warnings
+    pub fn run(&self) -> Result<(), LintError> {
+        let report = self.check();
+        report.print();
+        match report.problems.len() + report.warnings.len() {
+            0 => Ok(()),
+            problems => Err(LintError(problems)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    #[test]
+    fn reports_likely_mistakes() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let config = dir.path().join("config.yaml");
+        let args = LintArgs {
+            path: dir.path().to_path_buf(),
+            config: None,
+        };
+
+        std::fs::write(&config, "extensions: [jpg]\nformats: ['IMG_(']\n")?;
+        let report = args.check();
+        assert_eq!((report.problems.len(), report.warnings.len()), (1, 0));
+
+        std::fs::write(
+            &config,
+            "extensions: [jpg]\nformats: ['IMG_\\d+\\.(cr2|CR3)$', 'IMG_\\d+\\.jpg$']\n",
+        )?;
+        let report = args.check();
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].starts_with("formats[0]"));
+
+        std::fs::write(&config, "extensions: []\nformats: ['.*']\n")?;
+        assert_eq!(args.check().warnings.len(), 2);
+        assert!(matches!(args.run(), Err(LintError(2))));
+        std::fs::write(&config, "extensions: []\nformats: ['(?i)^.+$', 'IMG_.*']\n")?;
+        let report = args.check();
+        assert_eq!(report.warnings.len(), 2);
+        assert!(report.warnings[0].starts_with("formats[0]"));
+
+        std::fs::write(&config, "extensions: [JPG]\nformats: ['^IMG_', '\\.jpg$']\n")?;
+        assert!(args.check().warnings.is_empty());
+        assert!(args.run().is_ok());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,34 @@
+//! Module containing the subcommands of the app
+//!
+//! Each subcommand lives in its own child module.
+
+use clap::Subcommand;
+
+pub mod generate_keep;
+pub mod init;
+pub mod lint;
+pub mod pick;
+pub mod selftest;
+pub mod validate;
+pub mod wizard;
+
+/// Subcommands of the delete-rest app
+///
+/// When no subcommand is provided, the files are processed according to the [crate::Args] flags.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Interactively set up the config and keep files, and optionally run the action
+    Wizard,
+    /// Check that copying, moving and deleting work in a generated sandbox
+    Selftest(selftest::SelftestArgs),
+    /// Generate a keep file from the numbers of the files in a directory
+    GenerateKeep(generate_keep::GenerateKeepArgs),
+    /// Check the configuration and keep files, reporting all their problems without touching any file
+    Validate(validate::ValidateArgs),
+    /// Check the configuration file, and warn about the options that are likely mistakes
+    Lint(lint::LintArgs),
+    /// Pick the files to keep in a terminal UI, and write the keep file
+    Pick(pick::PickArgs),
+    /// Write a commented configuration template, and optionally an empty keep file, into a directory
+    Init(init::InitArgs),
+}
@@ -0,0 +1,411 @@
+//! Module containing the interactive keep list builder subcommand ([PickArgs])
+//!
+//! The files matching the configuration are grouped into frames by their number, so the RAW and JPEG files
+//! of a frame are picked together. The frames marked to keep are written to the keep file, and the frames
+//! marked to discard to its `[delete]` section. The terminal UI requires the `tui` feature.
+
+use std::path::PathBuf;
+
+use clap::Args;
+use itertools::Itertools;
+
+use crate::config::{ConfigFile, ConfigFileError};
+use crate::file_source::{FileSource, SelectedFiles};
+use crate::keepfile::{KeepFile, KeepFileLine, KeepTarget};
+use crate::SelectedDirectory;
+
+/// Arguments of the interactive keep list builder subcommand
+#[derive(Args, Debug, Clone)]
+pub struct PickArgs {
+    /// The directory to search for files
+    #[clap(short, long, default_value = ".", value_name = "DIR")]
+    path: PathBuf,
+
+    /// The configuration file selecting the candidate files,
+    /// instead of `config.yaml` or `config.toml` in the directory or its parents
+    #[clap(short = 'Y', long, visible_alias = "cfg", value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// The keep file to write, `keep.txt` in the searched directory by default
+    #[clap(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Overwrite the keep file if it exists
+    #[clap(short, long)]
+    force: bool,
+}
+
+/// Error type for the interactive keep list builder
+#[derive(thiserror::Error, Debug)]
+pub enum PickError {
+    /// An I/O error occurred while scanning the directory, drawing the UI or writing the keep file
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    /// The configuration file couldn't be loaded
+    #[error("{0}")]
+    Config(#[from] ConfigFileError),
+    /// The keep file exists, and overwriting it wasn't allowed
+    #[error("{0:?} already exists, pass --force to overwrite it")]
+    Exists(PathBuf),
+    /// The terminal UI isn't available
+    #[error("The terminal UI is not available, as the program was built without the `tui` feature")]
+    Unsupported,
+}
+
+/// Files sharing the same number, picked together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// The number of the files, or the file name if it doesn't contain a number
+    pub target: KeepTarget,
+    /// Names of the files of the frame
+    pub files: Vec<String>,
+}
+
+/// Decision taken for a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mark {
+    /// The frame is kept
+    Keep,
+    /// The frame is discarded
+    Discard,
+}
+
+/// State of the picker: the frames, the decisions taken so far, and the selected frame
+#[derive(Debug)]
+pub struct Picker {
+    frames: Vec<Frame>,
+    marks: Vec<Option<Mark>>,
+    cursor: usize,
+}
+
+/// What the picker should do after a key press
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Keep picking
+    Continue,
+    /// Write the keep file and quit
+    Save,
+    /// Quit without writing the keep file
+    Quit,
+}
+
+impl Picker {
+    /// Create a picker over the frames, with no decisions taken
+    pub fn new(frames: Vec<Frame>) -> Self {
+        Picker {
+            marks: vec![None; frames.len()],
+            frames,
+            cursor: 0,
+        }
+    }
+
+    /// Get the frames
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Get the decisions taken for the frames, in the same order
+    pub fn marks(&self) -> &[Option<Mark>] {
+        &self.marks
+    }
+
+    /// Get the index of the selected frame
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Handle a key press
+    ///
+    /// - `Up`/`Down`, `PageUp`/`PageDown`, `Home`/`End` move the selection
+    /// - `y` or `+` keeps the frame, `n`, `-` or `x` discards it, `Space` toggles keeping it,
+    ///   and `u` clears the decision; all of them but `u` move to the next frame
+    /// - `w` or `Enter` writes the keep file and quits, `q` or `Esc` quits without writing it
+    pub fn handle(&mut self, key: PickKey) -> Step {
+        let last = self.frames.len().saturating_sub(1);
+        match key {
+            PickKey::Up => self.cursor = self.cursor.saturating_sub(1),
+            PickKey::Down => self.cursor = (self.cursor + 1).min(last),
+            PickKey::PageUp => self.cursor = self.cursor.saturating_sub(10),
+            PickKey::PageDown => self.cursor = (self.cursor + 10).min(last),
+            PickKey::Home => self.cursor = 0,
+            PickKey::End => self.cursor = last,
+            PickKey::Char('y' | '+') => self.mark(Some(Mark::Keep)),
+            PickKey::Char('n' | '-' | 'x') => self.mark(Some(Mark::Discard)),
+            PickKey::Char(' ') => match self.marks.get(self.cursor) {
+                Some(Some(Mark::Keep)) => self.mark(None),
+                _ => self.mark(Some(Mark::Keep)),
+            },
+            PickKey::Char('u') => {
+                if let Some(mark) = self.marks.get_mut(self.cursor) {
+                    *mark = None;
+                }
+            }
+            PickKey::Char('w') | PickKey::Enter => return Step::Save,
+            PickKey::Char('q') | PickKey::Esc => return Step::Quit,
+            PickKey::Char(_) => {}
+        }
+        Step::Continue
+    }
+
+    /// Take the decision for the selected frame, and move to the next one
+    fn mark(&mut self, mark: Option<Mark>) {
+        if let Some(current) = self.marks.get_mut(self.cursor) {
+            *current = mark;
+            self.cursor = (self.cursor + 1).min(self.frames.len() - 1);
+        }
+    }
+
+    /// Build the keep file from the decisions
+    ///
+    /// The kept frames are the lines to keep, and the discarded ones are the lines of the `[delete]` section.
+    pub fn to_keepfile(&self) -> KeepFile {
+        let mut keepfile = KeepFile::default();
+        for (frame, mark) in self.frames.iter().zip(self.marks.iter()) {
+            let line = KeepFileLine::new(frame.target.clone());
+            match mark {
+                Some(Mark::Keep) => keepfile.push(line),
+                Some(Mark::Discard) => keepfile.denied.push(line),
+                None => {}
+            }
+        }
+        keepfile
+    }
+}
+
+/// Keys the picker responds to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickKey {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Esc,
+    Char(char),
+}
+
+impl PickArgs {
+    /// Get the keep file to write
+    pub fn output(&self) -> PathBuf {
+        self.output.clone().unwrap_or_else(|| self.path.join("keep.txt"))
+    }
+
+    /// Collect the frames of the files matching the configuration, sorted by their numbers
+    ///
+    /// # Errors
+    /// - If the directory can't be scanned
+    /// - If the configuration file can't be loaded
+    pub fn frames(&self) -> Result<Vec<Frame>, PickError> {
+        let config = match &self.config {
+            Some(config) => ConfigFile::try_load(config)?,
+            None => ConfigFile::try_discover(&self.path)?,
+        };
+        let number_pattern = config.number_pattern(None, None, None);
+        let dir = SelectedDirectory::try_from(self.path.clone())?;
+        let config = config.with_root(&dir);
+        let files = SelectedFiles::scan_limited(dir, true, &config.scan_limits())?;
+        let names: Vec<String> = files
+            .filter_by(config.into_filter())
+            .iter()
+            .filter_map(|file| Some(file.file_name()?.to_str()?.to_owned()))
+            .sorted_unstable()
+            .dedup()
+            .collect();
+
+        let frames = names
+            .into_iter()
+            .map(|name| match number_pattern.extract(&name) {
+                Some(num) => (KeepTarget::Number(num), name),
+                None => (KeepTarget::FileName(name.clone()), name),
+            })
+            .into_group_map();
+        Ok(frames
+            .into_iter()
+            .map(|(target, files)| Frame { target, files })
+            .sorted_unstable_by(|a, b| a.target.cmp(&b.target))
+            .collect())
+    }
+
+    /// Pick the frames in the terminal UI, and write the keep file
+    ///
+    /// # Errors
+    /// - If the keep file exists, and `--force` isn't given
+    /// - If the frames can't be collected
+    /// - If the terminal UI can't be drawn
+    /// - If the keep file can't be written
+    pub fn run(&self) -> Result<(), PickError> {
+        let output = self.output();
+        if output.exists() && !self.force {
+            return Err(PickError::Exists(output));
+        }
+        let mut picker = Picker::new(self.frames()?);
+        if picker.frames().is_empty() {
+            println!("No matching files found in \"{}\"", self.path.display());
+            return Ok(());
+        }
+        match tui::pick(&mut picker)? {
+            Step::Save => {
+                let keepfile = picker.to_keepfile();
+                keepfile.save(&output)?;
+                println!(
+                    "Wrote {} kept and {} discarded frames to \"{}\"",
+                    keepfile.lines.len(),
+                    keepfile.denied.len(),
+                    output.display()
+                );
+            }
+            _ => println!("Nothing was written"),
+        }
+        Ok(())
+    }
+}
+
+/// Terminal UI of the picker
+#[cfg(feature = "tui")]
+mod tui {
+    use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+    use ratatui::layout::{Constraint, Layout};
+    use ratatui::style::{Style, Stylize};
+    use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+    use ratatui::{DefaultTerminal, Frame};
+
+    use super::{Mark, PickError, PickKey, Picker, Step};
+
+    /// Help line shown below the frames
+    const HELP: &str = "y/space keep  n/x discard  u clear  ↑/↓ move  w/enter save  q/esc quit";
+
+    /// Run the picker until the user saves or quits
+    pub fn pick(picker: &mut Picker) -> Result<Step, PickError> {
+        let mut terminal = ratatui::init();
+        let result = run(&mut terminal, picker);
+        ratatui::restore();
+        result
+    }
+
+    /// Draw the picker and handle the key presses
+    fn run(terminal: &mut DefaultTerminal, picker: &mut Picker) -> Result<Step, PickError> {
+        let mut state = ListState::default();
+        loop {
+            state.select(Some(picker.cursor()));
+            terminal.draw(|frame| draw(frame, picker, &mut state))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let key = match key.code {
+                KeyCode::Up => PickKey::Up,
+                KeyCode::Down => PickKey::Down,
+                KeyCode::PageUp => PickKey::PageUp,
+                KeyCode::PageDown => PickKey::PageDown,
+                KeyCode::Home => PickKey::Home,
+                KeyCode::End => PickKey::End,
+                KeyCode::Enter => PickKey::Enter,
+                KeyCode::Esc => PickKey::Esc,
+                KeyCode::Char(c) => PickKey::Char(c),
+                _ => continue,
+            };
+            match picker.handle(key) {
+                Step::Continue => {}
+                step => return Ok(step),
+            }
+        }
+    }
+
+    /// Draw the frames with their decisions, and the help line
+    fn draw(frame: &mut Frame, picker: &Picker, state: &mut ListState) {
+        let [list_area, help_area] = Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let items: Vec<ListItem> = picker
+            .frames()
+            .iter()
+            .zip(picker.marks())
+            .map(|(item, mark)| {
+                let (symbol, style) = match mark {
+                    Some(Mark::Keep) => ("[+]", Style::new().green()),
+                    Some(Mark::Discard) => ("[-]", Style::new().red()),
+                    None => ("[ ]", Style::new()),
+                };
+                ListItem::new(format!("{symbol} {}  {}", item.target, item.files.join(", "))).style(style)
+            })
+            .collect();
+        let kept = picker.marks().iter().filter(|mark| **mark == Some(Mark::Keep)).count();
+        let title = format!(" Pick the frames to keep ({kept}/{} kept) ", picker.frames().len());
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().reversed())
+            .highlight_symbol("> ");
+        frame.render_stateful_widget(list, list_area, state);
+        frame.render_widget(Paragraph::new(HELP).dim(), help_area);
+    }
+}
+
+/// Terminal UI of the picker
+///
+/// Always fails, as the crate was built without the `tui` feature.
+#[cfg(not(feature = "tui"))]
+mod tui {
+    use super::{PickError, Picker, Step};
+
+    pub fn pick(_picker: &mut Picker) -> Result<Step, PickError> {
+        Err(PickError::Unsupported)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    #[test]
+    fn picks_frames_into_keep_file() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        for file in [
+            "IMG_0012.jpg",
+            "IMG_0012.cr2",
+            "IMG_0003.jpg",
+            "IMG_0007.jpg",
+            "cover.jpg",
+        ] {
+            std::fs::write(dir.path().join(file), b"content")?;
+        }
+        std::fs::write(
+            dir.path().join("config.yaml"),
+            "extensions: [jpg, cr2]\nformats: ['.+']\n",
+        )?;
+        let args = PickArgs {
+            path: dir.path().to_path_buf(),
+            config: None,
+            output: None,
+            force: false,
+        };
+
+        let mut picker = Picker::new(args.frames()?);
+        let targets: Vec<_> = picker.frames().iter().map(|frame| frame.target.to_string()).collect();
+        assert_eq!(targets, ["3", "7", "12", "cover.jpg"]);
+        assert_eq!(picker.frames()[2].files, ["IMG_0012.cr2", "IMG_0012.jpg"]);
+
+        assert_eq!(picker.handle(PickKey::Char('y')), Step::Continue);
+        assert_eq!(picker.handle(PickKey::Char('x')), Step::Continue);
+        assert_eq!(picker.handle(PickKey::Char(' ')), Step::Continue);
+        picker.handle(PickKey::Up);
+        picker.handle(PickKey::Char(' '));
+        picker.handle(PickKey::End);
+        picker.handle(PickKey::Char('y'));
+        assert_eq!(
+            picker.marks(),
+            [Some(Mark::Keep), Some(Mark::Discard), None, Some(Mark::Keep)]
+        );
+        assert_eq!(picker.handle(PickKey::Enter), Step::Save);
+
+        assert_eq!(args.output(), dir.path().join("keep.txt"));
+        picker.to_keepfile().save(args.output())?;
+        assert_eq!(std::fs::read_to_string(args.output())?, "3\ncover.jpg\n[delete]\n7\n");
+        assert!(matches!(args.run(), Err(PickError::Exists(_))));
+
+        Ok(())
+    }
+}
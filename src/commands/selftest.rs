@@ -0,0 +1,331 @@
+//! Module containing the self-test subcommand ([SelftestArgs])
+//!
+//! The self-test builds a sandbox tree, selects files in it the same way a normal run does,
+//! and runs copy, move and delete cycles against it, verifying the results and undoing
+//! the changes after each cycle. Running it with `--dir` pointing at a storage target
+//! (e.g. a NAS share) checks that the target behaves as expected before trusting it with real files.
+
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+
+use crate::action::{
+    ActionHandler, DeleteStrategy, DestinationLayout, ExecutionContext, MoveOrCopy, MoveOrCopyHandler, Selection,
+};
+use crate::config::ConfigFile;
+use crate::file_source::{FileSource, SelectedFiles};
+use crate::keepfile::KeepFile;
+use crate::{ExecutionOptions, SelectedDirectory};
+
+/// Files created in the sandbox, relative to its source directory
+const SANDBOX_FILES: &[&str] = &[
+    "IMG_0001.jpg",
+    "IMG_0002.jpg",
+    "IMG_0003.cr2",
+    "IMG_0004.cr2",
+    "inner/IMG_0005.jpg",
+    "inner/IMG_0006.jpg",
+    "notes.txt",
+];
+
+/// Keep numbers used in the sandbox
+const SANDBOX_KEEP: &str = "1\n3\n6\n";
+
+/// Files expected to be kept in the sandbox
+const EXPECTED_KEPT: &[&str] = &["IMG_0001.jpg", "IMG_0003.cr2", "inner/IMG_0006.jpg"];
+
+/// Files expected to be rejected in the sandbox
+const EXPECTED_REJECTED: &[&str] = &["IMG_0002.jpg", "IMG_0004.cr2", "inner/IMG_0005.jpg"];
+
+/// Arguments of the self-test subcommand
+#[derive(Args, Debug, Clone)]
+pub struct SelftestArgs {
+    /// Directory to create the sandbox in. Defaults to the system temporary directory
+    #[clap(long, value_name = "DIR")]
+    dir: Option<PathBuf>,
+
+    /// Print detailed information about every step
+    #[clap(short, long)]
+    verbose: bool,
+}
+
+/// Error type for the self-test
+#[derive(thiserror::Error, Debug)]
+pub enum SelftestError {
+    /// An I/O error occurred while running a step
+    #[error("{step}: {source}")]
+    Io { step: &'static str, source: std::io::Error },
+    /// A step produced unexpected results
+    #[error("{step}: {message}")]
+    Verification { step: &'static str, message: String },
+}
+
+/// Attach the name of the step to an I/O error
+trait StepContext<T> {
+    fn step(self, step: &'static str) -> Result<T, SelftestError>;
+}
+
+impl<T> StepContext<T> for std::io::Result<T> {
+    fn step(self, step: &'static str) -> Result<T, SelftestError> {
+        self.map_err(|source| SelftestError::Io { step, source })
+    }
+}
+
+/// Fail the step if the condition doesn't hold
+fn verify(step: &'static str, condition: bool, message: impl FnOnce() -> String) -> Result<(), SelftestError> {
+    if condition {
+        Ok(())
+    } else {
+        Err(SelftestError::Verification {
+            step,
+            message: message(),
+        })
+    }
+}
+
+/// Content written to a sandbox file, unique for every file
+fn content_of(file: &str) -> Vec<u8> {
+    format!("delete-rest selftest: {file}\n").repeat(64).into_bytes()
+}
+
+/// Sandbox tree the self-test runs in
+struct Sandbox {
+    root: tempfile::TempDir,
+    source: SelectedDirectory,
+    config_file: ConfigFile,
+}
+
+impl Sandbox {
+    /// Create the sandbox tree inside the provided directory
+    fn create(dir: &Path) -> std::io::Result<Sandbox> {
+        let root = tempfile::Builder::new().prefix("delete-rest-selftest").tempdir_in(dir)?;
+        let source_dir = root.path().join("source");
+        for file in SANDBOX_FILES {
+            let path = source_dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap_or(&source_dir))?;
+            std::fs::write(path, content_of(file))?;
+        }
+        let config_file = ConfigFile::new(
+            Some("selftest".to_owned()),
+            vec!["jpg".to_owned(), "cr2".to_owned()],
+            vec![regex::Regex::new(r"IMG_\d{4}").expect("Valid regex").into()],
+        );
+
+        Ok(Sandbox {
+            source: SelectedDirectory::try_from(source_dir)?,
+            root,
+            config_file,
+        })
+    }
+
+    /// Select the files the action with the provided selection is applied to
+    fn select(&self, selection: Selection) -> std::io::Result<Vec<PathBuf>> {
+        let keepfile = KeepFile::from_reader(SANDBOX_KEEP.as_bytes(), "<selftest>")
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+        let files = SelectedFiles::try_from(self.source.clone())?
+            .filter_by(self.config_file.clone().into_filter())
+            .filter_by(match selection {
+                Selection::Kept => keepfile.into_inclusion_matcher(),
+                Selection::Rejected => keepfile.into_exclusion_matcher(),
+            });
+        let mut selected: Vec<_> = files.iter().cloned().collect();
+        selected.sort();
+        Ok(selected)
+    }
+
+    /// Get the expected absolute paths of the files, relative to the source directory
+    fn expected(&self, files: &[&str]) -> Vec<PathBuf> {
+        let mut expected: Vec<_> = files.iter().map(|f| self.source.as_ref().join(f)).collect();
+        expected.sort();
+        expected
+    }
+
+    /// Apply the handler to all the files
+    fn apply(&self, step: &'static str, handler: &dyn ActionHandler, files: &[PathBuf]) -> Result<(), SelftestError> {
+        let options = ExecutionOptions {
+            dry_run: false,
+            protect: true,
+            ..Default::default()
+        };
+        let ctx = ExecutionContext {
+            source_dir: self.source.as_ref(),
+            walk_paths: None,
+            options: &options,
+        };
+        for file in files {
+            handler.apply(file, &ctx).step(step)?;
+        }
+        Ok(())
+    }
+
+    /// Verify that all the sandbox files are in the source directory, with their original content
+    fn verify_source_intact(&self, step: &'static str) -> Result<(), SelftestError> {
+        for file in SANDBOX_FILES {
+            let content = std::fs::read(self.source.as_ref().join(file)).step(step)?;
+            verify(step, content == content_of(file), || format!("\"{file}\" was modified"))?;
+        }
+        Ok(())
+    }
+}
+
+/// The self-test runner
+struct Selftest {
+    verbose: bool,
+    passed: usize,
+}
+
+impl Selftest {
+    /// Run a step and report its result
+    fn step(
+        &mut self,
+        name: &'static str,
+        step: impl FnOnce() -> Result<(), SelftestError>,
+    ) -> Result<(), SelftestError> {
+        step()?;
+        self.passed += 1;
+        if self.verbose {
+            println!("ok: {name}");
+        }
+        Ok(())
+    }
+
+    /// Run all the steps in the sandbox
+    fn run(&mut self, sandbox: &Sandbox) -> Result<(), SelftestError> {
+        let dest_dir = sandbox.root.path().join("destination");
+        let dest_of = |file: &str| dest_dir.join(file);
+
+        // Plan
+        let mut kept = vec![];
+        self.step("plan", || {
+            kept = sandbox.select(Selection::Kept).step("plan")?;
+            let expected = sandbox.expected(EXPECTED_KEPT);
+            verify("plan", kept == expected, || {
+                format!("selected {kept:?}, expected {expected:?}")
+            })
+        })?;
+
+        // Copy cycle
+        let copy = MoveOrCopyHandler::new(MoveOrCopy::Copy, dest_dir.clone(), DestinationLayout::Mirror);
+        self.step("copy", || sandbox.apply("copy", &copy, &kept))?;
+        self.step("verify copy", || {
+            for file in EXPECTED_KEPT {
+                let content = std::fs::read(dest_of(file)).step("verify copy")?;
+                verify("verify copy", content == content_of(file), || {
+                    format!("\"{file}\" differs from the source")
+                })?;
+            }
+            sandbox.verify_source_intact("verify copy")
+        })?;
+        self.step("undo copy", || {
+            std::fs::remove_dir_all(&dest_dir).step("undo copy")?;
+            verify("undo copy", !dest_dir.exists(), || {
+                "destination still exists".to_owned()
+            })
+        })?;
+
+        // Move cycle
+        let move_to = MoveOrCopyHandler::new(MoveOrCopy::Move, dest_dir.clone(), DestinationLayout::Mirror);
+        self.step("move", || sandbox.apply("move", &move_to, &kept))?;
+        self.step("verify move", || {
+            for (file, src) in EXPECTED_KEPT.iter().zip(&kept) {
+                let content = std::fs::read(dest_of(file)).step("verify move")?;
+                verify("verify move", content == content_of(file), || {
+                    format!("\"{file}\" differs from the source")
+                })?;
+                verify("verify move", !src.exists(), || {
+                    format!("\"{file}\" is still in the source")
+                })?;
+            }
+            Ok(())
+        })?;
+        self.step("undo move", || {
+            for (file, src) in EXPECTED_KEPT.iter().zip(&kept) {
+                MoveOrCopy::Move.move_or_copy(dest_of(file), src).step("undo move")?;
+            }
+            std::fs::remove_dir_all(&dest_dir).step("undo move")?;
+            sandbox.verify_source_intact("undo move")
+        })?;
+
+        // Delete cycle
+        let mut rejected = vec![];
+        self.step("plan delete", || {
+            rejected = sandbox.select(Selection::Rejected).step("plan delete")?;
+            let expected = sandbox.expected(EXPECTED_REJECTED);
+            verify("plan delete", rejected == expected, || {
+                format!("selected {rejected:?}, expected {expected:?}")
+            })
+        })?;
+        let backup_dir = sandbox.root.path().join("backup");
+        self.step("backup", || {
+            let backup = MoveOrCopyHandler::new(MoveOrCopy::Copy, backup_dir.clone(), DestinationLayout::Mirror);
+            sandbox.apply("backup", &backup, &rejected)
+        })?;
+        self.step("delete", || sandbox.apply("delete", &DeleteStrategy::Unlink, &rejected))?;
+        self.step("verify delete", || {
+            for file in &rejected {
+                verify("verify delete", !file.exists(), || format!("{file:?} still exists"))?;
+            }
+            for file in &kept {
+                verify("verify delete", file.exists(), || format!("{file:?} was deleted"))?;
+            }
+            Ok(())
+        })?;
+        self.step("undo delete", || {
+            for (file, src) in EXPECTED_REJECTED.iter().zip(&rejected) {
+                MoveOrCopy::Move.move_or_copy(backup_dir.join(file), src).step("undo delete")?;
+            }
+            sandbox.verify_source_intact("undo delete")
+        })?;
+
+        Ok(())
+    }
+}
+
+impl SelftestArgs {
+    /// Run the self-test
+    ///
+    /// The sandbox is removed once the self-test is finished, whether it succeeded or not.
+    ///
+    /// # Errors
+    /// - If the sandbox can't be created
+    /// - If any of the steps fails
+    pub fn run(&self) -> Result<(), SelftestError> {
+        let dir = self.dir.clone().unwrap_or_else(std::env::temp_dir);
+        let sandbox = Sandbox::create(&dir).step("create sandbox")?;
+        if self.verbose {
+            println!("Sandbox: {}", sandbox.root.path().display());
+        }
+
+        let mut selftest = Selftest {
+            verbose: self.verbose,
+            passed: 0,
+        };
+        selftest.run(&sandbox)?;
+        sandbox.root.close().step("remove sandbox")?;
+
+        println!("Self-test passed ({} steps)", selftest.passed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    #[test]
+    fn selftest_passes() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let args = SelftestArgs {
+            dir: Some(dir.path().to_path_buf()),
+            verbose: false,
+        };
+        args.run()?;
+
+        // The sandbox is cleaned up
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+//! Module containing the validation subcommand ([ValidateArgs])
+//!
+//! The configuration and keep files are loaded the same way a normal run loads them,
+//! but all their problems are reported at once, and no file is touched.
+//! This allows checking the inputs before a destructive run, e.g. in a script.
+
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::config::{ConfigFile, ConfigFormat};
+use crate::keepfile::formats::KeepFormat;
+use crate::keepfile::{KeepColumn, KeepFile, KeepFileError};
+
+/// Arguments of the validation subcommand
+#[derive(Args, Debug, Clone)]
+pub struct ValidateArgs {
+    /// The directory the default configuration and keep files are looked up in
+    #[clap(short, long, default_value = ".", value_name = "DIR")]
+    path: PathBuf,
+
+    /// The configuration file to check, instead of `config.yaml` or `config.toml` in the directory or its parents
+    #[clap(short = 'Y', long, visible_alias = "cfg", value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// The keep file to check, instead of `keep.txt` in the directory. Can be repeated
+    #[clap(short, long, value_name = "FILE")]
+    keep: Vec<PathBuf>,
+
+    /// Format of the keep files. Detected by the extension if not provided
+    #[clap(long, value_enum, value_name = "FORMAT")]
+    keep_format: Option<KeepFormat>,
+
+    /// Column of CSV keep files with the numbers or file names to keep, by header name or position from 1
+    #[clap(long, value_name = "NAME|INDEX")]
+    keep_column: Option<KeepColumn>,
+}
+
+/// Error type for the validation, reporting how many problems were found
+#[derive(thiserror::Error, Debug)]
+#[error("Found {0} problem(s)")]
+pub struct ValidateError(pub usize);
+
+/// Problems found in one of the checked files
+#[derive(Debug)]
+pub struct Report {
+    /// The checked file
+    pub file: PathBuf,
+    /// Problems preventing the file from being used
+    pub problems: Vec<String>,
+    /// Problems the file can still be used with
+    pub warnings: Vec<String>,
+}
+
+impl ValidateArgs {
+    /// Check the configuration and keep files, without touching any file
+    ///
+    /// The default configuration file is only checked if it exists, as the builtin configuration is used otherwise.
+    pub fn check(&self) -> Vec<Report> {
+        let mut reports = vec![];
+
+        let config = match &self.config {
+            Some(config) => Some(config.clone()),
+            None => ConfigFile::discover(&self.path),
+        };
+        if let Some(config) = config {
+            let problems = match std::fs::read(&config) {
+                Ok(content) => ConfigFile::check(&content, ConfigFormat::detect(&config)),
+                Err(e) => vec![e.to_string()],
+            };
+            reports.push(Report {
+                file: config,
+                problems,
+                warnings: vec![],
+            });
+        }
+
+        let keep = match self.keep.as_slice() {
+            [] => vec![self.path.join("keep.txt")],
+            keep => keep.to_vec(),
+        };
+        for keep in keep {
+            reports.push(self.check_keep(keep));
+        }
+        reports
+    }
+
+    /// Check the keep file, reporting every invalid line and every repeated number
+    fn check_keep(&self, keep: PathBuf) -> Report {
+        let format = self.keep_format.unwrap_or_else(|| KeepFormat::detect(&keep));
+        let column = self.keep_column.clone().unwrap_or_default();
+        let duplicates = |keepfile: &KeepFile| keepfile.duplicates().iter().map(ToString::to_string).collect();
+        let (problems, warnings) = match KeepFile::try_load_as(&keep, format, &column) {
+            Ok(keepfile) => (vec![], duplicates(&keepfile)),
+            Err(KeepFileError::Format { lines, partial, .. }) => {
+                (lines.0.iter().map(ToString::to_string).collect(), duplicates(&partial))
+            }
+            Err(e) => (vec![e.to_string()], vec![]),
+        };
+        Report {
+            file: keep,
+            problems,
+            warnings,
+        }
+    }
+
+    /// Check the configuration and keep files, and print the problems
+    ///
+    /// # Errors
+    /// - If any of the files has problems preventing it from being used
+    pub fn run(&self) -> Result<(), ValidateError> {
+        let reports = self.check();
+        for report in reports.iter() {
+            report.print();
+        }
+        match reports.iter().map(|report| report.problems.len()).sum() {
+            0 => Ok(()),
+            problems => Err(ValidateError(problems)),
+        }
+    }
+}
+
+impl Report {
+    /// Print the problems and the warnings of the file, or that it is valid
+    pub fn print(&self) {
+        if self.problems.is_empty() && self.warnings.is_empty() {
+            return println!("\"{}\": OK", self.file.display());
+        }
+        println!("\"{}\":", self.file.display());
+        for problem in self.problems.iter() {
+            println!("  Error: {}", problem.trim_end().replace('\n', "\n    "));
+        }
+        for warning in self.warnings.iter() {
+            println!("  Warning: {}", warning.trim_end().replace('\n', "\n    "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    #[test]
+    fn reports_all_problems() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(
+            dir.path().join("config.yaml"),
+            "extensions: [jpg]\nformats: ['IMG_(', '.+']\nnumber_pattern: '(\\d+'\nextension: [cr2]\n",
+        )?;
+        std::fs::write(dir.path().join("keep.txt"), "12\nhello\n12\n13-:x\n")?;
+        std::fs::write(dir.path().join("valid.txt"), "1\n2\n")?;
+
+        let mut args = ValidateArgs {
+            path: dir.path().to_path_buf(),
+            config: None,
+            keep: vec![],
+            keep_format: None,
+            keep_column: None,
+        };
+        let reports = args.check();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].problems.len(), 3);
+        assert!(reports[0].problems[0].starts_with("formats[0]"));
+        assert!(reports[0].problems[1].starts_with("number_pattern"));
+        assert_eq!(
+            reports[0].problems[2],
+            "unknown key \"extension\", did you mean \"extensions\"?"
+        );
+        assert_eq!(reports[1].problems.len(), 2);
+        assert!(reports[1].problems[1].starts_with("Line 4: 13-:x"));
+        assert_eq!(reports[1].warnings.len(), 1);
+        assert!(matches!(args.run(), Err(ValidateError(5))));
+
+        args.keep = vec![dir.path().join("valid.txt")];
+        std::fs::write(dir.path().join("config.yaml"), "extensions: [jpg]\nformats: ['.+']\n")?;
+        assert!(args.check().iter().all(|report| report.problems.is_empty()));
+        assert!(args.run().is_ok());
+
+        Ok(())
+    }
+}
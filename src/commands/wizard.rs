@@ -0,0 +1,326 @@
+//! Module containing the guided interactive setup ([Wizard])
+
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use itertools::Itertools;
+use regex::Regex;
+
+use crate::action::Selection;
+use crate::config::{ConfigFile, ConfigFileError};
+use crate::file_source::{FileSource, SelectedFiles};
+use crate::keepfile::KeepFile;
+use crate::{AppConfig, AppConfigError, Args, SelectedDirectory};
+
+/// Action chosen in the wizard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WizardAction {
+    Copy,
+    Move,
+    Delete,
+}
+
+impl WizardAction {
+    /// Get a description of the action
+    fn description(self) -> &'static str {
+        match self {
+            WizardAction::Copy => "copy",
+            WizardAction::Move => "move",
+            WizardAction::Delete => "delete",
+        }
+    }
+
+    /// Files the action is applied to
+    fn selection(self) -> Selection {
+        match self {
+            WizardAction::Delete => Selection::Rejected,
+            WizardAction::Copy | WizardAction::Move => Selection::Kept,
+        }
+    }
+}
+
+/// Guided interactive setup
+///
+/// The wizard asks for the source directory, the action, the destination,
+/// the extensions, the file name format and the keep numbers.
+/// It then shows how many files would be affected, writes the config and keep files
+/// into the source directory and optionally returns the configuration to execute.
+pub struct Wizard<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Wizard<R, W> {
+    /// Create a new wizard reading answers from `input` and writing questions to `output`
+    pub fn new(input: R, output: W) -> Self {
+        Wizard { input, output }
+    }
+
+    /// Print the prompt and read the trimmed answer
+    ///
+    /// # Errors
+    /// - If the input ends before the question is answered
+    fn prompt(&mut self, prompt: &str) -> std::io::Result<String> {
+        write!(self.output, "{prompt}: ")?;
+        self.output.flush()?;
+
+        let mut answer = String::new();
+        if self.input.read_line(&mut answer)? == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "Input ended before the wizard was finished",
+            ));
+        }
+        Ok(answer.trim().to_owned())
+    }
+
+    /// Ask a question, returning the answer or the default if the answer is empty
+    fn ask(&mut self, question: &str, default: Option<&str>) -> std::io::Result<String> {
+        match default {
+            Some(default) => {
+                let answer = self.prompt(&format!("{question} [{default}]"))?;
+                Ok(if answer.is_empty() { default.to_owned() } else { answer })
+            }
+            None => self.prompt(question),
+        }
+    }
+
+    /// Ask a yes/no question
+    fn confirm(&mut self, question: &str, default: bool) -> std::io::Result<bool> {
+        let hint = if default { "Y/n" } else { "y/N" };
+        loop {
+            let answer = self.prompt(&format!("{question} [{hint}]"))?;
+            match answer.to_ascii_lowercase().as_str() {
+                "" => return Ok(default),
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => writeln!(self.output, "Please answer with `y` or `n`")?,
+            }
+        }
+    }
+
+    /// Ask for the source directory
+    fn ask_source(&mut self) -> std::io::Result<SelectedDirectory> {
+        loop {
+            let answer = self.ask("Directory to search for files", Some("."))?;
+            match SelectedDirectory::try_from(PathBuf::from(&answer)) {
+                Ok(dir) => return Ok(dir),
+                Err(e) => writeln!(self.output, "\"{answer}\" can't be used: {e}")?,
+            }
+        }
+    }
+
+    /// Ask for the action to perform
+    fn ask_action(&mut self) -> std::io::Result<WizardAction> {
+        loop {
+            let answer = self.ask("Action to perform (copy, move, delete)", Some("copy"))?;
+            match answer.to_ascii_lowercase().as_str() {
+                "c" | "copy" => return Ok(WizardAction::Copy),
+                "m" | "move" => return Ok(WizardAction::Move),
+                "d" | "delete" => return Ok(WizardAction::Delete),
+                _ => writeln!(self.output, "Unknown action \"{answer}\"")?,
+            }
+        }
+    }
+
+    /// Ask for the file name format
+    fn ask_format(&mut self) -> std::io::Result<Regex> {
+        loop {
+            let answer = self.ask("File name format (regular expression)", Some(r".+\d+"))?;
+            match Regex::new(&answer) {
+                Ok(re) => return Ok(re),
+                Err(e) => writeln!(self.output, "Invalid format: {e}")?,
+            }
+        }
+    }
+
+    /// Ask for the keep numbers
+    fn ask_keep_numbers(&mut self) -> std::io::Result<String> {
+        loop {
+            let answer = self.ask("Numbers to keep (separated by spaces or commas)", None)?;
+            let lines = answer.split([' ', ',']).filter(|n| !n.is_empty()).join("\n");
+            match KeepFile::from_reader(lines.as_bytes(), "<input>") {
+                Ok(_) => return Ok(lines + "\n"),
+                Err(e) => writeln!(self.output, "{e}")?,
+            }
+        }
+    }
+
+    /// Write `content` to `path`, asking before overwriting an existing file
+    fn write_file(&mut self, path: &Path, content: &str) -> std::io::Result<bool> {
+        if path.exists() && !self.confirm(&format!("\"{}\" already exists. Overwrite it?", path.display()), false)? {
+            return Ok(false);
+        }
+        std::fs::write(path, content)?;
+        writeln!(self.output, "Written \"{}\"", path.display())?;
+        Ok(true)
+    }
+
+    /// Run the wizard
+    ///
+    /// Returns the configuration to execute if the user chose to run the action.
+    ///
+    /// # Errors
+    /// - If the input ends before the wizard is finished
+    /// - If the config or keep file can't be written
+    /// - If the written files can't be loaded back
+    pub fn run(mut self) -> Result<Option<AppConfig>, AppConfigError> {
+        let source = self.ask_source()?;
+        let action = self.ask_action()?;
+        let destination = match action {
+            WizardAction::Delete => None,
+            _ => Some(self.ask("Destination directory", Some("selected"))?),
+        };
+        let extensions = self
+            .ask("Extensions to match (separated by spaces or commas)", Some("jpg, png"))?
+            .split([' ', ','])
+            .filter(|ext| !ext.is_empty())
+            .map(|ext| ext.trim_start_matches('.').to_ascii_lowercase())
+            .collect();
+        let format = self.ask_format()?;
+        let keep_numbers = self.ask_keep_numbers()?;
+
+        let config_file = ConfigFile::new(Some("wizard".to_owned()), extensions, vec![format.into()]);
+        let keepfile = KeepFile::from_reader(keep_numbers.as_bytes(), "<input>")?;
+
+        // Preview the number of affected files
+        let files = SelectedFiles::try_from(source.clone())?;
+        let total = files.count();
+        let matching = files.filter_by(config_file.clone().into_filter());
+        let matching_count = matching.count();
+        let affected = matching.filter_by(match action.selection() {
+            Selection::Kept => keepfile.into_inclusion_matcher(),
+            Selection::Rejected => keepfile.into_exclusion_matcher(),
+        });
+        writeln!(self.output, "Matching files: {matching_count}/{total}")?;
+        writeln!(self.output, "Files to {}: {}", action.description(), affected.count())?;
+
+        let config_path = source.as_ref().join("config.yaml");
+        let keep_path = source.as_ref().join("keep.txt");
+        let config_yaml = serde_yaml::to_string(&config_file).map_err(ConfigFileError::from)?;
+        // Both files are offered for writing, even if the first one is kept
+        let written = self.write_file(&config_path, &config_yaml)? & self.write_file(&keep_path, &keep_numbers)?;
+        if !written {
+            writeln!(self.output, "Not executing, the existing files were kept")?;
+            return Ok(None);
+        }
+
+        if !self.confirm("Execute now?", false)? {
+            return Ok(None);
+        }
+
+        let mut args = vec![
+            "delete-rest".to_owned(),
+            "--verbose".to_owned(),
+            "--path".to_owned(),
+            source.as_ref().display().to_string(),
+            "--config".to_owned(),
+            config_path.display().to_string(),
+            "--keep".to_owned(),
+            keep_path.display().to_string(),
+        ];
+        match (action, destination) {
+            (WizardAction::Copy, Some(dest)) => args.extend(["-c".to_owned(), dest]),
+            (WizardAction::Move, Some(dest)) => args.extend(["-m".to_owned(), dest]),
+            _ => args.push("-d".to_owned()),
+        }
+
+        let args = Args::try_parse_from(args)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+        AppConfig::try_from(args).map(Some)
+    }
+}
+
+/// Run the wizard on the standard input and output
+pub fn run() -> Result<Option<AppConfig>, AppConfigError> {
+    Wizard::new(std::io::stdin().lock(), std::io::stdout()).run()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    fn sandbox() -> std::io::Result<tempfile::TempDir> {
+        let dir = tempfile::tempdir()?;
+        for i in 1..=4 {
+            std::fs::write(dir.path().join(format!("IMG_{i:04}.jpg")), b"")?;
+        }
+        Ok(dir)
+    }
+
+    #[test]
+    fn writes_config_and_keep_files() -> TestResult {
+        let dir = sandbox()?;
+        let input = format!("{}\ncopy\n\njpg\nIMG_\\d+\n1, 3\nn\n", dir.path().display());
+        let mut output = Vec::new();
+
+        let config = Wizard::new(input.as_bytes(), &mut output).run()?;
+        assert!(config.is_none());
+
+        let output = String::from_utf8(output)?;
+        assert!(output.contains("Matching files: 4/4"));
+        assert!(output.contains("Files to copy: 2"));
+
+        let keepfile = KeepFile::try_load(dir.path().join("keep.txt"))?;
+        assert_eq!(keepfile.lines.len(), 2);
+        let config = ConfigFile::try_load(dir.path().join("config.yaml"))?;
+        assert!(config.matches("IMG_0001.jpg"));
+        assert!(!config.matches("IMG_0001.png"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn returns_config_to_execute() -> TestResult {
+        let dir = sandbox()?;
+        let input = format!("{}\nd\nJPG\n\n2\ny\n", dir.path().display());
+
+        let config = Wizard::new(input.as_bytes(), Vec::new()).run()?.expect("Config to execute");
+        assert_eq!(config.action.handler().selection(), Selection::Rejected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn stops_when_overwrite_is_declined() -> TestResult {
+        let dir = sandbox()?;
+        std::fs::write(dir.path().join("config.yaml"), "existing")?;
+        let input = format!("{}\nd\nJPG\n\n2\nn\n", dir.path().display());
+        let mut output = Vec::new();
+
+        let config = Wizard::new(input.as_bytes(), &mut output).run()?;
+        assert!(config.is_none());
+        assert_eq!(std::fs::read_to_string(dir.path().join("config.yaml"))?, "existing");
+
+        let output = String::from_utf8(output)?;
+        assert!(output.contains("Not executing"));
+        assert!(!output.contains("Execute now?"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reasks_invalid_answers() -> TestResult {
+        let dir = sandbox()?;
+        let input = format!("{}\nshred\nmove\nout\njpg\n(\n\\d+\nfoo\n4\nn\n", dir.path().display());
+        let mut output = Vec::new();
+
+        Wizard::new(input.as_bytes(), &mut output).run()?;
+
+        let output = String::from_utf8(output)?;
+        assert!(output.contains("Unknown action \"shred\""));
+        assert!(output.contains("Invalid format"));
+        assert!(output.contains("Files to move: 1"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_early_eof() {
+        let result = Wizard::new(".\n".as_bytes(), Vec::new()).run();
+        assert!(result.is_err());
+    }
+}
@@ -1,35 +1,209 @@
 //! Module containing declarations related to [ConfigFile] struct
 
-use std::convert::identity;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::Infallible;
 use std::fmt::{Display, Formatter};
-use std::fs::File;
-use std::io::BufReader;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::str::FromStr;
+use std::time::SystemTime;
 
+use clap::ValueEnum;
 use itertools::Itertools;
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
 use regex_macro::regex;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+use crate::explain::Step;
+use crate::file_source::ScanLimits;
+use crate::filters::{FileSize, ImageInfo, TimeLimit};
+use crate::hooks::Hooks;
+use crate::keepfile::{NumberMatching, NumberPattern, NumberPosition};
+use crate::permissions::Permissions;
+
+/// Entries marking the root of a project, above which the configuration file isn't looked up
+pub const ROOT_MARKERS: [&str; 2] = [".git", ".delete-rest-root"];
+
+/// Builtin extension groups, which the extension lists can reference as `@name`
+const EXTENSION_GROUPS: [(&str, &[&str]); 5] = [
+    (
+        "raw",
+        &[
+            "3fr", "arw", "cr2", "cr3", "crw", "dng", "erf", "iiq", "kdc", "mrw", "nef", "nrw", "orf", "pef", "raf",
+            "rw2", "rwl", "sr2", "srf", "srw", "x3f",
+        ],
+    ),
+    ("jpeg", &["jpg", "jpeg", "jpe"]),
+    ("heif", &["heic", "heif", "hif"]),
+    ("video", &["mp4", "mov", "avi", "mkv", "mts", "m2ts", "m4v"]),
+    ("sidecar", &["xmp", "thm", "aae"]),
+];
+
 /// A file filter configuration
 ///
 /// This type describes how to filter files based on their names and extensions.
 ///
 /// # Default values
 /// Default configuration is resolved in the following order:
-/// 1. Look for a file named `config.yaml` in the same directory as the executable
-/// 2. Look for a file named `config.yaml` in the parent directory of the executable
+/// 1. Look for a file named `config.yaml`, `config.toml` or `config.json` in the same directory as the executable
+/// 2. Look for a file named `config.yaml`, `config.toml` or `config.json` in the parent directory of the executable
 /// 3. Use the default embedded configuration
 /// 4. Use the hardcoded default configuration
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ConfigFile {
     /// The name of the filter configuration
     name: Option<String>,
+    /// Path of the configuration this one is layered on top of, relative to this one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extends: Option<PathBuf>,
+    /// Whether the configuration files in the subdirectories are used for the files beneath them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    nested_configs: bool,
     /// The list of file extensions to match
+    #[serde(default)]
     extensions: Vec<String>,
     /// The list of file formats to match
+    #[serde(default)]
     formats: Vec<Format>,
+    /// Whether the extensions and the formats are matched regardless of case
+    #[serde(default, skip_serializing_if = "CaseInsensitive::is_default")]
+    case_insensitive: CaseInsensitive,
+    /// Whether the formats are matched against the path relative to the searched directory, instead of the file name
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    match_path: bool,
+    /// Whether the formats have to match the whole file name, with or without the extension, instead of a part of it,
+    /// unless a format sets it itself
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    anchored: bool,
+    /// Expression combining extension and format rules, which the matched files have to satisfy
+    #[serde(
+        rename = "match",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_yaml::with::singleton_map_recursive"
+    )]
+    filter: Option<FilterExpr>,
+    /// Content types, sniffed from the file headers, matching the files regardless of their extensions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    content_types: Vec<String>,
+    /// Named formats, which the formats can reference as `@name`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    patterns: BTreeMap<String, Format>,
+    /// Named lists of extensions, which the extension lists can reference as `@name`,
+    /// replacing the builtin groups with the same name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    extension_groups: BTreeMap<String, Vec<String>>,
+    /// The list of file extensions to never match, even if the other rules match them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exclude_extensions: Vec<String>,
+    /// The list of file formats to never match, even if the other rules match them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exclude_formats: Vec<Format>,
+    /// Names or glob patterns of the directories whose files are never matched, which aren't searched at all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exclude_dirs: Vec<DirPattern>,
+    /// Search the hidden files and directories, whose names start with a dot, which are skipped otherwise
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    include_hidden: bool,
+    /// Don't search the subdirectories on other filesystems than the searched directory, like mount points
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    one_file_system: bool,
+    /// Smallest number of subdirectories the matched files are nested in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_depth: Option<usize>,
+    /// Largest number of subdirectories the matched files are nested in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_depth: Option<usize>,
+    /// Smallest size of the files to match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_size: Option<FileSize>,
+    /// Largest size of the files to match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_size: Option<FileSize>,
+    /// Only match the files modified after this time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    newer_than: Option<TimeLimit>,
+    /// Only match the files modified before this time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    older_than: Option<TimeLimit>,
+    /// Smallest width of the images to match, in pixels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_width: Option<u32>,
+    /// Largest width of the images to match, in pixels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_width: Option<u32>,
+    /// Smallest height of the images to match, in pixels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_height: Option<u32>,
+    /// Largest height of the images to match, in pixels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_height: Option<u32>,
+    /// Camera models the EXIF data of the images to match has to contain one of, ignoring the case
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    camera_models: Vec<String>,
+    /// Smallest ISO speed of the images to match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_iso: Option<u32>,
+    /// Largest ISO speed of the images to match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_iso: Option<u32>,
+    /// Shell commands to run before and after the action
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    hooks: Hooks,
+    /// Permissions and ownership applied to the moved or copied files
+    #[serde(default, skip_serializing_if = "Permissions::is_empty")]
+    permissions: Permissions,
+    /// Pattern extracting the number a file name is compared by
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    number_pattern: Option<NumberPattern>,
+    /// Which of the numbers in the file name is compared against the keep file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    number_position: Option<NumberPosition>,
+    /// How the numbers of a file name are found, by the number pattern or as the whole numeric tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    number_matching: Option<NumberMatching>,
+    /// Named profiles, each overriding some of the filtering options
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    profiles: BTreeMap<String, Profile>,
+    /// Name of the profile used when none is selected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default_profile: Option<String>,
+    /// Name of the profile applied to the configuration, if any
+    #[serde(skip)]
+    profile: Option<String>,
+    /// Paths the configuration was loaded from, in the order they were merged
+    #[serde(skip)]
+    sources: Vec<PathBuf>,
+    /// Paths of the configurations the loaded ones extend
+    #[serde(skip)]
+    extended: Vec<PathBuf>,
+    /// Directories the paths are made relative to when the formats are matched against them
+    #[serde(skip)]
+    roots: Vec<PathBuf>,
+}
+
+/// A named set of filtering options, overriding the ones of the configuration it is defined in
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// The list of file extensions to match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extensions: Option<Vec<String>>,
+    /// The list of file formats to match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    formats: Option<Vec<Format>>,
+    /// Pattern extracting the number a file name is compared by
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    number_pattern: Option<NumberPattern>,
+    /// Which of the numbers in the file name is compared against the keep file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    number_position: Option<NumberPosition>,
+    /// How the numbers of a file name are found, by the number pattern or as the whole numeric tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    number_matching: Option<NumberMatching>,
 }
 
 impl Display for ConfigFile {
@@ -38,8 +212,110 @@ impl Display for ConfigFile {
         if let Some(name) = &self.name {
             writeln!(f, "    Name: {:?},", name)?;
         }
+        if let Some(profile) = &self.profile {
+            writeln!(f, "    Profile: {:?},", profile)?;
+        }
+        if let Some(extends) = &self.extends {
+            writeln!(f, "    Extends: {:?},", extends)?;
+        }
+        if self.nested_configs {
+            writeln!(f, "    Nested configs: true,")?;
+        }
         writeln!(f, "    Extensions: {:?},", self.extensions)?;
         writeln!(f, "    Formats: [{}],", self.formats.iter().join(", "))?;
+        if !self.case_insensitive.is_default() {
+            writeln!(f, "    Case insensitive: {},", self.case_insensitive)?;
+        }
+        if self.match_path {
+            writeln!(f, "    Match path: true,")?;
+        }
+        if self.anchored {
+            writeln!(f, "    Anchored: true,")?;
+        }
+        if let Some(filter) = &self.filter {
+            writeln!(f, "    Match: {},", filter)?;
+        }
+        if !self.content_types.is_empty() {
+            writeln!(f, "    Content types: {:?},", self.content_types)?;
+        }
+        if !self.patterns.is_empty() {
+            let patterns = self.patterns.iter().map(|(name, format)| format!("{name}: {format}"));
+            writeln!(f, "    Patterns: {{{}}},", patterns.format(", "))?;
+        }
+        if !self.extension_groups.is_empty() {
+            let groups = self
+                .extension_groups
+                .iter()
+                .map(|(name, extensions)| format!("{name}: {extensions:?}"));
+            writeln!(f, "    Extension groups: {{{}}},", groups.format(", "))?;
+        }
+        if !self.exclude_extensions.is_empty() {
+            writeln!(f, "    Excluded extensions: {:?},", self.exclude_extensions)?;
+        }
+        if !self.exclude_formats.is_empty() {
+            writeln!(f, "    Excluded formats: [{}],", self.exclude_formats.iter().join(", "))?;
+        }
+        if !self.exclude_dirs.is_empty() {
+            writeln!(
+                f,
+                "    Excluded directories: [{}],",
+                self.exclude_dirs.iter().join(", ")
+            )?;
+        }
+        if self.include_hidden {
+            writeln!(f, "    Include hidden: true,")?;
+        }
+        if self.one_file_system {
+            writeln!(f, "    One file system: true,")?;
+        }
+        if let Some(min_depth) = &self.min_depth {
+            writeln!(f, "    Minimum depth: {},", min_depth)?;
+        }
+        if let Some(max_depth) = &self.max_depth {
+            writeln!(f, "    Maximum depth: {},", max_depth)?;
+        }
+        if let Some(min_size) = &self.min_size {
+            writeln!(f, "    Minimum size: {},", min_size)?;
+        }
+        if let Some(max_size) = &self.max_size {
+            writeln!(f, "    Maximum size: {},", max_size)?;
+        }
+        if let Some(newer_than) = &self.newer_than {
+            writeln!(f, "    Newer than: {},", newer_than)?;
+        }
+        if let Some(older_than) = &self.older_than {
+            writeln!(f, "    Older than: {},", older_than)?;
+        }
+        for (name, limit) in [
+            ("Minimum width", self.min_width),
+            ("Maximum width", self.max_width),
+            ("Minimum height", self.min_height),
+            ("Maximum height", self.max_height),
+            ("Minimum ISO", self.min_iso),
+            ("Maximum ISO", self.max_iso),
+        ] {
+            if let Some(limit) = limit {
+                writeln!(f, "    {name}: {limit},")?;
+            }
+        }
+        if !self.camera_models.is_empty() {
+            writeln!(f, "    Camera models: {:?},", self.camera_models)?;
+        }
+        if !self.hooks.is_empty() {
+            writeln!(f, "    Hooks: {},", self.hooks)?;
+        }
+        if !self.permissions.is_empty() {
+            writeln!(f, "    Permissions: {},", self.permissions)?;
+        }
+        if let Some(number_pattern) = &self.number_pattern {
+            writeln!(f, "    Number pattern: {},", number_pattern)?;
+        }
+        if let Some(number_position) = &self.number_position {
+            writeln!(f, "    Number position: {},", number_position)?;
+        }
+        if let Some(number_matching) = &self.number_matching {
+            writeln!(f, "    Number matching: {},", number_matching)?;
+        }
         writeln!(f, "}}")?;
 
         Ok(())
@@ -47,147 +323,2387 @@ impl Display for ConfigFile {
 }
 
 impl Default for ConfigFile {
+    /// Get the default configuration, see [ConfigFile::try_default]
+    ///
+    /// If the default configuration file is broken, the builtin configuration is used instead.
+    /// Use [ConfigFile::try_default] to get the error, to report it.
     fn default() -> Self {
-        // Get the path of the executable
-        let install_dir = std::env::current_exe()
-            .ok()
-            .and_then(|p| p.parent().map(|p| p.to_owned()))
-            .filter(|p| p.exists());
-
-        let install_dir = install_dir.as_ref();
-
-        // Look for a file named `config.yaml` in the same directory as the executable
-        if let Some(filter) = install_dir
-            .map(|p| p.join("config.yaml"))
-            .filter(|p| p.exists() && p.is_file())
-            .and_then(|p| ConfigFile::try_load(p).ok())
-        {
-            return filter;
-        }
-
-        // Look for a file named `config.yaml` in the parent directory of the executable
-        if let Some(filter) = install_dir
-            .and_then(|p| p.parent().map(|p| p.join("config.yaml")))
-            .filter(|p| p.exists() && p.is_file())
-            .and_then(|p| ConfigFile::try_load(p).ok())
-        {
-            return filter;
-        }
+        ConfigFile::try_default().unwrap_or_else(|_| ConfigFile::builtin())
+    }
+}
 
+impl ConfigFile {
+    /// Get the builtin configuration, used when no configuration file is found
+    fn builtin() -> Self {
         // Try to load the default configuration from the embedded file
         if let Ok(config) = serde_yaml::from_str(include_str!("default_config.yaml")) {
             return config;
         }
 
-        // Fallback to the hardcoded default config
-        ConfigFile {
-            // Fallback to the hardcoded default config
-            name: Some("default_all".to_owned()),
-            extensions: vec![], // All extensions
-            formats: vec![regex!(r#".+\d+"#).clone().into()],
-        }
+        // Fallback to the hardcoded default config, matching all the extensions
+        let formats = vec![regex!(r#".+\d+"#).clone().into()];
+        ConfigFile::new(Some("default_all".to_owned()), vec![], formats)
     }
 }
 
 impl ConfigFile {
+    /// Create a new file filter configuration
+    pub fn new(name: Option<String>, extensions: Vec<String>, formats: Vec<Format>) -> Self {
+        ConfigFile {
+            name,
+            extends: None,
+            nested_configs: false,
+            extensions,
+            formats,
+            case_insensitive: CaseInsensitive::default(),
+            match_path: false,
+            anchored: false,
+            filter: None,
+            content_types: vec![],
+            patterns: BTreeMap::new(),
+            extension_groups: BTreeMap::new(),
+            exclude_extensions: vec![],
+            exclude_formats: vec![],
+            exclude_dirs: vec![],
+            include_hidden: false,
+            one_file_system: false,
+            min_depth: None,
+            max_depth: None,
+            min_size: None,
+            max_size: None,
+            newer_than: None,
+            older_than: None,
+            min_width: None,
+            max_width: None,
+            min_height: None,
+            max_height: None,
+            camera_models: vec![],
+            min_iso: None,
+            max_iso: None,
+            hooks: Hooks::default(),
+            permissions: Permissions::default(),
+            number_pattern: None,
+            number_position: None,
+            number_matching: None,
+            profiles: BTreeMap::new(),
+            default_profile: None,
+            profile: None,
+            sources: vec![],
+            extended: vec![],
+            roots: vec![],
+        }
+    }
+
     /// Try to load a file filter configuration from the specified path
     ///
     /// This method attempts to load a file filter configuration from the specified path.
-    ///Ya
-    /// If the file does not exist, or if an error occurs while reading the file, `None` is returned.
+    ///
+    /// The format is detected by the extension of the file, see [ConfigFormat::detect].
+    ///
+    /// If the file does not exist, or if an error occurs while reading the file, an error is returned.
     pub(crate) fn try_load<P: AsRef<Path>>(config_path: P) -> Result<Self, ConfigFileError> {
-        let config_file = File::open(config_path)?;
-        let reader = BufReader::new(config_file);
-        let filter = serde_yaml::from_reader(reader)?;
+        let format = ConfigFormat::detect(&config_path);
+        ConfigFile::try_load_as(config_path, format)
+    }
+
+    /// Try to load a file filter configuration in the `format` from the specified path
+    pub(crate) fn try_load_as<P: AsRef<Path>>(config_path: P, format: ConfigFormat) -> Result<Self, ConfigFileError> {
+        let content = std::fs::read(config_path.as_ref())?;
+        let source = config_path.as_ref().canonicalize().ok();
+        let mut chain = source.iter().cloned().collect();
+        let mut filter = ConfigFile::from_slice_in(&content, format, source.as_deref(), &mut chain)?;
+        filter.sources = source.into_iter().collect();
         Ok(filter)
     }
 
-    /// Load a file filter configuration from the specified path
+    /// Parse a file filter configuration in the `format` from the provided content
+    ///
+    /// Unknown keys are rejected, with the known key they were probably meant to be, if any.
+    /// The configuration it `extends` is looked up relative to the working directory.
+    #[cfg(test)]
+    pub(crate) fn from_slice(content: &[u8], format: ConfigFormat) -> Result<Self, ConfigFileError> {
+        ConfigFile::from_slice_in(content, format, None, &mut vec![])
+    }
+
+    /// Parse a file filter configuration read from the `origin` file, layering it on top of the one it `extends`
+    ///
+    /// The `chain` holds the configurations being loaded, which can't be extended again.
+    fn from_slice_in(
+        content: &[u8],
+        format: ConfigFormat,
+        origin: Option<&Path>,
+        chain: &mut Vec<PathBuf>,
+    ) -> Result<Self, ConfigFileError> {
+        let config = ConfigFile::parse(content, format)?;
+        if config.extends.is_none() {
+            return Ok(config.resolved()?.with_format_options());
+        }
+        let mut extended = vec![];
+        let value = ConfigFile::resolve_value(content, format, origin, chain, &mut extended)?;
+        let mut config: ConfigFile = serde_yaml::from_value(value)?;
+        config.extended = extended;
+        Ok(config.resolved()?.with_format_options())
+    }
+
+    /// Parse the configurations, merging every one of them on top of the previous ones
+    ///
+    /// The configurations are merged like the one a configuration `extends`, see [ConfigFile::resolve_value],
+    /// after each of them is merged with the ones it extends itself.
+    ///
+    /// # Errors
+    /// - If any of the configurations can't be parsed, naming it
+    pub fn merge(layers: Vec<ConfigLayer>) -> Result<Self, ConfigFileError> {
+        if let [layer] = layers.as_slice() {
+            let (source, mut chain) = (layer.source.as_deref(), layer.source.iter().cloned().collect());
+            let mut config = ConfigFile::from_slice_in(&layer.content, layer.format, source, &mut chain)?;
+            config.sources = layer.source.iter().cloned().collect();
+            return Ok(config);
+        }
+        let mut merged = serde_yaml::Value::Null;
+        let mut extended = vec![];
+        for layer in layers.iter() {
+            let mut chain = layer.source.iter().cloned().collect();
+            let source = layer.source.as_deref();
+            let value = ConfigFile::resolve_value(&layer.content, layer.format, source, &mut chain, &mut extended)
+                .map_err(|e| ConfigFileError::Layer(layer.name.clone(), Box::new(e)))?;
+            merged = merge_values(merged, value);
+        }
+        let mut config: ConfigFile = serde_yaml::from_value(merged)?;
+        config.sources = layers.into_iter().filter_map(|layer| layer.source).collect();
+        config.extended = extended;
+        Ok(config.resolved()?.with_format_options())
+    }
+
+    /// Resolve the named patterns and extension groups of the parsed configuration, and check its features
+    fn resolved(self) -> Result<Self, ConfigFileError> {
+        self.with_patterns()?.with_extension_groups()?.with_supported_features()
+    }
+
+    /// Check that the keys the configuration sets don't need a feature the program was built without
+    ///
+    /// # Errors
+    /// - If image limits are set without the `exif` feature
+    fn with_supported_features(self) -> Result<Self, ConfigFileError> {
+        match self.has_image_limits() && cfg!(not(feature = "exif")) {
+            true => Err(ConfigFileError::Unsupported("image limits", "exif")),
+            false => Ok(self),
+        }
+    }
+
+    /// Replace the references to the named patterns (`@name`) in the formats with the patterns
+    ///
+    /// # Errors
+    /// - If a referenced pattern isn't defined
+    fn with_patterns(mut self) -> Result<Self, ConfigFileError> {
+        let patterns = &self.patterns;
+        let mut resolve = |format: &Format| match format.as_str().strip_prefix('@') {
+            Some(name) => match patterns.get(name) {
+                Some(pattern) => Ok(format.resolve(pattern)),
+                None => Err(ConfigFileError::UnknownPattern(
+                    name.to_owned(),
+                    patterns.keys().join(", "),
+                )),
+            },
+            None => Ok(format.clone()),
+        };
+        self.formats = self.formats.iter().map(&mut resolve).try_collect()?;
+        self.exclude_formats = self.exclude_formats.iter().map(&mut resolve).try_collect()?;
+        for profile in self.profiles.values_mut() {
+            if let Some(formats) = &profile.formats {
+                profile.formats = Some(formats.iter().map(&mut resolve).try_collect()?);
+            }
+        }
+        self.filter = self
+            .filter
+            .as_ref()
+            .map(|filter| filter.map_formats(&mut resolve))
+            .transpose()?;
+        Ok(self)
+    }
+
+    /// Replace the references to the extension groups (`@name`) in the extension lists with their extensions
+    ///
+    /// The groups defined in the configuration replace the builtin groups with the same name.
+    ///
+    /// # Errors
+    /// - If a referenced group isn't defined
+    fn with_extension_groups(mut self) -> Result<Self, ConfigFileError> {
+        let groups = &self.extension_groups;
+        let group = |name: &str| match groups.get(name) {
+            Some(extensions) => Some(extensions.clone()),
+            None => EXTENSION_GROUPS
+                .iter()
+                .find(|(builtin, _)| *builtin == name)
+                .map(|(_, extensions)| extensions.iter().map(|extension| extension.to_string()).collect()),
+        };
+        let unknown = |name: &str| {
+            let defined = groups.keys().map(String::as_str).chain(EXTENSION_GROUPS.map(|(name, _)| name));
+            ConfigFileError::UnknownExtensionGroup(name.to_owned(), defined.sorted().dedup().join(", "))
+        };
+        let mut resolve = |extensions: &[String]| -> Result<Vec<String>, ConfigFileError> {
+            let resolved: Vec<Vec<String>> = extensions
+                .iter()
+                .map(|extension| match extension.strip_prefix('@') {
+                    Some(name) => group(name).ok_or_else(|| unknown(name)),
+                    None => Ok(vec![extension.clone()]),
+                })
+                .try_collect()?;
+            Ok(resolved.into_iter().flatten().unique().collect())
+        };
+        self.extensions = resolve(&self.extensions)?;
+        self.exclude_extensions = resolve(&self.exclude_extensions)?;
+        for profile in self.profiles.values_mut() {
+            if let Some(extensions) = &profile.extensions {
+                profile.extensions = Some(resolve(extensions)?);
+            }
+        }
+        self.filter = self
+            .filter
+            .as_ref()
+            .map(|filter| filter.map_extensions(&mut resolve))
+            .transpose()?;
+        Ok(self)
+    }
+
+    /// Parse the content as a generic value, merged on top of the configurations it extends
+    ///
+    /// Mappings, like the `hooks` and the `profiles`, are merged key by key, and lists are appended to the
+    /// ones of the extended configuration, skipping the repeated items. Other values replace the extended ones.
+    /// The paths of the extended configurations are added to `extended`.
+    fn resolve_value(
+        content: &[u8],
+        format: ConfigFormat,
+        origin: Option<&Path>,
+        chain: &mut Vec<PathBuf>,
+        extended: &mut Vec<PathBuf>,
+    ) -> Result<serde_yaml::Value, ConfigFileError> {
+        // Every configuration of the chain has to be valid on its own
+        let config = ConfigFile::parse(content, format)?;
+        // The content can be parsed as a generic value once it was parsed as a configuration
+        let mut value = ConfigFile::parse_value(content, format).unwrap_or_default();
+        interpolate(&mut value, "");
+        let Some(extends) = config.extends else {
+            return Ok(value);
+        };
+
+        let path = match origin.and_then(Path::parent) {
+            Some(dir) => dir.join(&extends),
+            None => extends,
+        };
+        let loaded = path
+            .canonicalize()
+            .and_then(|base| Ok((std::fs::read(&base)?, base)))
+            .map_err(|e| ConfigFileError::Extends(path.clone(), Box::new(e.into())));
+        let (base_content, base) = loaded?;
+        if chain.contains(&base) {
+            let cycle = chain.iter().chain([&base]).map(|path| path.display()).join(" -> ");
+            return Err(ConfigFileError::ExtendsCycle(cycle));
+        }
+
+        chain.push(base.clone());
+        extended.push(base.clone());
+        let base_format = ConfigFormat::detect(&base);
+        let base_value = ConfigFile::resolve_value(&base_content, base_format, Some(&base), chain, extended)
+            .map_err(|e| ConfigFileError::Extends(path, Box::new(e)));
+        chain.pop();
+        Ok(merge_values(base_value?, value))
+    }
+
+    /// Parse a single file filter configuration, without the one it `extends`
+    ///
+    /// The references to environment variables are expanded before the values are parsed, see [interpolate].
+    fn parse(content: &[u8], format: ConfigFormat) -> Result<Self, ConfigFileError> {
+        let parsed = match content.windows(2).any(|window| window == b"${") {
+            true => ConfigFile::parse_as(content, format).and_then(|mut value| {
+                match interpolate(&mut value, "").into_iter().next() {
+                    Some(unset) => Err(ConfigFileError::UnsetVariable(unset)),
+                    None => Ok(serde_yaml::from_value(value)?),
+                }
+            }),
+            false => ConfigFile::parse_as(content, format),
+        };
+        match parsed {
+            Ok(config) => Ok(config),
+            Err(e) => {
+                // Unknown keys are reported in the terms of the configuration, instead of the parser's
+                let value = ConfigFile::parse_value(content, format).unwrap_or_default();
+                match UnknownKey::find(&value).into_iter().next() {
+                    Some(unknown) => Err(ConfigFileError::UnknownKey(unknown)),
+                    None => Err(e),
+                }
+            }
+        }
+    }
+
+    /// Deserialize the content in the `format`
+    fn parse_as<T: DeserializeOwned>(content: &[u8], format: ConfigFormat) -> Result<T, ConfigFileError> {
+        match format {
+            ConfigFormat::Yaml => Ok(serde_yaml::from_slice(content)?),
+            ConfigFormat::Toml => Ok(toml::from_slice(content)?),
+            ConfigFormat::Json => Ok(serde_json::from_slice(content)?),
+        }
+    }
+
+    /// Parse the content in the `format` into a generic value
+    fn parse_value(content: &[u8], format: ConfigFormat) -> Result<serde_yaml::Value, String> {
+        match format {
+            ConfigFormat::Yaml => serde_yaml::from_slice(content).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => toml::from_slice(content).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::from_slice(content).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Make the formats match regardless of case, and the whole file name, as configured
+    fn with_format_options(mut self) -> Self {
+        let (case_insensitive, anchored) = (self.case_insensitive.formats, self.anchored);
+        let options = |format: &Format| format.with_options(case_insensitive, anchored);
+        self.formats = self.formats.iter().map(options).collect();
+        self.exclude_formats = self.exclude_formats.iter().map(options).collect();
+        self.filter = self
+            .filter
+            .as_ref()
+            .map(|filter| filter.with_format_options(case_insensitive, anchored));
+        self
+    }
+
+    /// Create a configuration without any rules, matching the files only by the extensions and formats added to it
+    ///
+    /// Unlike a configuration without extensions and formats, which matches no files, a bare configuration
+    /// matches a file by the formats alone if it has no extensions, and by the extensions alone if it has no formats.
+    pub fn bare() -> Self {
+        ConfigFile {
+            filter: Some(FilterExpr::All(vec![])),
+            ..ConfigFile::new(None, vec![], vec![])
+        }
+    }
+
+    /// Get the path of the configuration file in the directory
+    ///
+    /// This is the first existing one of `config.yaml`, `config.toml` and `config.json`, or else `config.yaml`.
+    pub fn locate<P: AsRef<Path>>(dir: P) -> PathBuf {
+        ["config.yaml", "config.toml", "config.json"]
+            .into_iter()
+            .map(|name| dir.as_ref().join(name))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| dir.as_ref().join("config.yaml"))
+    }
+
+    /// Get the directory of the user's default configuration, used when no configuration file is found
+    ///
+    /// This is `~/.config/delete-rest` on Linux, `%APPDATA%\delete-rest` on Windows
+    /// and `~/Library/Application Support/delete-rest` on macOS.
+    pub fn user_config_dir() -> Option<PathBuf> {
+        directories::BaseDirs::new().map(|dirs| dirs.config_dir().join("delete-rest"))
+    }
+
+    /// Find the closest configuration file, in the directory or in one of its parents
+    ///
+    /// The search stops at the filesystem root, or after the first directory containing one of the [ROOT_MARKERS],
+    /// so the configuration of an unrelated parent directory isn't picked up. Returns `None` if no file is found.
+    pub fn discover<P: AsRef<Path>>(dir: P) -> Option<PathBuf> {
+        let dir = dir.as_ref().canonicalize().unwrap_or_else(|_| dir.as_ref().to_owned());
+        for dir in dir.ancestors() {
+            let path = ConfigFile::locate(dir);
+            if path.exists() {
+                return Some(path);
+            }
+            if ROOT_MARKERS.iter().any(|marker| dir.join(marker).exists()) {
+                break;
+            }
+        }
+        None
+    }
+
+    /// Check the configuration content, reporting all its problems at once
+    ///
+    /// Besides the problems that prevent loading it, like invalid regular expressions,
+    /// the keys that are otherwise ignored are reported too. Returns no problems if the configuration is valid.
+    pub fn check(content: &[u8], format: ConfigFormat) -> Vec<String> {
+        let mut value = match ConfigFile::parse_value(content, format) {
+            Ok(value) => value,
+            Err(e) => return vec![e],
+        };
+
+        // Unknown keys and invalid regular expressions are removed once reported,
+        // so the rest of the configuration is still checked
+        let mut problems: Vec<_> = interpolate(&mut value, "").iter().map(ToString::to_string).collect();
+        for key in ["formats", "exclude_formats"] {
+            let Some(formats) = value.get_mut(key).and_then(serde_yaml::Value::as_sequence_mut) else {
+                continue;
+            };
+            for (index, format) in formats.iter().enumerate() {
+                if let Some(Err(e)) = format.as_str().map(Regex::new) {
+                    problems.push(format!("{key}[{index}]: {e}"));
+                }
+            }
+            formats.retain(|format| !matches!(format.as_str().map(Regex::new), Some(Err(_))));
+        }
+        if let Some(Err(e)) = value.get("number_pattern").and_then(serde_yaml::Value::as_str).map(Regex::new) {
+            problems.push(format!("number_pattern: {e}"));
+            value.as_mapping_mut().and_then(|mapping| mapping.remove("number_pattern"));
+        }
+
+        for unknown in UnknownKey::find(&value) {
+            value.as_mapping_mut().and_then(|mapping| mapping.remove(&unknown.key));
+            problems.push(unknown.to_string());
+        }
+        let mut unknown = vec![];
+        match serde_ignored::deserialize::<_, _, ConfigFile>(value, |path| unknown.push(path.to_string())) {
+            Ok(config) => match config.resolved() {
+                Ok(config) => problems.extend(config.with_profile(None).err().map(|e| e.to_string())),
+                Err(e) => problems.push(e.to_string()),
+            },
+            Err(e) => problems.push(e.to_string()),
+        }
+        problems.extend(unknown.into_iter().map(|key| format!("unknown key \"{key}\"")));
+        problems
+    }
+
+    /// Find the options of a valid configuration that are likely mistakes, like formats that can never match
+    ///
+    /// A format can't match if it ends with extensions, like `\.(jpg|png)$`, none of which is configured.
+    /// A format matches every file name if it's only `.*` or `.+`, see [Format::matches_everything], which together
+    /// with an empty extension list matches every file with a `match` expression, and none without one.
+    pub fn lint(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        for (index, format) in self.formats.iter().enumerate() {
+            let suffix = regex!(r"\\\.\((?:\?:)?([[:alnum:]|]+)\)\$$|\\\.([[:alnum:]]+)\$$").captures(format.as_str());
+            let format_extensions = suffix
+                .and_then(|captures| captures.get(1).or(captures.get(2)))
+                .map(|extensions| extensions.as_str().split('|').collect_vec())
+                .unwrap_or_default();
+            let configured = |ext: &&str| self.extensions.iter().any(|extension| extension.eq_ignore_ascii_case(ext));
+            let unconfigured =
+                !format.is_negated() && !format_extensions.is_empty() && !format_extensions.iter().any(configured);
+            if !self.extensions.is_empty() && unconfigured {
+                warnings.push(format!(
+                    "formats[{index}]: {format} only matches the extensions {}, none of which is in the extensions",
+                    format_extensions.join(", ")
+                ));
+            }
+            if self.extensions.is_empty() && format.matches_everything() {
+                warnings.push(match self.filter {
+                    Some(_) => format!("formats[{index}]: {format} matches every file, as the extensions are empty"),
+                    None => format!("formats[{index}]: {format} matches every file name, but the extensions are empty"),
+                });
+            }
+        }
+        if self.extensions.is_empty() && self.filter.is_none() && self.content_types.is_empty() {
+            warnings.push("extensions: the list is empty, so no file is matched".to_owned());
+        }
+        warnings
+    }
+
+    /// Try to load the configuration discovered for the directory, or the default one if none is found
+    ///
+    /// See [ConfigFile::discover] and [ConfigFile::try_default].
+    ///
+    /// # Errors
+    /// - If the discovered configuration file can't be loaded
+    pub fn try_discover<P: AsRef<Path>>(dir: P) -> Result<Self, ConfigFileError> {
+        match ConfigFile::discover(dir) {
+            Some(path) => ConfigFile::try_load(&path).map_err(|e| ConfigFileError::Discovered(path, Box::new(e))),
+            None => ConfigFile::try_default(),
+        }
+    }
+
+    /// Try to load the default configuration
+    ///
+    /// It's looked up in the user's configuration directory, then next to the executable and in its parent directory.
+    /// The builtin configuration is used if none of them has a configuration file.
+    ///
+    /// # Errors
+    /// - If the first configuration file found can't be loaded
+    pub fn try_default() -> Result<Self, ConfigFileError> {
+        let install_dir = std::env::current_exe().ok().and_then(|p| p.parent().map(Path::to_owned));
+        let parent_dir = install_dir.as_deref().and_then(Path::parent).map(Path::to_owned);
+        let found = [ConfigFile::user_config_dir(), install_dir, parent_dir]
+            .into_iter()
+            .flatten()
+            .map(ConfigFile::locate)
+            .find(|path| path.is_file());
+        match found {
+            Some(path) => ConfigFile::try_load(&path).map_err(|e| ConfigFileError::Discovered(path, Box::new(e))),
+            None => Ok(ConfigFile::builtin()),
+        }
+    }
+
+    /// Apply the named profile, or the default profile if no name is provided
+    ///
+    /// The options the profile sets override the ones of the configuration.
+    /// The configuration is returned unchanged if no profile is selected.
+    ///
+    /// # Errors
+    /// - If the configuration doesn't define the profile
+    pub fn with_profile(mut self, name: Option<&str>) -> Result<Self, ConfigFileError> {
+        let Some(name) = name.or(self.default_profile.as_deref()).map(str::to_owned) else {
+            return Ok(self);
+        };
+        let Some(profile) = self.profiles.get(&name).cloned() else {
+            let available = self.profiles.keys().join(", ");
+            return Err(ConfigFileError::UnknownProfile(name, available));
+        };
+        self.extensions = profile.extensions.unwrap_or(self.extensions);
+        self.formats = profile.formats.unwrap_or(self.formats);
+        self.number_pattern = profile.number_pattern.or(self.number_pattern);
+        self.number_position = profile.number_position.or(self.number_position);
+        self.number_matching = profile.number_matching.or(self.number_matching);
+        self.profile = Some(name);
+        Ok(self.with_format_options())
+    }
+
+    /// Get the name of the profile applied to the configuration, if any
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Get the path the configuration was loaded from, the last one if several were merged
+    ///
+    /// Returns `None` if the builtin configuration is used.
+    pub fn source(&self) -> Option<&Path> {
+        self.sources.last().map(PathBuf::as_path)
+    }
+
+    /// Get the paths of the configuration files, in the order they were merged
+    pub fn sources(&self) -> &[PathBuf] {
+        &self.sources
+    }
+
+    /// Get the paths of the configurations the configuration files extend
+    pub fn extended_sources(&self) -> &[PathBuf] {
+        &self.extended
+    }
+
+    /// Serialize the resolved configuration to YAML, which loads back into the same configuration
+    ///
+    /// The configurations it extends and the applied profile are already merged into it, so `extends`,
+    /// the `profiles` and the `default_profile` are left out, as loading them would apply a profile again
+    /// over the resolved options. The YAML starts with comments naming the source and the applied profile.
+    ///
+    /// # Errors
+    /// - If the configuration can't be serialized
+    pub fn to_yaml(&self) -> Result<String, ConfigFileError> {
+        let mut resolved = self.clone();
+        resolved.extends = None;
+        resolved.profiles = BTreeMap::new();
+        resolved.default_profile = None;
+        let mut header = match self.sources.as_slice() {
+            [] => "# Resolved configuration, not loaded from a file\n".to_owned(),
+            [source] => format!("# Resolved configuration loaded from {source:?}\n"),
+            sources => format!(
+                "# Resolved configuration merged from {:?}\n",
+                sources.iter().format(", ")
+            ),
+        };
+        if let Some(profile) = &self.profile {
+            header += &format!("# With the profile {profile:?} applied\n");
+        }
+        Ok(header + &serde_yaml::to_string(&resolved)?)
+    }
+
+    /// Get the configured hooks
+    pub fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    /// Get the permissions and ownership applied to the moved or copied files
+    pub fn permissions(&self) -> &Permissions {
+        &self.permissions
+    }
+
+    /// Get the pattern extracting the number a file name is compared by
     ///
-    /// Load a file filter configuration from the specified path, or return the default configuration if the file does not exist.
-    pub(crate) fn load<P: AsRef<Path>>(config_path: P) -> Self {
-        ConfigFile::try_load(config_path).unwrap_or_default()
+    /// The provided pattern, position and matching override the configured ones.
+    pub fn number_pattern(
+        &self,
+        pattern: Option<Regex>,
+        position: Option<NumberPosition>,
+        matching: Option<NumberMatching>,
+    ) -> NumberPattern {
+        let number_pattern = match pattern {
+            Some(pattern) => NumberPattern::from(pattern),
+            None => self.number_pattern.clone().unwrap_or_default(),
+        };
+        number_pattern
+            .with_position(position.or(self.number_position).unwrap_or_default())
+            .with_matching(matching.or(self.number_matching).unwrap_or_default())
     }
 
     /// Check if a file name has one of the configured extensions
     pub fn has_extension<P: AsRef<Path>>(&self, path: P) -> bool {
-        path.as_ref()
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| ext.to_ascii_lowercase())
-            .map_or(false, |ext| self.extensions.contains(&ext))
+        self.has_any_extension(path, &self.extensions)
+    }
+
+    /// Check if a file name has one of the extensions, regardless of case unless configured otherwise
+    fn has_any_extension<P: AsRef<Path>>(&self, path: P, extensions: &[String]) -> bool {
+        let Some(ext) = path.as_ref().extension().and_then(|ext| ext.to_str()) else {
+            return false;
+        };
+        match self.case_insensitive.extensions {
+            true => extensions.iter().any(|extension| extension.eq_ignore_ascii_case(ext)),
+            false => extensions.iter().any(|extension| extension == ext),
+        }
     }
 
     /// Check if a file name has one of the configured formats
+    ///
+    /// The name has to match one of the formats, and all the negated ones. If all the formats are negated,
+    /// matching them is enough.
     pub fn has_format<P: AsRef<Path>>(&self, path: P) -> bool {
-        self.formats.iter().filter_map(|f| f.matches(&path)).any(identity)
+        let (negated, formats): (Vec<_>, Vec<_>) = self.formats.iter().partition(|format| format.is_negated());
+        !self.formats.is_empty()
+            && self.format_subject(path.as_ref()).is_some_and(|subject| {
+                (formats.is_empty() || formats.iter().any(|format| format.is_match(&subject)))
+                    && negated.iter().all(|format| format.is_match(&subject))
+            })
+    }
+
+    /// Get the text the formats are matched against: the file name,
+    /// or with `match_path`, the path relative to the root directory it is in, separated by `/`
+    fn format_subject<'a>(&self, path: &'a Path) -> Option<Cow<'a, str>> {
+        if !self.match_path {
+            return path.file_name()?.to_str().map(Cow::Borrowed);
+        }
+        let relative = self.roots.iter().find_map(|root| path.strip_prefix(root).ok()).unwrap_or(path);
+        let relative = relative.to_str()?;
+        match std::path::MAIN_SEPARATOR {
+            '/' => Some(Cow::Borrowed(relative)),
+            separator => Some(Cow::Owned(relative.replace(separator, "/"))),
+        }
+    }
+
+    /// Set the directory the paths are made relative to, when `match_path` is set
+    pub fn with_root<P: AsRef<Path>>(self, root: P) -> Self {
+        self.with_roots([root])
+    }
+
+    /// Set the directories the paths are made relative to, when `match_path` is set
+    ///
+    /// A path is made relative to the first of the directories it is in.
+    pub fn with_roots<P: AsRef<Path>>(mut self, roots: impl IntoIterator<Item = P>) -> Self {
+        self.roots = roots.into_iter().map(|root| root.as_ref().to_owned()).collect();
+        self
+    }
+
+    /// Get the limits of the search for the files: the excluded, hidden and mounted directories
+    /// and the depth of the files
+    pub fn scan_limits(&self) -> ScanLimits {
+        ScanLimits {
+            exclude_dirs: self.exclude_dirs.clone(),
+            include_hidden: self.include_hidden,
+            one_file_system: self.one_file_system,
+            min_depth: self.min_depth,
+            max_depth: self.max_depth,
+        }
+    }
+
+    /// Check if a file name has one of the excluded extensions, or matches one of the excluded formats
+    pub fn is_excluded<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.has_any_extension(&path, &self.exclude_extensions)
+            || self
+                .format_subject(path.as_ref())
+                .is_some_and(|subject| self.exclude_formats.iter().any(|format| format.is_match(&subject)))
     }
 
-    /// Check if a file name matches one of the configured formats and has one of the configured extensions
+    /// Check if a file name matches one of the configured formats and has one of the configured extensions,
+    /// and isn't excluded
+    ///
+    /// If a `match` expression is configured, the file has to satisfy it too,
+    /// and the extensions and formats are only checked if they aren't empty.
     pub fn matches<P: AsRef<Path>>(&self, path: P) -> bool {
-        self.has_extension(&path) && self.has_format(&path)
+        let path = path.as_ref();
+        let rules = match &self.filter {
+            Some(filter) => {
+                filter.matches(self, path)
+                    && (self.extensions.is_empty() || self.has_extension(path))
+                    && (self.formats.is_empty() || self.has_format(path))
+            }
+            None => self.has_extension(path) && self.has_format(path),
+        };
+        rules && !self.is_excluded(path)
     }
 
-    /// Convert the  configuration into a filter function
+    /// Check if the content of the file is one of the configured content types, regardless of its extension,
+    /// and its name matches one of the formats and isn't excluded
     ///
-    /// Files are filtered based on the configured extensions and formats.
+    /// The file is only opened if content types are configured and its name matches. Files it can't be read for,
+    /// or whose type isn't recognized, don't match.
+    pub fn matches_content<P: AsRef<Path>>(&self, path: P) -> bool {
+        if self.content_types.is_empty() || !self.has_format(&path) || self.is_excluded(&path) {
+            return false;
+        }
+        let Ok(Some(kind)) = infer::get_from_path(path) else {
+            return false;
+        };
+        // Content types are MIME types (`image/jpeg`), groups of them (`image/*`) or their extensions (`jpg`)
+        self.content_types
+            .iter()
+            .any(|content_type| match content_type.strip_suffix("/*") {
+                Some(group) => kind.mime_type().split('/').next() == Some(group),
+                None => [kind.mime_type(), kind.extension()]
+                    .iter()
+                    .any(|name| content_type.eq_ignore_ascii_case(name)),
+            })
+    }
+
+    /// Check if the size and the modification time of the file are within the configured limits
     ///
-    /// Returned function takes a reference to a `PathBuf` and returns a boolean indicating whether the file should be kept.
-    pub fn into_filter(self) -> Rc<dyn Fn(&&PathBuf) -> bool> {
-        Rc::new(move |path| self.matches(path))
+    /// The limits given as durations are counted back from `now`.
+    /// The metadata of the file is only read if a limit is configured. Files it can't be read for don't match.
+    pub fn has_metadata<P: AsRef<Path>>(&self, path: P, now: SystemTime) -> bool {
+        let (sizes, times) = (self.min_size.or(self.max_size), self.newer_than.or(self.older_than));
+        if sizes.is_none() && times.is_none() {
+            return true;
+        }
+        let Ok(metadata) = std::fs::metadata(path) else {
+            return false;
+        };
+        let size = FileSize(metadata.len());
+        let within_size = self.min_size.is_none_or(|min_size| size >= min_size)
+            && self.max_size.is_none_or(|max_size| size <= max_size);
+        let within_time = match (self.newer_than, self.older_than) {
+            (None, None) => true,
+            (newer_than, older_than) => metadata.modified().is_ok_and(|modified| {
+                newer_than.is_none_or(|limit| modified >= limit.resolve(now))
+                    && older_than.is_none_or(|limit| modified <= limit.resolve(now))
+            }),
+        };
+        within_size && within_time
     }
-}
 
-/// A file name format
-///
-/// This is a wrapper around a regular expression that describes a file name format.
-///
-/// It provides Display and utility methods to check if a file name matches the format, given a list of extensions.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Format(#[serde(with = "serde_regex")] Regex);
+    /// Check if any of the limits on the dimensions and the EXIF fields of the images is set
+    fn has_image_limits(&self) -> bool {
+        [
+            self.min_width,
+            self.max_width,
+            self.min_height,
+            self.max_height,
+            self.min_iso,
+            self.max_iso,
+        ]
+        .iter()
+        .any(Option::is_some)
+            || !self.camera_models.is_empty()
+    }
 
-impl Display for Format {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "\"{}\"", self.0.as_str())
+    /// Check if the dimensions and the EXIF fields of the image are within the configured limits
+    ///
+    /// The image is only read if a limit is configured. Files that aren't images, and images missing
+    /// a limited field, don't match.
+    pub fn has_image_info<P: AsRef<Path>>(&self, path: P) -> bool {
+        !self.has_image_limits() || ImageInfo::read(path.as_ref()).is_some_and(|image| self.within_image_limits(&image))
     }
-}
 
-impl From<Regex> for Format {
-    fn from(re: Regex) -> Self {
-        Format(re)
+    /// Check if the dimensions and the EXIF fields of the image are within the configured limits
+    fn within_image_limits(&self, image: &ImageInfo) -> bool {
+        let within = |value: Option<u32>, min: Option<u32>, max: Option<u32>| match (min, max) {
+            (None, None) => true,
+            _ => value.is_some_and(|value| min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)),
+        };
+        let camera_model = image.camera_model.as_deref().unwrap_or_default().to_lowercase();
+        within(image.width, self.min_width, self.max_width)
+            && within(image.height, self.min_height, self.max_height)
+            && within(image.iso, self.min_iso, self.max_iso)
+            && (self.camera_models.is_empty()
+                || (image.camera_model.is_some()
+                    && self
+                        .camera_models
+                        .iter()
+                        .any(|model| camera_model.contains(&model.to_lowercase()))))
     }
-}
 
-impl Format {
-    /// Check if a file name matches the format, and has one of the specified extensions
-    pub fn matches<P: AsRef<Path>>(&self, path: P) -> Option<bool> {
-        let path = path.as_ref();
-        let file_name = path.file_name()?.to_str()?;
+    /// Override the configured size limits with the provided ones
+    pub fn with_size_limits(mut self, min_size: Option<FileSize>, max_size: Option<FileSize>) -> Self {
+        self.min_size = min_size.or(self.min_size);
+        self.max_size = max_size.or(self.max_size);
+        self
+    }
 
-        Some(self.0.is_match(file_name))
+    /// Override the configured modification time limits with the provided ones
+    pub fn with_time_limits(mut self, newer_than: Option<TimeLimit>, older_than: Option<TimeLimit>) -> Self {
+        self.newer_than = newer_than.or(self.newer_than);
+        self.older_than = older_than.or(self.older_than);
+        self
     }
-}
 
-#[derive(thiserror::Error, Debug)]
-pub enum ConfigFileError {
-    #[error("Config I/O error: {0}")]
-    Io(#[from] std::io::Error),
-    #[error("Config parsing error: {0}")]
-    Yaml(#[from] serde_yaml::Error),
-}
+    /// Override the configured depth limits with the provided ones
+    pub fn with_depth_limits(mut self, min_depth: Option<usize>, max_depth: Option<usize>) -> Self {
+        self.min_depth = min_depth.or(self.min_depth);
+        self.max_depth = max_depth.or(self.max_depth);
+        self
+    }
 
-#[cfg(test)]
-mod test {
-    use crate::test_utils::resource_dir;
+    /// Search the hidden files and directories, in addition to the configured setting
+    pub fn with_hidden(mut self, include_hidden: bool) -> Self {
+        self.include_hidden |= include_hidden;
+        self
+    }
 
-    use super::*;
+    /// Stay on the filesystem of the searched directory, in addition to the configured setting
+    pub fn with_one_file_system(mut self, one_file_system: bool) -> Self {
+        self.one_file_system |= one_file_system;
+        self
+    }
 
-    #[test]
-    fn load_config_file() {
-        let config = ConfigFile::load(resource_dir().join("cfg.yaml"));
-        assert_eq!(config.name, Some("test_cfg".to_owned()));
-        assert_eq!(config.extensions, vec!["txt".to_owned(), "csv".to_owned()]);
-        assert_eq!(config.formats.len(), 1);
+    /// Check if the configuration files in the subdirectories are used
+    pub fn nested_configs(&self) -> bool {
+        self.nested_configs
+    }
+
+    /// Use the configuration files in the subdirectories, in addition to the configured setting
+    pub fn with_nested_configs(mut self, nested_configs: bool) -> Self {
+        self.nested_configs |= nested_configs;
+        self
+    }
+
+    /// Override the configured extensions and formats with the provided ones, or add them if `append` is set
+    ///
+    /// Empty lists keep the configured values, and a leading dot of an extension is ignored.
+    pub fn with_filters(mut self, extensions: Vec<String>, formats: Vec<Regex>, append: bool) -> Self {
+        let extensions = extensions.iter().map(|ext| ext.trim_start_matches('.').to_owned());
+        let formats = formats.into_iter().map(Format::from);
+        match append {
+            true => {
+                self.extensions = self.extensions.into_iter().chain(extensions).unique().collect();
+                self.formats.extend(formats);
+            }
+            false => {
+                self.extensions = Some(extensions.collect_vec())
+                    .filter(|e| !e.is_empty())
+                    .unwrap_or(self.extensions);
+                self.formats = Some(formats.collect_vec()).filter(|f| !f.is_empty()).unwrap_or(self.formats);
+            }
+        }
+        self.with_format_options()
+    }
+
+    /// Check if the filters read the files, and not only their paths
+    ///
+    /// The files are read if size, modification time or image limits, or content types are configured.
+    pub fn reads_files(&self) -> bool {
+        self.min_size.or(self.max_size).is_some()
+            || self.newer_than.or(self.older_than).is_some()
+            || !self.content_types.is_empty()
+            || self.has_image_limits()
+    }
+
+    /// Explain which of the rules accept the file, and why, in the order [ConfigFile::into_filter] applies them
+    pub fn explain(&self, path: &Path, now: SystemTime) -> Vec<Step> {
+        let mut steps = vec![];
+        if self.filter.is_none() || !self.extensions.is_empty() {
+            let extensions = self.extensions.join(", ");
+            steps.push(match path.extension().map(|ext| ext.to_string_lossy()) {
+                Some(ext) if self.has_extension(path) => Step::accept(
+                    "extension",
+                    format!("\"{ext}\" is one of the extensions [{extensions}]"),
+                ),
+                Some(ext) => Step::reject(
+                    "extension",
+                    format!("\"{ext}\" isn't one of the extensions [{extensions}]"),
+                ),
+                None => Step::reject("extension", "the file has no extension"),
+            });
+        }
+        if self.filter.is_none() || !self.formats.is_empty() {
+            steps.push(self.explain_format(path));
+        }
+        if let Some(filter) = &self.filter {
+            steps.push(match filter.matches(self, path) {
+                true => Step::accept("match", format!("the expression {filter} matches")),
+                false => Step::reject("match", format!("the expression {filter} doesn't match")),
+            });
+        }
+        let subject = self.format_subject(path).unwrap_or_default();
+        if self.has_any_extension(path, &self.exclude_extensions) {
+            let extensions = self.exclude_extensions.join(", ");
+            steps.push(Step::reject(
+                "exclusion",
+                format!("the extension is one of [{extensions}]"),
+            ));
+        } else if let Some(format) = self.exclude_formats.iter().find(|format| format.is_match(&subject)) {
+            steps.push(Step::reject(
+                "exclusion",
+                format!("the name matches the excluded format {format}"),
+            ));
+        }
+        if !self.content_types.is_empty() && !self.matches(path) {
+            let content_types = self.content_types.join(", ");
+            steps.push(match self.matches_content(path) {
+                true => Step::accept("content", format!("the content is one of [{content_types}]")),
+                false => Step::reject("content", format!("the content isn't one of [{content_types}]")),
+            });
+        }
+        if [self.min_size, self.max_size].iter().any(Option::is_some)
+            || [self.newer_than, self.older_than].iter().any(Option::is_some)
+        {
+            steps.push(match self.has_metadata(path, now) {
+                true => Step::accept("metadata", "the size and the modification time are within the limits"),
+                false => Step::reject("metadata", "the size or the modification time is outside the limits"),
+            });
+        }
+        if self.has_image_limits() {
+            steps.push(match self.has_image_info(path) {
+                true => Step::accept("image", "the dimensions and the EXIF fields are within the limits"),
+                false => Step::reject("image", "the file isn't an image, or isn't within the limits"),
+            });
+        }
+        steps
+    }
+
+    /// Explain which of the formats accepts or rejects the file name, see [ConfigFile::has_format]
+    fn explain_format(&self, path: &Path) -> Step {
+        let Some(subject) = self.format_subject(path) else {
+            return Step::reject("format", "the name isn't valid UTF-8");
+        };
+        let (negated, formats): (Vec<_>, Vec<_>) = self.formats.iter().partition(|format| format.is_negated());
+        if self.formats.is_empty() {
+            Step::reject("format", "no formats are configured")
+        } else if let Some(format) = negated.iter().find(|format| !format.is_match(&subject)) {
+            Step::reject("format", format!("\"{subject}\" matches the negated format {format}"))
+        } else if let Some(format) = formats.iter().find(|format| format.is_match(&subject)) {
+            Step::accept("format", format!("\"{subject}\" matches the format {format}"))
+        } else if formats.is_empty() {
+            Step::accept(
+                "format",
+                format!("\"{subject}\" doesn't match any of the negated formats"),
+            )
+        } else {
+            let formats = formats.iter().join(", ");
+            Step::reject(
+                "format",
+                format!("\"{subject}\" doesn't match any of the formats [{formats}]"),
+            )
+        }
+    }
+
+    /// Convert the  configuration into a filter function
+    ///
+    /// Files are filtered based on the configured extensions and formats, and then on their size and modification time,
+    /// and the dimensions and EXIF fields of the images.
+    /// The files with other extensions are then matched by their content, if content types are configured.
+    /// The modification time limits given as durations are counted back from the creation of the filter.
+    ///
+    /// Returned function takes a reference to a `PathBuf` and returns a boolean indicating whether the file should be kept.
+    pub fn into_filter(self) -> Rc<dyn Fn(&&PathBuf) -> bool> {
+        let now = SystemTime::now();
+        Rc::new(move |path| {
+            (self.matches(path) || self.matches_content(path))
+                && self.has_metadata(path, now)
+                && self.has_image_info(path)
+        })
+    }
+}
+
+/// Options given on the command line, overriding the ones of the loaded configurations
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOverrides {
+    /// The profile to apply, instead of the default one
+    pub profile: Option<String>,
+    pub min_size: Option<FileSize>,
+    pub max_size: Option<FileSize>,
+    pub newer_than: Option<TimeLimit>,
+    pub older_than: Option<TimeLimit>,
+    pub min_depth: Option<usize>,
+    pub max_depth: Option<usize>,
+    /// Search the hidden files and directories
+    pub include_hidden: bool,
+    /// Don't search the subdirectories on other filesystems
+    pub one_file_system: bool,
+    /// Use the configuration files in the subdirectories
+    pub nested_configs: bool,
+    /// The extensions replacing, or added to, the configured ones
+    pub extensions: Vec<String>,
+    /// The formats replacing, or added to, the configured ones
+    pub formats: Vec<Regex>,
+    /// Add the extensions and formats to the configured ones, instead of replacing them
+    pub append_filters: bool,
+}
+
+impl ConfigOverrides {
+    /// Apply the profile and the overrides to the configuration
+    ///
+    /// # Errors
+    /// - If the configuration doesn't define the profile
+    pub fn apply(&self, config: ConfigFile) -> Result<ConfigFile, ConfigFileError> {
+        config
+            .with_profile(self.profile.as_deref())?
+            .with_size_limits(self.min_size, self.max_size)
+            .with_time_limits(self.newer_than, self.older_than)
+            .with_depth_limits(self.min_depth, self.max_depth)
+            .with_hidden(self.include_hidden)
+            .with_one_file_system(self.one_file_system)
+            .with_nested_configs(self.nested_configs)
+            .with_filters(self.extensions.clone(), self.formats.clone(), self.append_filters)
+            .with_extension_groups()
+    }
+
+    /// Apply the overrides to the configuration of a subdirectory
+    ///
+    /// Unlike the root configuration, the one of a subdirectory doesn't have to define the profile,
+    /// and its default profile is used if it doesn't.
+    fn apply_nested(&self, config: ConfigFile) -> Result<ConfigFile, ConfigFileError> {
+        let profile = self.profile.clone().filter(|profile| config.profiles.contains_key(profile));
+        let overrides = ConfigOverrides {
+            profile,
+            ..self.clone()
+        };
+        overrides.apply(config)
+    }
+}
+
+/// The configuration of the searched directory, with the ones of its subdirectories
+///
+/// Every file is matched by the configuration of its closest parent directory that has one.
+/// The configuration files of the subdirectories are only used if the root configuration enables `nested_configs`,
+/// and are never matched themselves.
+pub struct ConfigTree {
+    /// The configuration of the searched directory
+    root: ConfigFile,
+    /// The configurations of the subdirectories, with the path they were loaded from, by their directory
+    nested: HashMap<PathBuf, (PathBuf, ConfigFile)>,
+}
+
+impl ConfigTree {
+    /// Find and load the configurations of the subdirectories of the searched `roots` the `files` are in
+    ///
+    /// The `overrides` are applied to every loaded configuration.
+    ///
+    /// # Errors
+    /// - If the configuration of a subdirectory can't be loaded
+    pub fn discover<'a, I: IntoIterator<Item = &'a PathBuf>, P: AsRef<Path>>(
+        root: ConfigFile,
+        roots: &[P],
+        files: I,
+        overrides: &ConfigOverrides,
+    ) -> Result<Self, ConfigFileError> {
+        let root = root.with_roots(roots);
+        let mut nested = HashMap::new();
+        if !root.nested_configs {
+            return Ok(ConfigTree { root, nested });
+        }
+        let dirs: BTreeSet<&Path> = files
+            .into_iter()
+            .flat_map(|file| {
+                let dir = roots.iter().map(AsRef::as_ref).find(|dir| file.starts_with(dir));
+                let inside = move |parent: &&Path| dir.is_some_and(|dir| *parent != dir && parent.starts_with(dir));
+                file.ancestors().skip(1).take_while(inside)
+            })
+            .collect();
+        for subdir in dirs {
+            let path = ConfigFile::locate(subdir);
+            if !path.is_file() {
+                continue;
+            }
+            let config = ConfigFile::try_load(&path)
+                .and_then(|config| overrides.apply_nested(config.with_root(subdir)))
+                .map_err(|e| ConfigFileError::Nested(path.clone(), Box::new(e)))?;
+            nested.insert(subdir.to_owned(), (path, config));
+        }
+        Ok(ConfigTree { root, nested })
+    }
+
+    /// Get the paths of the loaded configurations of the subdirectories, sorted
+    pub fn nested_sources(&self) -> Vec<&Path> {
+        self.nested.values().map(|(path, _)| path.as_path()).sorted().collect()
+    }
+
+    /// Explain which configuration matches the file, and which of its rules accept the file
+    pub fn explain(&self, file: &Path, now: SystemTime) -> Vec<Step> {
+        match file.ancestors().skip(1).find_map(|parent| self.nested.get(parent)) {
+            Some((config, _)) if file == config => {
+                vec![Step::reject("config", "the file is the configuration of its directory")]
+            }
+            Some((path, config)) => {
+                let step = Step::accept("config", format!("matched by \"{}\"", path.display()));
+                [step].into_iter().chain(config.explain(file, now)).collect()
+            }
+            None => self.root.explain(file, now),
+        }
+    }
+
+    /// Convert the configurations into a filter function, matching every file by its closest configuration
+    pub fn into_filter(self) -> Rc<dyn Fn(&&PathBuf) -> bool> {
+        let root = self.root.into_filter();
+        let nested: HashMap<_, _> = self
+            .nested
+            .into_iter()
+            .map(|(dir, (path, config))| (dir, (path, config.into_filter())))
+            .collect();
+        Rc::new(
+            move |file| match file.ancestors().skip(1).find_map(|parent| nested.get(parent)) {
+                Some((config, filter)) => *file != config && filter(file),
+                None => root(file),
+            },
+        )
+    }
+}
+
+/// Expand the references to environment variables in the string values, returning the unset ones
+///
+/// A reference is written as `${VAR}`, or `${VAR:-default}` to use the default if the variable is not set,
+/// and `$${VAR}` is left as the literal `${VAR}`. Unset variables are expanded to empty strings.
+/// The `key` is the path of the value in the configuration, used to report the unset variables.
+fn interpolate(value: &mut serde_yaml::Value, key: &str) -> Vec<UnsetVariable> {
+    use serde_yaml::Value;
+
+    let mut unset = vec![];
+    match value {
+        Value::String(text) => {
+            let reference = regex!(r"\$(\$)?\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}");
+            let mut expanded = String::new();
+            let mut last = 0;
+            for captures in reference.captures_iter(text) {
+                let whole = captures.get(0).expect("The whole match is always captured");
+                expanded.push_str(&text[last..whole.start()]);
+                last = whole.end();
+                if captures.get(1).is_some() {
+                    expanded.push_str(&whole.as_str()[1..]);
+                    continue;
+                }
+                let variable = &captures[2];
+                match (std::env::var(variable), captures.get(3)) {
+                    (Ok(expansion), _) => expanded.push_str(&expansion),
+                    (Err(_), Some(default)) => expanded.push_str(default.as_str()),
+                    (Err(_), None) => unset.push(UnsetVariable {
+                        variable: variable.to_owned(),
+                        key: key.to_owned(),
+                    }),
+                }
+            }
+            expanded.push_str(&text[last..]);
+            *text = expanded;
+        }
+        Value::Sequence(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                unset.extend(interpolate(item, &format!("{key}[{index}]")));
+            }
+        }
+        Value::Mapping(mapping) => {
+            for (name, item) in mapping.iter_mut() {
+                let name = name.as_str().map(str::to_owned).unwrap_or_else(|| format!("{name:?}"));
+                let key = match key.is_empty() {
+                    true => name,
+                    false => format!("{key}.{name}"),
+                };
+                unset.extend(interpolate(item, &key));
+            }
+        }
+        _ => {}
+    }
+    unset
+}
+
+/// Environment variable referenced by the configuration, which is not set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsetVariable {
+    /// Name of the variable
+    pub variable: String,
+    /// Path of the value referencing the variable, like `hooks.pre`
+    pub key: String,
+}
+
+impl Display for UnsetVariable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "environment variable \"{}\" used by \"{}\" is not set",
+            self.variable, self.key
+        )
+    }
+}
+
+/// Merge the `value` on top of the `base` value of the configuration it extends
+///
+/// Mappings are merged key by key, lists are appended without the items the `base` already has,
+/// and the other values replace the `base`, unless they are missing (`null`).
+fn merge_values(base: serde_yaml::Value, value: serde_yaml::Value) -> serde_yaml::Value {
+    use serde_yaml::Value;
+
+    match (base, value) {
+        (Value::Mapping(mut base), Value::Mapping(value)) => {
+            for (key, value) in value {
+                let merged = match base.remove(&key) {
+                    Some(base) => merge_values(base, value),
+                    None => value,
+                };
+                base.insert(key, merged);
+            }
+            Value::Mapping(base)
+        }
+        (Value::Sequence(mut base), Value::Sequence(value)) => {
+            for item in value {
+                if !base.contains(&item) {
+                    base.push(item);
+                }
+            }
+            Value::Sequence(base)
+        }
+        (base, Value::Null) => base,
+        (_, value) => value,
+    }
+}
+
+/// Expression combining extension and format rules with `all`, `any` and `not`
+///
+/// Written as nested single-key mappings, like `{ all: [ { ext: [jpg] }, { not: { format: "^_" } } ] }`.
+/// The rules follow the options of the configuration they are in, like `case_insensitive` and `match_path`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterExpr {
+    /// Matches if all the expressions match
+    All(Vec<FilterExpr>),
+    /// Matches if any of the expressions matches
+    Any(Vec<FilterExpr>),
+    /// Matches if the expression doesn't match
+    Not(Box<FilterExpr>),
+    /// Matches the files with one of the extensions
+    Ext(Vec<String>),
+    /// Matches the files whose names match the format
+    Format(Format),
+}
+
+impl FilterExpr {
+    /// Check if the file matches the expression, with the options of the `config`
+    pub fn matches(&self, config: &ConfigFile, path: &Path) -> bool {
+        match self {
+            FilterExpr::All(filters) => filters.iter().all(|filter| filter.matches(config, path)),
+            FilterExpr::Any(filters) => filters.iter().any(|filter| filter.matches(config, path)),
+            FilterExpr::Not(filter) => !filter.matches(config, path),
+            FilterExpr::Ext(extensions) => config.has_any_extension(path, extensions),
+            FilterExpr::Format(format) => config.format_subject(path).is_some_and(|subject| format.is_match(&subject)),
+        }
+    }
+
+    /// Get the expression with the options of the configuration applied to all its formats, see [Format::with_options]
+    pub fn with_format_options(&self, case_insensitive: bool, anchored: bool) -> FilterExpr {
+        let options = &mut |format: &Format| Ok::<_, Infallible>(format.with_options(case_insensitive, anchored));
+        let Ok(filter) = self.map_formats(options);
+        filter
+    }
+
+    /// Get the expression with all its formats replaced by the function
+    ///
+    /// # Errors
+    /// - If the function fails for any of the formats
+    pub fn map_formats<E, F>(&self, map: &mut F) -> Result<FilterExpr, E>
+    where
+        F: FnMut(&Format) -> Result<Format, E>,
+    {
+        Ok(match self {
+            FilterExpr::All(filters) => FilterExpr::All(filters.iter().map(|f| f.map_formats(map)).try_collect()?),
+            FilterExpr::Any(filters) => FilterExpr::Any(filters.iter().map(|f| f.map_formats(map)).try_collect()?),
+            FilterExpr::Not(filter) => FilterExpr::Not(Box::new(filter.map_formats(map)?)),
+            FilterExpr::Ext(extensions) => FilterExpr::Ext(extensions.clone()),
+            FilterExpr::Format(format) => FilterExpr::Format(map(format)?),
+        })
+    }
+
+    /// Get the expression with all its extension lists replaced by the function
+    ///
+    /// # Errors
+    /// - If the function fails for any of the extension lists
+    pub fn map_extensions<E, F>(&self, map: &mut F) -> Result<FilterExpr, E>
+    where
+        F: FnMut(&[String]) -> Result<Vec<String>, E>,
+    {
+        Ok(match self {
+            FilterExpr::All(filters) => FilterExpr::All(filters.iter().map(|f| f.map_extensions(map)).try_collect()?),
+            FilterExpr::Any(filters) => FilterExpr::Any(filters.iter().map(|f| f.map_extensions(map)).try_collect()?),
+            FilterExpr::Not(filter) => FilterExpr::Not(Box::new(filter.map_extensions(map)?)),
+            FilterExpr::Ext(extensions) => FilterExpr::Ext(map(extensions)?),
+            FilterExpr::Format(format) => FilterExpr::Format(format.clone()),
+        })
+    }
+}
+
+impl Display for FilterExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilterExpr::All(filters) => write!(f, "all({})", filters.iter().join(", ")),
+            FilterExpr::Any(filters) => write!(f, "any({})", filters.iter().join(", ")),
+            FilterExpr::Not(filter) => write!(f, "not({filter})"),
+            FilterExpr::Ext(extensions) => write!(f, "ext({})", extensions.join(", ")),
+            FilterExpr::Format(format) => write!(f, "format({format})"),
+        }
+    }
+}
+
+/// A file name format
+///
+/// This is a wrapper around a regular expression that describes a file name format.
+/// A format written with a leading `!` is negated, and matches the file names the regular expression doesn't match,
+/// so a regular expression starting with `!` has to escape it as `\!`.
+///
+/// It provides Display and utility methods to check if a file name matches the format, given a list of extensions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "FormatValue", into = "FormatValue")]
+pub struct Format {
+    /// The regular expression as written, without the leading `!`
+    source: String,
+    /// The regular expression the file names are matched with, following the options of the configuration
+    regex: Regex,
+    /// Whether the format matches the file names the regular expression doesn't match
+    negated: bool,
+    /// Whether the format has to match the whole file name, if set for this format instead of the configuration
+    anchored: Option<bool>,
+}
+
+/// Format as written in the configuration, a regular expression or a mapping with its options
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum FormatValue {
+    Regex(String),
+    Detailed {
+        regex: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        anchored: Option<bool>,
+    },
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let negation = if self.negated { "!" } else { "" };
+        write!(f, "\"{negation}{}\"", self.source)
+    }
+}
+
+impl From<Regex> for Format {
+    fn from(regex: Regex) -> Self {
+        Format {
+            source: regex.as_str().to_owned(),
+            regex,
+            negated: false,
+            anchored: None,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = regex::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Format::new(s, None)
+    }
+}
+
+impl TryFrom<FormatValue> for Format {
+    type Error = regex::Error;
+
+    fn try_from(value: FormatValue) -> Result<Self, Self::Error> {
+        match value {
+            FormatValue::Regex(regex) => Format::new(&regex, None),
+            FormatValue::Detailed { regex, anchored } => Format::new(&regex, anchored),
+        }
+    }
+}
+
+impl From<Format> for FormatValue {
+    fn from(format: Format) -> Self {
+        let negation = if format.negated { "!" } else { "" };
+        let regex = format!("{negation}{}", format.source);
+        match format.anchored {
+            Some(anchored) => FormatValue::Detailed {
+                regex,
+                anchored: Some(anchored),
+            },
+            None => FormatValue::Regex(regex),
+        }
+    }
+}
+
+impl Format {
+    /// Parse the format, negated if it starts with `!`, anchored to the whole file name if `anchored` is set
+    ///
+    /// # Errors
+    /// - If the regular expression is invalid
+    pub fn new(format: &str, anchored: Option<bool>) -> Result<Format, regex::Error> {
+        let (source, negated) = match format.strip_prefix('!') {
+            Some(source) => (source, true),
+            None => (format, false),
+        };
+        Ok(Format {
+            source: source.to_owned(),
+            regex: Format::compile(source, anchored.unwrap_or_default(), false)?,
+            negated,
+            anchored,
+        })
+    }
+
+    /// Compile the regular expression, anchored if it has to match the whole file name
+    ///
+    /// An anchored regular expression matches the whole name, or the whole name without the extension,
+    /// so `IMG_\d{4}` matches `IMG_0001.jpg`, but not `backup_IMG_00012.jpg`.
+    fn compile(source: &str, anchored: bool, case_insensitive: bool) -> Result<Regex, regex::Error> {
+        let pattern = match anchored {
+            true => Cow::Owned(format!(r"^(?:{source})(?:\.[^.]*)?$")),
+            false => Cow::Borrowed(source),
+        };
+        RegexBuilder::new(&pattern).case_insensitive(case_insensitive).build()
+    }
+
+    /// Get the regular expression of the format as written, without the leading `!` of a negated format
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Check if the format matches the file names the regular expression doesn't match
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    /// Check if the regular expression is only `.*` or `.+`, so the format matches every file name
+    ///
+    /// The expression may be anchored with `^` and `$`, and start with flags like `(?i)`.
+    /// Other expressions matching every file name, like `\d*`, aren't recognized.
+    pub fn matches_everything(&self) -> bool {
+        !self.negated && regex!(r"^(?:\(\?[[:alpha:]]+\))?\^?(?:\.[*+]\??)?\$?$").is_match(&self.source)
+    }
+
+    /// Get the format with the options of the configuration applied
+    ///
+    /// The format matches regardless of case if `case_insensitive` is set,
+    /// and the whole file name if `anchored` is set, unless the format sets it itself.
+    pub fn with_options(&self, case_insensitive: bool, anchored: bool) -> Format {
+        let regex = Format::compile(&self.source, self.anchored.unwrap_or(anchored), case_insensitive);
+        Format {
+            regex: regex.unwrap_or_else(|_| self.regex.clone()),
+            ..self.clone()
+        }
+    }
+
+    /// Get the reference to a named pattern replaced by the `pattern`
+    ///
+    /// A negated reference negates the pattern, and the anchoring of the reference overrides the one of the pattern.
+    fn resolve(&self, pattern: &Format) -> Format {
+        let anchored = self.anchored.or(pattern.anchored);
+        let regex = Format::compile(&pattern.source, anchored.unwrap_or_default(), false);
+        Format {
+            regex: regex.unwrap_or_else(|_| pattern.regex.clone()),
+            negated: pattern.negated != self.negated,
+            anchored,
+            ..pattern.clone()
+        }
+    }
+
+    /// Check if a file name matches the format, and has one of the specified extensions
+    pub fn matches<P: AsRef<Path>>(&self, path: P) -> Option<bool> {
+        let path = path.as_ref();
+        let file_name = path.file_name()?.to_str()?;
+
+        Some(self.is_match(file_name))
+    }
+
+    /// Check if the text matches the format, or doesn't match it if the format is negated
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text) != self.negated
+    }
+}
+
+/// A key of the configuration file that isn't known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownKey {
+    /// The unknown key
+    pub key: String,
+    /// The known key closest to it, if it is close enough to be a typo
+    pub suggestion: Option<&'static str>,
+}
+
+impl UnknownKey {
+    /// Find the unknown top-level keys of the configuration
+    pub fn find(value: &serde_yaml::Value) -> Vec<UnknownKey> {
+        let known = config_keys();
+        let keys = value.as_mapping().into_iter().flat_map(|mapping| mapping.keys());
+        keys.filter_map(|key| key.as_str())
+            .filter(|key| !known.contains(key))
+            .map(|key| UnknownKey {
+                key: key.to_owned(),
+                suggestion: known
+                    .iter()
+                    .map(|known| (strsim::levenshtein(key, known), *known))
+                    .filter(|(distance, known)| *distance <= 3 && *distance < known.len() / 2 + 1)
+                    .min()
+                    .map(|(_, known)| known),
+            })
+            .collect()
+    }
+}
+
+impl Display for UnknownKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown key \"{}\"", self.key)?;
+        if let Some(suggestion) = self.suggestion {
+            write!(f, ", did you mean \"{suggestion}\"?")?;
+        }
+        Ok(())
+    }
+}
+
+/// Get the keys of the configuration file, as declared by its deserialization
+///
+/// The keys are captured by a deserializer that stops as soon as the configuration asks for its fields,
+/// so they can't get out of sync with the [ConfigFile] struct.
+fn config_keys() -> &'static [&'static str] {
+    use serde::de::{Error, Visitor};
+
+    struct KeysDeserializer<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> serde::Deserializer<'de> for KeysDeserializer<'_> {
+        type Error = serde::de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+            Err(Error::custom("expected a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(Error::custom("only the keys are read"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit
+            unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut keys: &'static [&'static str] = &[];
+    let _ = ConfigFile::deserialize(KeysDeserializer(&mut keys));
+    keys
+}
+
+/// Whether the extensions and the formats are matched regardless of case
+///
+/// Configured either for both of them, like `case_insensitive: true`,
+/// or separately, like `case_insensitive: { extensions: true, formats: true }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "CaseInsensitiveValue")]
+pub struct CaseInsensitive {
+    /// Match the extensions regardless of case, so `CR2` matches `cr2`. Enabled by default
+    pub extensions: bool,
+    /// Match the formats regardless of case. Disabled by default
+    pub formats: bool,
+}
+
+/// Case sensitivity as written in the configuration, for both the extensions and the formats or separately
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CaseInsensitiveValue {
+    Both(bool),
+    Separate {
+        #[serde(default = "CaseInsensitiveValue::default_extensions")]
+        extensions: bool,
+        #[serde(default)]
+        formats: bool,
+    },
+}
+
+impl CaseInsensitiveValue {
+    fn default_extensions() -> bool {
+        true
+    }
+}
+
+impl From<CaseInsensitiveValue> for CaseInsensitive {
+    fn from(value: CaseInsensitiveValue) -> Self {
+        match value {
+            CaseInsensitiveValue::Both(both) => CaseInsensitive {
+                extensions: both,
+                formats: both,
+            },
+            CaseInsensitiveValue::Separate { extensions, formats } => CaseInsensitive { extensions, formats },
+        }
+    }
+}
+
+impl Default for CaseInsensitive {
+    fn default() -> Self {
+        CaseInsensitive {
+            extensions: true,
+            formats: false,
+        }
+    }
+}
+
+impl CaseInsensitive {
+    /// Check if the default case sensitivity is configured
+    pub fn is_default(&self) -> bool {
+        *self == CaseInsensitive::default()
+    }
+}
+
+impl Display for CaseInsensitive {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{ extensions: {}, formats: {} }}", self.extensions, self.formats)
+    }
+}
+
+/// A directory excluded from the search
+///
+/// This is a glob pattern matched against the name of the directory, like `.thumbnails` or `backup*`,
+/// and against its path relative to the searched directory, like `exports/2023-*`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DirPattern(glob::Pattern);
+
+impl DirPattern {
+    /// Check if the directory, found at the `relative` path inside the searched directory, is excluded
+    pub fn matches<P: AsRef<Path>>(&self, relative: P) -> bool {
+        let relative = relative.as_ref();
+        relative
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| self.0.matches(name))
+            || self.0.matches_path(relative)
+    }
+}
+
+impl FromStr for DirPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Directories are often written with a trailing slash, like `backups/`
+        let trimmed = s.trim().trim_end_matches(['/', '\\']);
+        glob::Pattern::new(trimmed)
+            .map(DirPattern)
+            .map_err(|e| format!("invalid directory pattern \"{s}\": {}", e.msg))
+    }
+}
+
+impl TryFrom<String> for DirPattern {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<DirPattern> for String {
+    fn from(pattern: DirPattern) -> Self {
+        pattern.0.as_str().to_owned()
+    }
+}
+
+impl Display for DirPattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{}\"", self.0.as_str())
+    }
+}
+
+/// A configuration file to merge with the others, see [ConfigFile::merge]
+#[derive(Debug, Clone)]
+pub struct ConfigLayer {
+    /// Name of the configuration in the errors, like its path or URL
+    pub name: String,
+    /// Content of the configuration file
+    pub content: Vec<u8>,
+    /// Format of the content
+    pub format: ConfigFormat,
+    /// Path the configuration was read from, which the configuration it `extends` is relative to
+    pub source: Option<PathBuf>,
+}
+
+/// Format of a configuration file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ConfigFormat {
+    #[default]
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Detect the format of the configuration file by its extension
+    ///
+    /// Files with the `.toml` and `.json` extensions are read as TOML and JSON, and everything else as YAML.
+    pub fn detect<P: AsRef<Path>>(path: P) -> Self {
+        let extension = path.as_ref().extension().map(|ext| ext.to_ascii_lowercase());
+        match extension.as_ref().and_then(|ext| ext.to_str()) {
+            Some("toml") => ConfigFormat::Toml,
+            Some("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigFileError {
+    #[error("Config I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Config parsing error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("Config parsing error: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Config parsing error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Config parsing error: {0}")]
+    UnknownKey(UnknownKey),
+    #[error("Config parsing error: {0}")]
+    UnsetVariable(UnsetVariable),
+    #[error("Config profile \"{0}\" is not defined, the available profiles are: {1}")]
+    UnknownProfile(String, String),
+    #[error("Config pattern \"{0}\" is not defined, the defined patterns are: {1}")]
+    UnknownPattern(String, String),
+    #[error("Config extension group \"{0}\" is not defined, the defined groups are: {1}")]
+    UnknownExtensionGroup(String, String),
+    #[error("Config uses {0}, which need the `{1}` feature the program was built without")]
+    Unsupported(&'static str, &'static str),
+    #[error("Config {0:?} can't be extended: {1}")]
+    Extends(PathBuf, Box<ConfigFileError>),
+    #[error("Config extends itself: {0}")]
+    ExtendsCycle(String),
+    #[error("Config {0:?} of the subdirectory can't be loaded: {1}")]
+    Nested(PathBuf, Box<ConfigFileError>),
+    #[error("Config {0:?} can't be merged: {1}")]
+    Layer(String, Box<ConfigFileError>),
+    #[error("Config {0:?} was found, but can't be loaded: {1}")]
+    Discovered(PathBuf, Box<ConfigFileError>),
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::test_utils::{resource_dir, TestResult};
+
+    use super::*;
+
+    #[test]
+    fn load_config_file() -> TestResult {
+        let config = ConfigFile::try_load(resource_dir().join("cfg.yaml"))?;
+        assert_eq!(config.name, Some("test_cfg".to_owned()));
+        assert_eq!(config.extensions, vec!["txt".to_owned(), "csv".to_owned()]);
+        assert_eq!(config.formats.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_toml_config_file() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let content = "name = \"test_toml\"\nextensions = [\"jpg\"]\nformats = ['IMG_\\d+']\n";
+        std::fs::write(dir.path().join("config.toml"), content)?;
+        assert_eq!(ConfigFile::locate(dir.path()), dir.path().join("config.toml"));
+
+        let config = ConfigFile::try_load(ConfigFile::locate(dir.path()))?;
+        assert_eq!(config.name, Some("test_toml".to_owned()));
+        assert!(config.matches("IMG_0001.jpg"));
+        assert!(!config.matches("IMG_0001.png"));
+        assert!(ConfigFile::check(content.as_bytes(), ConfigFormat::Toml).is_empty());
+        assert_eq!(
+            ConfigFile::check(b"extensions = []\nformats = []\nextension = 1\n", ConfigFormat::Toml),
+            ["unknown key \"extension\", did you mean \"extensions\"?"]
+        );
+
+        std::fs::write(dir.path().join("config.yaml"), "extensions: []\nformats: []\n")?;
+        assert_eq!(ConfigFile::locate(dir.path()), dir.path().join("config.yaml"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn merge_config_files() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let layer = |name: &str, content: &str| -> std::io::Result<ConfigLayer> {
+            let path = dir.path().join(name);
+            std::fs::write(&path, content)?;
+            Ok(ConfigLayer {
+                name: name.to_owned(),
+                content: content.as_bytes().to_vec(),
+                format: ConfigFormat::detect(&path),
+                source: Some(path.canonicalize()?),
+            })
+        };
+        let base = layer(
+            "base.yaml",
+            "name: base\nextensions: [jpg]\nformats: ['^IMG_']\nmin_size: 1MB\n",
+        )?;
+        let shoot = layer("shoot.toml", "name = \"shoot\"\nextensions = [\"cr2\"]\n")?;
+        let config = ConfigFile::merge(vec![base.clone(), shoot])?;
+        assert_eq!(config.name.as_deref(), Some("shoot"));
+        assert_eq!(config.extensions, ["jpg", "cr2"]);
+        assert_eq!(config.min_size, Some("1MB".parse()?));
+        assert!(config.matches("IMG_0001.cr2"));
+        assert_eq!(config.sources().len(), 2);
+        assert!(config.to_yaml()?.starts_with("# Resolved configuration merged from "));
+
+        let broken = layer("broken.yaml", "formats: ['(']\n")?;
+        let error = ConfigFile::merge(vec![base, broken]).unwrap_err();
+        assert!(matches!(error, ConfigFileError::Layer(name, _) if name == "broken.yaml"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn discover_config_file() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let shoot = dir.path().join("project/shoot/day1");
+        std::fs::create_dir_all(&shoot)?;
+        let config = dir.path().join("project/config.toml");
+        std::fs::write(&config, "extensions = [\"jpg\"]\nformats = []\n")?;
+        assert_eq!(ConfigFile::discover(&shoot), Some(config.canonicalize()?));
+
+        std::fs::create_dir(dir.path().join("project/shoot/.git"))?;
+        assert_eq!(ConfigFile::discover(&shoot), None);
+
+        std::fs::write(shoot.join("config.yaml"), "extensions: []\nformats: []\n")?;
+        assert_eq!(
+            ConfigFile::discover(&shoot),
+            Some(shoot.join("config.yaml").canonicalize()?)
+        );
+
+        // A broken discovered configuration isn't replaced by the default one
+        std::fs::write(shoot.join("config.yaml"), "extensions: []\nformatz: []\n")?;
+        let error = ConfigFile::try_discover(&shoot).unwrap_err();
+        assert!(matches!(error, ConfigFileError::Discovered(path, _) if path.ends_with("day1/config.yaml")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_json_config_file() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let content = r#"{"name": "test_json", "extensions": ["jpg"], "formats": ["IMG_\\d+"]}"#;
+        std::fs::write(dir.path().join("config.json"), content)?;
+        let config = ConfigFile::try_load(ConfigFile::locate(dir.path()))?;
+        assert_eq!(config.name, Some("test_json".to_owned()));
+        assert!(config.matches("IMG_0001.jpg"));
+
+        let error = ConfigFile::from_slice(b"{\n  \"extensions\": [\"jpg\",]\n}", ConfigFormat::Json).unwrap_err();
+        assert!(matches!(error, ConfigFileError::Json(_)));
+        assert!(error.to_string().contains("line 2 column"));
+        assert_eq!(ConfigFormat::detect("config.JSON"), ConfigFormat::Json);
+
+        Ok(())
+    }
+
+    #[test]
+    fn config_profiles() -> TestResult {
+        let content = "extensions: [jpg, cr2]\nformats: ['IMG_\\d+']\ndefault_profile: raw-only\nprofiles:\n  \
+            raw-only:\n    extensions: [cr2]\n  video:\n    extensions: [mp4]\n    formats: ['MVI_\\d+']\n  \
+            tokens:\n    number_pattern: '(\\d{3})\\.'\n    number_matching: token\n";
+        let config = ConfigFile::from_slice(content.as_bytes(), ConfigFormat::Yaml)?;
+
+        let raw = config.clone().with_profile(None)?;
+        assert_eq!(raw.profile(), Some("raw-only"));
+        assert!(raw.matches("IMG_0001.cr2"));
+        assert!(!raw.matches("IMG_0001.jpg"));
+
+        let video = config.clone().with_profile(Some("video"))?;
+        assert!(video.matches("MVI_0001.mp4"));
+        assert!(!video.matches("IMG_0001.mp4"));
+        assert!(video.to_string().contains("Profile: \"video\""));
+
+        let tokens = config.clone().with_profile(Some("tokens"))?;
+        assert_eq!(
+            tokens.number_pattern(None, None, None).extract("IMG_1003_100.jpg"),
+            Some(1003)
+        );
+        let regex = tokens.number_pattern(None, None, Some(NumberMatching::Regex));
+        assert_eq!(regex.extract("IMG_1003_100.jpg"), Some(100));
+
+        let error = config.with_profile(Some("jpeg-only")).unwrap_err();
+        assert!(matches!(error, ConfigFileError::UnknownProfile(name, _) if name == "jpeg-only"));
+        assert_eq!(
+            ConfigFile::check(b"default_profile: raw\n", ConfigFormat::Yaml).len(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn exclude_rules() -> TestResult {
+        let content = "extensions: [jpg, png, cr2]\nformats: ['.+']\n\
+            exclude_extensions: [cr2]\nexclude_formats: ['^Screen']\n";
+        let config = ConfigFile::from_slice(content.as_bytes(), ConfigFormat::Yaml)?;
+        assert!(config.matches("IMG_0001.jpg"));
+        assert!(config.matches("IMG_Screen.png"));
+        assert!(!config.matches("IMG_0001.CR2"));
+        assert!(!config.matches("Screenshot 2024-06-01.png"));
+        let problems = ConfigFile::check(
+            b"extensions: []\nformats: []\nexclude_formats: ['(']\n",
+            ConfigFormat::Yaml,
+        );
+        assert!(problems[0].starts_with("exclude_formats[0]"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn size_limits() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        for (file, size) in [("IMG_0001.jpg", 10), ("IMG_0002.jpg", 2000), ("IMG_0003.jpg", 50_000)] {
+            std::fs::write(dir.path().join(file), vec![0; size])?;
+        }
+        let content = "extensions: [jpg]\nformats: ['.+']\nmin_size: 1KB\nmax_size: 10000\n";
+        let config = ConfigFile::from_slice(content.as_bytes(), ConfigFormat::Yaml)?;
+        let now = SystemTime::now();
+        assert!(!config.has_metadata(dir.path().join("IMG_0001.jpg"), now));
+        assert!(config.has_metadata(dir.path().join("IMG_0002.jpg"), now));
+        assert!(!config.has_metadata(dir.path().join("IMG_0003.jpg"), now));
+        assert!(config.to_string().contains("Maximum size: 10KB"));
+
+        let config = config.with_size_limits(None, Some(FileSize(100_000)));
+        let filter = config.into_filter();
+        assert!(filter(&&dir.path().join("IMG_0003.jpg")));
+        assert!(!filter(&&dir.path().join("IMG_0001.jpg")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn time_limits() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("IMG_0001.jpg");
+        std::fs::write(&file, b"content")?;
+        let config = ConfigFile::from_slice(b"extensions: [jpg]\nformats: ['.+']\n", ConfigFormat::Yaml)?;
+
+        let now = SystemTime::now();
+        let older = config.clone().with_time_limits(None, Some("7d".parse()?));
+        assert!(!older.has_metadata(&file, now));
+        assert!(older.has_metadata(&file, now + Duration::from_secs(8 * 86_400)));
+
+        let newer = config.clone().with_time_limits(Some("1h".parse()?), None);
+        assert!(newer.has_metadata(&file, now));
+        assert!(!newer.has_metadata(&file, now + Duration::from_secs(7200)));
+        assert!(newer.to_string().contains("Newer than: 1h"));
+
+        let between = config.with_time_limits(Some("2000-01-01".parse()?), Some("2000-02-01".parse()?));
+        assert!(!between.has_metadata(&file, now));
+
+        Ok(())
+    }
+
+    #[test]
+    fn case_insensitive() -> TestResult {
+        let config = ConfigFile::from_slice(b"extensions: [CR2, jpg]\nformats: ['^IMG_']\n", ConfigFormat::Yaml)?;
+        assert!(config.matches("IMG_0001.cr2"));
+        assert!(config.matches("IMG_0001.JPG"));
+        assert!(!config.matches("img_0001.jpg"));
+
+        let content = b"extensions: [jpg]\nformats: ['^IMG_']\ncase_insensitive: { formats: true }\n";
+        let config = ConfigFile::from_slice(content, ConfigFormat::Yaml)?;
+        assert!(config.matches("img_0001.JPG"));
+        assert!(config
+            .to_string()
+            .contains("Case insensitive: { extensions: true, formats: true }"));
+
+        let content = b"extensions: [jpg]\nformats: ['^IMG_']\ncase_insensitive: false\n";
+        let config = ConfigFile::from_slice(content, ConfigFormat::Yaml)?;
+        assert!(config.matches("IMG_0001.jpg"));
+        assert!(!config.matches("IMG_0001.JPG"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_keys() -> TestResult {
+        let error = ConfigFile::from_slice(b"extentions: [jpg]\nformats: ['.+']\n", ConfigFormat::Yaml).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Config parsing error: unknown key \"extentions\", did you mean \"extensions\"?"
+        );
+        let error = ConfigFile::from_slice(br#"{"formats": [], "colour": 1}"#, ConfigFormat::Json).unwrap_err();
+        assert!(matches!(
+            error,
+            ConfigFileError::UnknownKey(UnknownKey { suggestion: None, .. })
+        ));
+
+        let problems = ConfigFile::check(b"extensions = []\nformats = []\nmax_dept = 2\n", ConfigFormat::Toml);
+        assert_eq!(problems, ["unknown key \"max_dept\", did you mean \"max_depth\"?"]);
+        assert!(config_keys().contains(&"default_profile"));
+        assert!(!config_keys().contains(&"source"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn print_round_trip() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(
+            dir.path().join("base.yaml"),
+            "formats: ['^IMG_']\nexclude_dirs: [exports/]\n",
+        )?;
+        let content = "extends: base.yaml\nextensions: [jpg]\ncase_insensitive: true\nmin_size: 1MB\n\
+                       profiles:\n  raw:\n    extensions: [cr2]\n";
+        std::fs::write(dir.path().join("config.yaml"), content)?;
+
+        let config = ConfigFile::try_load(dir.path().join("config.yaml"))?.with_profile(Some("raw"))?;
+        let yaml = config.to_yaml()?;
+        assert!(yaml.starts_with("# Resolved configuration loaded from "));
+        let printed = ConfigFile::from_slice(yaml.as_bytes(), ConfigFormat::Yaml)?.with_profile(None)?;
+        let resolved = config.to_string().replace("    Extends: \"base.yaml\",\n", "");
+        assert_eq!(printed.to_string(), resolved.replace("    Profile: \"raw\",\n", ""));
+        assert!(printed.matches("img_0001.CR2"));
+
+        // The overrides given with the profile are printed, instead of the profile applied again over them
+        let overrides = ConfigOverrides {
+            profile: Some("raw".to_owned()),
+            extensions: vec!["dng".to_owned()],
+            ..ConfigOverrides::default()
+        };
+        let config = overrides.apply(ConfigFile::try_load(dir.path().join("config.yaml"))?)?;
+        let yaml = config.to_yaml()?;
+        assert!(yaml.contains("# With the profile \"raw\" applied\n"));
+        assert!(!yaml.contains("default_profile"));
+        let printed = ConfigOverrides::default().apply(ConfigFile::from_slice(yaml.as_bytes(), ConfigFormat::Yaml)?)?;
+        assert!(printed.matches("img_0001.DNG"));
+        assert!(!printed.matches("img_0001.CR2"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn nested_configs() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir_all(dir.path().join("video/day2"))?;
+        std::fs::write(
+            dir.path().join("video/config.yaml"),
+            "extensions: [mp4]\nformats: ['^MVI_']\n",
+        )?;
+        let files = [
+            "IMG_0001.jpg",
+            "MVI_0002.mp4",
+            "video/IMG_0003.jpg",
+            "video/day2/MVI_0004.mp4",
+        ]
+        .map(|file| dir.path().join(file));
+        for file in files.iter() {
+            std::fs::write(file, b"")?;
+        }
+        let root = ConfigFile::from_slice(b"extensions: [jpg, mp4]\nformats: ['^IMG_']\n", ConfigFormat::Yaml)?;
+        let overrides = ConfigOverrides::default();
+
+        let tree = ConfigTree::discover(root.clone(), &[dir.path()], files.iter(), &overrides)?;
+        assert!(tree.nested_sources().is_empty());
+        let filter = tree.into_filter();
+        assert_eq!(files.iter().filter(|file| filter(file)).count(), 2);
+
+        let overrides = ConfigOverrides {
+            nested_configs: true,
+            min_size: Some(FileSize(0)),
+            ..ConfigOverrides::default()
+        };
+        let tree = ConfigTree::discover(overrides.apply(root)?, &[dir.path()], files.iter(), &overrides)?;
+        assert_eq!(tree.nested_sources(), [dir.path().join("video/config.yaml")]);
+        let filter = tree.into_filter();
+        let matching: Vec<_> = files.iter().filter(|file| filter(file)).collect();
+        assert_eq!(matching, [&files[0], &files[3]]);
+        assert!(!filter(&&dir.path().join("video/config.yaml")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn environment_variables() -> TestResult {
+        std::env::set_var("DELETE_REST_TEST_CAMERA", "IMG");
+        let content = "extensions: [jpg]\nformats: ['^${DELETE_REST_TEST_CAMERA}_\\d{4}\\.jpg$']\n\
+                       hooks:\n  pre: echo $${HOME} ${DELETE_REST_TEST_UNSET:-default}\n";
+        let config = ConfigFile::from_slice(content.as_bytes(), ConfigFormat::Yaml)?;
+        assert!(config.matches("IMG_0001.jpg"));
+        assert!(!config.matches("backup_IMG_0001.jpg"));
+        assert_eq!(config.hooks.pre.as_deref(), Some("echo ${HOME} default"));
+
+        let content = b"formats: ['.+']\nhooks:\n  post: cp log ${DELETE_REST_TEST_UNSET}\n";
+        let error = ConfigFile::from_slice(content, ConfigFormat::Yaml).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Config parsing error: environment variable \"DELETE_REST_TEST_UNSET\" used by \"hooks.post\" is not set"
+        );
+        assert_eq!(ConfigFile::check(content, ConfigFormat::Yaml).len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn match_path() -> TestResult {
+        let content = b"extensions: [jpg]\nformats: ['^100CANON/']\nexclude_formats: ['/rejects/']\nmatch_path: true\n";
+        let config = ConfigFile::from_slice(content, ConfigFormat::Yaml)?.with_root("/shoot");
+        assert!(config.matches("/shoot/100CANON/IMG_0001.jpg"));
+        assert!(config.matches("100CANON/day2/IMG_0002.jpg"));
+        assert!(!config.matches("/shoot/101CANON/IMG_0001.jpg"));
+        assert!(!config.matches("/shoot/100CANON/rejects/IMG_0003.jpg"));
+
+        let config = ConfigFile::from_slice(b"extensions: [jpg]\nformats: ['^100CANON/']\n", ConfigFormat::Yaml)?;
+        assert!(!config.matches("100CANON/IMG_0001.jpg"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn content_types() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, b'J', b'F', b'I', b'F', 0x00];
+        std::fs::write(dir.path().join("IMG_0001"), jpeg)?;
+        std::fs::write(dir.path().join("IMG_0002.dat"), jpeg)?;
+        std::fs::write(dir.path().join("IMG_0003.jpg"), b"not an image")?;
+        std::fs::write(dir.path().join("IMG_0004"), b"not an image")?;
+        let files = ["IMG_0001", "IMG_0002.dat", "IMG_0003.jpg", "IMG_0004"].map(|file| dir.path().join(file));
+
+        let content = b"extensions: [jpg]\nformats: ['^IMG_']\ncontent_types: ['image/*']\n";
+        let filter = ConfigFile::from_slice(content, ConfigFormat::Yaml)?.into_filter();
+        assert_eq!(files.iter().filter(|file| filter(file)).count(), 3);
+        let content = b"extensions: [jpg]\nformats: ['^IMG_']\ncontent_types: [png]\n";
+        let filter = ConfigFile::from_slice(content, ConfigFormat::Yaml)?.into_filter();
+        assert_eq!(files.iter().filter(|file| filter(file)).collect_vec(), [&files[2]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn image_limits() -> TestResult {
+        let content = b"extensions: [jpg, png]\nformats: ['.+']\nmin_width: 3000\nmax_iso: 800\n\
+                        camera_models: [eos r5]\n";
+        let config = match ConfigFile::from_slice(content, ConfigFormat::Yaml) {
+            Err(ConfigFileError::Unsupported(..)) if cfg!(not(feature = "exif")) => return Ok(()),
+            config => config?,
+        };
+        let image = ImageInfo {
+            width: Some(6000),
+            height: Some(4000),
+            camera_model: Some("Canon EOS R5".to_owned()),
+            iso: Some(400),
+        };
+        assert!(config.within_image_limits(&image));
+        assert!(!config.within_image_limits(&ImageInfo {
+            width: Some(1920),
+            ..image.clone()
+        }));
+        assert!(!config.within_image_limits(&ImageInfo {
+            iso: None,
+            ..image.clone()
+        }));
+        assert!(!config.within_image_limits(&ImageInfo {
+            camera_model: Some("EOS R6".to_owned()),
+            ..image
+        }));
+
+        let dir = tempfile::tempdir()?;
+        let png = |width: u32, height: u32| {
+            let mut header = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+            header.extend(width.to_be_bytes().into_iter().chain(height.to_be_bytes()));
+            header.extend([8, 2, 0, 0, 0]);
+            header
+        };
+        std::fs::write(dir.path().join("large.png"), png(4000, 3000))?;
+        std::fs::write(dir.path().join("small.png"), png(1600, 1200))?;
+        std::fs::write(dir.path().join("text.jpg"), b"not an image")?;
+        let content = b"extensions: [jpg, png]\nformats: ['.+']\nmin_width: 3000\n";
+        let config = ConfigFile::from_slice(content, ConfigFormat::Yaml)?;
+        assert!(config.has_image_info(dir.path().join("large.png")));
+        assert!(!config.has_image_info(dir.path().join("small.png")));
+        assert!(!config.has_image_info(dir.path().join("text.jpg")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn filter_expressions() -> TestResult {
+        let content =
+            "formats: ['^IMG_']\nmatch:\n  any:\n    - all: [{ ext: [jpg] }, { not: { format: '^IMG_9' } }]\n\
+                       \x20   - ext: [cr2]\n";
+        assert!(ConfigFile::check(content.as_bytes(), ConfigFormat::Yaml).is_empty());
+        let config = ConfigFile::from_slice(content.as_bytes(), ConfigFormat::Yaml)?;
+        assert!(config.matches("IMG_0001.jpg"));
+        assert!(config.matches("IMG_9001.CR2"));
+        assert!(!config.matches("IMG_9001.jpg"));
+        assert!(!config.matches("DSC_0001.jpg"));
+        assert_eq!(
+            config.filter.as_ref().map(ToString::to_string).as_deref(),
+            Some(r#"any(all(ext(jpg), not(format("^IMG_9"))), ext(cr2))"#)
+        );
+
+        let printed = ConfigFile::from_slice(config.to_yaml()?.as_bytes(), ConfigFormat::Yaml)?;
+        assert!(printed.matches("IMG_9001.CR2") && !printed.matches("IMG_9001.jpg"));
+
+        let content = b"case_insensitive = true\nmatch = { not = { format = '^img_9' } }\n";
+        let config = ConfigFile::from_slice(content, ConfigFormat::Toml)?;
+        assert!(config.matches("IMG_0001.png"));
+        assert!(!config.matches("IMG_9001.png"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn named_patterns() -> TestResult {
+        let content = "extensions: [jpg, mp4]\nformats: ['@canon', '^DSC_']\n\
+                       patterns:\n  canon: ^IMG_\\d{4}\n  video: ^MVI_\\d{4}\n\
+                       match: { not: { format: '@video' } }\nprofiles:\n  video:\n    formats: ['@video']\n";
+        let config = ConfigFile::from_slice(content.as_bytes(), ConfigFormat::Yaml)?;
+        assert!(config.matches("IMG_0001.jpg"));
+        assert!(!config.matches("MVI_0001.mp4"));
+        assert!(config.to_string().contains(r#"Formats: ["^IMG_\d{4}", "^DSC_"]"#));
+        let video = config.with_profile(Some("video"))?;
+        assert_eq!(video.formats[0].as_str(), r"^MVI_\d{4}");
+
+        let content = b"formats: ['@nikon']\npatterns:\n  canon: ^IMG_\n";
+        let error = ConfigFile::from_slice(content, ConfigFormat::Yaml).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Config pattern \"nikon\" is not defined, the defined patterns are: canon"
+        );
+        assert_eq!(ConfigFile::check(content, ConfigFormat::Yaml).len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn anchored_formats() -> TestResult {
+        let content = b"extensions: [jpg]\nformats: ['IMG_\\d{4}', { regex: '^DSC_', anchored: false }]\n\
+                        anchored: true\n";
+        let config = ConfigFile::from_slice(content, ConfigFormat::Yaml)?;
+        assert!(config.matches("IMG_1234.jpg"));
+        assert!(!config.matches("backup_IMG_12345_old.jpg"));
+        assert!(config.matches("DSC_0001.jpg"));
+        let yaml = config.to_yaml()?;
+        assert!(yaml.contains("regex: ^DSC_\n  anchored: false"));
+        assert!(ConfigFile::from_slice(yaml.as_bytes(), ConfigFormat::Yaml)?.matches("IMG_1234.jpg"));
+
+        let content = b"extensions: [jpg]\nformats: [{ regex: '!@canon', anchored: true }, '\\.jpg$']\n\
+                        patterns:\n  canon: IMG_\\d{4}\ncase_insensitive: true\n";
+        let config = ConfigFile::from_slice(content, ConfigFormat::Yaml)?;
+        assert!(!config.matches("IMG_0001.jpg"));
+        assert!(!config.matches("img_0001.JPG"));
+        assert!(config.matches("DSC_IMG_0001.jpg"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn bare_config() {
+        let config = ConfigFile::bare().with_filters(vec!["jpg".to_owned()], vec![], false);
+        assert!(config.matches("IMG_0001.jpg"));
+        assert!(config.matches("notes.jpg"));
+        assert!(!config.matches("IMG_0001.png"));
+
+        let config = ConfigFile::bare().with_filters(vec![], vec![Regex::new("^IMG_").unwrap()], false);
+        assert!(config.matches("IMG_0001.png"));
+        assert!(!config.matches("DSC_0001.jpg"));
+    }
+
+    #[test]
+    fn explain() -> TestResult {
+        let content = b"extensions: [jpg]\nformats: ['^IMG_', '!_edit']\nexclude_formats: ['_copy']\n";
+        let config = ConfigFile::from_slice(content, ConfigFormat::Yaml)?;
+        let now = SystemTime::now();
+        let outcomes = |file: &str| {
+            let steps = config.explain(Path::new(file), now);
+            steps.iter().map(|step| (step.stage, step.accepted)).collect_vec()
+        };
+        assert_eq!(outcomes("IMG_0001.jpg"), [("extension", true), ("format", true)]);
+        assert_eq!(outcomes("IMG_0001.png"), [("extension", false), ("format", true)]);
+        assert_eq!(outcomes("IMG_0001_edit.jpg"), [("extension", true), ("format", false)]);
+        assert_eq!(outcomes("DSC_0001.jpg"), [("extension", true), ("format", false)]);
+        assert_eq!(
+            outcomes("IMG_0001_copy.jpg"),
+            [("extension", true), ("format", true), ("exclusion", false)]
+        );
+        assert!(config.explain(Path::new("IMG_0001_edit.jpg"), now)[1]
+            .reason
+            .contains("\"!_edit\""));
+
+        Ok(())
+    }
+
+    #[test]
+    fn negated_formats() -> TestResult {
+        let content = b"extensions: [jpg]\nformats: ['^IMG_', '^DSC_', '!_edit', '!@copy']\n\
+                        patterns:\n  copy: \\(\\d\\)\nmatch: { format: '!^DSC_9' }\n";
+        let config = ConfigFile::from_slice(content, ConfigFormat::Yaml)?;
+        assert!(config.matches("IMG_0001.jpg"));
+        assert!(config.matches("DSC_0001.jpg"));
+        assert!(!config.matches("IMG_0001_edit.jpg"));
+        assert!(!config.matches("IMG_0001 (1).jpg"));
+        assert!(!config.matches("DSC_9001.jpg"));
+        assert!(!config.matches("PXL_0001.jpg"));
+        assert!(config.to_yaml()?.contains("- '!_edit'"));
+
+        let config = ConfigFile::from_slice(b"extensions: [jpg]\nformats: ['!^\\.', '\\!']\n", ConfigFormat::Yaml)?;
+        assert!(config.matches("hello!.jpg"));
+        assert!(!config.matches(".hello!.jpg"));
+        let config = ConfigFile::from_slice(b"extensions: [jpg]\nformats: ['!^\\.']\n", ConfigFormat::Yaml)?;
+        assert!(config.matches("IMG_0001.jpg"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn extension_groups() -> TestResult {
+        let content = b"extensions: ['@raw', '@jpeg', jpg]\nexclude_extensions: ['@sidecar']\n\
+                        extension_groups:\n  jpeg: [jpg, jxl]\nmatch: { not: { ext: ['@video'] } }\n\
+                        formats: ['^IMG_']\n";
+        let config = ConfigFile::from_slice(content, ConfigFormat::Yaml)?;
+        assert!(config.matches("IMG_0001.CR3"));
+        assert!(config.matches("IMG_0001.jxl"));
+        assert!(!config.matches("IMG_0001.jpeg"));
+        assert!(config.extensions.ends_with(&["jpg".to_owned(), "jxl".to_owned()]));
+        assert_eq!(config.exclude_extensions, ["xmp", "thm", "aae"]);
+
+        let overrides = ConfigOverrides {
+            extensions: vec!["@video".to_owned()],
+            ..ConfigOverrides::default()
+        };
+        assert!(overrides.apply(config)?.has_extension("MVI_0001.mov"));
+
+        let error = ConfigFile::from_slice(b"extensions: ['@rav']\n", ConfigFormat::Yaml).unwrap_err();
+        assert!(matches!(error, ConfigFileError::UnknownExtensionGroup(name, _) if name == "rav"));
+        assert_eq!(
+            ConfigFile::check(b"extensions: ['@rav']\n", ConfigFormat::Yaml).len(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn cli_filters() -> TestResult {
+        let content = b"extensions: [jpg]\nformats: ['^IMG_']\n";
+        let config = ConfigFile::from_slice(content, ConfigFormat::Yaml)?;
+        let config = config.with_filters(vec![".cr2".to_owned()], vec![Regex::new("^DSC_")?], false);
+        assert_eq!(config.extensions, ["cr2"]);
+        assert!(config.matches("DSC_0001.cr2"));
+        assert!(!config.matches("IMG_0001.jpg"));
+
+        let config = ConfigFile::from_slice(content, ConfigFormat::Yaml)?;
+        let config = config.with_filters(vec!["cr2".to_owned(), "jpg".to_owned()], vec![], true);
+        assert_eq!(config.extensions, ["jpg", "cr2"]);
+        assert!(config.matches("IMG_0001.cr2"));
+        assert!(!config.matches("DSC_0001.cr2"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn extends_config() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let base = "name: base\nextensions: [jpg]\nformats: ['^IMG_']\nhooks:\n  pre: echo base\n";
+        std::fs::write(dir.path().join("base.yaml"), base)?;
+        std::fs::create_dir(dir.path().join("shoot"))?;
+        let content = "name = \"shoot\"\nextends = \"../base.yaml\"\nextensions = [\"cr2\", \"jpg\"]\n";
+        let content = format!("{content}\n[hooks]\npost = \"echo\"\n");
+        std::fs::write(dir.path().join("shoot/config.toml"), content)?;
+
+        let config = ConfigFile::try_load(dir.path().join("shoot/config.toml"))?;
+        assert_eq!(config.name.as_deref(), Some("shoot"));
+        assert_eq!(config.extensions, ["jpg", "cr2"]);
+        assert_eq!(config.formats.len(), 1);
+        assert_eq!(config.hooks.pre.as_deref(), Some("echo base"));
+        assert_eq!(config.hooks.post.as_deref(), Some("echo"));
+
+        std::fs::write(
+            dir.path().join("base.yaml"),
+            format!("{base}extends: shoot/config.toml\n"),
+        )?;
+        let error = ConfigFile::try_load(dir.path().join("shoot/config.toml")).unwrap_err();
+        assert!(matches!(error, ConfigFileError::Extends(_, ref e) if matches!(**e, ConfigFileError::ExtendsCycle(_))));
+        let error = ConfigFile::from_slice(b"extends: missing.yaml\n", ConfigFormat::Yaml).unwrap_err();
+        assert!(matches!(error, ConfigFileError::Extends(..)));
+
+        Ok(())
     }
 
     #[test]
@@ -199,8 +2715,46 @@ mod test {
     fn has_extension() {
         let config = ConfigFile {
             name: None,
+            extends: None,
+            nested_configs: false,
             extensions: vec!["txt".to_owned(), "csv".to_owned()],
             formats: vec![],
+            case_insensitive: CaseInsensitive::default(),
+            match_path: false,
+            anchored: false,
+            filter: None,
+            content_types: vec![],
+            patterns: BTreeMap::new(),
+            extension_groups: BTreeMap::new(),
+            exclude_extensions: vec![],
+            exclude_formats: vec![],
+            exclude_dirs: vec![],
+            include_hidden: false,
+            one_file_system: false,
+            min_depth: None,
+            max_depth: None,
+            min_size: None,
+            max_size: None,
+            newer_than: None,
+            older_than: None,
+            min_width: None,
+            max_width: None,
+            min_height: None,
+            max_height: None,
+            camera_models: vec![],
+            min_iso: None,
+            max_iso: None,
+            hooks: Hooks::default(),
+            permissions: Permissions::default(),
+            number_pattern: None,
+            number_position: None,
+            number_matching: None,
+            profiles: BTreeMap::new(),
+            default_profile: None,
+            profile: None,
+            sources: vec![],
+            extended: vec![],
+            roots: vec![],
         };
 
         assert!(config.has_extension("test.txt"));
@@ -212,8 +2766,46 @@ mod test {
     fn has_format_no_ext() {
         let config = ConfigFile {
             name: None,
+            extends: None,
+            nested_configs: false,
             extensions: vec![],
             formats: vec![regex!(r#".+\d+"#).clone().into()],
+            case_insensitive: CaseInsensitive::default(),
+            match_path: false,
+            anchored: false,
+            filter: None,
+            content_types: vec![],
+            patterns: BTreeMap::new(),
+            extension_groups: BTreeMap::new(),
+            exclude_extensions: vec![],
+            exclude_formats: vec![],
+            exclude_dirs: vec![],
+            include_hidden: false,
+            one_file_system: false,
+            min_depth: None,
+            max_depth: None,
+            min_size: None,
+            max_size: None,
+            newer_than: None,
+            older_than: None,
+            min_width: None,
+            max_width: None,
+            min_height: None,
+            max_height: None,
+            camera_models: vec![],
+            min_iso: None,
+            max_iso: None,
+            hooks: Hooks::default(),
+            permissions: Permissions::default(),
+            number_pattern: None,
+            number_position: None,
+            number_matching: None,
+            profiles: BTreeMap::new(),
+            default_profile: None,
+            profile: None,
+            sources: vec![],
+            extended: vec![],
+            roots: vec![],
         };
 
         assert!(config.has_format("test1"));
@@ -225,8 +2817,46 @@ mod test {
     fn into_filter() {
         let config = ConfigFile {
             name: None,
+            extends: None,
+            nested_configs: false,
             extensions: vec!["txt".to_owned()],
             formats: vec![regex!(r#".+\d+"#).clone().into()],
+            case_insensitive: CaseInsensitive::default(),
+            match_path: false,
+            anchored: false,
+            filter: None,
+            content_types: vec![],
+            patterns: BTreeMap::new(),
+            extension_groups: BTreeMap::new(),
+            exclude_extensions: vec![],
+            exclude_formats: vec![],
+            exclude_dirs: vec![],
+            include_hidden: false,
+            one_file_system: false,
+            min_depth: None,
+            max_depth: None,
+            min_size: None,
+            max_size: None,
+            newer_than: None,
+            older_than: None,
+            min_width: None,
+            max_width: None,
+            min_height: None,
+            max_height: None,
+            camera_models: vec![],
+            min_iso: None,
+            max_iso: None,
+            hooks: Hooks::default(),
+            permissions: Permissions::default(),
+            number_pattern: None,
+            number_position: None,
+            number_matching: None,
+            profiles: BTreeMap::new(),
+            default_profile: None,
+            profile: None,
+            sources: vec![],
+            extended: vec![],
+            roots: vec![],
         };
 
         let filter = config.into_filter();
@@ -0,0 +1,173 @@
+//! Module containing declarations related to [ConflictResolver] struct
+//!
+//! A conflict occurs when the destination of a moved or copied file already exists.
+
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter};
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::UNIX_EPOCH;
+
+use clap::ValueEnum;
+
+use crate::dedupe::content_hash;
+
+/// What to do when the destination of a moved or copied file already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ConflictPolicy {
+    /// The existing file is overwritten
+    Overwrite,
+    /// The file is not moved or copied
+    #[default]
+    Skip,
+    /// The user chooses for each conflicting file
+    Prompt,
+}
+
+/// Resolution of a single conflict
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Overwrite the existing file
+    Overwrite,
+    /// Leave the existing file, and don't move or copy the file
+    Skip,
+}
+
+/// Interactive conflict prompt
+struct ConflictPrompt {
+    /// Where the answers are read from
+    input: Box<dyn BufRead>,
+    /// Where the questions are written to
+    output: Box<dyn Write>,
+    /// Resolution chosen for all the remaining conflicts
+    remembered: Option<Resolution>,
+}
+
+impl ConflictPrompt {
+    /// Show both files and ask the user how to resolve the conflict
+    fn ask(&mut self, src: &Path, dest: &Path) -> std::io::Result<Resolution> {
+        if let Some(resolution) = self.remembered {
+            return Ok(resolution);
+        }
+
+        writeln!(self.output, "\"{}\" already exists", dest.display())?;
+        writeln!(self.output, "  new:      {}", describe(src)?)?;
+        writeln!(self.output, "  existing: {}", describe(dest)?)?;
+        loop {
+            write!(self.output, "[o]verwrite, [s]kip, overwrite [a]ll, skip a[l]l: ")?;
+            self.output.flush()?;
+
+            let mut answer = String::new();
+            if self.input.read_line(&mut answer)? == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "Input ended before the conflict was resolved",
+                ));
+            }
+            match answer.trim().to_ascii_lowercase().as_str() {
+                "o" | "overwrite" => return Ok(Resolution::Overwrite),
+                "s" | "skip" => return Ok(Resolution::Skip),
+                "a" => return Ok(*self.remembered.insert(Resolution::Overwrite)),
+                "l" => return Ok(*self.remembered.insert(Resolution::Skip)),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Resolves conflicts according to the [ConflictPolicy]
+///
+/// Clones share the prompt, so "apply to all" answers hold for the whole run.
+#[derive(Clone)]
+pub struct ConflictResolver {
+    /// The policy
+    pub policy: ConflictPolicy,
+    /// The prompt used by [ConflictPolicy::Prompt]
+    prompt: Rc<RefCell<ConflictPrompt>>,
+}
+
+impl Debug for ConflictResolver {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConflictResolver").field("policy", &self.policy).finish()
+    }
+}
+
+impl Default for ConflictResolver {
+    fn default() -> Self {
+        ConflictResolver::new(ConflictPolicy::default())
+    }
+}
+
+impl ConflictResolver {
+    /// Create a resolver prompting on the terminal, if the policy requires it
+    pub fn new(policy: ConflictPolicy) -> Self {
+        let input = std::io::BufReader::new(std::io::stdin());
+        Self::with_io(policy, input, std::io::stderr())
+    }
+
+    /// Create a resolver reading the answers from `input` and writing the questions to `output`
+    pub fn with_io<R: BufRead + 'static, W: Write + 'static>(policy: ConflictPolicy, input: R, output: W) -> Self {
+        ConflictResolver {
+            policy,
+            prompt: Rc::new(RefCell::new(ConflictPrompt {
+                input: Box::new(input),
+                output: Box::new(output),
+                remembered: None,
+            })),
+        }
+    }
+
+    /// Decide what to do with the file whose destination already exists
+    ///
+    /// # Errors
+    /// - If the files can't be read, or the prompt fails
+    pub fn resolve(&self, src: &Path, dest: &Path) -> std::io::Result<Resolution> {
+        match self.policy {
+            ConflictPolicy::Overwrite => Ok(Resolution::Overwrite),
+            ConflictPolicy::Skip => Ok(Resolution::Skip),
+            ConflictPolicy::Prompt => self.prompt.borrow_mut().ask(src, dest),
+        }
+    }
+}
+
+/// Describe the file by its size, modification time and content hash
+fn describe(path: &Path) -> std::io::Result<String> {
+    let metadata = std::fs::metadata(path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    Ok(format!(
+        "{} bytes, modified {} (unix time), hash {:016x}",
+        metadata.len(),
+        modified,
+        content_hash(path)?
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    #[test]
+    fn prompt_remembers_apply_to_all() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let src = dir.path().join("new.jpg");
+        let dest = dir.path().join("old.jpg");
+        std::fs::write(&src, b"new")?;
+        std::fs::write(&dest, b"old")?;
+
+        let resolver = ConflictResolver::with_io(ConflictPolicy::Prompt, "x\ns\nl\n".as_bytes(), std::io::sink());
+        assert_eq!(resolver.resolve(&src, &dest)?, Resolution::Skip);
+        assert_eq!(resolver.resolve(&src, &dest)?, Resolution::Skip);
+        // The answer applies to all the remaining conflicts, so the input isn't read anymore
+        assert_eq!(resolver.clone().resolve(&src, &dest)?, Resolution::Skip);
+        assert_eq!(resolver.resolve(&src, &dest)?, Resolution::Skip);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+//! Module containing declarations related to finding byte-identical files
+//!
+//! Camera card recoveries often contain duplicate frames, which only waste the destination space.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+/// What to do with the duplicates of an already transferred file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DedupeMode {
+    /// Duplicates are not transferred, only reported
+    Skip,
+    /// Duplicates are hard linked to the transferred file in the destination directory
+    Hardlink,
+}
+
+/// Hash the contents of the file
+///
+/// The hash is only used to tell the files apart quickly, so it isn't cryptographically secure.
+pub fn content_hash(path: &Path) -> std::io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(hasher.finish()),
+            read => hasher.write(&buffer[..read]),
+        }
+    }
+}
+
+/// Check whether the files have the same contents, comparing them a chunk at a time
+fn same_contents(a: &Path, b: &Path) -> std::io::Result<bool> {
+    let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+    let (mut chunk_a, mut chunk_b) = (vec![0; 64 * 1024], vec![0; 64 * 1024]);
+    loop {
+        let read = fill(&mut a, &mut chunk_a)?;
+        if read != fill(&mut b, &mut chunk_b)? || chunk_a[..read] != chunk_b[..read] {
+            return Ok(false);
+        }
+        if read == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Read from the reader until the buffer is full or the reader ends
+///
+/// Returns the number of bytes read, which is less than the size of the buffer only at the end.
+fn fill<R: Read>(reader: &mut R, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Find the byte-identical files
+///
+/// Returns a map from every duplicate to the first of the files it is identical to.
+/// Only files of the same size are hashed, and files with the same hash are compared byte by byte.
+///
+/// # Errors
+/// - If any of the files can't be read
+pub fn find_duplicates(files: &[PathBuf]) -> std::io::Result<HashMap<PathBuf, PathBuf>> {
+    let mut by_size: HashMap<u64, Vec<&PathBuf>> = HashMap::new();
+    for file in files {
+        by_size.entry(std::fs::metadata(file)?.len()).or_default().push(file);
+    }
+
+    let mut duplicates = HashMap::new();
+    for group in by_size.into_values().filter(|group| group.len() > 1) {
+        let mut originals: HashMap<u64, Vec<&PathBuf>> = HashMap::new();
+        for file in group {
+            let candidates = originals.entry(content_hash(file)?).or_default();
+            let mut original = None;
+            for candidate in candidates.iter() {
+                if same_contents(candidate, file)? {
+                    original = Some(*candidate);
+                    break;
+                }
+            }
+            match original {
+                Some(original) => {
+                    duplicates.insert(file.clone(), original.clone());
+                }
+                None => candidates.push(file),
+            }
+        }
+    }
+    Ok(duplicates)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    #[test]
+    fn finds_identical_files() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let files: Vec<_> = ["IMG_0001.jpg", "IMG_0002.jpg", "IMG_0003.jpg", "IMG_0004.jpg"]
+            .into_iter()
+            .map(|name| dir.path().join(name))
+            .collect();
+        std::fs::write(&files[0], b"frame")?;
+        std::fs::write(&files[1], b"other")?;
+        std::fs::write(&files[2], b"frame")?;
+        std::fs::write(&files[3], b"frame")?;
+
+        let duplicates = find_duplicates(&files)?;
+        assert_eq!(duplicates.len(), 2);
+        assert_eq!(duplicates[&files[2]], files[0]);
+        assert_eq!(duplicates[&files[3]], files[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn compares_past_the_first_chunk() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let (a, b, c) = (dir.path().join("a"), dir.path().join("b"), dir.path().join("c"));
+        let content = vec![7; 64 * 1024 + 10];
+        std::fs::write(&a, &content)?;
+        std::fs::write(&b, &content)?;
+        let mut changed = content.clone();
+        *changed.last_mut().unwrap() = 8;
+        std::fs::write(&c, &changed)?;
+
+        assert!(same_contents(&a, &b)?);
+        assert!(!same_contents(&a, &c)?);
+
+        Ok(())
+    }
+}
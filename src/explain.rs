@@ -0,0 +1,70 @@
+//! Module containing the explanation of the filters a single file goes through ([Explanation])
+//!
+//! Every stage, from the directory scan to the keep file, reports whether it accepted the file and why,
+//! so a skipped file can be traced to the rule skipping it.
+
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+/// Outcome of one stage of the filters for a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    /// Name of the stage, like `extension` or `keep file`
+    pub stage: &'static str,
+    /// Whether the stage accepted the file
+    pub accepted: bool,
+    /// Why the stage accepted or rejected the file
+    pub reason: String,
+}
+
+impl Step {
+    /// Create the outcome of the stage
+    pub fn new(stage: &'static str, accepted: bool, reason: impl Into<String>) -> Self {
+        Step {
+            stage,
+            accepted,
+            reason: reason.into(),
+        }
+    }
+
+    /// Create the outcome of a stage accepting the file
+    pub fn accept(stage: &'static str, reason: impl Into<String>) -> Self {
+        Step::new(stage, true, reason)
+    }
+
+    /// Create the outcome of a stage rejecting the file
+    pub fn reject(stage: &'static str, reason: impl Into<String>) -> Self {
+        Step::new(stage, false, reason)
+    }
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let outcome = if self.accepted { "accepted" } else { "rejected" };
+        write!(f, "{}: {outcome}, {}", self.stage, self.reason)
+    }
+}
+
+/// The stages a file went through, and whether the action is applied to it
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    /// The explained file
+    pub file: PathBuf,
+    /// Outcomes of the stages, in the order they are applied
+    pub steps: Vec<Step>,
+    /// Whether the action is applied to the file
+    pub selected: bool,
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "\"{}\":", self.file.display())?;
+        for step in self.steps.iter() {
+            writeln!(f, "  {step}")?;
+        }
+        match self.selected {
+            true => writeln!(f, "The action is applied to the file"),
+            false => writeln!(f, "The file is left untouched"),
+        }
+    }
+}
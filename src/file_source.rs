@@ -1,10 +1,19 @@
 //! Module with declarations related to [FileSource] trait
 
+use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
 
+use clap::ValueEnum;
+use itertools::Itertools;
+
+use crate::config::DirPattern;
+use crate::explain::Step;
+use crate::output::Terminator;
 use crate::SelectedDirectory;
 
 /// Files selected from a directory
@@ -14,13 +23,592 @@ pub struct SelectedFiles {
     pub dir: SelectedDirectory,
     /// Selected files' paths
     pub files: Vec<PathBuf>,
+    /// Paths the files were found at relative to the directory, if they differ from the selected paths
+    pub walk_paths: HashMap<PathBuf, PathBuf>,
+    /// Paths the search couldn't read, whose files may be missing
+    pub scan_errors: Vec<ScanError>,
+}
+
+/// Limits of the search for files in a directory
+///
+/// The depth of a file is the number of subdirectories it is nested in, so the files directly
+/// in the searched directory have the depth 0.
+#[derive(Debug, Clone, Default)]
+pub struct ScanLimits {
+    /// Subdirectories that aren't searched
+    pub exclude_dirs: Vec<DirPattern>,
+    /// Search the hidden files and directories, whose names start with a dot
+    pub include_hidden: bool,
+    /// Don't search the subdirectories on other filesystems than the searched directory
+    pub one_file_system: bool,
+    /// Smallest depth of the found files
+    pub min_depth: Option<usize>,
+    /// Largest depth of the found files
+    pub max_depth: Option<usize>,
+}
+
+impl ScanLimits {
+    /// Check if a subdirectory, found at the `relative` path at the `depth`, should be searched
+    pub fn allows_dir(&self, relative: &Path, depth: usize) -> bool {
+        // The files in the subdirectory are one level deeper
+        self.max_depth.is_none_or(|max_depth| depth < max_depth)
+            && self.allows_hidden(relative)
+            && !self.exclude_dirs.iter().any(|pattern| pattern.matches(relative))
+    }
+
+    /// Check if a file, found at the `path` at the `depth`, should be included
+    pub fn allows_file(&self, path: &Path, depth: usize) -> bool {
+        self.min_depth.is_none_or(|min_depth| depth >= min_depth) && self.allows_hidden(path)
+    }
+
+    /// Explain whether the file, at the `relative` path, is found by the search, and if it isn't, why
+    pub fn explain(&self, relative: &Path) -> Step {
+        let dirs = relative
+            .ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect_vec();
+        for (depth, dir) in dirs.iter().rev().enumerate() {
+            if self.allows_dir(dir, depth) {
+                continue;
+            }
+            let reason = match self.exclude_dirs.iter().find(|pattern| pattern.matches(dir)) {
+                _ if !self.allows_hidden(dir) => format!("the directory \"{}\" is hidden", dir.display()),
+                Some(pattern) => {
+                    format!(
+                        "the directory \"{}\" matches the excluded directories {pattern}",
+                        dir.display()
+                    )
+                }
+                None => format!("the directory \"{}\" is deeper than the maximum depth", dir.display()),
+            };
+            return Step::reject("scan", reason);
+        }
+        match (self.allows_hidden(relative), self.allows_file(relative, dirs.len())) {
+            (false, _) => Step::reject("scan", "the file is hidden"),
+            (_, false) => Step::reject("scan", format!("the depth {} is below the minimum depth", dirs.len())),
+            _ => Step::accept("scan", format!("found at the depth {}", dirs.len())),
+        }
+    }
+
+    /// Check if the entry isn't hidden, or the hidden entries are included
+    fn allows_hidden(&self, path: &Path) -> bool {
+        self.include_hidden || !path.file_name().is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+    }
+
+    /// Check if a file, at the `relative` path, is found by the search
+    pub fn allows_path(&self, relative: &Path) -> bool {
+        let dirs: Vec<_> = relative.ancestors().skip(1).filter(|dir| !dir.as_os_str().is_empty()).collect();
+        dirs.iter().rev().enumerate().all(|(depth, dir)| self.allows_dir(dir, depth))
+            && self.allows_file(relative, dirs.len())
+    }
+
+    /// Get the device of the searched `root` directory the search has to stay on, if it has to stay on one
+    fn root_device(&self, root: &Path) -> Option<u64> {
+        self.one_file_system.then(|| device_of(root)).flatten()
+    }
+}
+
+/// Get the ID of the device the path is on
+///
+/// The devices are only known on Unix, so the search never leaves a filesystem elsewhere.
+#[cfg(unix)]
+fn device_of(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    path.metadata().ok().map(|metadata| metadata.dev())
+}
+
+/// Get the ID of the device the path is on, which isn't known outside of Unix
+#[cfg(not(unix))]
+fn device_of(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Check if the directory is on another device than the `root_device`, if the search has to stay on it
+fn leaves_device(root_device: Option<u64>, dir: &Path) -> bool {
+    root_device.is_some_and(|root_device| device_of(dir) != Some(root_device))
+}
+
+/// Get the names the files of each of the searched directories are put under at the destination
+///
+/// A directory is named after its last component, followed by a number if an earlier directory
+/// has the same name, so `card` and `backup/card` are named `card` and `card-2`.
+pub fn root_names(roots: &[SelectedDirectory]) -> Vec<PathBuf> {
+    let mut names: Vec<PathBuf> = Vec::with_capacity(roots.len());
+    for root in roots {
+        let base = root.as_ref().file_name().unwrap_or("root".as_ref());
+        let mut name = PathBuf::from(base);
+        for number in 2.. {
+            if !names.contains(&name) {
+                break;
+            }
+            let mut numbered = base.to_os_string();
+            numbered.push(format!("-{number}"));
+            name = PathBuf::from(numbered);
+        }
+        names.push(name);
+    }
+    names
+}
+
+/// Parse a list of files, each one ended by the `terminator`
+///
+/// Empty entries are skipped, so the list can end with a terminator, like the output of `find -print0`.
+///
+/// # Errors
+/// - If the list isn't valid UTF-8
+pub fn parse_file_list(content: &[u8], terminator: Terminator) -> std::io::Result<Vec<PathBuf>> {
+    let content = std::str::from_utf8(content).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    Ok(terminator.split(content).map(PathBuf::from).collect())
+}
+
+/// Order of the found files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ScanOrder {
+    /// Lexicographic order of the paths, comparing one path component at a time
+    #[default]
+    Path,
+    /// Lexicographic order of the file names, then of the paths
+    Name,
+    /// Oldest modification time first, then the order of the paths
+    Mtime,
+    /// Smallest size first, then the order of the paths
+    Size,
+}
+
+impl ScanOrder {
+    /// Sort the files in the order
+    ///
+    /// Files whose metadata can't be read are sorted as if they were the oldest and the smallest.
+    pub fn sort(&self, files: &mut [PathBuf]) {
+        // Sorting by the paths first makes the order of the ties deterministic
+        files.sort();
+        match self {
+            ScanOrder::Path => {}
+            ScanOrder::Name => files.sort_by(|a, b| a.file_name().cmp(&b.file_name())),
+            ScanOrder::Mtime => files.sort_by_cached_key(|file| file.metadata().and_then(|m| m.modified()).ok()),
+            ScanOrder::Size => files.sort_by_cached_key(|file| file.metadata().map(|m| m.len()).ok()),
+        }
+    }
+}
+
+/// A path the search couldn't read, or skipped as it leads to a file found at another path,
+/// reported instead of stopping the search
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("\"{}\": {message}", path.display())]
+pub struct ScanError {
+    /// The directory that couldn't be read, the file that couldn't be canonicalized, or the skipped path
+    pub path: PathBuf,
+    /// Why the path couldn't be read
+    pub message: String,
+}
+
+impl ScanError {
+    /// Create the error reading the `path`
+    pub fn new<P: AsRef<Path>>(path: P, error: &std::io::Error) -> Self {
+        ScanError {
+            path: path.as_ref().to_path_buf(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// A file found by the [Walk]
+#[derive(Debug, Clone)]
+pub struct WalkedFile {
+    /// Path of the file, canonical unless the walk keeps the paths the files were found at
+    pub path: PathBuf,
+    /// Path the file was found at relative to the directory, if it differs from `path`
+    pub walk_path: Option<PathBuf>,
+}
+
+/// Lazy search of the files in a directory and its subdirectories, within the limits
+///
+/// Subdirectories are only read once the walk reaches them, so the files are produced while
+/// the directory tree is searched, instead of after all of it was read.
+/// The entries of every directory are visited in the order of their names, so the files are found
+/// in the [ScanOrder::Path] order of the paths they are found at.
+/// Subdirectories outside the `limits`, excluded or too deep, are skipped without being read.
+/// The paths that can't be read are produced as errors, and the walk goes on with the next entries.
+#[derive(Debug)]
+pub struct Walk {
+    /// The searched directory
+    root: PathBuf,
+    /// Should the paths of the found files be canonicalized?
+    canonicalize: bool,
+    /// Limits of the search
+    limits: ScanLimits,
+    /// Device of the searched directory, if the search stays on its filesystem
+    device: Option<u64>,
+    /// Entries left to visit, with their depth, the next one last
+    stack: Vec<(std::fs::DirEntry, usize)>,
+    /// Errors reading the entries of the visited directories, not produced yet
+    errors: Vec<ScanError>,
+}
+
+/// Read the entries of the directory, in the reverse order of their names, so they are popped in order,
+/// and the errors reading some of them
+fn reversed_entries(dir: &Path) -> std::io::Result<(Vec<std::fs::DirEntry>, Vec<ScanError>)> {
+    let (mut entries, errors): (Vec<_>, Vec<_>) = dir.read_dir()?.partition_result();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.file_name()));
+    Ok((entries, errors.iter().map(|e| ScanError::new(dir, e)).collect()))
+}
+
+impl Walk {
+    /// Start the search of the directory, reading only its own entries
+    ///
+    /// If `canonicalize` is false, files are only joined to the directory path,
+    /// which avoids a few system calls per file on network filesystems.
+    ///
+    /// # Errors
+    /// - If the directory can't be read
+    pub fn new(root: &Path, canonicalize: bool, limits: ScanLimits) -> std::io::Result<Self> {
+        let (entries, errors) = reversed_entries(root)?;
+        Ok(Walk {
+            root: root.to_path_buf(),
+            canonicalize,
+            device: limits.root_device(root),
+            limits,
+            stack: entries.into_iter().map(|entry| (entry, 0)).collect(),
+            errors,
+        })
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<WalkedFile, ScanError>;
+
+    /// Find the next file, reading the subdirectories on the way
+    ///
+    /// An error is returned if a subdirectory or some of its entries can't be read, or a path can't be canonicalized.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(error) = self.errors.pop() {
+                return Some(Err(error));
+            }
+            let (entry, depth) = self.stack.pop()?;
+            let path = entry.path();
+            if path.is_dir() {
+                let relative = path.strip_prefix(&self.root).map(Path::to_path_buf).unwrap_or(path.clone());
+                if !self.limits.allows_dir(&relative, depth) || leaves_device(self.device, &path) {
+                    continue;
+                }
+                // If the entry is a directory, add its contents to the stack
+                match reversed_entries(&path) {
+                    Ok((entries, errors)) => {
+                        self.stack.extend(entries.into_iter().map(|entry| (entry, depth + 1)));
+                        self.errors.extend(errors);
+                    }
+                    Err(e) => return Some(Err(ScanError::new(path, &e))),
+                }
+            } else if self.limits.allows_file(&path, depth) {
+                return Some(WalkedFile::found(&self.root, path, self.canonicalize));
+            }
+        }
+    }
+}
+
+impl WalkedFile {
+    /// Create the found file from the `path` it was found at in the `root` directory
+    ///
+    /// # Errors
+    /// - If `canonicalize` is set, and the path can't be canonicalized
+    fn found(root: &Path, path: PathBuf, canonicalize: bool) -> Result<Self, ScanError> {
+        if !canonicalize {
+            return Ok(WalkedFile { path, walk_path: None });
+        }
+        let file = path.canonicalize().map_err(|e| ScanError::new(&path, &e))?;
+        let walk_path = match file != path {
+            true => path.strip_prefix(root).ok().map(Path::to_path_buf),
+            false => None,
+        };
+        Ok(WalkedFile { path: file, walk_path })
+    }
+}
+
+/// Search of the files in a directory, with several threads reading the subdirectories at once
+///
+/// Like the [Walk], the files are produced while the directory tree is searched, within the limits,
+/// but the order they are found in changes from one search to another, so they have to be collected and
+/// sorted for a deterministic order.
+/// The paths that can't be read are produced as errors, and the threads only stop once the search is dropped.
+#[derive(Debug)]
+pub struct ParallelWalk {
+    /// Files found by the threads
+    found: Receiver<Result<WalkedFile, ScanError>>,
+}
+
+/// State of the [ParallelWalk] shared by its threads
+#[derive(Debug)]
+struct SharedWalk {
+    /// The searched directory
+    root: PathBuf,
+    /// Should the paths of the found files be canonicalized?
+    canonicalize: bool,
+    /// Limits of the search
+    limits: ScanLimits,
+    /// Device of the searched directory, if the search stays on its filesystem
+    device: Option<u64>,
+    /// Directories left to read
+    pending: Mutex<PendingDirs>,
+    /// Signals the threads waiting for a directory to read
+    ready: Condvar,
+}
+
+/// Directories left to read by the [ParallelWalk]
+#[derive(Debug, Default)]
+struct PendingDirs {
+    /// Directories left to read, with the depth of their entries
+    dirs: Vec<(PathBuf, usize)>,
+    /// Number of directories being read, which may add more directories
+    busy: usize,
+    /// Was the search dropped?
+    stopped: bool,
+}
+
+impl ParallelWalk {
+    /// Start the search of the directory with `threads` threads
+    ///
+    /// See [Walk::new] for the meaning of `canonicalize`.
+    ///
+    /// # Errors
+    /// - If the directory can't be read
+    pub fn new(root: &Path, canonicalize: bool, limits: ScanLimits, threads: usize) -> std::io::Result<Self> {
+        // Reported right away, like by the single-threaded walk
+        root.read_dir()?;
+        let shared = Arc::new(SharedWalk {
+            root: root.to_path_buf(),
+            canonicalize,
+            device: limits.root_device(root),
+            limits,
+            pending: Mutex::new(PendingDirs {
+                dirs: vec![(root.to_path_buf(), 0)],
+                ..PendingDirs::default()
+            }),
+            ready: Condvar::new(),
+        });
+        let (sender, found) = std::sync::mpsc::channel();
+        for _ in 0..threads.max(1) {
+            let (shared, sender) = (shared.clone(), sender.clone());
+            std::thread::spawn(move || shared.work(&sender));
+        }
+        Ok(ParallelWalk { found })
+    }
+}
+
+impl Iterator for ParallelWalk {
+    type Item = Result<WalkedFile, ScanError>;
+
+    /// Wait for the next file found by the threads
+    fn next(&mut self) -> Option<Self::Item> {
+        self.found.recv().ok()
+    }
+}
+
+impl SharedWalk {
+    /// Read the pending directories until all of them are read, or the search is dropped
+    fn work(&self, sender: &Sender<Result<WalkedFile, ScanError>>) {
+        while let Some((dir, depth)) = self.next_dir() {
+            let mut dirs = Vec::new();
+            // Sending fails once the search is dropped
+            let sent = match dir.read_dir() {
+                Ok(mut entries) => entries.all(|entry| match entry {
+                    Ok(entry) => self.visit(entry.path(), depth, &mut dirs, sender),
+                    Err(e) => sender.send(Err(ScanError::new(&dir, &e))).is_ok(),
+                }),
+                Err(e) => sender.send(Err(ScanError::new(&dir, &e))).is_ok(),
+            };
+            self.finish_dir(dirs, !sent);
+        }
+    }
+
+    /// Queue the subdirectory, or send the file, returning false if the search was dropped
+    fn visit(
+        &self,
+        path: PathBuf,
+        depth: usize,
+        dirs: &mut Vec<(PathBuf, usize)>,
+        sender: &Sender<Result<WalkedFile, ScanError>>,
+    ) -> bool {
+        if path.is_dir() {
+            let relative = path.strip_prefix(&self.root).unwrap_or(&path);
+            if self.limits.allows_dir(relative, depth) && !leaves_device(self.device, &path) {
+                dirs.push((path, depth + 1));
+            }
+            true
+        } else if self.limits.allows_file(&path, depth) {
+            sender.send(WalkedFile::found(&self.root, path, self.canonicalize)).is_ok()
+        } else {
+            true
+        }
+    }
+
+    /// Wait for a directory to read, or return `None` once there are none left
+    fn next_dir(&self) -> Option<(PathBuf, usize)> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if pending.stopped {
+                return None;
+            }
+            if let Some(dir) = pending.dirs.pop() {
+                pending.busy += 1;
+                return Some(dir);
+            }
+            // The directories being read may still add more
+            if pending.busy == 0 {
+                return None;
+            }
+            pending = self.ready.wait(pending).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Queue the subdirectories found in a read directory, and wake up the waiting threads
+    fn finish_dir(&self, dirs: Vec<(PathBuf, usize)>, stopped: bool) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.dirs.extend(dirs);
+        pending.busy -= 1;
+        pending.stopped |= stopped;
+        self.ready.notify_all();
+    }
 }
 
 impl TryFrom<SelectedDirectory> for SelectedFiles {
     type Error = std::io::Error;
     fn try_from(selected: SelectedDirectory) -> Result<Self, Self::Error> {
-        let files = selected.read_recursive_path()?;
-        Ok(SelectedFiles { dir: selected, files })
+        SelectedFiles::scan(selected, true)
+    }
+}
+
+impl SelectedFiles {
+    /// Find the files in the directory and its subdirectories
+    ///
+    /// If `canonicalize` is false, only the directory path is canonical,
+    /// and files keep their paths relative to it, even if they are symbolic links.
+    pub fn scan(selected: SelectedDirectory, canonicalize: bool) -> std::io::Result<Self> {
+        SelectedFiles::scan_limited(selected, canonicalize, &ScanLimits::default())
+    }
+
+    /// Find the files in the directory and its subdirectories, within the limits
+    ///
+    /// The subdirectories outside the limits are not read at all, see [SelectedFiles::scan].
+    pub fn scan_limited(selected: SelectedDirectory, canonicalize: bool, limits: &ScanLimits) -> std::io::Result<Self> {
+        SelectedFiles::scan_threaded(selected, canonicalize, limits, 1)
+    }
+
+    /// Find the files in the directory and its subdirectories, within the limits, with `threads` threads
+    ///
+    /// The files are sorted in the [ScanOrder::Path] order, no matter how many threads found them,
+    /// see [SelectedFiles::scan_limited].
+    pub fn scan_threaded(
+        selected: SelectedDirectory,
+        canonicalize: bool,
+        limits: &ScanLimits,
+        threads: usize,
+    ) -> std::io::Result<Self> {
+        let (mut files, walk_paths, scan_errors) = selected.read_recursive_path(canonicalize, limits, threads)?;
+        ScanOrder::Path.sort(&mut files);
+        Ok(SelectedFiles {
+            dir: selected,
+            files,
+            walk_paths,
+            scan_errors,
+        })
+    }
+
+    /// Select the listed files, instead of searching the directory, or the `others` directories
+    ///
+    /// Relative paths are relative to the current directory. The files are canonicalized, deduplicated and sorted
+    /// in the [ScanOrder::Path] order, and the symbolic links are mapped to the paths they were listed at,
+    /// like the ones found by the search. With `others` directories, the files are mapped to their paths
+    /// under the name of their directory, like [SelectedFiles::merge] does. The limits of the search don't apply
+    /// to the listed files.
+    ///
+    /// # Errors
+    /// - If a listed file doesn't exist, or isn't a file
+    /// - If a listed file isn't inside any of the directories
+    pub fn listed(
+        selected: SelectedDirectory,
+        others: &[SelectedDirectory],
+        listed: &[PathBuf],
+    ) -> std::io::Result<Self> {
+        use std::io::{Error, ErrorKind::InvalidInput};
+        let mut files = Vec::with_capacity(listed.len());
+        let mut walk_paths = HashMap::new();
+        let roots = std::iter::once(&selected).chain(others).cloned().collect_vec();
+        let names = root_names(&roots);
+        for path in listed {
+            let invalid = |reason: String| Error::new(InvalidInput, format!("\"{}\" {reason}", path.display()));
+            let found = std::path::absolute(path)?;
+            let file = found.canonicalize().map_err(|e| invalid(format!("can't be selected: {e}")))?;
+            if !file.is_file() {
+                return Err(invalid("isn't a file".to_owned()));
+            }
+            let Some(root) = roots.iter().position(|root| file.starts_with(root)) else {
+                let roots = roots.iter().map(|root| format!("\"{}\"", root.as_ref().display())).join(" or ");
+                return Err(invalid(format!("isn't inside {roots}")));
+            };
+            // Symbolic links are mapped to the path they were listed at
+            let (root, listed_at) = match roots.iter().position(|root| found.starts_with(root)) {
+                Some(listed_in) if found != file => (listed_in, &found),
+                _ => (root, &file),
+            };
+            let relative = listed_at.strip_prefix(&roots[root]).unwrap_or(listed_at);
+            if !others.is_empty() {
+                walk_paths.insert(file.clone(), names[root].join(relative));
+            } else if found != file {
+                walk_paths.insert(file.clone(), relative.to_path_buf());
+            }
+            files.push(file);
+        }
+        ScanOrder::Path.sort(&mut files);
+        files.dedup();
+        Ok(SelectedFiles {
+            dir: selected,
+            files,
+            walk_paths,
+            scan_errors: vec![],
+        })
+    }
+
+    /// Add the files found in other directories, keeping the directory of each file
+    ///
+    /// The files of every directory are mapped to their paths relative to it, under the name of the directory,
+    /// see [root_names], so the same paths in different directories don't end up at the same destination.
+    /// The files are deduplicated and sorted in the [ScanOrder::Path] order, and the errors of all the searches
+    /// are kept. Without other directories, the files are returned as they are.
+    pub fn merge(self, others: Vec<SelectedFiles>) -> Self {
+        if others.is_empty() {
+            return self;
+        }
+        let dirs = std::iter::once(&self.dir)
+            .chain(others.iter().map(|other| &other.dir))
+            .cloned()
+            .collect_vec();
+        let mut merged = SelectedFiles {
+            dir: self.dir.clone(),
+            files: vec![],
+            walk_paths: HashMap::new(),
+            scan_errors: vec![],
+        };
+        for (found, name) in std::iter::once(self).chain(others).zip(root_names(&dirs)) {
+            merged.scan_errors.extend(found.scan_errors);
+            for file in found.files {
+                let walk_path = found.walk_paths.get(&file).map(PathBuf::as_path);
+                if let Some(walk_path) = walk_path.or_else(|| file.strip_prefix(&found.dir).ok()) {
+                    merged.walk_paths.entry(file.clone()).or_insert_with(|| name.join(walk_path));
+                }
+                merged.files.push(file);
+            }
+        }
+        ScanOrder::Path.sort(&mut merged.files);
+        merged.files.dedup();
+        merged
+    }
+
+    /// Sort the files in the `order`
+    pub fn sorted(mut self, order: ScanOrder) -> Self {
+        order.sort(&mut self.files);
+        self
     }
 }
 
@@ -28,6 +616,12 @@ pub trait FileSource: Debug {
     /// Get the path of the directory files are located in
     fn dir(&self) -> &Path;
 
+    /// Get the paths the files were found at relative to the directory,
+    /// for the files whose paths differ from them
+    fn walk_paths(&self) -> Option<&HashMap<PathBuf, PathBuf>> {
+        None
+    }
+
     /// Get an iterator over the files in the source
     fn iter(&self) -> impl Iterator<Item = &PathBuf> + Clone;
 
@@ -57,6 +651,10 @@ impl FileSource for SelectedFiles {
         &self.dir.0
     }
 
+    fn walk_paths(&self) -> Option<&HashMap<PathBuf, PathBuf>> {
+        Some(&self.walk_paths)
+    }
+
     fn iter(&self) -> impl Iterator<Item = &PathBuf> + Clone {
         self.files.iter()
     }
@@ -87,6 +685,9 @@ impl<F: FileSource> FileSource for FilteredFiles<F> {
     fn dir(&self) -> &Path {
         self.source.dir()
     }
+    fn walk_paths(&self) -> Option<&HashMap<PathBuf, PathBuf>> {
+        self.source.walk_paths()
+    }
     fn iter(&self) -> impl Iterator<Item = &PathBuf> + Clone {
         self.source.iter().filter(self.matcher.deref())
     }
@@ -100,9 +701,11 @@ impl<F: FileSource> FilteredFiles<F> {
 
 #[cfg(test)]
 mod test {
+    use itertools::Itertools;
+
     use super::*;
     use crate::test_utils::*;
-    
+
     #[test]
     fn test_selected_directory() -> TestResult {
         let selected = SelectedDirectory::try_from(resource_dir())?;
@@ -125,6 +728,391 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_selected_files_without_canonicalization() -> TestResult {
+        let selected = SelectedDirectory::try_from(resource_dir())?;
+        let canonical = SelectedFiles::try_from(selected.clone())?;
+        let walked = SelectedFiles::scan(selected, false)?;
+
+        assert_eq!(walked.files.len(), canonical.files.len());
+        for file in walked.files.iter() {
+            assert!(file.starts_with(resource_dir()));
+            assert!(canonical.files.contains(file));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_selected_files_within_limits() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let files = [
+            "IMG_1.jpg",
+            "backups/IMG_2.jpg",
+            "shoot/.thumbnails/IMG_3.jpg",
+            "shoot/IMG_4.jpg",
+            "exports/2023-06/IMG_5.jpg",
+        ];
+        for file in files {
+            std::fs::create_dir_all(dir.path().join(file).parent().unwrap())?;
+            std::fs::write(dir.path().join(file), b"content")?;
+        }
+        let names = |limits: ScanLimits| -> std::io::Result<Vec<String>> {
+            let selected = SelectedDirectory::try_from(dir.path().to_path_buf())?;
+            let files = SelectedFiles::scan_limited(selected, true, &limits)?;
+            let names = files.iter().filter_map(|file| Some(file.file_name()?.to_str()?.to_owned()));
+            Ok(names.sorted_unstable().collect())
+        };
+
+        let exclude_dirs = vec!["backups/".parse()?, ".thumb*".parse()?, "exports/2023-*".parse()?];
+        let limits = ScanLimits {
+            exclude_dirs,
+            include_hidden: true,
+            ..ScanLimits::default()
+        };
+        assert_eq!(names(limits)?, ["IMG_1.jpg", "IMG_4.jpg"]);
+
+        std::fs::write(dir.path().join(".DS_Store"), b"content")?;
+        assert_eq!(
+            names(ScanLimits::default())?,
+            ["IMG_1.jpg", "IMG_2.jpg", "IMG_4.jpg", "IMG_5.jpg"]
+        );
+        let limits = ScanLimits {
+            include_hidden: true,
+            ..ScanLimits::default()
+        };
+        assert_eq!(
+            names(limits)?,
+            [
+                ".DS_Store",
+                "IMG_1.jpg",
+                "IMG_2.jpg",
+                "IMG_3.jpg",
+                "IMG_4.jpg",
+                "IMG_5.jpg"
+            ]
+        );
+
+        let limits = ScanLimits {
+            max_depth: Some(0),
+            ..ScanLimits::default()
+        };
+        assert_eq!(names(limits)?, ["IMG_1.jpg"]);
+        let limits = ScanLimits {
+            min_depth: Some(1),
+            max_depth: Some(1),
+            ..ScanLimits::default()
+        };
+        assert_eq!(names(limits)?, ["IMG_2.jpg", "IMG_4.jpg"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_walk_finds_same_files() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        for day in 1..=8 {
+            for shot in 1..=5 {
+                let file = dir.path().join(format!("shoot/day{day}/raw/IMG_{day}{shot}.jpg"));
+                std::fs::create_dir_all(file.parent().unwrap())?;
+                std::fs::write(file, b"content")?;
+            }
+        }
+        std::fs::create_dir_all(dir.path().join("shoot/.thumbnails"))?;
+        std::fs::write(dir.path().join("shoot/.thumbnails/IMG_11.jpg"), b"content")?;
+        let selected = SelectedDirectory::try_from(dir.path().to_path_buf())?;
+        let scan = |limits: &ScanLimits, threads| -> std::io::Result<Vec<PathBuf>> {
+            let files = SelectedFiles::scan_threaded(selected.clone(), true, limits, threads)?;
+            Ok(files.files.into_iter().sorted_unstable().collect())
+        };
+
+        let limits = ScanLimits::default();
+        let found = scan(&limits, 4)?;
+        assert_eq!(found.len(), 40);
+        assert_eq!(found, scan(&limits, 1)?);
+
+        let limits = ScanLimits {
+            exclude_dirs: vec!["day3/".parse()?],
+            include_hidden: true,
+            max_depth: Some(2),
+            ..ScanLimits::default()
+        };
+        let found = scan(&limits, 3)?;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found, scan(&limits, 1)?);
+
+        let missing = SelectedDirectory(dir.path().join("missing"));
+        assert!(missing.walk(true, &ScanLimits::default(), 4).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_order() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let files = [
+            ("b/IMG_1.jpg", 30),
+            ("a.jpg", 10),
+            ("a/IMG_2.jpg", 20),
+            ("c.jpg", 20),
+            ("b/a.jpg", 5),
+        ];
+        for (file, size) in files {
+            std::fs::create_dir_all(dir.path().join(file).parent().unwrap())?;
+            std::fs::write(dir.path().join(file), vec![0; size])?;
+        }
+        let selected = SelectedDirectory::try_from(dir.path().to_path_buf())?;
+        let relative = |files: &[PathBuf]| {
+            files
+                .iter()
+                .map(|file| file.strip_prefix(&selected).unwrap().to_str().unwrap().to_owned())
+                .collect_vec()
+        };
+
+        // The walk finds the files of a directory before the ones of the next entry
+        let found: Vec<_> = selected
+            .walk(false, &ScanLimits::default(), 1)?
+            .map_ok(|file| file.path)
+            .try_collect()?;
+        assert_eq!(
+            relative(&found),
+            ["a/IMG_2.jpg", "a.jpg", "b/IMG_1.jpg", "b/a.jpg", "c.jpg"]
+        );
+        let files = SelectedFiles::scan_threaded(selected.clone(), false, &ScanLimits::default(), 4)?;
+        assert_eq!(files.files, found);
+
+        let sorted = |order| relative(&files.clone().sorted(order).files);
+        assert_eq!(
+            sorted(ScanOrder::Name),
+            ["b/IMG_1.jpg", "a/IMG_2.jpg", "a.jpg", "b/a.jpg", "c.jpg"]
+        );
+        assert_eq!(
+            sorted(ScanOrder::Size),
+            ["b/a.jpg", "a.jpg", "a/IMG_2.jpg", "c.jpg", "b/IMG_1.jpg"]
+        );
+        assert_eq!(sorted(ScanOrder::Path), relative(&found));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_listed_files() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir_all(dir.path().join("shoot/.thumbnails"))?;
+        for file in ["IMG_2.jpg", "shoot/IMG_1.jpg", "shoot/.thumbnails/IMG_1.jpg"] {
+            std::fs::write(dir.path().join(file), b"content")?;
+        }
+        let selected = SelectedDirectory::try_from(dir.path().to_path_buf())?;
+
+        let root = selected.as_ref().display();
+        let list = format!("{root}/shoot/IMG_1.jpg\r\n{root}/IMG_2.jpg\n\n{root}/shoot/IMG_1.jpg\n");
+        let listed = parse_file_list(list.as_bytes(), Terminator::Newline)?;
+        assert_eq!(listed.len(), 3);
+        let files = SelectedFiles::listed(selected.clone(), &[], &listed)?;
+        assert_eq!(
+            files.files,
+            [
+                selected.as_ref().join("IMG_2.jpg"),
+                selected.as_ref().join("shoot/IMG_1.jpg")
+            ]
+        );
+
+        // Listed files aren't limited like the searched ones
+        std::fs::write(dir.path().join("shoot/.thumbnails/IMG\n1.jpg"), b"content")?;
+        let list = format!("{root}/shoot/.thumbnails/IMG_1.jpg\0{root}/shoot/.thumbnails/IMG\n1.jpg\0");
+        let files = SelectedFiles::listed(
+            selected.clone(),
+            &[],
+            &parse_file_list(list.as_bytes(), Terminator::Nul)?,
+        )?;
+        assert_eq!(files.files.len(), 2);
+
+        let outside = tempfile::NamedTempFile::new()?;
+        assert!(SelectedFiles::listed(selected.clone(), &[], &[outside.path().to_path_buf()]).is_err());
+        assert!(SelectedFiles::listed(selected.clone(), &[], &[dir.path().join("missing.jpg")]).is_err());
+        assert!(SelectedFiles::listed(selected, &[], &[dir.path().join("shoot")]).is_err());
+        assert!(parse_file_list(b"\xff\n", Terminator::Newline).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merged_files() -> TestResult {
+        let (first, second) = (tempfile::tempdir()?, tempfile::tempdir()?);
+        for dir in [&first, &second] {
+            std::fs::create_dir_all(dir.path().join("DCIM"))?;
+        }
+        std::fs::write(first.path().join("DCIM/IMG_1.jpg"), b"content")?;
+        std::fs::write(second.path().join("DCIM/IMG_2.jpg"), b"content")?;
+        let first = SelectedDirectory::try_from(first.path().to_path_buf())?;
+        let second = SelectedDirectory::try_from(second.path().to_path_buf())?;
+
+        let files = SelectedFiles::scan(first.clone(), true)?;
+        let merged = files.merge(vec![SelectedFiles::scan(second.clone(), true)?]);
+        assert_eq!(merged.dir, first);
+        assert_eq!(merged.files.len(), 2);
+        // The files of each directory keep their structure, under the name of the directory
+        let [first_name, second_name] = [&first, &second].map(|dir| PathBuf::from(dir.as_ref().file_name().unwrap()));
+        let second_file = second.as_ref().join("DCIM/IMG_2.jpg");
+        assert_eq!(
+            merged.walk_paths.get(&second_file),
+            Some(&second_name.join("DCIM/IMG_2.jpg"))
+        );
+        let first_file = first.as_ref().join("DCIM/IMG_1.jpg");
+        assert_eq!(
+            merged.walk_paths.get(&first_file),
+            Some(&first_name.join("DCIM/IMG_1.jpg"))
+        );
+
+        let listed = SelectedFiles::listed(first.clone(), &[second], std::slice::from_ref(&second_file))?;
+        assert_eq!(listed.files, std::slice::from_ref(&second_file));
+        assert_eq!(
+            listed.walk_paths.get(&second_file),
+            Some(&second_name.join("DCIM/IMG_2.jpg"))
+        );
+        assert!(SelectedFiles::listed(first, &[], &[second_file]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merged_same_paths() -> TestResult {
+        let base = tempfile::tempdir()?;
+        let cards = ["card", "backup/card"].map(|card| base.path().join(card));
+        for card in &cards {
+            std::fs::create_dir_all(card.join("DCIM"))?;
+            std::fs::write(card.join("DCIM/IMG_0001.jpg"), b"content")?;
+        }
+        let [first, second] = cards.map(SelectedDirectory::try_from);
+        let (first, second) = (first?, second?);
+        assert_eq!(
+            root_names(&[first.clone(), second.clone()]),
+            [PathBuf::from("card"), PathBuf::from("card-2")]
+        );
+
+        let merged = SelectedFiles::scan(first.clone(), true)?.merge(vec![SelectedFiles::scan(second.clone(), true)?]);
+        assert_eq!(merged.files.len(), 2);
+        // The same path in both directories is mapped to different destinations
+        let walk_paths = merged
+            .files
+            .iter()
+            .filter_map(|file| merged.walk_paths.get(file))
+            .sorted()
+            .collect_vec();
+        let expected = [
+            PathBuf::from("card/DCIM/IMG_0001.jpg"),
+            PathBuf::from("card-2/DCIM/IMG_0001.jpg"),
+        ];
+        assert_eq!(walk_paths, expected.iter().collect_vec());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_one_file_system() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir_all(dir.path().join("shoot/day1"))?;
+        std::fs::write(dir.path().join("shoot/day1/IMG_1.jpg"), b"content")?;
+        let selected = SelectedDirectory::try_from(dir.path().to_path_buf())?;
+        let limits = ScanLimits {
+            one_file_system: true,
+            ..ScanLimits::default()
+        };
+        assert_eq!(
+            SelectedFiles::scan_limited(selected.clone(), true, &limits)?.files.len(),
+            1
+        );
+        assert_eq!(
+            SelectedFiles::scan_threaded(selected.clone(), true, &limits, 2)?.files.len(),
+            1
+        );
+
+        let device = limits.root_device(selected.as_ref());
+        assert_eq!(device.is_some(), cfg!(unix));
+        assert!(!leaves_device(device, &dir.path().join("shoot")));
+        assert!(!leaves_device(None, &dir.path().join("shoot")));
+        if let Some(device) = device {
+            assert!(leaves_device(Some(device.wrapping_add(1)), &dir.path().join("shoot")));
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_continues_after_errors() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir_all(dir.path().join("shoot"))?;
+        std::fs::write(dir.path().join("IMG_1.jpg"), b"content")?;
+        std::fs::write(dir.path().join("shoot/IMG_2.jpg"), b"content")?;
+        // The link can't be canonicalized, as the file it points to is missing
+        let broken = dir.path().join("IMG_0.jpg");
+        std::os::unix::fs::symlink(dir.path().join("missing.jpg"), &broken)?;
+        let selected = SelectedDirectory::try_from(dir.path().to_path_buf())?;
+
+        for threads in [1, 4] {
+            let files = SelectedFiles::scan_threaded(selected.clone(), true, &ScanLimits::default(), threads)?;
+            assert_eq!(files.files.len(), 2);
+            assert_eq!(files.scan_errors.len(), 1);
+            assert!(files.scan_errors[0].path.ends_with("IMG_0.jpg"));
+        }
+        let files = SelectedFiles::scan(selected, false)?;
+        assert_eq!((files.files.len(), files.scan_errors.len()), (3, 0));
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_reports_linked_files() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let outside = tempfile::tempdir()?;
+        std::fs::create_dir_all(dir.path().join("shoot"))?;
+        std::fs::write(outside.path().join("IMG_1.jpg"), b"content")?;
+        std::fs::write(dir.path().join("IMG_2.jpg"), b"content")?;
+        // The file outside is kept at the first of the links, the one inside at its own path
+        for link in ["shoot/b.jpg", "shoot/a.jpg"] {
+            std::os::unix::fs::symlink(outside.path().join("IMG_1.jpg"), dir.path().join(link))?;
+        }
+        std::os::unix::fs::symlink(dir.path().join("IMG_2.jpg"), dir.path().join("shoot/IMG_2.jpg"))?;
+        let selected = SelectedDirectory::try_from(dir.path().to_path_buf())?;
+        let root = selected.as_ref().to_path_buf();
+        let linked = outside.path().canonicalize()?.join("IMG_1.jpg");
+
+        for threads in [1, 4] {
+            let files = SelectedFiles::scan_threaded(selected.clone(), true, &ScanLimits::default(), threads)?;
+            assert_eq!(files.files.len(), 2);
+            assert_eq!(files.walk_paths.get(&linked), Some(&PathBuf::from("shoot/a.jpg")));
+            assert!(!files.walk_paths.contains_key(&root.join("IMG_2.jpg")));
+            let skipped = files.scan_errors.iter().map(|error| &error.path).sorted().collect_vec();
+            assert_eq!(skipped, [&root.join("shoot/IMG_2.jpg"), &root.join("shoot/b.jpg")]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_walk_reads_subdirectories_lazily() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir_all(dir.path().join("shoot/day1"))?;
+        std::fs::write(dir.path().join("IMG_1.jpg"), b"content")?;
+        let selected = SelectedDirectory::try_from(dir.path().to_path_buf())?;
+
+        let mut walk = selected.walk(true, &ScanLimits::default(), 1)?;
+        // The subdirectories are read once the walk reaches them
+        std::fs::write(dir.path().join("shoot/day1/IMG_2.jpg"), b"content")?;
+        let first = walk.next().transpose()?;
+        assert!(first.is_some_and(|file| file.walk_path.is_none()));
+        assert!(walk.next().is_some());
+        assert!(walk.next().is_none());
+
+        let found: Vec<_> = selected.walk(false, &ScanLimits::default(), 1)?.try_collect()?;
+        let files = SelectedFiles::scan(selected, false)?;
+        assert_eq!(found.into_iter().map(|file| file.path).collect_vec(), files.files);
+
+        Ok(())
+    }
+
     #[test]
     fn test_filtered_files() -> TestResult {
         let selected = SelectedDirectory::try_from(resource_dir()).unwrap();
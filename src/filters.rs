@@ -0,0 +1,284 @@
+//! Module containing the filters applied to the file metadata ([FileSize], [TimeLimit], [ImageInfo])
+//!
+//! Unlike the extensions and formats, these filters can't be decided by the file name alone,
+//! so they are checked after the name based filters, only for the files those match.
+
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+
+/// Size of a file, parsed from a number of bytes with an optional unit, like `10MB`, `1.5GiB` or `512`
+///
+/// The decimal units (`KB`, `MB`, `GB`, `TB`) are powers of 1000, and the binary ones (`KiB`, `MiB`, `GiB`, `TiB`)
+/// powers of 1024. Units are case-insensitive, and the trailing `B` can be left out, like in `10m`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "SizeValue", into = "String")]
+pub struct FileSize(pub u64);
+
+/// Size as written in the configuration, a number of bytes or a string with a unit
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SizeValue {
+    Bytes(u64),
+    Text(String),
+}
+
+/// Units a size can be written in, with their number of bytes
+const UNITS: [(&str, u64); 13] = [
+    ("", 1),
+    ("b", 1),
+    ("k", 1_000),
+    ("kb", 1_000),
+    ("kib", 1 << 10),
+    ("m", 1_000_000),
+    ("mb", 1_000_000),
+    ("mib", 1 << 20),
+    ("g", 1_000_000_000),
+    ("gb", 1_000_000_000),
+    ("gib", 1 << 30),
+    ("tb", 1_000_000_000_000),
+    ("tib", 1 << 40),
+];
+
+impl FromStr for FileSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let unit = unit.trim();
+        let multiplier = UNITS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(unit))
+            .map(|(_, bytes)| *bytes);
+        match (number.parse::<f64>(), multiplier) {
+            (Ok(number), Some(multiplier)) => Ok(FileSize((number * multiplier as f64).round() as u64)),
+            (Err(_), _) => Err(format!(
+                "invalid size \"{s}\", expected a number of bytes with an optional unit"
+            )),
+            (_, None) => Err(format!(
+                "invalid size unit \"{unit}\", expected B, KB, MB, GB, TB, KiB, MiB, GiB or TiB"
+            )),
+        }
+    }
+}
+
+impl TryFrom<SizeValue> for FileSize {
+    type Error = String;
+
+    fn try_from(value: SizeValue) -> Result<Self, Self::Error> {
+        match value {
+            SizeValue::Bytes(bytes) => Ok(FileSize(bytes)),
+            SizeValue::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl From<FileSize> for String {
+    fn from(size: FileSize) -> Self {
+        size.to_string()
+    }
+}
+
+impl Display for FileSize {
+    /// Display the size in the largest decimal unit it is a whole number of
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let units = [
+            ("TB", 1_000_000_000_000),
+            ("GB", 1_000_000_000),
+            ("MB", 1_000_000),
+            ("KB", 1_000),
+        ];
+        match units
+            .iter()
+            .find(|(_, bytes)| self.0 >= *bytes && self.0.is_multiple_of(*bytes))
+        {
+            Some((unit, bytes)) => write!(f, "{}{unit}", self.0 / bytes),
+            None => write!(f, "{}B", self.0),
+        }
+    }
+}
+
+/// Point in time the modification time of a file is compared to
+///
+/// Parsed from a duration before the start of the run, like `30m`, `12h`, `7d` or `2w`,
+/// or from a date, like `2024-06-01` (local midnight) or the RFC 3339 `2024-06-01T12:00:00+02:00`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum TimeLimit {
+    /// The duration before the start of the run
+    Ago(Duration),
+    /// A fixed point in time
+    At(DateTime<FixedOffset>),
+}
+
+/// Units a duration can be written in, with their number of seconds, from the largest
+const DURATION_UNITS: [(&str, u64); 5] = [("w", 604_800), ("d", 86_400), ("h", 3_600), ("m", 60), ("s", 1)];
+
+impl TimeLimit {
+    /// Get the point in time, counting the durations back from `now`
+    pub fn resolve(&self, now: SystemTime) -> SystemTime {
+        match self {
+            TimeLimit::Ago(duration) => now.checked_sub(*duration).unwrap_or(SystemTime::UNIX_EPOCH),
+            TimeLimit::At(time) => SystemTime::from(*time),
+        }
+    }
+}
+
+impl FromStr for TimeLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+            return Ok(TimeLimit::At(time));
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            let midnight = Local.from_local_datetime(&date.and_time(Default::default())).earliest();
+            return midnight
+                .map(|time| TimeLimit::At(time.fixed_offset()))
+                .ok_or_else(|| format!("the local midnight of {s} doesn't exist"));
+        }
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let seconds = DURATION_UNITS
+            .iter()
+            .find(|(name, _)| *name == unit.trim())
+            .map(|(_, seconds)| *seconds);
+        match (number.parse::<u64>(), seconds) {
+            (Ok(number), Some(seconds)) => match number.checked_mul(seconds) {
+                Some(seconds) => Ok(TimeLimit::Ago(Duration::from_secs(seconds))),
+                None => Err(format!("the duration \"{s}\" is too long")),
+            },
+            _ => Err(format!(
+                "invalid time \"{s}\", expected a duration like 7d, or a date like 2024-06-01"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for TimeLimit {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<TimeLimit> for String {
+    fn from(limit: TimeLimit) -> Self {
+        limit.to_string()
+    }
+}
+
+impl Display for TimeLimit {
+    /// Display the duration in the largest unit it is a whole number of, or the date in the RFC 3339 format
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeLimit::Ago(duration) => {
+                let seconds = duration.as_secs();
+                let unit = DURATION_UNITS.iter().find(|(_, unit)| seconds.is_multiple_of(*unit));
+                match unit {
+                    Some((name, unit)) if seconds > 0 => write!(f, "{}{name}", seconds / unit),
+                    _ => write!(f, "0s"),
+                }
+            }
+            TimeLimit::At(time) => write!(f, "{}", time.to_rfc3339()),
+        }
+    }
+}
+
+/// Dimensions and basic EXIF fields of an image, compared to the image limits of the configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// The camera model from the EXIF data, like `Canon EOS R5`
+    pub camera_model: Option<String>,
+    /// The ISO speed from the EXIF data
+    pub iso: Option<u32>,
+}
+
+impl ImageInfo {
+    /// Read the dimensions from the header of the image, and the EXIF fields if the image has them
+    ///
+    /// Returns `None` if the file can't be read or isn't an image.
+    #[cfg(feature = "exif")]
+    pub fn read(path: &Path) -> Option<ImageInfo> {
+        use exif::{In, Tag, Value};
+
+        let size = imagesize::size(path).ok()?;
+        let file = std::fs::File::open(path).ok()?;
+        let exif = exif::Reader::new().read_from_container(&mut std::io::BufReader::new(file)).ok();
+        let field = |tag| exif.as_ref().and_then(|exif| exif.get_field(tag, In::PRIMARY));
+        let camera_model = field(Tag::Model).and_then(|field| match &field.value {
+            Value::Ascii(values) => values.first().map(|value| String::from_utf8_lossy(value).trim().to_owned()),
+            _ => None,
+        });
+        Some(ImageInfo {
+            width: u32::try_from(size.width).ok(),
+            height: u32::try_from(size.height).ok(),
+            camera_model,
+            iso: field(Tag::PhotographicSensitivity).and_then(|field| field.value.get_uint(0)),
+        })
+    }
+
+    /// Reading the images requires the `exif` feature, so no file is an image without it
+    #[cfg(not(feature = "exif"))]
+    pub fn read(_path: &Path) -> Option<ImageInfo> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_file_sizes() {
+        assert_eq!("512".parse(), Ok(FileSize(512)));
+        assert_eq!("10MB".parse(), Ok(FileSize(10_000_000)));
+        assert_eq!("1.5 GiB".parse(), Ok(FileSize(3 << 29)));
+        assert_eq!("64k".parse(), Ok(FileSize(64_000)));
+        assert!("10 apples".parse::<FileSize>().is_err());
+        assert!("MB".parse::<FileSize>().is_err());
+
+        assert_eq!(FileSize(10_000_000).to_string(), "10MB");
+        assert_eq!(FileSize(1536).to_string(), "1536B");
+    }
+
+    #[test]
+    fn parses_time_limits() {
+        assert_eq!("7d".parse(), Ok(TimeLimit::Ago(Duration::from_secs(7 * 86_400))));
+        assert_eq!(
+            "90m".parse::<TimeLimit>().map(|limit| limit.to_string()),
+            Ok("90m".to_owned())
+        );
+        assert_eq!(
+            "14d".parse::<TimeLimit>().map(|limit| limit.to_string()),
+            Ok("2w".to_owned())
+        );
+        assert!("7 days".parse::<TimeLimit>().is_err());
+        assert_eq!(
+            "40000000000000w".parse::<TimeLimit>(),
+            Err("the duration \"40000000000000w\" is too long".to_owned())
+        );
+
+        let limit: TimeLimit = "2024-06-01T12:00:00+02:00".parse().unwrap();
+        assert_eq!(limit.to_string(), "2024-06-01T12:00:00+02:00");
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_717_236_000);
+        assert_eq!(limit.resolve(SystemTime::now()), at);
+        assert!(matches!("2024-06-01".parse(), Ok(TimeLimit::At(_))));
+
+        let now = SystemTime::now();
+        assert_eq!(
+            TimeLimit::Ago(Duration::from_secs(60)).resolve(now),
+            now - Duration::from_secs(60)
+        );
+    }
+}
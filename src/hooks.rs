@@ -0,0 +1,140 @@
+//! Module containing declarations related to [Hooks] struct
+//!
+//! Hooks are shell commands executed before and after the action is applied.
+//! Information about the run is passed to them through environment variables:
+//!
+//! - `DELETE_REST_SOURCE` - the directory files were selected from
+//! - `DELETE_REST_ACTION` - the action being performed
+//! - `DELETE_REST_DESTINATION` - the destination directory, if the action has one
+//! - `DELETE_REST_TOTAL` - the number of files found in the source directory
+//! - `DELETE_REST_MATCHING` - the number of files matching the configuration
+//! - `DELETE_REST_SELECTED` - the number of files the action is applied to
+//! - `DELETE_REST_DRY_RUN` - `1` if the run is a dry run, `0` otherwise
+//! - `DELETE_REST_ERRORS` - the number of errors that occurred (post hook only)
+
+use std::fmt::{Display, Formatter};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+/// Shell commands to run before and after the action
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Hooks {
+    /// Command run before the action is applied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre: Option<String>,
+    /// Command run after the action is applied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post: Option<String>,
+}
+
+impl Display for Hooks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{ ")?;
+        if let Some(pre) = &self.pre {
+            write!(f, "pre: {:?} ", pre)?;
+        }
+        if let Some(post) = &self.post {
+            write!(f, "post: {:?} ", post)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl Hooks {
+    /// Check if no hooks are configured
+    pub fn is_empty(&self) -> bool {
+        self.pre.is_none() && self.post.is_none()
+    }
+
+    /// Override the configured hooks with the provided ones
+    pub fn with_overrides(self, pre: Option<String>, post: Option<String>) -> Self {
+        Hooks {
+            pre: pre.or(self.pre),
+            post: post.or(self.post),
+        }
+    }
+
+    /// Run the pre hook, if configured
+    ///
+    /// # Errors
+    /// - If the command can't be started
+    /// - If the command exits unsuccessfully
+    pub fn run_pre(&self, env: &[(&str, String)]) -> Result<(), HookError> {
+        self.pre.as_deref().map_or(Ok(()), |command| run_hook("pre", command, env))
+    }
+
+    /// Run the post hook, if configured
+    ///
+    /// # Errors
+    /// - If the command can't be started
+    /// - If the command exits unsuccessfully
+    pub fn run_post(&self, env: &[(&str, String)]) -> Result<(), HookError> {
+        self.post.as_deref().map_or(Ok(()), |command| run_hook("post", command, env))
+    }
+}
+
+/// Run a shell command with the provided environment variables
+fn run_hook(name: &'static str, command: &str, env: &[(&str, String)]) -> Result<(), HookError> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+
+    let status = shell
+        .arg(command)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .status()
+        .map_err(|e| HookError::Spawn(name, e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(HookError::Failed(name, status.to_string()))
+    }
+}
+
+/// Error type for running hooks
+#[derive(thiserror::Error, Debug)]
+pub enum HookError {
+    /// The hook command couldn't be started
+    #[error("Failed to run the {0} hook: {1}")]
+    Spawn(&'static str, std::io::Error),
+    /// The hook command exited unsuccessfully
+    #[error("The {0} hook failed: {1}")]
+    Failed(&'static str, String),
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hooks_receive_environment() {
+        let hooks = Hooks {
+            pre: Some("test \"$DELETE_REST_SELECTED\" = 3".to_owned()),
+            post: Some("exit 1".to_owned()),
+        };
+        let env = [("DELETE_REST_SELECTED", "3".to_owned())];
+
+        assert!(hooks.run_pre(&env).is_ok());
+        assert!(matches!(hooks.run_post(&env), Err(HookError::Failed("post", _))));
+    }
+
+    #[test]
+    fn overrides_replace_configured_hooks() {
+        let hooks = Hooks {
+            pre: Some("configured".to_owned()),
+            post: Some("configured".to_owned()),
+        }
+        .with_overrides(Some("cli".to_owned()), None);
+
+        assert_eq!(hooks.pre.as_deref(), Some("cli"));
+        assert_eq!(hooks.post.as_deref(), Some("configured"));
+    }
+}
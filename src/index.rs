@@ -0,0 +1,251 @@
+//! Module containing declarations related to [TransferIndex] struct
+//!
+//! The index lists the files transferred to the destination directory,
+//! so the exported set can be quickly reviewed.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+use crate::keepfile::NumberPattern;
+
+/// Extensions of the images browsers can display
+const BROWSER_IMAGES: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "avif", "bmp"];
+
+/// Format of the generated index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum IndexFormat {
+    /// `index.html` contact sheet, with thumbnails of the images browsers can display
+    Html,
+    /// `index.txt` tab-separated manifest
+    Text,
+}
+
+impl IndexFormat {
+    /// Get the file name of the index
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            IndexFormat::Html => "index.html",
+            IndexFormat::Text => "index.txt",
+        }
+    }
+}
+
+/// A transferred file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Original path of the file
+    pub source: PathBuf,
+    /// Path of the file in the destination directory
+    pub destination: PathBuf,
+    /// Number the file was kept by
+    pub number: Option<u32>,
+    /// Annotation of the number in the keep file
+    pub annotation: Option<String>,
+}
+
+/// Index of the files transferred to the destination directory
+#[derive(Debug, Clone)]
+pub struct TransferIndex {
+    /// The destination directory
+    dest_dir: PathBuf,
+    /// The transferred files
+    entries: Vec<IndexEntry>,
+    /// Annotations of the numbers in the keep file
+    annotations: HashMap<u32, String>,
+    /// Pattern extracting the numbers of the files
+    number_pattern: NumberPattern,
+}
+
+impl TransferIndex {
+    /// Create an empty index of the destination directory
+    pub fn new(dest_dir: PathBuf) -> Self {
+        TransferIndex {
+            dest_dir,
+            entries: vec![],
+            annotations: HashMap::new(),
+            number_pattern: NumberPattern::default(),
+        }
+    }
+
+    /// Show the annotations of the numbers alongside the files kept by them
+    pub fn with_annotations(mut self, annotations: HashMap<u32, String>) -> Self {
+        self.annotations = annotations;
+        self
+    }
+
+    /// Extract the numbers of the files with the pattern
+    pub fn with_number_pattern(mut self, number_pattern: NumberPattern) -> Self {
+        self.number_pattern = number_pattern;
+        self
+    }
+
+    /// Add a transferred file to the index
+    pub fn push(&mut self, source: &Path, destination: &Path) {
+        let number = source
+            .file_name()
+            .and_then(|f| f.to_str())
+            .and_then(|f| self.number_pattern.extract(f));
+        self.entries.push(IndexEntry {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+            annotation: number.and_then(|num| self.annotations.get(&num).cloned()),
+            number,
+        });
+    }
+
+    /// Keep only the entries matching the predicate
+    pub fn retain(&mut self, f: impl FnMut(&IndexEntry) -> bool) {
+        self.entries.retain(f);
+    }
+
+    /// Get the path of the destination relative to the destination directory
+    fn relative<'a>(&self, destination: &'a Path) -> &'a Path {
+        destination.strip_prefix(&self.dest_dir).unwrap_or(destination)
+    }
+
+    /// Write the index as a tab-separated manifest
+    pub fn write_text<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writeln!(writer, "file\toriginal\tnumber\tnote")?;
+        for entry in &self.entries {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}",
+                self.relative(&entry.destination).display(),
+                entry.source.display(),
+                entry.number.map(|n| n.to_string()).unwrap_or_default(),
+                entry.annotation.as_deref().unwrap_or_default()
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write the index as an HTML contact sheet
+    pub fn write_html<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writeln!(writer, "<!DOCTYPE html>")?;
+        writeln!(
+            writer,
+            "<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Selection</title>"
+        )?;
+        writeln!(
+            writer,
+            "<style>figure {{ display: inline-block; margin: 8px; width: 220px; vertical-align: top; }} \
+             img {{ max-width: 220px; max-height: 220px; }} figcaption {{ font: 12px sans-serif; word-wrap: break-word; }}</style>"
+        )?;
+        writeln!(writer, "</head>\n<body>")?;
+        writeln!(writer, "<h1>Selection ({} files)</h1>", self.entries.len())?;
+        for entry in &self.entries {
+            let relative = self.relative(&entry.destination);
+            let href = encode_href(relative);
+            let name = escape_html(&relative.to_string_lossy());
+            let is_image = relative
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| BROWSER_IMAGES.contains(&ext.to_ascii_lowercase().as_str()));
+
+            writeln!(writer, "<figure>")?;
+            if is_image {
+                writeln!(writer, "<a href=\"{href}\"><img src=\"{href}\" loading=\"lazy\"></a>")?;
+            }
+            write!(writer, "<figcaption><a href=\"{href}\">{name}</a>")?;
+            if let Some(number) = entry.number {
+                write!(writer, "<br>#{number}")?;
+            }
+            if let Some(annotation) = &entry.annotation {
+                write!(writer, "<br><em>{}</em>", escape_html(annotation))?;
+            }
+            writeln!(
+                writer,
+                "<br>{}</figcaption>",
+                escape_html(&entry.source.to_string_lossy())
+            )?;
+            writeln!(writer, "</figure>")?;
+        }
+        writeln!(writer, "</body>\n</html>")
+    }
+
+    /// Write the index into the destination directory
+    ///
+    /// Returns the path of the written index.
+    pub fn save(&self, format: IndexFormat) -> std::io::Result<PathBuf> {
+        std::fs::create_dir_all(&self.dest_dir)?;
+        let path = self.dest_dir.join(format.file_name());
+        let writer = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        match format {
+            IndexFormat::Html => self.write_html(writer)?,
+            IndexFormat::Text => self.write_text(writer)?,
+        }
+        Ok(path)
+    }
+}
+
+/// Percent-encode the relative path to be used as a link
+fn encode_href(path: &Path) -> String {
+    let mut href = String::new();
+    for (i, component) in path.components().enumerate() {
+        if i > 0 {
+            href.push('/');
+        }
+        for byte in component.as_os_str().to_string_lossy().bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => href.push(byte as char),
+                _ => href.push_str(&format!("%{byte:02X}")),
+            }
+        }
+    }
+    href
+}
+
+/// Escape the text to be safely included in HTML
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    fn index() -> TransferIndex {
+        let annotations = HashMap::from([(12, "bride & groom".to_owned())]);
+        let mut index = TransferIndex::new(PathBuf::from("/dest")).with_annotations(annotations);
+        index.push(Path::new("/src/IMG_0012.jpg"), Path::new("/dest/IMG_0012.jpg"));
+        index.push(
+            Path::new("/src/<raw>/IMG_0012.cr2"),
+            Path::new("/dest/<raw>/IMG_0012.cr2"),
+        );
+        index
+    }
+
+    #[test]
+    fn text_manifest() -> TestResult {
+        let mut output = Vec::new();
+        index().write_text(&mut output)?;
+        let output = String::from_utf8(output)?;
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines[1], "IMG_0012.jpg\t/src/IMG_0012.jpg\t12\tbride & groom");
+        assert_eq!(lines.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn html_contact_sheet() -> TestResult {
+        let mut output = Vec::new();
+        index().write_html(&mut output)?;
+        let output = String::from_utf8(output)?;
+        assert!(output.contains("<img src=\"IMG_0012.jpg\""));
+        assert!(output.contains("<a href=\"%3Craw%3E/IMG_0012.cr2\">&lt;raw&gt;/IMG_0012.cr2</a>"));
+        assert!(!output.contains("<img src=\"%3Craw%3E/IMG_0012.cr2\""));
+        assert!(output.contains("#12"));
+        assert!(output.contains("<em>bride &amp; groom</em>"));
+
+        Ok(())
+    }
+}
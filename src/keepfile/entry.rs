@@ -0,0 +1,316 @@
+//! Module containing the parsing of a single keep file line ([parse_line])
+//!
+//! The parsed [KeepEntry] is public, so tools built on the library can inspect the keep files
+//! the same way they are matched, instead of re-implementing the syntax.
+
+use std::iter::StepBy;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use super::{KeepFileLine, KeepTarget};
+
+/// A parsed line of a keep file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeepEntry {
+    /// A blank line, or a line with only a comment, like `# ceremony`
+    Comment(Option<String>),
+    /// The start of a section, `[keep]` or `[delete]`
+    Section(KeepSection),
+    /// A number, like `123`
+    Number(u32, KeepModifiers),
+    /// An inclusive range of numbers, like `120-145`, `120..145` or `100-200:2`
+    Range(KeepRange, KeepModifiers),
+    /// A file name, like `IMG_0451.CR2`
+    FileName(String, KeepModifiers),
+    /// A glob pattern, like `IMG_04*.CR2`
+    Pattern(glob::Pattern, KeepModifiers),
+}
+
+/// Section of a keep file
+///
+/// The lines before the first section header are in the `[keep]` section.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum KeepSection {
+    /// Files to keep
+    #[default]
+    Keep,
+    /// Files to delete, even if the `[keep]` section selects them
+    Delete,
+}
+
+/// What a line applies to its numbers, file names or patterns
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeepModifiers {
+    /// The line excludes its targets (`!123`) instead of including them
+    pub excluded: bool,
+    /// The lowercase extensions the targets are kept for (`123 jpg,cr2`), all of them if empty
+    pub extensions: Vec<String>,
+    /// The annotation following the targets (`123  # bride portrait`)
+    pub annotation: Option<String>,
+}
+
+/// Inclusive range of numbers, keeping every `step`-th number from the `start`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepRange {
+    pub start: u32,
+    pub end: u32,
+    pub step: usize,
+}
+
+impl KeepRange {
+    /// Most numbers a range can keep, enough for every number with up to 5 digits
+    ///
+    /// The ranges are expanded into their numbers, so a typo like `1-400000000` is rejected
+    /// instead of exhausting the memory.
+    pub const MAX_NUMBERS: usize = 100_000;
+
+    /// Get how many numbers the range keeps
+    pub fn count(&self) -> usize {
+        (self.end - self.start) as usize / self.step + 1
+    }
+
+    /// Get the numbers in the range
+    pub fn numbers(&self) -> StepBy<RangeInclusive<u32>> {
+        (self.start..=self.end).step_by(self.step)
+    }
+}
+
+/// Error type for a keep file line that can't be parsed
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum KeepLineError {
+    /// The line isn't a number, a range, a file name or a glob pattern
+    #[error("expected a number, a range, a file name or a glob pattern")]
+    Invalid,
+    /// The end of the range is before its start
+    #[error("the range from {0} to {1} is empty")]
+    EmptyRange(u32, u32),
+    /// The step of the range is not a positive number
+    #[error("the step \"{0}\" is not a positive number")]
+    InvalidStep(String),
+    /// The range keeps more numbers than [KeepRange::MAX_NUMBERS]
+    #[error("the range from {0} to {1} keeps more than {max} numbers", max = KeepRange::MAX_NUMBERS)]
+    RangeTooLarge(u32, u32),
+    /// A single number has a step
+    #[error("only ranges can have a step, like 100-200:2")]
+    StepWithoutRange,
+    /// An exclusion is qualified by extensions
+    #[error("exclusions can't be limited to extensions")]
+    QualifiedExclusion,
+    /// The glob pattern is malformed
+    #[error("invalid glob pattern: {0}")]
+    InvalidPattern(String),
+}
+
+impl KeepEntry {
+    /// Get the modifiers of the line, or `None` for comments and section headers
+    pub fn modifiers(&self) -> Option<&KeepModifiers> {
+        match self {
+            KeepEntry::Comment(_) | KeepEntry::Section(_) => None,
+            KeepEntry::Number(_, modifiers)
+            | KeepEntry::Range(_, modifiers)
+            | KeepEntry::FileName(_, modifiers)
+            | KeepEntry::Pattern(_, modifiers) => Some(modifiers),
+        }
+    }
+
+    /// Get the targets the line selects, with the ranges expanded into their numbers
+    pub fn targets(&self) -> Vec<KeepTarget> {
+        match self {
+            KeepEntry::Comment(_) | KeepEntry::Section(_) => vec![],
+            KeepEntry::Number(num, _) => vec![KeepTarget::Number(*num)],
+            KeepEntry::Range(range, _) => range.numbers().map(KeepTarget::Number).collect(),
+            KeepEntry::FileName(name, _) => vec![KeepTarget::FileName(name.clone())],
+            KeepEntry::Pattern(pattern, _) => vec![KeepTarget::Pattern(pattern.clone())],
+        }
+    }
+
+    /// Get the keep file lines of the targets, carrying the annotation and the extensions of the line
+    pub fn lines(&self) -> Vec<KeepFileLine> {
+        let Some(modifiers) = self.modifiers() else {
+            return vec![];
+        };
+        self.targets()
+            .into_iter()
+            .map(|target| KeepFileLine {
+                target,
+                annotation: modifiers.annotation.clone(),
+                extensions: modifiers.extensions.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Parse a line of a keep file
+///
+/// # Errors
+/// - If the line isn't a blank line, a comment, a section header, a number, a range, a file name or a glob pattern
+/// - If the range or its step is malformed
+pub fn parse_line(line: &str) -> Result<KeepEntry, KeepLineError> {
+    // Keep files saved by Windows editors may start with a byte order mark
+    let line = line.strip_prefix('\u{feff}').unwrap_or(line);
+    let (value, annotation) = match line.split_once('#') {
+        Some((value, annotation)) => (value, Some(annotation.trim()).filter(|a| !a.is_empty())),
+        None => (line, None),
+    };
+    let annotation = annotation.map(str::to_owned);
+    match value.trim().to_ascii_lowercase().as_str() {
+        "[keep]" => return Ok(KeepEntry::Section(KeepSection::Keep)),
+        "[delete]" => return Ok(KeepEntry::Section(KeepSection::Delete)),
+        _ => {}
+    }
+    // Exclusions (`!123`) remove the number even if it is included by another line
+    let (excluded, value) = match value.trim().strip_prefix('!') {
+        Some(value) => (true, value.trim()),
+        None => (false, value.trim()),
+    };
+    // Blank lines and comment lines don't contain a number
+    if !excluded && value.is_empty() {
+        return Ok(KeepEntry::Comment(annotation));
+    }
+    // Extension qualifiers (`123 jpg,cr2`) keep the numbers only for the listed extensions
+    let (value, extensions) = match split_extensions(value) {
+        Some(_) if excluded => return Err(KeepLineError::QualifiedExclusion),
+        Some((value, extensions)) => (value, extensions),
+        None => (value, vec![]),
+    };
+
+    let modifiers = KeepModifiers {
+        excluded,
+        extensions,
+        annotation,
+    };
+    match (parse_range(value), value.parse()) {
+        (Some(range), _) => Ok(KeepEntry::Range(range?, modifiers)),
+        (None, Ok(num)) => Ok(KeepEntry::Number(num, modifiers)),
+        _ if value.contains(['*', '?', '[']) => match glob::Pattern::new(value) {
+            Ok(pattern) => Ok(KeepEntry::Pattern(pattern, modifiers)),
+            Err(e) => Err(KeepLineError::InvalidPattern(e.msg.to_owned())),
+        },
+        _ if is_file_name(value) => Ok(KeepEntry::FileName(value.to_owned(), modifiers)),
+        _ => Err(KeepLineError::Invalid),
+    }
+}
+
+/// Parse an inclusive range of numbers (`120-145` or `120..145`),
+/// optionally keeping only every n-th number of the range (`120-145:2`)
+///
+/// Returns `None` if the value is not a range, and an error if the range or its step is malformed.
+fn parse_range(value: &str) -> Option<Result<KeepRange, KeepLineError>> {
+    let (value, step) = match value.split_once(':') {
+        Some((value, step)) => (value, Some(step.trim())),
+        None => (value, None),
+    };
+    let (start, end) = match value.split_once("..").or_else(|| value.split_once('-')) {
+        Some((start, end)) => (start.trim().parse().ok()?, end.trim().parse().ok()?),
+        None if step.is_some() && value.trim().parse::<u32>().is_ok() => {
+            return Some(Err(KeepLineError::StepWithoutRange));
+        }
+        None => return None,
+    };
+    let step = match step {
+        Some(step) => match step.parse() {
+            Ok(step) if step > 0 => step,
+            _ => return Some(Err(KeepLineError::InvalidStep(step.to_owned()))),
+        },
+        None => 1,
+    };
+    if start > end {
+        return Some(Err(KeepLineError::EmptyRange(start, end)));
+    }
+    let range = KeepRange { start, end, step };
+    match range.count() <= KeepRange::MAX_NUMBERS {
+        true => Some(Ok(range)),
+        false => Some(Err(KeepLineError::RangeTooLarge(start, end))),
+    }
+}
+
+/// Split a number or a range from the extensions qualifying it, like `123 jpg,cr2`
+///
+/// Returns `None` if the value isn't a qualified number or range.
+fn split_extensions(value: &str) -> Option<(&str, Vec<String>)> {
+    let (value, extensions) = value.split_once(char::is_whitespace)?;
+    // Malformed ranges are reported once the range is parsed without the extensions
+    if parse_range(value).is_none() && value.parse::<u32>().is_err() {
+        return None;
+    }
+    let extensions: Vec<_> = extensions
+        .split(',')
+        .map(|ext| ext.trim().trim_start_matches('.').to_ascii_lowercase())
+        .collect();
+    let is_extension = |ext: &String| !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric());
+    match extensions.iter().all(is_extension) {
+        true => Some((value, extensions)),
+        false => None,
+    }
+}
+
+/// Check if the value is a file name with an extension, like `IMG_0451.CR2`
+fn is_file_name(value: &str) -> bool {
+    let path = Path::new(value);
+    path.extension().is_some() && path.file_name().is_some_and(|name| name == value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_entries() {
+        assert_eq!(
+            parse_line("  # ceremony"),
+            Ok(KeepEntry::Comment(Some("ceremony".to_owned())))
+        );
+        assert_eq!(parse_line(""), Ok(KeepEntry::Comment(None)));
+        assert_eq!(parse_line("123"), Ok(KeepEntry::Number(123, KeepModifiers::default())));
+        assert_eq!(
+            parse_line(" [Delete]  # rejects"),
+            Ok(KeepEntry::Section(KeepSection::Delete))
+        );
+        assert_eq!(
+            parse_line("!120-145:5"),
+            Ok(KeepEntry::Range(
+                KeepRange {
+                    start: 120,
+                    end: 145,
+                    step: 5
+                },
+                KeepModifiers {
+                    excluded: true,
+                    ..KeepModifiers::default()
+                }
+            ))
+        );
+        assert_eq!(
+            parse_line("IMG_0451.CR2  # bride"),
+            Ok(KeepEntry::FileName(
+                "IMG_0451.CR2".to_owned(),
+                KeepModifiers {
+                    annotation: Some("bride".to_owned()),
+                    ..KeepModifiers::default()
+                }
+            ))
+        );
+
+        let entry = parse_line("7 JPG,.cr2").unwrap();
+        assert_eq!(entry.modifiers().unwrap().extensions, ["jpg", "cr2"]);
+        assert_eq!(entry.targets(), [KeepTarget::Number(7)]);
+        assert!(matches!(parse_line("IMG_04*.CR2"), Ok(KeepEntry::Pattern(..))));
+    }
+
+    #[test]
+    fn reports_invalid_lines() {
+        assert_eq!(parse_line("hello"), Err(KeepLineError::Invalid));
+        assert_eq!(parse_line("!"), Err(KeepLineError::Invalid));
+        assert_eq!(parse_line("145-120"), Err(KeepLineError::EmptyRange(145, 120)));
+        assert_eq!(
+            parse_line("1-400000000"),
+            Err(KeepLineError::RangeTooLarge(1, 400000000))
+        );
+        assert!(parse_line("0-99999").is_ok());
+        assert!(parse_line("1-400000000:4000 jpg").is_ok());
+        assert_eq!(parse_line("1-9:0 jpg"), Err(KeepLineError::InvalidStep("0".to_owned())));
+        assert_eq!(parse_line("7:2"), Err(KeepLineError::StepWithoutRange));
+        assert_eq!(parse_line("!7 jpg"), Err(KeepLineError::QualifiedExclusion));
+        assert!(matches!(parse_line("IMG_[.CR2"), Err(KeepLineError::InvalidPattern(_))));
+    }
+}
@@ -0,0 +1,162 @@
+//! Module containing the formats keep files are read from ([KeepFormat])
+//!
+//! Besides plain keep files, the selections exported by common culling tools are read directly,
+//! so the picked file names don't have to be turned into a list of numbers by hand.
+
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use super::{KeepColumn, KeepFile, KeepFileError, KeepFileLine, KeepTarget};
+
+/// Format of a keep file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum KeepFormat {
+    /// A number, a range, a file name or a glob pattern per line
+    #[default]
+    Plain,
+    /// CSV file, with the numbers or file names in the selected column
+    Csv,
+    /// File names copied or exported from Capture One, separated by commas, semicolons or lines
+    #[value(name = "capture-one")]
+    CaptureOne,
+    /// File names or paths exported from Photo Mechanic, one per line, optionally followed by tab-separated fields
+    #[value(name = "photomechanic")]
+    PhotoMechanic,
+}
+
+impl KeepFormat {
+    /// Detect the format of the keep file by its extension
+    ///
+    /// Files with the `.csv` extension are read as CSV, and everything else as plain keep files.
+    pub fn detect<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv")) {
+            true => KeepFormat::Csv,
+            false => KeepFormat::Plain,
+        }
+    }
+
+    /// Parse the keep file in this format from the provided reader
+    ///
+    /// The `column` is only used by CSV keep files, and the `origin` describes the content in errors.
+    ///
+    /// # Errors
+    /// - If the content can't be read
+    /// - If the content is not valid in this format
+    pub fn parse<R: BufRead, P: AsRef<Path>>(
+        self,
+        mut reader: R,
+        column: &KeepColumn,
+        origin: P,
+    ) -> Result<KeepFile, KeepFileError> {
+        match self {
+            KeepFormat::Plain => KeepFile::from_reader(reader, origin),
+            KeepFormat::Csv => KeepFile::from_csv(reader, column, origin),
+            KeepFormat::CaptureOne => {
+                let mut content = String::new();
+                reader.read_to_string(&mut content)?;
+                Ok(from_file_names(content.split([',', ';', '\n'])))
+            }
+            KeepFormat::PhotoMechanic => {
+                let lines = reader.lines().collect::<Result<Vec<_>, _>>()?;
+                // Only the first field is the file name, the rest are the exported metadata
+                let names = lines.iter().map(|line| line.split('\t').next().unwrap_or_default());
+                Ok(from_file_names(names))
+            }
+        }
+    }
+}
+
+/// Build a keep file from the exported file names or paths
+///
+/// Culling tools usually export one of the files of a frame, like the JPEG proof, while the rest of
+/// the frame should be kept too, so every file name selects all the files with the same stem.
+fn from_file_names<'a>(names: impl Iterator<Item = &'a str>) -> KeepFile {
+    let mut seen = HashSet::new();
+    let lines = names
+        .map(|name| name.trim_start_matches('\u{feff}').trim().trim_matches('"'))
+        // Exports may contain full paths, from Windows as well
+        .filter_map(|name| name.rsplit(['/', '\\']).next())
+        .map(|name| Path::new(name).file_stem().and_then(|stem| stem.to_str()).unwrap_or_default())
+        .filter(|stem| !stem.is_empty() && seen.insert(stem.to_owned()))
+        .filter_map(|stem| glob::Pattern::new(&format!("{}.*", glob::Pattern::escape(stem))).ok())
+        .map(|pattern| KeepFileLine::new(KeepTarget::Pattern(pattern)))
+        .collect();
+    KeepFile {
+        lines,
+        ..KeepFile::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    /// Check which of the files the keep file selects
+    fn selected<'a>(keepfile: KeepFile, files: &[&'a str]) -> Vec<&'a str> {
+        let matcher = keepfile.into_inclusion_matcher();
+        files.iter().copied().filter(|file| matcher(&&PathBuf::from(file))).collect()
+    }
+
+    const FILES: [&str; 5] = [
+        "IMG_0001.CR3",
+        "IMG_0001.jpg",
+        "IMG_0002.CR3",
+        "IMG_0010.CR3",
+        "IMG_0010.jpg",
+    ];
+
+    #[test]
+    fn detects_format_by_extension() {
+        assert_eq!(KeepFormat::detect("picks.CSV"), KeepFormat::Csv);
+        assert_eq!(KeepFormat::detect("keep.txt"), KeepFormat::Plain);
+        assert_eq!(KeepFormat::detect("keep"), KeepFormat::Plain);
+    }
+
+    #[test]
+    fn parses_plain_keep_file() -> TestResult {
+        let keepfile = KeepFormat::Plain.parse("1\n10 jpg\n".as_bytes(), &KeepColumn::default(), "keep.txt")?;
+        assert_eq!(
+            selected(keepfile, &FILES),
+            vec!["IMG_0001.CR3", "IMG_0001.jpg", "IMG_0010.jpg"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parses_csv_keep_file() -> TestResult {
+        let csv = "Rating,File\n5,IMG_0002.CR3\n4,IMG_0010.jpg\n";
+        let keepfile = KeepFormat::Csv.parse(csv.as_bytes(), &"File".parse()?, "picks.csv")?;
+        assert_eq!(selected(keepfile, &FILES), vec!["IMG_0002.CR3", "IMG_0010.jpg"]);
+        Ok(())
+    }
+
+    #[test]
+    fn parses_capture_one_selection() -> TestResult {
+        let export = "IMG_0001.jpg, IMG_0010.CR3;IMG_0001.CR3\nIMG_0099.CR3\n";
+        let keepfile = KeepFormat::CaptureOne.parse(export.as_bytes(), &KeepColumn::default(), "picks.txt")?;
+        assert_eq!(keepfile.lines.len(), 3);
+        assert_eq!(
+            selected(keepfile, &FILES),
+            vec!["IMG_0001.CR3", "IMG_0001.jpg", "IMG_0010.CR3", "IMG_0010.jpg"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parses_photo_mechanic_selection() -> TestResult {
+        let export = "/Volumes/Card/DCIM/IMG_0002.CR3\t5\n\"C:\\Shoot\\IMG_0010.jpg\"\n\n";
+        let keepfile = KeepFormat::PhotoMechanic.parse(export.as_bytes(), &KeepColumn::default(), "picks.txt")?;
+        assert_eq!(
+            selected(keepfile, &FILES),
+            vec!["IMG_0002.CR3", "IMG_0010.CR3", "IMG_0010.jpg"]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,1573 @@
+//! Module containing declarations related to [KeepFile] struct
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use itertools::Itertools;
+use regex::Regex;
+use regex_macro::regex;
+use serde::{Deserialize, Serialize};
+
+use crate::explain::Step;
+
+pub use entry::{parse_line, KeepEntry, KeepLineError, KeepModifiers, KeepRange, KeepSection};
+use formats::KeepFormat;
+
+mod entry;
+pub mod formats;
+
+/// A list of numbers to keep
+///
+/// This type represents a list of numbers to keep from the matching files.
+/// The lines of the `[delete]` section are kept apart, and win over the lines to keep.
+/// The lines prefixed with `!` are kept apart as well, and win over the other lines of their section.
+#[derive(Debug, Default)]
+pub struct KeepFile {
+    pub lines: Vec<KeepFileLine>,
+    /// Lines of the `[delete]` section, rejecting the files even if other lines keep them
+    pub denied: Vec<KeepFileLine>,
+    /// Lines prefixed with `!`, never keeping the files even if other lines keep them
+    excluded: Vec<KeepFileLine>,
+    /// Lines of the `[delete]` section prefixed with `!`, sparing the files from the `[delete]` section
+    excluded_denied: Vec<KeepFileLine>,
+    /// Lines of the reject list given apart, never keeping the files even if other lines keep them
+    rejected: Vec<KeepFileLine>,
+    /// Lines of the reject list prefixed with `!`, sparing the files from the reject list
+    excluded_rejected: Vec<KeepFileLine>,
+    /// Paths the keep file was loaded from
+    sources: Vec<PathBuf>,
+    /// Pattern extracting the number a file name is compared by
+    number_pattern: NumberPattern,
+    /// Lines repeating the numbers or file names of the previous lines
+    duplicates: Vec<KeepFileDuplicates>,
+    /// Invalid lines skipped instead of rejecting the keep file
+    skipped: Vec<KeepFileSkipped>,
+}
+
+/// Regular expression extracting the number a file name is compared by
+///
+/// The number is the capture group named `number`, or else the last capture group of a match.
+/// When the pattern matches several times, the [NumberPosition] selects the match.
+/// The default pattern, `(\d+)`, extracts the first number in the file name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NumberPattern {
+    #[serde(with = "serde_regex")]
+    regex: Regex,
+    #[serde(skip)]
+    position: NumberPosition,
+    #[serde(skip)]
+    strict_width: bool,
+    #[serde(skip)]
+    matching: NumberMatching,
+}
+
+impl Default for NumberPattern {
+    fn default() -> Self {
+        NumberPattern::from(regex!(r#"(\d+)"#).clone())
+    }
+}
+
+impl From<Regex> for NumberPattern {
+    fn from(regex: Regex) -> Self {
+        NumberPattern {
+            regex,
+            position: NumberPosition::default(),
+            strict_width: false,
+            matching: NumberMatching::default(),
+        }
+    }
+}
+
+impl Display for NumberPattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{}\"", self.regex.as_str())
+    }
+}
+
+impl NumberPattern {
+    /// Select the match of the pattern by its position
+    pub fn with_position(mut self, position: NumberPosition) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// Only extract numbers spanning all the adjacent digits in the file name
+    ///
+    /// With the strict width, `7` is only compared against numbers like `0007`, and never against
+    /// a part of a longer number, like the `007` a pattern such as `(\d{3})` extracts from `IMG_0070`.
+    pub fn with_strict_width(mut self, strict_width: bool) -> Self {
+        self.strict_width = strict_width;
+        self
+    }
+
+    /// Select how the numbers are found in the file name, by the pattern or as the whole numeric tokens
+    pub fn with_matching(mut self, matching: NumberMatching) -> Self {
+        self.matching = matching;
+        self
+    }
+
+    /// Extract the number from the file name
+    pub fn extract(&self, filename: &str) -> Option<u32> {
+        let is_whole = |m: &regex::Match| {
+            let before = filename[..m.start()].chars().next_back();
+            let after = filename[m.end()..].chars().next();
+            !before.is_some_and(|c| c.is_ascii_digit()) && !after.is_some_and(|c| c.is_ascii_digit())
+        };
+        let numbers: Vec<&str> = match self.matching {
+            NumberMatching::Regex => self
+                .regex
+                .captures_iter(filename)
+                .filter_map(|captures| captures.name("number").or_else(|| captures.iter().last()?))
+                .filter(|m| !self.strict_width || is_whole(m))
+                .map(|m| m.as_str())
+                .collect(),
+            NumberMatching::Token => filename
+                .split(|c: char| !c.is_ascii_digit())
+                .filter(|token| !token.is_empty())
+                .collect(),
+        };
+        let number = match self.position {
+            NumberPosition::First => numbers.first(),
+            NumberPosition::Last => numbers.last(),
+            NumberPosition::Nth(n) => numbers.get(n.get() - 1),
+        };
+        number?.parse().ok()
+    }
+}
+
+/// How the numbers of a file name are found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum NumberMatching {
+    /// The matches of the [NumberPattern]
+    #[default]
+    Regex,
+    /// The whole runs of digits, split on the other characters, so `100` never matches a part of `IMG_1003`
+    Token,
+}
+
+impl Display for NumberMatching {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NumberMatching::Regex => write!(f, "regex"),
+            NumberMatching::Token => write!(f, "token"),
+        }
+    }
+}
+
+/// Which of the numbers in the file name is compared against the keep file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum NumberPosition {
+    /// The first number, like `2024` in `2024_05_IMG_0123.jpg`
+    #[default]
+    First,
+    /// The last number, like `0123` in `2024_05_IMG_0123.jpg`
+    Last,
+    /// The number at this position, starting from 1, like `05` for `nth(2)` in `2024_05_IMG_0123.jpg`
+    Nth(NonZeroUsize),
+}
+
+impl FromStr for NumberPosition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let nth = s.strip_prefix("nth(").and_then(|s| s.strip_suffix(')'));
+        match (s, nth.map(str::parse::<usize>)) {
+            ("first", _) => Ok(NumberPosition::First),
+            ("last", _) => Ok(NumberPosition::Last),
+            (_, Some(Ok(n))) => NonZeroUsize::new(n)
+                .map(NumberPosition::Nth)
+                .ok_or_else(|| "positions start from 1".to_owned()),
+            _ => Err(format!(
+                "invalid number position \"{s}\", expected first, last or nth(N)"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for NumberPosition {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<NumberPosition> for String {
+    fn from(position: NumberPosition) -> Self {
+        position.to_string()
+    }
+}
+
+impl Display for NumberPosition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NumberPosition::First => write!(f, "first"),
+            NumberPosition::Last => write!(f, "last"),
+            NumberPosition::Nth(n) => write!(f, "nth({n})"),
+        }
+    }
+}
+
+/// What a line of the keep file selects
+///
+/// Targets are ordered with the numbers first, then the file names, and the glob patterns last.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KeepTarget {
+    /// Files whose name contains the number
+    Number(u32),
+    /// The file with exactly this name, like `IMG_0451.CR2`
+    FileName(String),
+    /// Files whose name matches the glob pattern, like `IMG_04*.CR2`
+    Pattern(glob::Pattern),
+}
+
+impl KeepTarget {
+    /// Check if the file name is selected by the target, extracting its number with the pattern
+    pub fn matches(&self, filename: &str, pattern: &NumberPattern) -> bool {
+        match self {
+            KeepTarget::Number(num) => pattern.extract(filename) == Some(*num),
+            KeepTarget::FileName(name) => filename == name,
+            KeepTarget::Pattern(pattern) => pattern.matches(filename),
+        }
+    }
+}
+
+impl Display for KeepTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeepTarget::Number(num) => write!(f, "{num}"),
+            KeepTarget::FileName(name) => write!(f, "{name}"),
+            KeepTarget::Pattern(pattern) => write!(f, "{pattern}"),
+        }
+    }
+}
+
+/// Line of the keep file, keeping a single target
+#[derive(Debug)]
+pub struct KeepFileLine {
+    /// The number, file name or pattern to keep
+    pub target: KeepTarget,
+    /// The annotation following the target, like `123  # bride portrait`
+    pub annotation: Option<String>,
+    /// The lowercase extensions the target is kept for, like `123 jpg,cr2`, all of them if empty
+    pub extensions: Vec<String>,
+}
+
+impl Display for KeepFileLine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.extensions.is_empty() {
+            true => write!(f, "{}", self.target),
+            false => write!(f, "{} {}", self.target, self.extensions.join(",")),
+        }
+    }
+}
+
+impl KeepFileLine {
+    /// Create a line keeping the target for all the extensions, without an annotation
+    pub fn new(target: KeepTarget) -> Self {
+        KeepFileLine {
+            target,
+            annotation: None,
+            extensions: vec![],
+        }
+    }
+
+    /// Annotate the line, like `123  # bride portrait`
+    pub fn with_annotation(mut self, annotation: impl Into<String>) -> Self {
+        self.annotation = Some(annotation.into());
+        self
+    }
+
+    /// Keep the target only for the extensions, like `123 jpg,cr2`
+    pub fn with_extensions<S: AsRef<str>>(mut self, extensions: impl IntoIterator<Item = S>) -> Self {
+        self.extensions = extensions
+            .into_iter()
+            .map(|ext| ext.as_ref().trim_start_matches('.').to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Get the target to keep
+    pub fn target(&self) -> &KeepTarget {
+        &self.target
+    }
+
+    /// Get the number to keep, or the number in the file name to keep
+    ///
+    /// Returns `None` for glob patterns.
+    pub fn number(&self) -> Option<u32> {
+        match &self.target {
+            KeepTarget::Number(num) => Some(*num),
+            KeepTarget::FileName(name) => KeepFile::extract_number(name),
+            KeepTarget::Pattern(_) => None,
+        }
+    }
+
+    /// Get the annotation of the number
+    pub fn annotation(&self) -> Option<&str> {
+        self.annotation.as_deref()
+    }
+
+    /// Get the lowercase extensions the target is kept for, all of them if empty
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    /// Check if the file name has one of the extensions the target is kept for
+    fn allows_extension(&self, filename: &str) -> bool {
+        self.extensions.is_empty() || self.extensions.contains(&extension_of(filename))
+    }
+}
+
+/// Column of a CSV keep file containing the numbers or the file names to keep
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeepColumn {
+    /// Column with the header of this name
+    Name(String),
+    /// Column at this position, starting from 1
+    Index(usize),
+}
+
+impl Default for KeepColumn {
+    fn default() -> Self {
+        KeepColumn::Index(1)
+    }
+}
+
+impl FromStr for KeepColumn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<usize>() {
+            Ok(0) => Err("column indices start from 1".to_owned()),
+            Ok(index) => Ok(KeepColumn::Index(index)),
+            Err(_) => Ok(KeepColumn::Name(s.to_owned())),
+        }
+    }
+}
+
+/// Number and content of a line in keep file that doesn't contain a number,
+/// and the reason it is invalid, if it is known
+#[derive(Debug)]
+pub struct KeepFileBadLine(pub usize, pub String, pub Option<KeepLineError>);
+
+/// Lines of a keep file repeating the numbers or file names of the previous lines
+#[derive(thiserror::Error, Debug)]
+#[error("One or more lines in the keepfile \"{}\" repeat previous numbers:\n{}", .file.display(), .lines)]
+pub struct KeepFileDuplicates {
+    pub file: PathBuf,
+    pub lines: KeepFileFormatError,
+}
+
+/// Invalid lines of a keep file, skipped instead of rejecting the keep file
+#[derive(thiserror::Error, Debug)]
+#[error("Skipped one or more invalid lines in the keepfile \"{}\":\n{}", .file.display(), .lines)]
+pub struct KeepFileSkipped {
+    pub file: PathBuf,
+    pub lines: KeepFileFormatError,
+}
+
+/// List of lines in the keep file that don't contain a number
+#[derive(thiserror::Error, Debug)]
+pub struct KeepFileFormatError(pub Vec<KeepFileBadLine>);
+
+impl KeepFile {
+    /// Load the keepfile from the provided path
+    ///
+    /// This method loads the keepfile from the provided path, and returns a `KeepFile` if successful.
+    ///
+    /// If the file is not found, or if the file is not valid, an error is returned.
+    ///
+    /// # Errors
+    /// - If the file is not found
+    /// - If the file is not valid
+    pub fn try_load<P: AsRef<Path>>(path: P) -> Result<KeepFile, KeepFileError> {
+        let format = KeepFormat::detect(path.as_ref());
+        KeepFile::try_load_as(path, format, &KeepColumn::default())
+    }
+
+    /// Load the keepfile in the `format` from the provided path, reading the `column` if it is a CSV file
+    ///
+    /// # Errors
+    /// - If the file is not found
+    /// - If the file is not valid
+    pub fn try_load_as<P: AsRef<Path>>(
+        path: P,
+        format: KeepFormat,
+        column: &KeepColumn,
+    ) -> Result<KeepFile, KeepFileError> {
+        let file = File::open(path.as_ref())?;
+        let mut keepfile = format.parse(BufReader::new(file), column, path.as_ref())?;
+        keepfile.sources = path.as_ref().canonicalize().into_iter().collect();
+        Ok(keepfile)
+    }
+
+    /// Parse the keepfile from the `column` of the CSV content of the provided reader
+    ///
+    /// Every value of the column is parsed like a line of a plain keep file.
+    /// If the column is selected by its index, the header row is optional.
+    ///
+    /// # Errors
+    /// - If the content is not valid CSV
+    /// - If the column is not found
+    /// - If any of the values is not valid
+    pub fn from_csv<R: Read, P: AsRef<Path>>(
+        reader: R,
+        column: &KeepColumn,
+        origin: P,
+    ) -> Result<KeepFile, KeepFileError> {
+        let mut records = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(reader)
+            .into_records();
+        let Some(header) = records.next().transpose()? else {
+            return Ok(KeepFile::default());
+        };
+        let (index, header) = match column {
+            KeepColumn::Name(name) => match header.iter().position(|cell| cell.trim() == name) {
+                Some(index) => (index, None),
+                None => return Err(KeepFileError::MissingColumn(name.clone())),
+            },
+            // The first row is a header row only if its value isn't valid
+            KeepColumn::Index(index) => {
+                let value = header.get(index - 1).unwrap_or_default().to_owned();
+                match KeepFile::from_reader(value.as_bytes(), origin.as_ref()) {
+                    Ok(_) => (index - 1, Some(value)),
+                    Err(_) => (index - 1, None),
+                }
+            }
+        };
+
+        // The header row is kept as a blank line, so the line numbers in errors match the rows
+        let mut content = header.unwrap_or_default();
+        for record in records {
+            content.push('\n');
+            content.push_str(record?.get(index).unwrap_or_default());
+        }
+        KeepFile::from_reader(content.as_bytes(), origin)
+    }
+
+    /// Parse the keepfile from the provided reader
+    ///
+    /// The `origin` is only used to describe where the content came from in errors.
+    ///
+    /// # Errors
+    /// - If the content is not valid
+    pub fn from_reader<R: BufRead, P: AsRef<Path>>(reader: R, origin: P) -> Result<KeepFile, KeepFileError> {
+        // Lines that can't be read, or aren't valid UTF-8, fail the whole file instead of being dropped
+        let content = reader.lines().collect::<Result<Vec<_>, _>>()?;
+        // Split the lines into valid and invalid lines
+        let (valid, invalid): (Vec<_>, Vec<_>) = content
+            .into_iter()
+            .enumerate()
+            // Parse the lines into numbers and annotations, skipping the comments, or return an error
+            .scan(KeepSection::default(), |section, (num, line)| match parse_line(&line) {
+                Ok(entry) => {
+                    if let KeepEntry::Section(started) = entry {
+                        *section = started;
+                    }
+                    let excluded = entry.modifiers().is_some_and(|modifiers| modifiers.excluded);
+                    Some(Ok((
+                        *section,
+                        excluded,
+                        entry.lines(),
+                        KeepFileBadLine(num + 1, line, None),
+                    )))
+                }
+                Err(e) => Some(Err(KeepFileBadLine(num + 1, line, Some(e)))),
+            })
+            .partition_result();
+
+        // Lines including numbers or file names already included by the previous lines are duplicates
+        let mut seen = HashSet::new();
+        let duplicates: Vec<_> = valid
+            .iter()
+            .filter(|(section, excluded, lines, _)| {
+                let repeated = lines
+                    .iter()
+                    .filter(|line| !seen.insert((*section, line.target().clone(), line.extensions().to_vec())))
+                    .count();
+                !excluded && repeated > 0
+            })
+            .map(|(_, _, _, KeepFileBadLine(num, line, _))| KeepFileBadLine(*num, line.clone(), None))
+            .collect();
+
+        // Exclusions only apply to the section they are in
+        let (kept, denied): (Vec<_>, Vec<_>) =
+            valid.into_iter().partition(|(section, ..)| *section == KeepSection::Keep);
+        let (lines, excluded) = KeepFile::resolve(kept.into_iter().map(|(_, excluded, lines, _)| (excluded, lines)));
+        let (denied, excluded_denied) =
+            KeepFile::resolve(denied.into_iter().map(|(_, excluded, lines, _)| (excluded, lines)));
+        let mut keepfile = KeepFile {
+            lines,
+            denied,
+            excluded,
+            excluded_denied,
+            ..KeepFile::default()
+        };
+        if !duplicates.is_empty() {
+            keepfile.duplicates.push(KeepFileDuplicates {
+                file: origin.as_ref().to_path_buf(),
+                lines: KeepFileFormatError(duplicates),
+            });
+        }
+        match invalid.is_empty() {
+            true => Ok(keepfile),
+            false => Err(KeepFileError::Format {
+                file: origin.as_ref().to_path_buf(),
+                lines: KeepFileFormatError(invalid),
+                partial: Box::new(keepfile),
+            }),
+        }
+    }
+
+    /// Split the parsed lines into the included and the excluded lines
+    ///
+    /// The included lines with exactly the target of an exclusion are dropped. The exclusions are kept too,
+    /// as they also apply to the files the other targets select, like `!IMG_0007.CR2` to the files of `7`.
+    fn resolve(parsed: impl IntoIterator<Item = (bool, Vec<KeepFileLine>)>) -> (Vec<KeepFileLine>, Vec<KeepFileLine>) {
+        let (excluded, included): (Vec<_>, Vec<_>) = parsed.into_iter().partition(|(excluded, _)| *excluded);
+        let excluded: Vec<KeepFileLine> = excluded.into_iter().flat_map(|(_, lines)| lines).collect();
+        let targets: HashSet<&KeepTarget> = excluded.iter().map(KeepFileLine::target).collect();
+        let included = included
+            .into_iter()
+            .flat_map(|(_, lines)| lines)
+            .filter(|line| !targets.contains(&line.target))
+            .collect();
+        (included, excluded)
+    }
+
+    /// Parse the keepfile from the contents of the system clipboard
+    ///
+    /// # Errors
+    /// - If the clipboard can't be accessed or doesn't contain text
+    /// - If the content is not valid
+    #[cfg(feature = "clipboard")]
+    pub fn from_clipboard() -> Result<KeepFile, KeepFileError> {
+        let text = arboard::Clipboard::new()
+            .and_then(|mut clipboard| clipboard.get_text())
+            .map_err(|e| KeepFileError::Clipboard(e.to_string()))?;
+        KeepFile::from_reader(text.as_bytes(), "<clipboard>")
+    }
+
+    /// Parse the keepfile from the contents of the system clipboard
+    ///
+    /// Always fails, as the crate was built without the `clipboard` feature.
+    #[cfg(not(feature = "clipboard"))]
+    pub fn from_clipboard() -> Result<KeepFile, KeepFileError> {
+        Err(KeepFileError::Clipboard(
+            "built without the `clipboard` feature".to_owned(),
+        ))
+    }
+
+    /// Merge the loaded keep files into one
+    ///
+    /// The exclusions of each keep file apply to the files the other keep files select as well.
+    ///
+    /// # Errors
+    /// - If any of the keep files failed to load, all the errors are reported
+    pub fn merge(loaded: impl IntoIterator<Item = Result<KeepFile, KeepFileError>>) -> Result<KeepFile, KeepFileError> {
+        let (mut keepfiles, mut errors): (Vec<_>, Vec<_>) = loaded.into_iter().partition_result();
+        match errors.len() {
+            0 => Ok(KeepFile {
+                sources: keepfiles.iter().flat_map(|keepfile| keepfile.sources.clone()).collect(),
+                duplicates: keepfiles
+                    .iter_mut()
+                    .flat_map(|keepfile| keepfile.duplicates.drain(..))
+                    .collect(),
+                skipped: keepfiles.iter_mut().flat_map(|keepfile| keepfile.skipped.drain(..)).collect(),
+                denied: keepfiles.iter_mut().flat_map(|keepfile| keepfile.denied.drain(..)).collect(),
+                excluded: keepfiles.iter_mut().flat_map(|keepfile| keepfile.excluded.drain(..)).collect(),
+                excluded_denied: keepfiles
+                    .iter_mut()
+                    .flat_map(|keepfile| keepfile.excluded_denied.drain(..))
+                    .collect(),
+                rejected: keepfiles.iter_mut().flat_map(|keepfile| keepfile.rejected.drain(..)).collect(),
+                excluded_rejected: keepfiles
+                    .iter_mut()
+                    .flat_map(|keepfile| keepfile.excluded_rejected.drain(..))
+                    .collect(),
+                lines: keepfiles.into_iter().flat_map(|keepfile| keepfile.lines).collect(),
+                number_pattern: NumberPattern::default(),
+            }),
+            1 => Err(errors.remove(0)),
+            _ => Err(KeepFileError::Multiple(KeepFileErrors(errors))),
+        }
+    }
+
+    /// Never keep the files the other keep file selects, like a reject list maintained apart from the picks
+    ///
+    /// The lines to keep of the other keep file reject the files they select, unless its `[delete]` section
+    /// or its exclusions take them out of the reject list. The sections of this keep file alone decide
+    /// whether the files nothing selects are kept.
+    pub fn exclude(mut self, rejected: KeepFile) -> Self {
+        self.sources.extend(rejected.sources);
+        self.duplicates.extend(rejected.duplicates);
+        self.skipped.extend(rejected.skipped);
+        self.rejected.extend(rejected.lines);
+        self.excluded_rejected.extend(rejected.excluded);
+        // The `[delete]` section of a reject list names the files it doesn't reject, while the exclusions
+        // of that section only spare files from it, so they can't reject any file and are left out
+        self.excluded_rejected.extend(rejected.denied);
+        self
+    }
+
+    /// Accept the keep file even if some of its lines are invalid, skipping them
+    ///
+    /// The skipped lines are reported by [KeepFile::skipped] instead.
+    ///
+    /// # Errors
+    /// - If the keep file failed to load for any other reason
+    pub fn skip_invalid(loaded: Result<KeepFile, KeepFileError>) -> Result<KeepFile, KeepFileError> {
+        match loaded {
+            Err(KeepFileError::Format { file, lines, partial }) => {
+                let mut keepfile = *partial;
+                keepfile.sources = file.canonicalize().into_iter().collect();
+                keepfile.skipped.push(KeepFileSkipped { file, lines });
+                Ok(keepfile)
+            }
+            loaded => loaded,
+        }
+    }
+
+    /// Get the invalid lines skipped instead of rejecting the keep file, for every keep file
+    pub fn skipped(&self) -> &[KeepFileSkipped] {
+        &self.skipped
+    }
+
+    /// Get the lines repeating the numbers or file names of the previous lines, for every keep file
+    pub fn duplicates(&self) -> &[KeepFileDuplicates] {
+        &self.duplicates
+    }
+
+    /// Reject the keep file if any of its lines repeat the numbers or file names of the previous lines
+    ///
+    /// # Errors
+    /// - If the keep file contains duplicates
+    pub fn deny_duplicates(mut self) -> Result<Self, KeepFileError> {
+        match self.duplicates.len() {
+            0 => Ok(self),
+            1 => Err(KeepFileError::Duplicates(self.duplicates.remove(0))),
+            _ => Err(KeepFileError::Multiple(KeepFileErrors(
+                self.duplicates.into_iter().map(KeepFileError::Duplicates).collect(),
+            ))),
+        }
+    }
+
+    /// Compare the file names by the number extracted with the pattern
+    pub fn with_number_pattern(mut self, number_pattern: NumberPattern) -> Self {
+        self.number_pattern = number_pattern;
+        self
+    }
+
+    /// Get the pattern extracting the number a file name is compared by
+    pub fn number_pattern(&self) -> &NumberPattern {
+        &self.number_pattern
+    }
+
+    /// Get the paths the keep file was loaded from
+    pub fn sources(&self) -> &[PathBuf] {
+        &self.sources
+    }
+
+    /// Get an iterator over the list of numbers to keep
+    pub fn iter(&self) -> std::slice::Iter<'_, KeepFileLine> {
+        self.lines.iter()
+    }
+
+    /// Get a mutable iterator over the list of numbers to keep
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, KeepFileLine> {
+        self.lines.iter_mut()
+    }
+
+    /// Add the line to the end of the keep file
+    pub fn push(&mut self, line: KeepFileLine) {
+        self.lines.push(line);
+    }
+
+    /// Remove all the lines keeping the target, returning how many were removed
+    pub fn remove(&mut self, target: &KeepTarget) -> usize {
+        let before = self.lines.len();
+        self.lines.retain(|line| line.target() != target);
+        before - self.lines.len()
+    }
+
+    /// Sort the lines by their targets, keeping the order of the lines with the same target
+    pub fn sort(&mut self) {
+        self.lines.sort_by(|a, b| a.target().cmp(b.target()));
+    }
+
+    /// Remove the lines repeating the target and the extensions of a previous line
+    pub fn dedup(&mut self) {
+        let mut seen = HashSet::new();
+        self.lines
+            .retain(|line| seen.insert((line.target().clone(), line.extensions().to_vec())));
+    }
+
+    /// Write the keep file in the plain format, one line per target, followed by its annotation
+    ///
+    /// # Errors
+    /// - If the keep file can't be written
+    pub fn write<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        let write_line = |writer: &mut W, line: &KeepFileLine| match line.annotation() {
+            Some(annotation) => writeln!(writer, "{line}  # {annotation}"),
+            None => writeln!(writer, "{line}"),
+        };
+        for line in self.lines.iter() {
+            write_line(&mut writer, line)?;
+        }
+        for line in self.excluded.iter() {
+            write!(writer, "!")?;
+            write_line(&mut writer, line)?;
+        }
+        if !self.denied.is_empty() || !self.excluded_denied.is_empty() {
+            writeln!(writer, "[delete]")?;
+            for line in self.denied.iter() {
+                write_line(&mut writer, line)?;
+            }
+            for line in self.excluded_denied.iter() {
+                write!(writer, "!")?;
+                write_line(&mut writer, line)?;
+            }
+        }
+        writer.flush()
+    }
+
+    /// Save the keep file to the provided path in the plain format, so it can be loaded again
+    ///
+    /// # Errors
+    /// - If the file can't be created or written
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+
+    /// Get the numbers to keep, in the order they appear in the keep file
+    ///
+    /// Kept file names contribute the number they contain.
+    pub fn numbers(&self) -> Vec<u32> {
+        self.lines.iter().filter_map(KeepFileLine::number).collect()
+    }
+
+    /// Get the annotations of the numbers
+    pub fn annotations(&self) -> HashMap<u32, String> {
+        self.lines
+            .iter()
+            .filter_map(|line| Some((line.number()?, line.annotation()?.to_owned())))
+            .collect()
+    }
+
+    /// Count the distinct numbers and file names in the keep file that match at least one of the files
+    pub fn count_found<'a>(&self, files: impl IntoIterator<Item = &'a PathBuf>) -> usize {
+        let (found, _) = self.partition_found(files);
+        found.len()
+    }
+
+    /// Get the distinct numbers and file names in the keep file that don't match any of the files,
+    /// in the order they appear in the keep file
+    pub fn unmatched<'a>(&self, files: impl IntoIterator<Item = &'a PathBuf>) -> Vec<&KeepFileLine> {
+        let (_, unmatched) = self.partition_found(files);
+        unmatched
+    }
+
+    /// Split the distinct targets in the keep file into the ones matching at least one of the files,
+    /// and the ones that don't match any
+    fn partition_found<'a>(
+        &self,
+        files: impl IntoIterator<Item = &'a PathBuf>,
+    ) -> (Vec<&KeepFileLine>, Vec<&KeepFileLine>) {
+        let names: HashSet<&str> = files.into_iter().filter_map(|path| path.file_name()?.to_str()).collect();
+        let mut numbers: HashMap<u32, Vec<&str>> = HashMap::new();
+        for name in names.iter() {
+            if let Some(num) = self.number_pattern.extract(name) {
+                numbers.entry(num).or_default().push(name);
+            }
+        }
+        self.lines
+            .iter()
+            .unique_by(|line| (line.target(), line.extensions()))
+            .partition(|line| match line.target() {
+                KeepTarget::Number(num) => numbers
+                    .get(num)
+                    .is_some_and(|names| names.iter().any(|name| line.allows_extension(name))),
+                KeepTarget::FileName(name) => names.contains(name.as_str()) && line.allows_extension(name),
+                KeepTarget::Pattern(pattern) => {
+                    names.iter().any(|name| pattern.matches(name) && line.allows_extension(name))
+                }
+            })
+    }
+
+    /// Extract the number a file name is compared by, with the default [NumberPattern]
+    pub fn extract_number(filename: &str) -> Option<u32> {
+        NumberPattern::default().extract(filename)
+    }
+
+    /// Check if a file name matches contains a number
+    ///
+    /// This method checks if a file name contains a number that matches the specified number.
+    pub fn matches_number(filename: &str, num: u32) -> bool {
+        Self::extract_number(filename).is_some_and(|m| m == num)
+    }
+
+    /// Explain whether the file name is kept, and by which line
+    pub fn explain(&self, filename: &str) -> Step {
+        let selects = |line: &&KeepFileLine| {
+            line.target().matches(filename, &self.number_pattern) && line.allows_extension(filename)
+        };
+        if let Some(line) = self.denied.iter().find(selects) {
+            if !self.excluded_denied.iter().any(|excluded| selects(&excluded)) {
+                return Step::reject(
+                    "keep file",
+                    format!("deleted by the line \"{line}\" of the [delete] section"),
+                );
+            }
+        }
+        if let Some(line) = self.rejected.iter().find(selects) {
+            if !self.excluded_rejected.iter().any(|excluded| selects(&excluded)) {
+                return Step::reject(
+                    "keep file",
+                    format!("rejected by the line \"{line}\" of the reject list"),
+                );
+            }
+        }
+        if let Some(line) = self.excluded.iter().find(selects) {
+            return Step::reject("keep file", format!("excluded by the line \"!{line}\""));
+        }
+        if let Some(line) = self.lines.iter().find(selects) {
+            return Step::accept("keep file", format!("kept by the line \"{line}\""));
+        }
+        if self.lines.is_empty() && !self.denied.is_empty() {
+            return Step::accept("keep file", "kept, as the keep file only has a [delete] section");
+        }
+        match self.number_pattern.extract(filename) {
+            Some(number) => Step::reject("keep file", format!("the number {number} isn't kept by any line")),
+            None => Step::reject(
+                "keep file",
+                format!("no number matches the number pattern {}", self.number_pattern),
+            ),
+        }
+    }
+
+    /// Convert the keep file into an inclusive filter
+    ///
+    /// Filter will allow files that were found in the keepfile
+    ///
+    /// The filter function takes a reference to a `PathBuf` and returns a boolean indicating whether the file should be kept.
+    ///
+    pub fn into_inclusion_matcher(self) -> Rc<dyn Fn(&&PathBuf) -> bool> {
+        let matcher = self.into_matcher();
+        Rc::new(move |path| {
+            let Some(filename) = path.file_name().and_then(|f| f.to_str()) else {
+                return false;
+            };
+            matcher.keeps(filename)
+        })
+    }
+
+    /// Convert the keep file into an inclusive filter
+    ///
+    /// Filter will allow files that were **not** found in the keep file
+    ///
+    /// The filter function takes a reference to a `PathBuf` and returns a boolean indicating whether the file should be kept.
+    pub fn into_exclusion_matcher(self) -> Rc<dyn Fn(&&PathBuf) -> bool> {
+        let matcher = self.into_matcher();
+        Rc::new(move |path| {
+            let Some(filename) = path.file_name().and_then(|f| f.to_str()) else {
+                return false;
+            };
+            !matcher.keeps(filename)
+        })
+    }
+
+    /// Convert the keep file into a matcher combining the lines to keep with the `[delete]` section
+    pub fn into_matcher(self) -> KeepMatcher {
+        KeepMatcher {
+            keep_all: self.lines.is_empty() && !self.denied.is_empty(),
+            kept: KeepLookup::new(self.lines, self.number_pattern.clone()),
+            denied: KeepLookup::new(self.denied, self.number_pattern.clone()),
+            excluded: KeepLookup::new(self.excluded, self.number_pattern.clone()),
+            excluded_denied: KeepLookup::new(self.excluded_denied, self.number_pattern.clone()),
+            rejected: KeepLookup::new(self.rejected, self.number_pattern.clone()),
+            excluded_rejected: KeepLookup::new(self.excluded_rejected, self.number_pattern),
+        }
+    }
+}
+
+/// Matcher deciding whether a file is kept, by the lines to keep and the lines of the `[delete]` section
+///
+/// A file is kept if any line to keep selects it, and no line of the `[delete]` section does.
+/// If the keep file only has the `[delete]` section, all the files it doesn't select are kept.
+/// The exclusions of a section take the files they select out of that section.
+/// The files selected by the reject list given apart are never kept either.
+#[derive(Debug)]
+pub struct KeepMatcher {
+    kept: KeepLookup,
+    denied: KeepLookup,
+    excluded: KeepLookup,
+    excluded_denied: KeepLookup,
+    rejected: KeepLookup,
+    excluded_rejected: KeepLookup,
+    keep_all: bool,
+}
+
+impl KeepMatcher {
+    /// Check if the file with the name is kept
+    pub fn keeps(&self, filename: &str) -> bool {
+        let kept = (self.keep_all || self.kept.contains(filename)) && !self.excluded.contains(filename);
+        let denied = self.denied.contains(filename) && !self.excluded_denied.contains(filename);
+        let rejected = self.rejected.contains(filename) && !self.excluded_rejected.contains(filename);
+        kept && !denied && !rejected
+    }
+}
+
+/// Get the lowercase extension of the file name
+fn extension_of(filename: &str) -> String {
+    Path::new(filename)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+/// Allow the target for the extensions, in addition to the ones it is already allowed for
+///
+/// `None` allows the target for all the extensions.
+fn merge_extensions<K>(entry: Entry<K, Option<HashSet<String>>>, extensions: Option<Vec<String>>) {
+    match (entry, extensions) {
+        (Entry::Vacant(entry), extensions) => {
+            entry.insert(extensions.map(HashSet::from_iter));
+        }
+        (Entry::Occupied(mut entry), Some(extensions)) => {
+            if let Some(allowed) = entry.get_mut() {
+                allowed.extend(extensions);
+            }
+        }
+        (Entry::Occupied(mut entry), None) => {
+            entry.insert(None);
+        }
+    }
+}
+
+/// Targets of the keep file, indexed by their kind, with the extensions they are kept for
+///
+/// The number of a file name is extracted once, and looked up in constant time.
+#[derive(Debug)]
+struct KeepLookup {
+    numbers: HashMap<u32, Option<HashSet<String>>>,
+    names: HashMap<String, Option<HashSet<String>>>,
+    patterns: Vec<(glob::Pattern, Option<HashSet<String>>)>,
+    number_pattern: NumberPattern,
+}
+
+impl KeepLookup {
+    /// Index the targets of the lines, so files can be looked up without going through all the lines
+    fn new(lines: Vec<KeepFileLine>, number_pattern: NumberPattern) -> Self {
+        let mut lookup = KeepLookup {
+            numbers: HashMap::new(),
+            names: HashMap::new(),
+            patterns: vec![],
+            number_pattern,
+        };
+        for KeepFileLine { target, extensions, .. } in lines {
+            let extensions = Some(extensions).filter(|extensions| !extensions.is_empty());
+            match target {
+                KeepTarget::Number(num) => merge_extensions(lookup.numbers.entry(num), extensions),
+                KeepTarget::FileName(name) => merge_extensions(lookup.names.entry(name), extensions),
+                KeepTarget::Pattern(pattern) => lookup.patterns.push((pattern, extensions.map(HashSet::from_iter))),
+            }
+        }
+        lookup
+    }
+
+    /// Check if the file name is selected by any of the targets
+    fn contains(&self, filename: &str) -> bool {
+        let allows = |extensions: &Option<HashSet<String>>| match extensions {
+            Some(extensions) => extensions.contains(&extension_of(filename)),
+            None => true,
+        };
+        self.names.get(filename).is_some_and(allows)
+            || self
+                .number_pattern
+                .extract(filename)
+                .and_then(|num| self.numbers.get(&num))
+                .is_some_and(allows)
+            || self
+                .patterns
+                .iter()
+                .any(|(pattern, extensions)| pattern.matches(filename) && allows(extensions))
+    }
+}
+
+impl Display for KeepFileBadLine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let KeepFileBadLine(line, content, reason) = self;
+        match reason {
+            Some(reason) => write!(f, "Line {line}: {content} ({reason})"),
+            None => write!(f, "Line {line}: {content}"),
+        }
+    }
+}
+
+impl Display for KeepFileFormatError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for line in self.0.iter() {
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Error type for keep file loading
+///
+/// This type represents the possible errors that can occur while loading the keep file.
+#[derive(thiserror::Error, Debug)]
+pub enum KeepFileError {
+    /// The keep file contains invalid lines
+    #[error("One or more lines in the keepfile \"{}\" are invalid:\n{}", .file.display(), .lines)]
+    Format {
+        file: PathBuf,
+        lines: KeepFileFormatError,
+        /// The keep file without the invalid lines
+        partial: Box<KeepFile>,
+    },
+    /// An I/O error occurred while reading the keep file
+    #[error("Keepfile I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// The keep list couldn't be read from the clipboard
+    #[error("Clipboard error: {0}")]
+    Clipboard(String),
+    /// The keep file repeats numbers, and duplicates are not allowed
+    #[error("{0}")]
+    Duplicates(KeepFileDuplicates),
+    /// Several keep files failed to load
+    #[error("{0}")]
+    Multiple(KeepFileErrors),
+    /// The CSV keep file couldn't be read
+    #[error("Keepfile CSV error: {0}")]
+    Csv(#[from] csv::Error),
+    /// The CSV keep file doesn't have the column
+    #[error("Column \"{0}\" not found in the keepfile")]
+    MissingColumn(String),
+}
+
+/// Errors of the keep files that failed to load
+#[derive(Debug)]
+pub struct KeepFileErrors(pub Vec<KeepFileError>);
+
+impl Display for KeepFileErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for error in self.0.iter() {
+            writeln!(f, "{error}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::*;
+
+    use super::*;
+
+    #[test]
+    pub fn test_load_keepfile() -> TestResult {
+        KeepFile::try_load(resource_dir().join("keep.txt"))?;
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_load_keepfile_error() -> TestResult {
+        let result = KeepFile::try_load(resource_dir().join("keep_bad.txt"));
+        assert!(result.is_err());
+
+        let error = result.unwrap_err();
+
+        match error {
+            KeepFileError::Format { file, lines, .. } => {
+                assert_eq!(file, resource_dir().join("keep_bad.txt"));
+                assert_eq!(lines.0.len(), 2);
+
+                let mut lines = lines.0.iter();
+                let error = lines.next().unwrap();
+                assert_eq!(error.0, 1);
+                assert_eq!(error.1, "daf");
+                let error = lines.next().unwrap();
+                assert_eq!(error.0, 2);
+                assert_eq!(error.1, "hello");
+
+                assert!(lines.next().is_none(), "No more errors");
+            }
+            _ => panic!("Unexpected error: {:?}", error),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_properly_loaded() -> TestResult {
+        let keepfile = KeepFile::try_load(resource_dir().join("keep.txt"))?;
+        assert_eq!(keepfile.lines.len(), 2);
+        // Keep TXT_1
+        assert_eq!(keepfile.lines[0].number(), Some(1));
+        // Keep TXT_4
+        assert_eq!(keepfile.lines[1].number(), Some(4));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_from_reader() -> TestResult {
+        let keepfile = KeepFile::from_reader("12\n 7 \n".as_bytes(), "memory")?;
+        assert_eq!(keepfile.lines.len(), 2);
+        assert_eq!(keepfile.lines[0].number(), Some(12));
+        assert_eq!(keepfile.lines[1].number(), Some(7));
+        assert!(keepfile.sources().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_unreadable_lines() {
+        let error = KeepFile::from_reader(&b"12\n\xff\n7\n"[..], "memory").unwrap_err();
+        assert!(matches!(error, KeepFileError::Io(_)), "Unexpected error: {error:?}");
+    }
+
+    #[test]
+    pub fn test_keepfile_annotations() -> TestResult {
+        let keepfile = KeepFile::from_reader("123  # bride portrait\n7 #\n".as_bytes(), "memory")?;
+        assert_eq!(keepfile.numbers(), vec![123, 7]);
+        assert_eq!(keepfile.lines[0].annotation(), Some("bride portrait"));
+        assert_eq!(keepfile.lines[1].annotation(), None);
+        assert_eq!(keepfile.annotations()[&123], "bride portrait");
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_comments() -> TestResult {
+        let keepfile = KeepFile::from_reader("# ceremony\n\n12\n   \n  # reception\n7 # toast\n".as_bytes(), "memory")?;
+        assert_eq!(keepfile.numbers(), vec![12, 7]);
+        assert_eq!(keepfile.annotations()[&7], "toast");
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_ranges() -> TestResult {
+        let keepfile = KeepFile::from_reader("120-123\n7\n 200 .. 201 # ceremony\n".as_bytes(), "memory")?;
+        assert_eq!(keepfile.numbers(), vec![120, 121, 122, 123, 7, 200, 201]);
+        assert_eq!(keepfile.annotations()[&201], "ceremony");
+
+        let result = KeepFile::from_reader("145-120\n1-\n".as_bytes(), "memory");
+        assert!(matches!(result, Err(KeepFileError::Format { lines, .. }) if lines.0.len() == 2));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_range_steps() -> TestResult {
+        let keepfile = KeepFile::from_reader("100-106:2\n201..203:5 jpg\n".as_bytes(), "memory")?;
+        assert_eq!(keepfile.numbers(), vec![100, 102, 104, 106, 201]);
+        assert_eq!(keepfile.lines[4].extensions(), ["jpg"]);
+
+        let result = KeepFile::from_reader("100-200:0\n100-200:x\n100:2\n".as_bytes(), "memory");
+        let Err(KeepFileError::Format { lines, .. }) = result else {
+            panic!("expected a format error, got {result:?}");
+        };
+        assert_eq!(
+            lines.to_string(),
+            "Line 1: 100-200:0 (the step \"0\" is not a positive number)\n\
+             Line 2: 100-200:x (the step \"x\" is not a positive number)\n\
+             Line 3: 100:2 (only ranges can have a step, like 100-200:2)\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_count_found() -> TestResult {
+        let keepfile = KeepFile::from_reader("1\n4\n1\n9\n".as_bytes(), "memory")?;
+        let files: Vec<_> = ["IMG_0001.jpg", "IMG_0001.cr2", "IMG_0004.jpg", "IMG_0005.jpg"]
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(keepfile.count_found(&files), 2);
+        let unmatched: Vec<_> = keepfile.unmatched(&files).into_iter().map(KeepFileLine::target).collect();
+        assert_eq!(unmatched, vec![&KeepTarget::Number(9)]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_exclusions() -> TestResult {
+        let keepfile = KeepFile::from_reader("!122\n120-125\n!124-125 # blurry\n7\n".as_bytes(), "memory")?;
+        assert_eq!(keepfile.numbers(), vec![120, 121, 123, 7]);
+
+        // Exclusions select the files like the other lines, not only the lines with the same target
+        let keepfile = KeepFile::from_reader("120-125\n!IMG_0122.CR2\n!IMG_0124.*\n".as_bytes(), "memory")?;
+        let dir = tempfile::tempdir()?;
+        keepfile.save(dir.path().join("keep.txt"))?;
+        for keepfile in [keepfile, KeepFile::try_load(dir.path().join("keep.txt"))?] {
+            let matcher = keepfile.into_matcher();
+            assert!(matcher.keeps("IMG_0122.jpg"));
+            assert!(!matcher.keeps("IMG_0122.CR2"));
+            assert!(!matcher.keeps("IMG_0124.jpg"));
+            assert!(matcher.keeps("IMG_0125.jpg"));
+        }
+
+        let result = KeepFile::from_reader("!\n!abc\n".as_bytes(), "memory");
+        assert!(matches!(result, Err(KeepFileError::Format { lines, .. }) if lines.0.len() == 2));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_file_names() -> TestResult {
+        let keepfile = KeepFile::from_reader("IMG_0451.CR2\n7\n!IMG_0007.CR2\n".as_bytes(), "memory")?;
+        assert_eq!(
+            keepfile.lines[0].target(),
+            &KeepTarget::FileName("IMG_0451.CR2".to_owned())
+        );
+        assert_eq!(keepfile.numbers(), vec![451, 7]);
+
+        let matcher = keepfile.into_matcher();
+        assert!(matcher.keeps("IMG_0451.CR2"));
+        assert!(!matcher.keeps("IMG_0451.JPG"));
+        assert!(matcher.keeps("IMG_0007.JPG"));
+        assert!(!matcher.keeps("IMG_0007.CR2"));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_globs() -> TestResult {
+        let keepfile = KeepFile::from_reader("IMG_04*.CR2\n7\n".as_bytes(), "memory")?;
+        assert_eq!(keepfile.numbers(), vec![7]);
+
+        let matcher = keepfile.into_inclusion_matcher();
+        assert!(matcher(&&PathBuf::from("IMG_0451.CR2")));
+        assert!(!matcher(&&PathBuf::from("IMG_0451.JPG")));
+        assert!(!matcher(&&PathBuf::from("IMG_0551.CR2")));
+        assert!(matcher(&&PathBuf::from("IMG_0007.JPG")));
+
+        let matcher = KeepFile::from_reader("7\n8\n!IMG_000[78].CR2\n".as_bytes(), "memory")?.into_matcher();
+        assert!(matcher.keeps("IMG_0007.JPG"));
+        assert!(!matcher.keeps("IMG_0007.CR2"));
+        assert!(!matcher.keeps("IMG_0008.CR2"));
+
+        let result = KeepFile::from_reader("IMG_[04*.CR2\n".as_bytes(), "memory");
+        assert!(matches!(result, Err(KeepFileError::Format { lines, .. }) if lines.0.len() == 1));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_merge_keepfiles() -> TestResult {
+        let keepfile = KeepFile::merge([
+            KeepFile::try_load(resource_dir().join("keep.txt")),
+            KeepFile::from_reader("7\n".as_bytes(), "memory"),
+        ])?;
+        assert_eq!(keepfile.numbers(), vec![1, 4, 7]);
+        assert_eq!(keepfile.sources(), [resource_dir().join("keep.txt")]);
+
+        let result = KeepFile::merge([
+            KeepFile::try_load(resource_dir().join("keep_bad.txt")),
+            KeepFile::try_load(resource_dir().join("keep.txt")),
+            KeepFile::from_reader("hello\n".as_bytes(), "memory"),
+        ]);
+        assert!(matches!(result, Err(KeepFileError::Multiple(errors)) if errors.0.len() == 2));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_from_csv() -> TestResult {
+        let csv = "Rating,File\n5,IMG_0451.CR2\n4,\"12\"\n";
+        let keepfile = KeepFile::from_csv(csv.as_bytes(), &"File".parse()?, "memory.csv")?;
+        assert_eq!(keepfile.numbers(), vec![451, 12]);
+
+        let keepfile = KeepFile::from_csv("7,a\n9,b\n".as_bytes(), &"1".parse()?, "memory.csv")?;
+        assert_eq!(keepfile.numbers(), vec![7, 9]);
+
+        let result = KeepFile::from_csv(csv.as_bytes(), &"Name".parse()?, "memory.csv");
+        assert!(matches!(result, Err(KeepFileError::MissingColumn(name)) if name == "Name"));
+        let result = KeepFile::from_csv("File\nIMG\nhello\n".as_bytes(), &KeepColumn::Index(1), "memory.csv");
+        assert!(matches!(result, Err(KeepFileError::Format { lines, .. }) if lines.0.len() == 2));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_number_pattern() -> TestResult {
+        let keepfile = KeepFile::from_reader("123\n".as_bytes(), "memory")?;
+        let pattern = NumberPattern::from(Regex::new(r"_(?<number>\d+)\.")?);
+        assert_eq!(pattern.extract("20240601_0123.jpg"), Some(123));
+        assert_eq!(KeepFile::extract_number("20240601_0123.jpg"), Some(20240601));
+
+        let matcher = keepfile.with_number_pattern(pattern).into_inclusion_matcher();
+        assert!(matcher(&&PathBuf::from("20240601_0123.jpg")));
+        assert!(!matcher(&&PathBuf::from("0123_20240601.jpg")));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_duplicates() -> TestResult {
+        let keepfile = KeepFile::from_reader("1\n4\n!4\n3-5\n1 # again\n".as_bytes(), "memory")?;
+        assert_eq!(keepfile.duplicates().len(), 1);
+        let lines = &keepfile.duplicates()[0].lines.0;
+        assert_eq!(lines.iter().map(|line| line.0).collect::<Vec<_>>(), vec![4, 5]);
+
+        assert!(matches!(keepfile.deny_duplicates(), Err(KeepFileError::Duplicates(_))));
+        assert!(KeepFile::from_reader("1\n2\n".as_bytes(), "memory")?.deny_duplicates().is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_number_position() -> TestResult {
+        let filename = "2024_05_IMG_0123.jpg";
+        let pattern =
+            |position: &str| -> Result<_, String> { Ok(NumberPattern::default().with_position(position.parse()?)) };
+        assert_eq!(pattern("first")?.extract(filename), Some(2024));
+        assert_eq!(pattern("last")?.extract(filename), Some(123));
+        assert_eq!(pattern("nth(2)")?.extract(filename), Some(5));
+        assert_eq!(pattern("nth(5)")?.extract(filename), None);
+        assert!("nth(0)".parse::<NumberPosition>().is_err());
+        assert!("second".parse::<NumberPosition>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_strict_number_width() -> TestResult {
+        let pattern = NumberPattern::from(Regex::new(r"IMG_(\d{3})")?);
+        assert_eq!(pattern.extract("IMG_0070.jpg"), Some(7));
+        let pattern = pattern.with_strict_width(true);
+        assert_eq!(pattern.extract("IMG_0070.jpg"), None);
+        assert_eq!(pattern.extract("IMG_007.jpg"), Some(7));
+        assert_eq!(
+            NumberPattern::default().with_strict_width(true).extract("IMG_0007.jpg"),
+            Some(7)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_token_number_matching() -> TestResult {
+        let pattern = NumberPattern::from(Regex::new(r"(\d{3})")?).with_matching(NumberMatching::Token);
+        assert_eq!(pattern.extract("IMG_1003.jpg"), Some(1003));
+        assert_eq!(
+            pattern
+                .clone()
+                .with_position(NumberPosition::Last)
+                .extract("2024-05_IMG_0100.jpg"),
+            Some(100)
+        );
+        assert_eq!(pattern.extract("notes.txt"), None);
+
+        let keepfile = KeepFile::from_reader("100\n".as_bytes(), "memory")?;
+        let matcher = keepfile.with_number_pattern(pattern).into_matcher();
+        assert!(!matcher.keeps("IMG_1003.jpg"));
+        assert!(matcher.keeps("IMG_100.jpg"));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_large_keepfile_lookup() -> TestResult {
+        let keepfile = KeepFile::from_reader("1-50000\n!25000\n".as_bytes(), "memory")?;
+        let matcher = keepfile.into_exclusion_matcher();
+        let files: Vec<_> = (0..60000).map(|num| PathBuf::from(format!("IMG_{num:05}.jpg"))).collect();
+        let rejected: Vec<_> = files.iter().filter(|file| matcher(file)).collect();
+        assert_eq!(rejected.len(), 10001);
+        assert!(rejected.contains(&&PathBuf::from("IMG_25000.jpg")));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_extension_qualifiers() -> TestResult {
+        let keepfile = KeepFile::from_reader("123 jpg,CR2\n7 jpg\n8\n8 jpg\n".as_bytes(), "memory")?;
+        assert_eq!(keepfile.lines[0].extensions(), ["jpg", "cr2"]);
+        assert!(keepfile.duplicates().is_empty());
+
+        let files = [PathBuf::from("IMG_0007.cr2"), PathBuf::from("IMG_0008.cr2")];
+        assert_eq!(keepfile.unmatched(&files).len(), 3);
+
+        let matcher = keepfile.into_inclusion_matcher();
+        assert!(matcher(&&PathBuf::from("IMG_0123.cr2")));
+        assert!(!matcher(&&PathBuf::from("IMG_0123.png")));
+        assert!(matcher(&&PathBuf::from("IMG_0007.jpg")));
+        assert!(!matcher(&&PathBuf::from("IMG_0007.cr2")));
+        assert!(matcher(&&PathBuf::from("IMG_0008.cr2")));
+
+        let result = KeepFile::from_reader("!7 jpg\n7 jpg;cr2\n".as_bytes(), "memory");
+        assert!(matches!(result, Err(KeepFileError::Format { lines, .. }) if lines.0.len() == 2));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_edit_and_save() -> TestResult {
+        let mut keepfile = KeepFile::from_reader("12  # bride\nIMG_0003.CR2\n7 jpg\n12\n".as_bytes(), "memory")?;
+        keepfile.push(KeepFileLine::new(KeepTarget::Number(5)).with_annotation("rings"));
+        keepfile.push(KeepFileLine::new(KeepTarget::Number(9)).with_extensions([".CR2"]));
+        assert_eq!(keepfile.remove(&KeepTarget::Number(7)), 1);
+        keepfile.dedup();
+        keepfile.sort();
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("keep.txt");
+        keepfile.save(&path)?;
+        assert_eq!(
+            std::fs::read_to_string(&path)?,
+            "5  # rings\n9 cr2\n12  # bride\nIMG_0003.CR2\n"
+        );
+
+        let loaded = KeepFile::try_load(&path)?;
+        assert_eq!(loaded.numbers(), vec![5, 9, 12, 3]);
+        assert_eq!(loaded.annotations().get(&12).map(String::as_str), Some("bride"));
+        assert!(loaded.duplicates().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_windows_line_endings() -> TestResult {
+        let keepfile = KeepFile::from_reader("\u{feff}12\r\n 13 \r\n\r\n14  # last\r\n".as_bytes(), "memory")?;
+        assert_eq!(keepfile.numbers(), vec![12, 13, 14]);
+        assert_eq!(keepfile.annotations()[&14], "last");
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_skip_invalid() -> TestResult {
+        let keepfile = KeepFile::skip_invalid(KeepFile::from_reader("12\nhello\n13\n".as_bytes(), "memory"))?;
+        assert_eq!(keepfile.numbers(), vec![12, 13]);
+        assert_eq!(keepfile.skipped().len(), 1);
+        assert_eq!(keepfile.skipped()[0].lines.0[0].1, "hello");
+
+        let result = KeepFile::skip_invalid(KeepFile::try_load("missing.txt"));
+        assert!(matches!(result, Err(KeepFileError::Io(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_sections() -> TestResult {
+        let content = "120-125\n[delete]\n122\nIMG_0124.CR2\n!124\n[keep]\n130\n";
+        let keepfile = KeepFile::from_reader(content.as_bytes(), "memory")?;
+        assert_eq!(keepfile.numbers(), vec![120, 121, 122, 123, 124, 125, 130]);
+        assert_eq!(keepfile.denied.len(), 2);
+        assert!(keepfile.duplicates().is_empty());
+
+        let dir = tempfile::tempdir()?;
+        keepfile.save(dir.path().join("keep.txt"))?;
+        let matcher = KeepFile::try_load(dir.path().join("keep.txt"))?.into_matcher();
+        assert!(matcher.keeps("IMG_0121.jpg"));
+        assert!(!matcher.keeps("IMG_0122.jpg"));
+        // The exclusion spares all the files of the number from the [delete] section, the file name included
+        assert!(matcher.keeps("IMG_0124.jpg"));
+        assert!(matcher.keeps("IMG_0124.CR2"));
+        assert!(matcher.keeps("IMG_0130.jpg"));
+        assert!(!matcher.keeps("IMG_0131.jpg"));
+
+        let matcher = KeepFile::from_reader("[delete]\n7\n".as_bytes(), "memory")?.into_matcher();
+        assert!(matcher.keeps("IMG_0008.jpg"));
+        assert!(!matcher.keeps("IMG_0007.jpg"));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_explain() -> TestResult {
+        let keepfile = KeepFile::from_reader("120-125\n[delete]\n122\n".as_bytes(), "memory")?;
+        let step = keepfile.explain("IMG_0121.jpg");
+        assert!(step.accepted);
+        assert!(step.reason.contains("\"121\""));
+        assert!(!keepfile.explain("IMG_0122.jpg").accepted);
+        assert!(keepfile.explain("IMG_0126.jpg").reason.contains("126"));
+        assert!(!keepfile.explain("notes.txt").accepted);
+
+        let keepfile = KeepFile::from_reader("[delete]\n7\n".as_bytes(), "memory")?;
+        assert!(keepfile.explain("IMG_0008.jpg").accepted);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_exclude() -> TestResult {
+        let keepfile = KeepFile::try_load(resource_dir().join("keep.txt"))?;
+        let rejected = KeepFile::from_reader(
+            "4
+10-12
+[delete]
+1
+"
+            .as_bytes(),
+            "memory",
+        )?;
+        let matcher = keepfile.exclude(rejected).into_matcher();
+        assert!(matcher.keeps("IMG_0001.jpg"));
+        assert!(!matcher.keeps("IMG_0004.jpg"));
+        assert!(!matcher.keeps("IMG_0011.jpg"));
+
+        // The reject list doesn't make a keep file without lines to keep keep all the other files
+        let rejected = KeepFile::from_reader("4\n".as_bytes(), "memory")?;
+        let matcher = KeepFile::default().exclude(rejected).into_matcher();
+        assert!(!matcher.keeps("IMG_0001.jpg"));
+        assert!(!matcher.keeps("IMG_0004.jpg"));
+
+        // The [delete] section of the reject list takes the files out of it
+        let keepfile = KeepFile::from_reader("[delete]\n7\n".as_bytes(), "memory")?;
+        let rejected = KeepFile::from_reader("1-9\n[delete]\n5\n".as_bytes(), "memory")?;
+        let matcher = keepfile.exclude(rejected).into_matcher();
+        assert!(!matcher.keeps("IMG_0004.jpg"));
+        assert!(matcher.keeps("IMG_0005.jpg"));
+        assert!(!matcher.keeps("IMG_0007.jpg"));
+        assert!(matcher.keeps("IMG_0010.jpg"));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_source() -> TestResult {
+        let keepfile = KeepFile::try_load(resource_dir().join("keep.txt"))?;
+        assert_eq!(keepfile.sources(), [resource_dir().join("keep.txt")]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_keepfile_inclusion_matcher() -> TestResult {
+        let keepfile = KeepFile::try_load(resource_dir().join("keep.txt"))?;
+        let matcher = keepfile.into_inclusion_matcher();
+
+        // In the keepfile
+        assert!(matcher(&&PathBuf::from("TXT_1")));
+        assert!(matcher(&&PathBuf::from("TXT_4")));
+
+        // Not in the keepfile
+        assert!(!matcher(&&PathBuf::from("TXT_2")));
+        assert!(!matcher(&&PathBuf::from("TXT_3")));
+        assert!(!matcher(&&PathBuf::from("TXT_5")));
+
+        // Without a number
+        assert!(!matcher(&&PathBuf::from("TXT")));
+
+        Ok(())
+    }
+}
@@ -4,26 +4,64 @@
 //! one of the child modules.
 
 use std::clone::Clone;
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use clap::Parser;
+use itertools::Itertools;
 
-use action::Action;
-use keepfile::{KeepFile, KeepFileError};
+use action::{render_subdir_template, Action, DestinationLayout, MoveOrCopy, RenumberOrder};
+use chrono::Local;
+use keepfile::formats::KeepFormat;
+use keepfile::{KeepColumn, KeepFile, KeepFileError, NumberMatching, NumberPosition};
 
-use crate::config::{ConfigFile, ConfigFileError};
+use crate::archive::Archive;
+use crate::commands::Command;
+use crate::config::{ConfigFile, ConfigFileError, ConfigFormat, ConfigLayer, ConfigOverrides};
+use crate::conflict::{ConflictPolicy, ConflictResolver};
+use crate::dedupe::DedupeMode;
+use crate::file_source::{parse_file_list, ParallelWalk, ScanError, ScanLimits, ScanOrder, Walk, WalkedFile};
+use crate::filters::{FileSize, TimeLimit};
+use crate::hooks::Hooks;
+use crate::index::IndexFormat;
+use crate::output::Terminator;
+use crate::permissions::PermissionsError;
+use crate::remote::RemoteError;
+use crate::session::CopySession;
+use crate::throttle::RateLimiter;
 
 pub mod action;
+pub mod archive;
+pub mod commands;
 pub mod config;
+pub mod conflict;
+pub mod dedupe;
+pub mod explain;
 pub mod file_source;
+pub mod filters;
+pub mod hooks;
+pub mod index;
 pub mod keepfile;
+pub mod output;
+pub mod permissions;
+pub mod remote;
+pub mod script;
+pub mod session;
+pub mod stats;
 #[cfg(test)]
 #[doc(hidden)]
 pub mod test_utils;
+pub mod throttle;
+
+/// Files found in a directory, the paths they were found at if they differ from the canonical ones,
+/// and the paths that couldn't be read
+type FoundPaths = (Vec<PathBuf>, HashMap<PathBuf, PathBuf>, Vec<ScanError>);
 
 /// Selected source directory to seek files from
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SelectedDirectory(PathBuf);
 
 impl TryFrom<PathBuf> for SelectedDirectory {
@@ -51,33 +89,80 @@ impl SelectedDirectory {
     ///
     /// Directories are searched recursively.
     ///
+    /// If `canonicalize` is false, files are only joined to the (canonical) directory path,
+    /// which avoids a few system calls per file on network filesystems.
+    ///
+    /// Files whose canonical path is not the path they were found at (e.g. symbolic links)
+    /// are mapped to the path they were found at, relative to the directory, in the returned map.
+    ///
+    /// Subdirectories outside the `limits`, excluded or too deep, are skipped without being read.
+    /// The subdirectories and files that can't be read are returned with the found files,
+    /// instead of stopping the search. A file found at several paths is only returned once,
+    /// and its other paths are returned with the ones that can't be read.
+    ///
+    /// With more than one of the `threads`, the subdirectories are read in parallel,
+    /// and the files are found in a different order every time.
+    ///
     /// # Errors
     ///
     /// Errors are returned in the following cases, but not limited to:
     ///
     /// - If the specified directory does not exist
     /// - If the specified directory is not readable
-    /// - If an I/O error occurs while reading the directory
-    /// - Path canonicalization fails
-    fn read_recursive_path(&self) -> std::io::Result<Vec<PathBuf>> {
-        let path = Path::new(&self.0);
+    fn read_recursive_path(
+        &self,
+        canonicalize: bool,
+        limits: &ScanLimits,
+        threads: usize,
+    ) -> std::io::Result<FoundPaths> {
         // All found files
         let mut files = Vec::new();
-        // Stack for recursive search
-        let mut stack: Vec<_> = path.read_dir()?.flat_map(Result::ok).collect();
-
-        // Iterate over the stack until it's empty
-        while let Some(entry) = stack.pop() {
-            if entry.path().is_dir() {
-                // If the entry is a directory, add its contents to the stack
-                stack.extend(entry.path().read_dir()?.flat_map(Result::ok));
-            } else {
-                // Else, add the file to the list of found files
-                files.push(entry.path().canonicalize()?);
+        // Paths the files were found at, if they differ from the canonical ones
+        let mut walk_paths = HashMap::new();
+        // Paths that couldn't be read, or were skipped
+        let (mut found, mut errors): (Vec<WalkedFile>, Vec<_>) =
+            self.walk(canonicalize, limits, threads)?.partition_result();
+        // A file found at several paths, through symbolic links, is kept at its own path, or else the first link
+        // to it, so the search is deterministic. The other paths are reported, as the file is only handled once
+        found.sort_by(|a, b| (&a.path, &a.walk_path).cmp(&(&b.path, &b.walk_path)));
+        for found in found {
+            if files.last() == Some(&found.path) {
+                let found_at =
+                    |walk_path: Option<&PathBuf>| walk_path.map_or(found.path.clone(), |path| self.0.join(path));
+                let kept = found_at(walk_paths.get(&found.path));
+                errors.push(ScanError {
+                    path: found_at(found.walk_path.as_ref()),
+                    message: format!("the same file as \"{}\", which it is only handled at", kept.display()),
+                });
+                continue;
+            }
+            if let Some(walk_path) = found.walk_path {
+                walk_paths.insert(found.path.clone(), walk_path);
             }
+            files.push(found.path);
         }
 
-        Ok(files)
+        Ok((files, walk_paths, errors))
+    }
+
+    /// Search the directory lazily, producing the files while the subdirectories are read
+    ///
+    /// See [SelectedDirectory::read_recursive_path] for the found paths, which collects them all instead.
+    ///
+    /// With more than one of the `threads`, the search is a [ParallelWalk], and a [Walk] otherwise.
+    ///
+    /// # Errors
+    /// - If the directory can't be read
+    pub fn walk(
+        &self,
+        canonicalize: bool,
+        limits: &ScanLimits,
+        threads: usize,
+    ) -> std::io::Result<Box<dyn Iterator<Item = Result<WalkedFile, ScanError>>>> {
+        Ok(match threads > 1 {
+            true => Box::new(ParallelWalk::new(&self.0, canonicalize, limits.clone(), threads)?),
+            false => Box::new(Walk::new(&self.0, canonicalize, limits.clone())?),
+        })
     }
 }
 
@@ -93,14 +178,73 @@ impl SelectedDirectory {
 /// - Delete non-matching files
 ///
 /// ## Options:
-/// - `path`: The directory to search for files
-/// - `keep`: The file to use as the keep file
-/// - `config`: The configuration file to use
+/// - `path`: The directories to search for files
+/// - `keep`: The files to use as the keep file
+/// - `keep_format`: Format of the keep files, detected by their extension if not provided
+/// - `keep_column`: Column of CSV keep files with the numbers or file names to keep
+/// - `keep_sha256`: Expected SHA-256 checksum of a remote keep file
+/// - `keep_clipboard`: Read the keep list from the system clipboard
+/// - `exclude_keep`: A keep file with the numbers to never keep, whatever the keep file selects
+/// - `strict_keep`: Refuse to run if the keep file repeats numbers
+/// - `lenient_keep`: Skip the invalid lines of the keep file with a warning, instead of refusing to run
+/// - `report_unmatched`: Report the numbers and file names in the keep file that don't match any file
+/// - `number_pattern`: Regular expression extracting the number file names are compared by
+/// - `number_position`: Which of the numbers in the file name is compared against the keep file
+/// - `strict_number_width`: Only compare numbers spanning all the adjacent digits in the file name
+/// - `number_matching`: Find the numbers of the file names by the number pattern, or as the whole numeric tokens
+/// - `expect_keeps`: Abort unless exactly N numbers from the keep file are found
+/// - `config`: The configuration files to use, merged in order
+/// - `config_format`: Format of the configuration file, detected by its extension if not provided
+/// - `no_config`: Don't use any configuration file, only match the files by `ext` and `pattern`
+/// - `profile`: The profile of the configuration file to use, instead of its default profile
+/// - `min_size`: Only match files at least this large
+/// - `max_size`: Only match files at most this large
+/// - `newer_than`: Only match files modified after this time
+/// - `older_than`: Only match files modified before this time
+/// - `min_depth`: Only match files nested in at least this many subdirectories
+/// - `max_depth`: Only search this many levels of subdirectories
+/// - `ext`: Only match files with these extensions, instead of the configured ones
+/// - `pattern`: Only match file names matching these regular expressions, instead of the configured formats
+/// - `append_filters`: Add the `ext` and `pattern` values to the configured ones, instead of replacing them
+/// - `hidden`: Also search the hidden files and directories
+/// - `one_file_system`: Don't search the subdirectories on other filesystems
+/// - `strict_scan`: Don't apply the action if some paths can't be read during the search
+/// - `nested_configs`: Match the files in subdirectories with a configuration file by that configuration
+/// - `config_sha256`: Expected SHA-256 checksum of a remote configuration file
+/// - `offline`: Use the cached copies of the remote configuration and keep files, instead of fetching them
 /// - `move_to`: Move matching files to the specified directory
-/// - `copy_to`: Copy matching files to the specified directory
+/// - `copy_to`: Copy matching files to the specified directories
+/// - `layout`: How moved or copied files are placed in the destination directory
+/// - `dest_subdir_template`: Place moved or copied files into a subdirectory unique to the run
+/// - `renumber`: Sequentially renumber moved or copied files
+/// - `on_conflict`: What to do when the destination of a moved or copied file already exists
+/// - `chmod`: File mode applied to the moved or copied files
+/// - `chown`: Owner applied to the moved or copied files
+/// - `dedupe`: Transfer only one of the byte-identical files
+/// - `throttle`: Limit the copy throughput, in megabytes per second
+/// - `resume`: Resume an interrupted move or copy, skipping the files already transferred
+/// - `index`: Generate an index of the transferred files in the destination directory
 /// - `delete`: Delete non-matching files
+/// - `quarantine`: Move non-matching files to the specified directory instead of deleting them
+/// - `invert`: Apply the action to the files the keep file would otherwise spare, and vice versa
+/// - `list`: Only print the matching files
+/// - `print0`: Separate the listed files and the printed operations by NUL characters
+/// - `shred`: Overwrite deleted files with random data before removing them
 /// - `dry_run`: Only print what would be done, don't actually do anything.
+/// - `sample`: Print a random sample of the planned operations, in dry-run mode
+/// - `emit_script`: Write the planned operations to a shell script, in dry-run mode
+/// - `relative`: Show paths relative to the searched directory
+/// - `no_canonicalize`: Don't canonicalize the paths of the found files
+/// - `threads`: Number of threads reading the subdirectories in parallel
+/// - `order`: Order the found files are processed and printed in
+/// - `files_from`: Read the files to filter from a list, instead of searching the directory
+/// - `null`: Split the list of files on NUL characters
 /// - `verbose`: Print detailed information about what's happening
+/// - `no_protect`: Allow the files the run reads or writes, like the active config and keep files, to be acted on
+/// - `pre_hook`: Shell command to run before the action
+/// - `post_hook`: Shell command to run after the action
+/// - `stats_csv`: Export per-directory and per-extension statistics to a CSV file
+/// - `explain`: Explain which filters accept or reject the file, instead of applying the action
 /// - `print_config`: Print parsed configuration and exit
 #[derive(Parser, Debug, Clone)]
 #[clap(
@@ -108,19 +252,167 @@ impl SelectedDirectory {
     author = "lordofdestiny",
     about = "A CLI app to delete files based on a configuration file"
 )]
-#[command(arg_required_else_help(true))]
+#[command(arg_required_else_help(true), args_conflicts_with_subcommands(true))]
 pub struct Args {
-    /// The directory to search for files
+    /// Subcommand to run instead of processing the files
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// The directory to search for files, or a zip or tar archive to copy or list the files of.
+    /// Repeat it to merge the files of several directories, keeping the structure of each one at the destination
     #[clap(short, long, default_value = ".", value_name = "DIR")]
-    path: Option<String>,
+    path: Vec<String>,
 
-    /// The file to use as the keep file
+    /// The file to use as the keep file. Can be an HTTP(S) URL, or `-` to read standard input.
+    /// Can be repeated to merge several keep files
     #[clap(short, long)]
-    keep: Option<String>,
+    keep: Vec<String>,
+
+    /// Format of the keep files, like the selection exported from a culling tool.
+    /// Detected by the extension if not provided
+    #[clap(long, value_enum, value_name = "FORMAT", requires = "keep")]
+    keep_format: Option<KeepFormat>,
+
+    /// Column of CSV keep files with the numbers or file names to keep, by header name or position from 1
+    #[clap(long, value_name = "NAME|INDEX", requires = "keep")]
+    keep_column: Option<KeepColumn>,
+
+    /// Expected SHA-256 checksum of the keep file fetched from a URL. Only valid if the keep file is a single URL
+    #[clap(long, value_name = "HEX", requires = "keep")]
+    keep_sha256: Option<String>,
+
+    /// Read the keep list from the system clipboard instead of a file
+    #[clap(long, conflicts_with = "keep")]
+    keep_clipboard: bool,
+
+    /// A keep file with the numbers to never keep, even if the keep file selects them
+    #[clap(long, value_name = "FILE")]
+    exclude_keep: Option<PathBuf>,
+
+    /// Refuse to run if the keep file repeats numbers, instead of only warning about them
+    #[clap(long)]
+    strict_keep: bool,
+
+    /// Skip the invalid lines of the keep file with a warning, instead of refusing to run
+    #[clap(long)]
+    lenient_keep: bool,
+
+    /// Report the numbers and file names in the keep file that don't match any file
+    #[clap(long)]
+    report_unmatched: bool,
 
-    /// The configuration file to use
+    /// Regular expression extracting the number file names are compared by.
+    /// The number is the capture group named `number`, or else the last capture group
+    #[clap(long, value_name = "REGEX")]
+    number_pattern: Option<regex::Regex>,
+
+    /// Which of the numbers in the file name is compared against the keep file: first, last or nth(N)
+    #[clap(long, value_name = "POSITION")]
+    number_position: Option<NumberPosition>,
+
+    /// Only compare numbers spanning all the adjacent digits in the file name,
+    /// so `7` matches `IMG_0007` but never a part of a longer number like `IMG_0070`
+    #[clap(long)]
+    strict_number_width: bool,
+
+    /// How the numbers of the file names are found: by the number pattern, or as the whole runs of digits,
+    /// so `100` never matches a part of `IMG_1003`. Overrides `number_matching` from the config
+    #[clap(long, value_enum, value_name = "MODE")]
+    number_matching: Option<NumberMatching>,
+
+    /// Abort, before any file is touched, unless exactly N numbers from the keep file
+    /// are found among the matching files
+    #[clap(long, value_name = "N")]
+    expect_keeps: Option<usize>,
+
+    /// The configuration file to use. Can be an HTTP(S) URL, or `-` to read standard input.
+    /// Can be repeated, to merge the files in order, the later ones overriding and extending the earlier ones
     #[clap(long, visible_alias = "cfg", visible_short_alias = 'Y')]
-    config: Option<String>,
+    config: Vec<String>,
+
+    /// Format of the configuration file. Detected by the extension if not provided, YAML for standard input
+    #[clap(long, value_enum, value_name = "FORMAT", requires = "config")]
+    config_format: Option<ConfigFormat>,
+
+    /// Don't look up or load any configuration file, and match the files only by `ext` and `pattern`.
+    /// At least one of them is required
+    #[clap(long, conflicts_with_all = ["config", "profile", "nested_configs"])]
+    no_config: bool,
+
+    /// The profile of the configuration file to use, instead of its default profile
+    #[clap(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Only match files at least this large, like `100KB`. Overrides `min_size` from the config
+    #[clap(long, value_name = "SIZE")]
+    min_size: Option<FileSize>,
+
+    /// Only match files at most this large, like `2GB`. Overrides `max_size` from the config
+    #[clap(long, value_name = "SIZE")]
+    max_size: Option<FileSize>,
+
+    /// Only match files modified after this time, like `7d` ago or `2024-06-01`.
+    /// Overrides `newer_than` from the config
+    #[clap(long, value_name = "TIME")]
+    newer_than: Option<TimeLimit>,
+
+    /// Only match files modified before this time, like `30d` ago or `2024-06-01`.
+    /// Overrides `older_than` from the config
+    #[clap(long, value_name = "TIME")]
+    older_than: Option<TimeLimit>,
+
+    /// Only match files nested in at least this many subdirectories. Overrides `min_depth` from the config
+    #[clap(long, value_name = "N")]
+    min_depth: Option<usize>,
+
+    /// Only search this many levels of subdirectories, 0 for the files directly in the directory.
+    /// Overrides `max_depth` from the config
+    #[clap(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Only match files with this extension, like `jpg`, or extension group, like `@raw`,
+    /// instead of the extensions from the config.
+    /// Can be repeated
+    #[clap(long, value_name = "EXT")]
+    ext: Vec<String>,
+
+    /// Only match file names matching this regular expression, instead of the formats from the config.
+    /// Can be repeated
+    #[clap(long, value_name = "REGEX")]
+    pattern: Vec<regex::Regex>,
+
+    /// Add the `--ext` and `--pattern` values to the extensions and formats from the config, instead of replacing them
+    #[clap(long)]
+    append_filters: bool,
+
+    /// Also search the hidden files and directories, whose names start with a dot.
+    /// Sets `include_hidden` in the config
+    #[clap(long)]
+    hidden: bool,
+
+    /// Don't search the subdirectories on other filesystems than the searched directory, like mount points.
+    /// Sets `one_file_system` in the config. Only supported on Unix
+    #[clap(long)]
+    one_file_system: bool,
+
+    /// Don't apply the action if some subdirectories or files can't be read during the search,
+    /// instead of reporting them after the action
+    #[clap(long)]
+    strict_scan: bool,
+
+    /// Match the files in the subdirectories with their own configuration file by that configuration,
+    /// instead of the root one
+    #[clap(long)]
+    nested_configs: bool,
+
+    /// Expected SHA-256 checksum of the configuration file fetched from a URL.
+    /// Only valid if the configuration file is a single URL
+    #[clap(long, value_name = "HEX", requires = "config")]
+    config_sha256: Option<String>,
+
+    /// Use the cached copies of the configuration and keep files given as URLs, instead of fetching them
+    #[clap(long)]
+    offline: bool,
 
     /// Move matching files to the specified directory.
     /// Mutually exclusive with `delete` and `copy-to`
@@ -133,14 +425,74 @@ pub struct Args {
     move_to: Option<String>,
 
     /// Copy matching files to the specified directory.
+    /// Can be repeated, or given a comma separated list, to copy to several directories in one pass.
     /// Mutually exclusive with `move-to` and `delete`
     #[clap(
         short,
         conflicts_with_all = &["move_to", "delete"],
         group = "action",
-        value_name = "DIR"
+        value_name = "DIR",
+        value_delimiter = ','
     )]
-    copy_to: Option<String>,
+    copy_to: Vec<String>,
+
+    /// How moved or copied files are placed in the destination directory
+    #[clap(long, value_enum, default_value_t = DestinationLayout::Mirror, conflicts_with = "delete")]
+    layout: DestinationLayout,
+
+    /// Place moved or copied files into a subdirectory of the destination, unique to the run.
+    /// Supports `{date}`, `{time}` and `{name}` (the searched directory name) placeholders
+    #[clap(long, value_name = "TEMPLATE", conflicts_with = "delete")]
+    dest_subdir_template: Option<String>,
+
+    /// Sequentially renumber moved or copied files (`001.jpg`, `002.jpg`, ...),
+    /// in the order of the keep file or of their original numbers
+    #[clap(long, value_enum, value_name = "ORDER", conflicts_with = "delete")]
+    renumber: Option<RenumberOrder>,
+
+    /// What to do when the destination of a moved or copied file already exists
+    #[clap(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        default_value_t = ConflictPolicy::Skip,
+        conflicts_with = "delete"
+    )]
+    on_conflict: ConflictPolicy,
+
+    /// Octal file mode applied to the moved or copied files, like `644`. Unix only.
+    /// Overrides `permissions.chmod` from the config
+    #[clap(long, value_name = "MODE", conflicts_with = "delete")]
+    chmod: Option<String>,
+
+    /// Owner applied to the moved or copied files, as `user`, `user:group` or `:group`. Unix only.
+    /// Overrides `permissions.chown` from the config
+    #[clap(long, value_name = "OWNER", conflicts_with = "delete")]
+    chown: Option<String>,
+
+    /// Transfer only one of the byte-identical files, and skip or hard link the rest.
+    /// Optionally specifies what to do with the duplicates
+    #[clap(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "skip",
+        value_name = "MODE",
+        conflicts_with = "delete"
+    )]
+    dedupe: Option<DedupeMode>,
+
+    /// Limit the copy throughput, in megabytes per second
+    #[clap(long, value_name = "MB/s", value_parser = parse_throttle, conflicts_with = "delete")]
+    throttle: Option<f64>,
+
+    /// Resume an interrupted move or copy, skipping the files already transferred
+    #[clap(long, conflicts_with = "delete")]
+    resume: bool,
+
+    /// Generate an index of the moved or copied files in the destination directory
+    #[clap(long, value_enum, value_name = "FORMAT", conflicts_with = "delete")]
+    index: Option<IndexFormat>,
 
     /// Delete non-matching files.
     /// Mutually exclusive with `move-to` and `copy-to`
@@ -151,19 +503,132 @@ pub struct Args {
     )]
     delete: bool,
 
+    /// Move non-matching files to the specified directory instead of deleting them.
+    /// Mutually exclusive with `move-to`, `copy-to` and `delete`
+    #[clap(long, group = "action", value_name = "DIR")]
+    quarantine: Option<String>,
+
+    /// Invert the keep file: copy or move the files that are NOT in it, or delete the files that are
+    #[clap(long)]
+    invert: bool,
+
+    /// Only print the matching files, one per line.
+    /// Mutually exclusive with `move-to`, `copy-to` and `delete`
+    #[clap(short, long, group = "action")]
+    list: bool,
+
+    /// Separate the listed files, and the operations printed by `verbose` and `sample`,
+    /// by NUL characters instead of newlines
+    #[clap(long)]
+    print0: bool,
+
+    /// Overwrite files with random data before deleting them.
+    /// Optionally specifies the number of passes
+    #[clap(
+        long,
+        requires = "delete",
+        num_args = 0..=1,
+        default_missing_value = "3",
+        value_name = "PASSES",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    shred: Option<u32>,
+
     /// Only print what would be done, don't actually do anything.
     #[clap(long, default_value = "false")]
     dry_run: bool,
 
+    /// Print a random sample of N planned operations per category instead of all of them.
+    /// Requires `dry-run`
+    #[clap(long, value_name = "N", requires = "dry_run")]
+    sample: Option<usize>,
+
+    /// Write the equivalent shell commands of the planned operations to a script.
+    /// Requires `dry-run`
+    #[clap(long, value_name = "FILE", requires = "dry_run")]
+    emit_script: Option<PathBuf>,
+
+    /// Show paths in listings, plans and verbose output relative to the searched directory
+    #[clap(long)]
+    relative: bool,
+
+    /// Don't canonicalize the paths of the found files, only the searched directory.
+    /// Speeds up scanning network filesystems
+    #[clap(long)]
+    no_canonicalize: bool,
+
+    /// Read the subdirectories with N threads in parallel.
+    /// Speeds up scanning large trees on network shares and slow disks
+    #[clap(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    threads: u16,
+
+    /// Order the found files are processed and printed in.
+    /// Every order breaks ties by the paths
+    #[clap(long, value_enum, value_name = "ORDER", default_value_t = ScanOrder::Path)]
+    order: ScanOrder,
+
+    /// Take the files from a list, one per line, instead of searching the directory.
+    /// Use `-` to read the list from stdin
+    #[clap(long, value_name = "FILE")]
+    files_from: Option<String>,
+
+    /// The files of `files-from` are separated by NUL characters instead of newlines
+    #[clap(short = '0', long, requires = "files_from")]
+    null: bool,
+
     /// Print detailed information about what's happening
     #[clap(short, long)]
     verbose: bool,
 
+    /// Don't exclude the files the run reads or writes, like the active config and keep files,
+    /// from the matched files
+    #[clap(long)]
+    no_protect: bool,
+
+    /// Shell command to run before the action. Overrides `hooks.pre` from the config
+    #[clap(long, value_name = "COMMAND")]
+    pre_hook: Option<String>,
+
+    /// Shell command to run after the action. Overrides `hooks.post` from the config
+    #[clap(long, value_name = "COMMAND")]
+    post_hook: Option<String>,
+
+    /// Export per-directory and per-extension file counts and byte totals to a CSV file
+    #[clap(long, value_name = "FILE")]
+    stats_csv: Option<PathBuf>,
+
+    /// Print which stages, from the search to the keep file, accept or reject the file and why, and exit.
+    /// The action isn't applied
+    #[clap(long, visible_alias = "why", value_name = "FILE")]
+    explain: Option<PathBuf>,
+
     /// Print parsed configuration and exit
     #[clap(long)]
     pub print_config: bool,
 }
 
+/// Parse the throughput limit, which must be a positive number of megabytes per second
+fn parse_throttle(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(mbps) if mbps.is_finite() && mbps > 0.0 => Ok(mbps),
+        _ => Err(format!("\"{value}\" is not a positive number of megabytes per second")),
+    }
+}
+
+/// Get the absolute path, with the symbolic links of its existing part resolved
+///
+/// The path doesn't have to exist, so the files the run didn't write yet are named like the files found by the search.
+fn normalized(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    match (absolute.parent(), absolute.file_name()) {
+        (Some(parent), Some(name)) => normalized(parent).join(name),
+        _ => absolute,
+    }
+}
+
 /// Parsed configuration
 ///
 /// This struct contains the data needed to execute the program.
@@ -172,18 +637,32 @@ pub struct Args {
 pub struct AppConfig {
     /// Directory the script will be executed from
     pub path: SelectedDirectory,
+    /// Other directories the files are looked up in, along with `path`
+    pub extra_paths: Vec<SelectedDirectory>,
     /// Configuration describing what files to look up in `path` field
     pub config_file: ConfigFile,
+    /// Command line options overriding the ones of the configuration files
+    pub overrides: ConfigOverrides,
     /// A parsed keepfile
     pub keepfile: KeepFile,
     /// Action to perform once the files are filtered
     pub action: Action,
+    /// Subdirectory of the destination unique to the run, which the directories of the action already end with
+    pub dest_subdir: Option<String>,
+    /// Shell commands to run before and after the action
+    pub hooks: Hooks,
+    /// Archive the files are read from instead of `path`, which is the directory of the archive then
+    pub archive: Option<Archive>,
+    /// Files to filter instead of the ones found in the directory, if they were listed
+    pub files_from: Option<Vec<PathBuf>>,
+    /// The file the files were listed in, unless the list was read from stdin
+    pub file_list: Option<PathBuf>,
     /// Additional options
     pub options: ExecutionOptions,
 }
 
 /// Options for executing the action
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ExecutionOptions {
     /// Should the action be performed in dry-run mode?
     pub dry_run: bool,
@@ -191,6 +670,38 @@ pub struct ExecutionOptions {
     pub verbose: bool,
     /// Should the parsed configuration be printed?
     pub print: bool,
+    /// Should the files the run reads or writes be excluded from the action?
+    pub protect: bool,
+    /// File to export the run statistics to
+    pub stats_csv: Option<PathBuf>,
+    /// File to write the equivalent shell script to
+    pub emit_script: Option<PathBuf>,
+    /// Format of the index of transferred files to generate
+    pub index: Option<IndexFormat>,
+    /// Should the files transferred by the interrupted session be skipped?
+    pub resume: bool,
+    /// Should the keep file entries without matching files be reported?
+    pub report_unmatched: bool,
+    /// Number of keep file numbers that must be found among the matching files
+    pub expect_keeps: Option<usize>,
+    /// Number of planned operations per category to print in dry-run mode, instead of all of them
+    pub sample: Option<usize>,
+    /// Should the keep file filter be inverted?
+    pub invert: bool,
+    /// Should paths be shown relative to the source directory?
+    pub relative: bool,
+    /// Should the paths of the found files be canonicalized?
+    pub canonicalize: bool,
+    /// Number of threads reading the subdirectories
+    pub threads: usize,
+    /// Order the found files are processed in
+    pub order: ScanOrder,
+    /// Character ending the listed files and the printed operations
+    pub terminator: Terminator,
+    /// Should the action be skipped if some paths couldn't be read during the search?
+    pub strict_scan: bool,
+    /// File to explain the filters of, instead of applying the action
+    pub explain: Option<PathBuf>,
 }
 
 /// An error that occurs when parsing the [Args]
@@ -202,6 +713,10 @@ pub enum AppConfigError {
     Config(#[from] ConfigFileError),
     #[error("{0}")]
     KeepFile(#[from] KeepFileError),
+    #[error("{0}")]
+    Remote(#[from] RemoteError),
+    #[error("{0}")]
+    Permissions(#[from] PermissionsError),
 }
 
 impl TryFrom<Args> for AppConfig {
@@ -210,41 +725,558 @@ impl TryFrom<Args> for AppConfig {
         use std::io::{Error, ErrorKind::InvalidInput};
         #[rustfmt::skip]
         let Args {
-            path, config,  keep,
-            copy_to, move_to, delete,
-            dry_run, verbose, print_config: print,
+            command: _,
+            path, config, config_format, no_config, profile,
+            min_size, max_size, newer_than, older_than, min_depth, max_depth,
+            ext, pattern, append_filters, hidden, one_file_system, strict_scan, nested_configs, config_sha256, offline,
+            keep, keep_format, keep_column, keep_sha256, keep_clipboard, exclude_keep, strict_keep, lenient_keep,
+            report_unmatched, number_pattern, number_position, strict_number_width, number_matching,
+            expect_keeps,
+            copy_to, move_to, layout, dest_subdir_template, renumber, on_conflict, chmod, chown, dedupe,
+            throttle, resume, index,
+            delete, shred, quarantine, invert, list, print0,
+            dry_run, sample, emit_script, relative, no_canonicalize, threads, order, files_from, null,
+            verbose, no_protect,
+            pre_hook, post_hook, stats_csv, explain,
+            print_config: print,
         } = args;
 
-        let path = path
-            .as_deref()
-            .or(Some("."))
-            .map(PathBuf::from)
-            .filter(|p| p.exists() && p.is_dir())
-            .ok_or_else(|| Error::new(InvalidInput, "Invalid directory"))
-            .and_then(SelectedDirectory::try_from)?;
-
-        let config_file = match config.map(PathBuf::from).map(ConfigFile::try_load) {
-            Some(file) => file?,
-            None => ConfigFile::load(path.as_ref().join("config.yaml")),
+        // An archive is searched instead of a directory, and its config and keep files are looked up next to it
+        let searched = path.len();
+        let mut archive = None;
+        let mut paths: Vec<SelectedDirectory> = vec![];
+        for path in path {
+            let dir = match PathBuf::from(&path) {
+                file if file.is_file() => {
+                    let opened = Archive::open(&file)?;
+                    let dir = opened.path.parent().map(Path::to_path_buf).unwrap_or_default();
+                    archive = Some(opened);
+                    SelectedDirectory::try_from(dir)?
+                }
+                dir => Some(dir)
+                    .filter(|p| p.exists() && p.is_dir())
+                    .ok_or_else(|| Error::new(InvalidInput, format!("Invalid directory \"{path}\"")))
+                    .and_then(SelectedDirectory::try_from)?,
+            };
+            if !paths.contains(&dir) {
+                paths.push(dir);
+            }
+        }
+        if archive.is_some() && searched > 1 {
+            let message = "An archive can only be searched on its own, without other directories or archives";
+            return Err(Error::new(InvalidInput, message).into());
+        }
+        let path = paths.remove(0);
+        let extra_paths = paths;
+
+        let inputs = config.iter().chain(keep.iter()).chain(files_from.iter());
+        if inputs.filter(|input| *input == "-").count() > 1 {
+            let message = "Only one of the config files, the keep files and the file list can be read from stdin";
+            return Err(Error::new(InvalidInput, message).into());
+        }
+        // A pinned checksum is only verified for the downloaded files, so it is rejected for the local ones,
+        // and it can only match one of the downloaded files, so it is rejected for several of them
+        let pinned = [
+            ("--config-sha256", &config_sha256, &config),
+            ("--keep-sha256", &keep_sha256, &keep),
+        ];
+        for (flag, _, locations) in pinned.iter().filter(|(_, sha256, _)| sha256.is_some()) {
+            if let Some(local) = locations.iter().find(|location| !remote::is_url(location)) {
+                let message = format!("{flag} only applies to URLs, but \"{local}\" isn't one");
+                return Err(Error::new(InvalidInput, message).into());
+            }
+            if locations.len() > 1 {
+                let message = format!("{flag} pins the checksum of one URL, but {} are given", locations.len());
+                return Err(Error::new(InvalidInput, message).into());
+            }
+        }
+        if no_config && ext.is_empty() && pattern.is_empty() {
+            let message = "--no-config requires --ext or --pattern to match the files by";
+            return Err(Error::new(InvalidInput, message).into());
+        }
+        let config_file = match config.as_slice() {
+            _ if no_config => ConfigFile::bare(),
+            [] => ConfigFile::try_discover(&path)?,
+            config => {
+                let mut layers = vec![];
+                for config in config {
+                    let name = config.clone();
+                    layers.push(match config {
+                        stdin if stdin == "-" => {
+                            let mut content = vec![];
+                            std::io::stdin().lock().read_to_end(&mut content)?;
+                            let format = config_format.unwrap_or_default();
+                            ConfigLayer {
+                                name: "<stdin>".to_owned(),
+                                content,
+                                format,
+                                source: None,
+                            }
+                        }
+                        url if remote::is_url(url) => {
+                            let content = remote::fetch_cached(url, config_sha256.as_deref(), offline)?;
+                            let format = config_format.unwrap_or_else(|| ConfigFormat::detect(url));
+                            ConfigLayer {
+                                name,
+                                content,
+                                format,
+                                source: None,
+                            }
+                        }
+                        file => {
+                            let content = std::fs::read(file).map_err(ConfigFileError::from)?;
+                            let format = config_format.unwrap_or_else(|| ConfigFormat::detect(file));
+                            let source = Path::new(file).canonicalize().ok();
+                            ConfigLayer {
+                                name,
+                                content,
+                                format,
+                                source,
+                            }
+                        }
+                    });
+                }
+                ConfigFile::merge(layers)?
+            }
+        };
+        let overrides = ConfigOverrides {
+            profile,
+            min_size,
+            max_size,
+            newer_than,
+            older_than,
+            min_depth,
+            max_depth,
+            include_hidden: hidden,
+            one_file_system,
+            nested_configs,
+            extensions: ext,
+            formats: pattern,
+            append_filters,
+        };
+        let config_file = overrides.apply(config_file)?;
+
+        let lenient = |loaded| match lenient_keep {
+            true => KeepFile::skip_invalid(loaded),
+            false => loaded,
+        };
+        let keepfile = match keep.as_slice() {
+            _ if keep_clipboard => lenient(KeepFile::from_clipboard())?,
+            [] => lenient(KeepFile::try_load(path.as_ref().join("keep.txt")))?,
+            keep => {
+                let column = keep_column.unwrap_or_default();
+                let mut loaded = vec![];
+                for keep in keep {
+                    loaded.push(lenient(match keep {
+                        stdin if stdin == "-" => {
+                            let format = keep_format.unwrap_or_default();
+                            format.parse(std::io::stdin().lock(), &column, "<stdin>")
+                        }
+                        url if remote::is_url(url) => {
+                            let content = remote::fetch_cached(url, keep_sha256.as_deref(), offline)?;
+                            let format = keep_format.unwrap_or_else(|| KeepFormat::detect(url));
+                            format.parse(content.as_slice(), &column, url)
+                        }
+                        file => {
+                            let format = keep_format.unwrap_or_else(|| KeepFormat::detect(file));
+                            KeepFile::try_load_as(file, format, &column)
+                        }
+                    }));
+                }
+                KeepFile::merge(loaded)?
+            }
+        };
+        let keepfile = match exclude_keep {
+            Some(rejected) => keepfile.exclude(lenient(KeepFile::try_load(rejected))?),
+            None => keepfile,
         };
 
-        let keepfile = match keep.map(PathBuf::from).map(KeepFile::try_load) {
-            Some(file) => file?,
-            None => KeepFile::try_load(path.as_ref().join("keep.txt"))?,
+        let number_pattern = config_file.number_pattern(number_pattern, number_position, number_matching);
+        let keepfile = keepfile.with_number_pattern(number_pattern.with_strict_width(strict_number_width));
+        let keepfile = match strict_keep {
+            true => keepfile.deny_duplicates()?,
+            false => keepfile,
         };
 
-        let action = Action::new(copy_to, move_to, delete);
+        let mut copy_to = copy_to.into_iter();
+        let mut action = Action::new(copy_to.next(), move_to, quarantine, delete, shred, list, layout);
+        let mut dest_subdir = None;
+        if let Action::MoveOrCopyTo(handler) = &mut action {
+            handler.mirrors = copy_to.map(PathBuf::from).collect();
+            if let Some(template) = dest_subdir_template {
+                let name = path.as_ref().file_name().unwrap_or_default().to_string_lossy();
+                let subdir = render_subdir_template(&template, &name, Local::now().naive_local())?;
+                dest_subdir = Some(handler.use_subdir(&subdir, resume));
+            }
+            handler.renumber = renumber;
+            handler.conflicts = ConflictResolver::new(on_conflict);
+            handler.dedupe = dedupe;
+            handler.permissions = config_file.permissions().clone().with_overrides(chmod, chown).resolve()?;
+            handler.throttle = throttle.map(|mbps| Rc::new(RateLimiter::from_megabytes_per_second(mbps)));
+        }
+        let hooks = config_file.hooks().clone().with_overrides(pre_hook, post_hook);
+        let listed = match files_from.as_deref() {
+            Some("-") => {
+                let mut content = vec![];
+                std::io::stdin().lock().read_to_end(&mut content)?;
+                Some(parse_file_list(&content, Terminator::nul_if(null))?)
+            }
+            Some(file) => {
+                let content = std::fs::read(file).map_err(|e| Error::new(e.kind(), format!("{file}: {e}")))?;
+                Some(parse_file_list(&content, Terminator::nul_if(null))?)
+            }
+            None => None,
+        };
+        let file_list = files_from.filter(|file| file != "-").map(PathBuf::from);
 
-        Ok(AppConfig {
+        let config = AppConfig {
             path,
+            extra_paths,
             config_file,
+            overrides,
             keepfile,
             action,
+            dest_subdir,
+            hooks,
+            archive,
+            files_from: listed,
+            file_list,
             options: ExecutionOptions {
                 dry_run,
                 verbose,
                 print,
+                protect: !no_protect,
+                stats_csv,
+                emit_script,
+                index,
+                resume,
+                report_unmatched,
+                expect_keeps,
+                sample,
+                invert,
+                relative,
+                canonicalize: !no_canonicalize,
+                threads: threads.into(),
+                order,
+                terminator: Terminator::nul_if(print0),
+                strict_scan,
+                explain,
             },
-        })
+        };
+        config.check_archive()?;
+        Ok(config)
+    }
+}
+
+impl AppConfig {
+    /// Get the paths of the files the run reads or writes
+    ///
+    /// These are the active config and keep files, the configurations they extend, the list of files,
+    /// the statistics and the script written by the run, and the state file of the copy session.
+    /// The paths are normalized, see [normalized], so they match the found files even if they don't exist yet.
+    pub fn input_paths(&self) -> Vec<PathBuf> {
+        let outputs = [&self.options.stats_csv, &self.options.emit_script].into_iter().flatten();
+        let session = match &self.action {
+            Action::MoveOrCopyTo(handler) => {
+                // With a subdirectory unique to the run, the state file naming it is in the destination directory
+                let subdir_state = self.dest_subdir.as_ref().and_then(|_| handler.dest_dir.parent());
+                let subdir_state = subdir_state.map(|dir| dir.join(CopySession::FILE_NAME));
+                std::iter::once(handler.dest_dir.join(CopySession::FILE_NAME))
+                    .chain(subdir_state)
+                    .collect()
+            }
+            _ => vec![],
+        };
+        let written = self.file_list.iter().chain(outputs).cloned().chain(session);
+        self.config_file
+            .sources()
+            .iter()
+            .chain(self.config_file.extended_sources())
+            .chain(self.keepfile.sources())
+            .cloned()
+            .chain(written)
+            .map(|path| normalized(&path))
+            .collect()
+    }
+
+    /// Get the paths of the files the run reads or writes located inside the searched directory
+    ///
+    /// These files could be matched and acted on, if they weren't protected.
+    pub fn overlapping_inputs(&self) -> Vec<PathBuf> {
+        let mut inputs = self.input_paths();
+        inputs.retain(|p| self.archive.is_none() && self.roots().iter().any(|root| p.starts_with(root)));
+        inputs
+    }
+
+    /// Check that the action and the options can be applied to the files of the archive, if one is searched
+    ///
+    /// The archive is only read, and only the paths of its files are known until they are extracted,
+    /// so the files can only be copied or listed, and the options that need the files on disk are rejected.
+    fn check_archive(&self) -> std::io::Result<()> {
+        use std::io::{Error, ErrorKind::InvalidInput};
+        if self.archive.is_none() {
+            return Ok(());
+        }
+        let copy = match &self.action {
+            Action::MoveOrCopyTo(handler) if matches!(handler.op, MoveOrCopy::Copy) => Some(handler),
+            Action::List(_) => None,
+            _ => {
+                let message = "The files of an archive can only be copied or listed";
+                return Err(Error::new(InvalidInput, message));
+            }
+        };
+        let options = &self.options;
+        let prompts = copy.is_some_and(|handler| handler.conflicts.policy == ConflictPolicy::Prompt);
+        let unsupported = [
+            ("--dedupe", copy.is_some_and(|handler| handler.dedupe.is_some())),
+            ("--on-conflict prompt", prompts),
+            ("--resume", options.resume),
+            ("--stats-csv", options.stats_csv.is_some()),
+            ("--emit-script", options.emit_script.is_some()),
+            ("--files-from", self.files_from.is_some()),
+            ("Nested configurations", self.config_file.nested_configs()),
+            (
+                "The size, modification time, content and image filters",
+                self.config_file.reads_files(),
+            ),
+        ];
+        match unsupported.iter().find(|(_, used)| *used) {
+            Some((option, _)) => {
+                let message = format!("{option} can't be applied to the files of an archive");
+                Err(Error::new(InvalidInput, message))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Get the searched directories, starting with `path`
+    pub fn roots(&self) -> Vec<SelectedDirectory> {
+        std::iter::once(&self.path).chain(self.extra_paths.iter()).cloned().collect()
+    }
+
+    /// Get the paths of the files that must not be acted on
+    ///
+    /// These are the files the run reads or writes, unless protection was disabled.
+    pub fn protected_paths(&self) -> Vec<PathBuf> {
+        if !self.options.protect {
+            return vec![];
+        }
+        self.input_paths()
+    }
+
+    /// Get a filter that rejects the protected files
+    ///
+    /// The filter function takes a reference to a `PathBuf` and returns a boolean indicating whether the file should be kept.
+    pub fn protection_filter(&self) -> Rc<dyn Fn(&&PathBuf) -> bool> {
+        let protected = self.protected_paths();
+        Rc::new(move |path| !protected.contains(path))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::*;
+
+    use super::*;
+
+    fn app_config(extra: &[&str]) -> Result<AppConfig, AppConfigError> {
+        let dir = resource_dir();
+        let mut args = vec![
+            "delete-rest".to_owned(),
+            "--path".to_owned(),
+            dir.display().to_string(),
+            "--config".to_owned(),
+            dir.join("cfg.yaml").display().to_string(),
+        ];
+        args.extend(extra.iter().map(|arg| arg.to_string()));
+        AppConfig::try_from(Args::parse_from(args))
+    }
+
+    #[test]
+    fn shred_needs_a_pass() {
+        assert!(Args::try_parse_from(["delete-rest", "-d", "--shred", "0"]).is_err());
+        assert!(Args::try_parse_from(["delete-rest", "-d", "--shred", "1"]).is_ok());
+    }
+
+    #[test]
+    fn inputs_inside_source_are_protected() -> TestResult {
+        let config = app_config(&["-d"])?;
+        let overlapping = config.overlapping_inputs();
+        assert!(overlapping.contains(&resource_dir().join("keep.txt")));
+        assert!(overlapping.contains(&resource_dir().join("cfg.yaml")));
+
+        let filter = config.protection_filter();
+        assert!(!filter(&&resource_dir().join("keep.txt")));
+        assert!(filter(&&resource_dir().join("TXT_1.txt")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn written_files_are_protected() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let dir = dir.path().canonicalize()?;
+        let session = dir.join("out").join(CopySession::FILE_NAME);
+        std::fs::create_dir(dir.join("out"))?;
+        let written = ["keep.txt", "files.txt", "stats.csv", "plan.sh"].map(|file| dir.join(file));
+        for file in written.iter().chain([&session]) {
+            std::fs::write(file, b"1\n")?;
+        }
+        let path = |file: &str| dir.join(file).display().to_string();
+        let args = [
+            "--keep",
+            &path("keep.txt"),
+            "--files-from",
+            &path("files.txt"),
+            "--stats-csv",
+            &path("stats.csv"),
+        ];
+        let dry_run = ["-d", "--dry-run", "--emit-script", &path("plan.sh")];
+        let transfer = ["-c", &path("out")];
+
+        let config = app_config(&[args.as_slice(), &dry_run].concat())?;
+        let inputs = config.input_paths();
+        assert!(written.iter().all(|file| inputs.contains(file)));
+        let config = app_config(&[args.as_slice(), &transfer].concat())?;
+        assert!(config.protected_paths().contains(&session));
+
+        // The files aren't written yet, and are named through another directory
+        let later = dir.join("out").join("..").join("later");
+        let args = ["-d", "--stats-csv", &later.join("stats.csv").display().to_string()];
+        let config = app_config(&args)?;
+        assert!(config.protected_paths().contains(&dir.join("later").join("stats.csv")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn extended_configs_are_protected() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let dir = dir.path().canonicalize()?;
+        std::fs::write(dir.join("base.yaml"), "extensions: [jpg]\n")?;
+        std::fs::write(dir.join("config.yaml"), "extends: base.yaml\n")?;
+        std::fs::write(dir.join("keep.txt"), "1\n")?;
+        let path = |file: &str| dir.join(file).display().to_string();
+        let args = [
+            "delete-rest",
+            "-p",
+            &dir.display().to_string(),
+            "--config",
+            &path("config.yaml"),
+            "-k",
+            &path("keep.txt"),
+            "-d",
+        ];
+
+        let config = AppConfig::try_from(Args::parse_from(args))?;
+        assert!(config.protected_paths().contains(&dir.join("base.yaml")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn protection_can_be_disabled() -> TestResult {
+        let config = app_config(&["-d", "--no-protect"])?;
+        assert!(!config.overlapping_inputs().is_empty());
+        assert!(config.protected_paths().is_empty());
+
+        let filter = config.protection_filter();
+        assert!(filter(&&resource_dir().join("keep.txt")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn checksums_require_urls() {
+        let error = app_config(&["-l", "--config-sha256", "00"]).unwrap_err();
+        assert!(error.to_string().starts_with("--config-sha256 only applies to URLs"));
+        let keep = resource_dir().join("keep.txt").display().to_string();
+        assert!(app_config(&["-l", "-k", &keep, "--keep-sha256", "00"]).is_err());
+
+        let urls = [
+            "-l",
+            "-k",
+            "https://example.com/a.txt",
+            "-k",
+            "https://example.com/b.txt",
+            "--keep-sha256",
+            "00",
+        ];
+        let error = app_config(&urls).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("--keep-sha256 pins the checksum of one URL, but 2 are given"));
+    }
+
+    #[test]
+    fn runs_without_config() -> TestResult {
+        let dir = resource_dir().display().to_string();
+        let args = ["delete-rest", "--path", &dir, "--no-config", "-l"];
+        assert!(AppConfig::try_from(Args::parse_from(args)).is_err());
+
+        let config = AppConfig::try_from(Args::parse_from(args.into_iter().chain(["--ext", "txt"])))?;
+        assert_eq!(config.config_file.source(), None);
+        assert!(config.config_file.matches("notes.txt"));
+        assert!(!config.config_file.matches("IMG_0001.jpg"));
+
+        let nested = args.into_iter().chain(["--ext", "txt", "--nested-configs"]);
+        assert!(Args::try_parse_from(nested).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn searches_several_directories() -> TestResult {
+        let card = tempfile::tempdir()?;
+        let card_path = card.path().display().to_string();
+        let config = app_config(&[
+            "-d",
+            "--path",
+            &card_path,
+            "--path",
+            &resource_dir().display().to_string(),
+        ])?;
+        assert_eq!(config.path.as_ref(), resource_dir());
+        assert_eq!(
+            config.extra_paths,
+            [SelectedDirectory::try_from(card.path().to_path_buf())?]
+        );
+        assert_eq!(config.roots().len(), 2);
+
+        assert!(app_config(&["-d", "--path", &card.path().join("missing").display().to_string()]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn archives_are_only_copied_or_listed() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let archive = dir.path().join("photos.zip");
+        std::fs::write(&archive, b"")?;
+        let keep = resource_dir().join("keep.txt").display().to_string();
+        let config = resource_dir().join("cfg.yaml").display().to_string();
+        let archive_config = |extra: &[&str]| {
+            let archive = archive.display().to_string();
+            let args = ["delete-rest", "-p", &archive, "--config", &config, "-k", &keep];
+            AppConfig::try_from(Args::parse_from(args.iter().chain(extra)))
+        };
+
+        let config = archive_config(&["-c", "out"])?;
+        assert!(config.overlapping_inputs().is_empty());
+        assert!(archive_config(&["-l"]).is_ok());
+        let path = archive.canonicalize()?;
+        assert_eq!(config.archive.map(|archive| archive.path), Some(path));
+        assert_eq!(config.path.as_ref(), dir.path().canonicalize()?);
+
+        assert!(archive_config(&["-d"]).is_err());
+        assert!(archive_config(&["-m", "out"]).is_err());
+        assert!(archive_config(&["-c", "out", "--resume"]).is_err());
+        assert!(archive_config(&["-c", "out", "--dedupe", "skip"]).is_err());
+        assert!(archive_config(&["-l", "--min-size", "1K"]).is_err());
+        assert!(archive_config(&["-l", "--nested-configs"]).is_err());
+        assert!(archive_config(&["-c", "out", "--on-conflict", "prompt"]).is_err());
+        assert!(archive_config(&["-l", "-p", "."]).is_err());
+
+        Ok(())
     }
 }
@@ -1,81 +1,325 @@
 #[doc = include_str!("../README.md")]
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
 
 use clap::Parser;
+use itertools::Itertools;
 
-use delete_rest_lib::action::{Action, MoveOrCopy};
-use delete_rest_lib::file_source::{FileSource, SelectedFiles};
-use delete_rest_lib::{AppConfig, Args, ExecutionOptions};
+use delete_rest_lib::action::{
+    Action, ActionHandler, ExecutionContext, MoveOrCopy, Outcome, Selection, UnmappablePathError,
+};
+use delete_rest_lib::archive::Archive;
+use delete_rest_lib::commands::{wizard, Command};
+use delete_rest_lib::config::ConfigTree;
+use delete_rest_lib::explain::{Explanation, Step};
+use delete_rest_lib::file_source::{FileSource, ScanError, ScanLimits, ScanOrder, SelectedFiles};
+use delete_rest_lib::index::TransferIndex;
+use delete_rest_lib::keepfile::NumberPattern;
+use delete_rest_lib::output::Terminator;
+use delete_rest_lib::script::ShellScript;
+use delete_rest_lib::session::{CopySession, ResumableHandler};
+use delete_rest_lib::stats::RunStats;
+use delete_rest_lib::{AppConfig, Args, SelectedDirectory};
 
-/// Deletes files that from the provided source
+/// Applies the action to the provided files
 ///
-/// If `options.dry_run` is true, the files will not be deleted.
-/// If `options.verbose` is true, the files will be printed before being deleted.
+/// If `options.dry_run` is true, the handler only describes what it would do.
+/// If `options.verbose` is true, the outcome for each file is printed.
+///
+/// Descriptions of files kept by an annotated number are followed by the annotation.
 ///
 /// # Arguments
-/// options - the execution options
-/// matching_files - files that should be deleted
-fn handle_delete(options: ExecutionOptions, matching_files: impl FileSource) {
-    let mut errors = 0;
+/// handler - the handler executing the action
+/// ctx - the directory the files were selected from, and the execution options
+/// annotations - annotations of the numbers in the keep file
+/// number_pattern - the pattern extracting the numbers of the files
+/// matching_files - files the action should be applied to, which can be produced while the action is applied
+/// archive - the archive the files are extracted from, if one is searched
+///
+/// Returns the files the action failed for, and the number of errors, which counts the script failing to be written
+fn handle_action<P: AsRef<Path>>(
+    handler: &dyn ActionHandler,
+    ctx: &ExecutionContext,
+    annotations: &HashMap<u32, String>,
+    number_pattern: &NumberPattern,
+    matching_files: impl IntoIterator<Item = P>,
+    archive: Option<&Archive>,
+) -> (Vec<PathBuf>, usize) {
+    let options = ctx.options;
+    let mut failed = vec![];
+    let mut unmappable = 0;
+    let mut planned = vec![];
+    let mut skipped = vec![];
+    let mut script = options.emit_script.as_ref().map(|_| ShellScript::default());
 
-    if options.dry_run {
-        if options.verbose {
-            matching_files.iter().for_each(|file| println!("Deleted: {}", file.display()));
+    let mut record = |file: &Path, outcome: std::io::Result<Outcome>| {
+        if let Some(script) = &mut script {
+            script.push(file, handler.shell_command(file, ctx));
         }
-        return;
+        let annotation = file
+            .file_name()
+            .and_then(|f| f.to_str())
+            .and_then(|f| number_pattern.extract(f))
+            .and_then(|num| annotations.get(&num));
+        let outcome = outcome.map(|outcome| match (outcome, annotation) {
+            (Outcome::Applied(description), Some(note)) => Outcome::Applied(format!("{description}  # {note}")),
+            (Outcome::Skipped(description), Some(note)) => Outcome::Skipped(format!("{description}  # {note}")),
+            (outcome, _) => outcome,
+        });
+        match outcome {
+            Ok(Outcome::Applied(description)) if options.sample.is_some() => planned.push(description),
+            Ok(Outcome::Skipped(description)) if options.sample.is_some() => skipped.push(description),
+            Ok(Outcome::Applied(description)) | Ok(Outcome::Skipped(description)) => {
+                if options.verbose {
+                    print_record(options.terminator, &description);
+                }
+            }
+            Ok(Outcome::Printed) => {}
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                if e.get_ref().is_some_and(|inner| inner.is::<UnmappablePathError>()) {
+                    unmappable += 1;
+                }
+                failed.push(file.to_path_buf());
+            }
+        }
+    };
+    match archive {
+        // The selected files are extracted while the archive is read, in the order of its entries
+        Some(archive) => {
+            let matching_files: Vec<_> = matching_files.into_iter().collect();
+            let selected: HashSet<_> = matching_files.iter().map(AsRef::as_ref).collect();
+            let mut extracted = HashSet::new();
+            let read = archive.extract(
+                |file| selected.contains(file),
+                |file, content| {
+                    extracted.insert(file.to_path_buf());
+                    record(file, handler.extract(file, content, ctx));
+                },
+            );
+            // The files the archive wasn't read up to, or that weren't found in it, are failed
+            let message = read.map_or_else(|e| e.to_string(), |_| "the file wasn't found in the archive".to_owned());
+            for file in matching_files
+                .iter()
+                .map(AsRef::as_ref)
+                .filter(|file| !extracted.contains(*file))
+            {
+                let error = std::io::Error::other(format!("\"{}\": {message}", file.display()));
+                record(file, Err(error));
+            }
+        }
+        None => matching_files
+            .into_iter()
+            .for_each(|file| record(file.as_ref(), handler.apply(file.as_ref(), ctx))),
     }
 
-    for file in matching_files.iter() {
-        if let Err(e) = std::fs::remove_file(file) {
-            eprintln!("Error: {}", e);
+    if let Some(sample) = options.sample {
+        print_sample("Planned", &planned, sample, options.terminator);
+        print_sample("Skipped", &skipped, sample, options.terminator);
+    }
+
+    let mut errors = failed.len();
+    if let (Some(script), Some(path)) = (script, &options.emit_script) {
+        if let Err(e) = script.save(path) {
+            eprintln!("Failed to write the script to \"{}\": {e}", path.display());
             errors += 1;
         }
-        if options.verbose {
-            println!("Deleted: {}", file.display());
-        }
     }
 
     if errors > 0 {
         eprintln!("{} errors occurred", errors);
     }
+    if unmappable > 0 {
+        eprintln!("{unmappable} files couldn't be mapped to a destination and were not transferred");
+    }
+    (failed, errors)
+}
+
+/// Prints the record, ended by the terminator, reporting the write errors instead of panicking like `println!`
+fn print_record(terminator: Terminator, record: &str) {
+    if let Err(e) = terminator.print(record) {
+        eprintln!("Error: {e}");
+    }
 }
 
-/// Moves or copies files to the specified directory.
+/// Prints a random sample of the descriptions of a category of operations, in their original order
+fn print_sample(category: &str, descriptions: &[String], sample: usize, terminator: Terminator) {
+    if descriptions.is_empty() {
+        return;
+    }
+    let count = sample.min(descriptions.len());
+    let mut indices = rand::seq::index::sample(&mut rand::thread_rng(), descriptions.len(), count).into_vec();
+    indices.sort_unstable();
+    print_record(
+        terminator,
+        &format!("{category}: {} operations, showing {count}", descriptions.len()),
+    );
+    for index in indices {
+        print_record(terminator, &format!("  {}", descriptions[index]));
+    }
+}
+
+/// Finds the explained file among the found files, and explains whether the search and the protection accept it
 ///
-/// If `options.dry_run` is true, the files will not be moved.
-/// If `options.verbose` is true, the files will be printed before being moved.
+/// Returns the path the file was found at, or its canonical path if it wasn't found, and the steps
+fn explain_scan(
+    file: &Path,
+    source_dir: &Path,
+    limits: &ScanLimits,
+    files: &impl FileSource,
+    protected: &[PathBuf],
+) -> (PathBuf, Vec<Step>) {
+    let canonical = match std::fs::canonicalize(file) {
+        Ok(canonical) => canonical,
+        Err(e) => {
+            return (
+                file.to_path_buf(),
+                vec![Step::reject("scan", format!("the file can't be found: {e}"))],
+            )
+        }
+    };
+    let path = files
+        .iter()
+        .find(|found| std::fs::canonicalize(found).is_ok_and(|found| found == canonical))
+        .cloned()
+        .unwrap_or_else(|| canonical.clone());
+    let scan = match canonical.strip_prefix(source_dir) {
+        Ok(relative) => limits.explain(relative),
+        Err(_) => Step::reject("scan", "the file isn't inside the searched directory"),
+    };
+    let protection = match protected.contains(&canonical) {
+        true => Step::reject(
+            "protection",
+            "the file is an active config or keep file, see --no-protect",
+        ),
+        false => Step::accept("protection", "the file isn't an active config or keep file"),
+    };
+    (path, vec![scan, protection])
+}
+
+/// Checks if the action applies to the files the keep file keeps, or to the other ones
+fn acts_on_kept(selection: Selection, invert: bool) -> bool {
+    matches!(
+        (selection, invert),
+        (Selection::Kept, false) | (Selection::Rejected, true)
+    )
+}
+
+/// Checks if the action can be applied to the files while the directory is still being searched
 ///
-/// # Arguments
-/// op - the move or copy operation
-/// options - the execution options
-/// matching_files - files that should be moved or copied
-/// dest_dir - the destination directory
-fn handle_move_or_copy(op: MoveOrCopy, options: ExecutionOptions, matching_files: impl FileSource, dest_dir: PathBuf) {
-    let ExecutionOptions { dry_run, verbose, .. } = options;
-    let mut errors = 0;
-
-    let src_dir = matching_files.dir();
-    for src in matching_files.iter() {
-        let Ok(dest) = src.strip_prefix(src_dir).map(|p| dest_dir.join(p)) else {
-            continue;
-        };
-        if !dry_run {
-            if let Err(e) = op.move_or_copy(src, &dest) {
-                eprintln!("Error: {}", e);
-                errors += 1;
-            }
+/// Listing and deleting only need the file at hand. The transfers, the counts, the checks of the keep file,
+/// the statistics, the sampled plans and the hooks, which are given the counts, need all the files first,
+/// and so do the nested configurations, which are found among the files, and the orders the files are sorted in.
+fn can_stream(config: &AppConfig) -> bool {
+    let options = &config.options;
+    matches!(config.action, Action::Delete(_) | Action::List(_))
+        && config.archive.is_none()
+        && !config.config_file.nested_configs()
+        && config.hooks.is_empty()
+        && !(options.verbose || options.report_unmatched || options.print)
+        && options.expect_keeps.is_none()
+        && options.sample.is_none()
+        && options.stats_csv.is_none()
+        && options.explain.is_none()
+        // Only a single thread finds the files in the order of their paths
+        && options.order == ScanOrder::Path
+        && options.threads <= 1
+        && config.files_from.is_none()
+        && config.extra_paths.is_empty()
+        // The action can't be skipped once it was applied to the files found before an error
+        && !options.strict_scan
+}
+
+/// Applies the action to the files while the directory is searched, see [can_stream]
+///
+/// The paths that can't be read are reported once the action was applied to all the other files.
+fn stream_action(config: AppConfig, limits: &ScanLimits) {
+    let protection_filter = config.protection_filter();
+    let walk = match config.path.walk(config.options.canonicalize, limits, config.options.threads) {
+        Ok(walk) => walk,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
         }
-        if verbose {
-            println!(
-                "{} \"{}\" from to \"{}\"",
-                op.description(),
-                src.display(),
-                dest.display()
-            );
+    };
+    let tree = ConfigTree::discover(
+        config.config_file,
+        &[&config.path],
+        std::iter::empty(),
+        &config.overrides,
+    );
+    let filter = match tree {
+        Ok(tree) => tree.into_filter(),
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
         }
+    };
+
+    for skipped in config.keepfile.skipped() {
+        eprint!("Warning: {skipped}");
     }
+    for duplicates in config.keepfile.duplicates() {
+        eprint!("Warning: {duplicates}");
+    }
+
+    let annotations = config.keepfile.annotations();
+    let number_pattern = config.keepfile.number_pattern().clone();
+    let handler = config.action.handler();
+    let keep_filter = match acts_on_kept(handler.selection(), config.options.invert) {
+        false => config.keepfile.into_exclusion_matcher(),
+        true => config.keepfile.into_inclusion_matcher(),
+    };
+    let mut scan_errors = vec![];
+    let matching_files = walk
+        .filter_map(|found| found.map_err(|e| scan_errors.push(e)).ok())
+        .map(|found| found.path)
+        .filter(|file| protection_filter(&file) && filter(&file) && keep_filter(&file));
+
+    let ctx = ExecutionContext {
+        source_dir: config.path.as_ref(),
+        walk_paths: None,
+        options: &config.options,
+    };
+    let (_, errors) = handle_action(
+        handler.as_ref(),
+        &ctx,
+        &annotations,
+        &number_pattern,
+        matching_files,
+        None,
+    );
+    print_scan_errors(&scan_errors);
     if errors > 0 {
-        eprintln!("{} errors occurred", errors);
+        std::process::exit(1);
+    }
+}
+
+/// Prints how many paths, and which ones, couldn't be read or were skipped during the search
+fn print_scan_errors(scan_errors: &[ScanError]) {
+    if scan_errors.is_empty() {
+        return;
+    }
+    let count = scan_errors.len();
+    eprintln!("{count} paths couldn't be read or were skipped during the search, their files may be missing:");
+    for error in scan_errors {
+        eprintln!("  {error}");
+    }
+}
+
+/// Get the name of the action, as passed to the hooks
+fn action_name(action: &Action) -> &'static str {
+    match action {
+        Action::MoveOrCopyTo(handler) => match (&handler.op, handler.selection) {
+            (MoveOrCopy::Move, Selection::Rejected) => "quarantine",
+            (MoveOrCopy::Copy, _) => "copy",
+            (MoveOrCopy::Move, _) => "move",
+        },
+        Action::Delete(_) => "delete",
+        Action::List(_) => "list",
+        Action::Custom(_) => "custom",
     }
 }
 
@@ -85,48 +329,363 @@ fn handle_move_or_copy(op: MoveOrCopy, options: ExecutionOptions, matching_files
 fn main() {
     let args = Args::parse();
 
-    let config = match AppConfig::try_from(args) {
-        Ok(config) => config,
-        Err(e) => return eprintln!("{e}"),
+    let config = match args.command {
+        Some(Command::Wizard) => wizard::run().transpose(),
+        Some(Command::Selftest(selftest)) => {
+            if let Err(e) = selftest.run() {
+                eprintln!("Self-test failed: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::GenerateKeep(generate)) => {
+            if let Err(e) = generate.run() {
+                eprintln!("Failed to generate the keep file: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Validate(validate)) => {
+            if let Err(e) = validate.run() {
+                eprintln!("Validation failed: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Lint(lint)) => {
+            if let Err(e) = lint.run() {
+                eprintln!("Lint failed: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Init(init)) => {
+            if let Err(e) = init.run() {
+                eprintln!("Failed to initialize the directory: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Pick(pick)) => {
+            if let Err(e) = pick.run() {
+                eprintln!("Failed to pick the files: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        None => Some(AppConfig::try_from(args)),
+    };
+
+    let config = match config {
+        Some(Ok(config)) => config,
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        None => return,
     };
 
     if config.options.print {
-        return println!("{}", config.config_file);
+        return match config.config_file.to_yaml() {
+            Ok(yaml) => print!("{yaml}"),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        };
+    }
+
+    for input in config.overlapping_inputs() {
+        if !config.options.protect {
+            eprintln!(
+                "Warning: \"{}\" is inside the searched directory and may be acted on",
+                input.display()
+            );
+        } else if config.options.verbose {
+            print_record(config.options.terminator, &format!("Protected: {}", input.display()));
+        }
+    }
+
+    let limits = config.config_file.scan_limits();
+    if limits.one_file_system && !cfg!(unix) {
+        eprintln!("Warning: one_file_system is only supported on Unix, the search may enter other filesystems");
+    }
+    if can_stream(&config) {
+        return stream_action(config, &limits);
     }
 
-    let files = match SelectedFiles::try_from(config.path) {
+    let protection_filter = config.protection_filter();
+    let (canonicalize, threads) = (config.options.canonicalize, config.options.threads);
+    let scan = |root: &SelectedDirectory| SelectedFiles::scan_threaded(root.clone(), canonicalize, &limits, threads);
+    let files = match (&config.archive, &config.files_from) {
+        (Some(archive), _) => archive.list(&limits),
+        (None, Some(listed)) => SelectedFiles::listed(config.path.clone(), &config.extra_paths, listed),
+        // With several directories, the files of each one keep their paths under the name of the directory
+        (None, None) => scan(&config.path)
+            .and_then(|files| Ok(files.merge(config.extra_paths.iter().map(scan).collect::<Result<_, _>>()?))),
+    };
+    let files = match files {
         Ok(files) => files,
-        Err(e) => return eprintln!("{e}"),
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
     };
+    let scan_errors = files.scan_errors.clone();
+    if config.options.strict_scan && !scan_errors.is_empty() {
+        print_scan_errors(&scan_errors);
+        eprintln!("The search is incomplete, and --strict-scan was passed. No files were changed");
+        std::process::exit(1);
+    }
+    let files = files.sorted(config.options.order).filter_by(protection_filter);
+    let mut explained = config
+        .options
+        .explain
+        .as_ref()
+        .map(|file| explain_scan(file, config.path.as_ref(), &limits, &files, &config.protected_paths()));
 
-    let matching_files = files.filter_by(config.config_file.into_filter());
+    let total_count = files.count();
+    let roots = config.roots();
+    let tree = match ConfigTree::discover(config.config_file, &roots, files.iter(), &config.overrides) {
+        Ok(tree) => tree,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    if config.options.verbose {
+        for source in tree.nested_sources() {
+            print_record(
+                config.options.terminator,
+                &format!("Nested config: {}", source.display()),
+            );
+        }
+    }
+    if let Some((path, steps)) = &mut explained {
+        steps.extend(tree.explain(path, SystemTime::now()));
+        steps.push(match path.file_name().and_then(|f| f.to_str()) {
+            Some(filename) => config.keepfile.explain(filename),
+            None => Step::reject("keep file", "the file name isn't valid UTF-8"),
+        });
+    }
+    let matching_files = files.filter_by(tree.into_filter());
+    let matching_count = matching_files.count();
 
     if config.options.verbose {
-        println!(
-            "Matching files: {}/{}",
-            matching_files.count(),
-            matching_files.source().count()
+        print_record(
+            config.options.terminator,
+            &format!("Matching files: {matching_count}/{total_count}"),
         );
     }
 
-    let matching_files = matching_files.filter_by(match config.action {
-        Action::Delete => config.keepfile.into_exclusion_matcher(),
-        Action::MoveOrCopyTo(_, _) => config.keepfile.into_inclusion_matcher(),
+    for skipped in config.keepfile.skipped() {
+        eprint!("Warning: {skipped}");
+    }
+    for duplicates in config.keepfile.duplicates() {
+        eprint!("Warning: {duplicates}");
+    }
+
+    if config.options.verbose || config.options.report_unmatched {
+        let unmatched = config.keepfile.unmatched(matching_files.iter());
+        if !unmatched.is_empty() {
+            eprintln!(
+                "Warning: {} keep file entries don't match any file: {}",
+                unmatched.len(),
+                unmatched.iter().join(", ")
+            );
+        }
+    }
+
+    if let Some(expected) = config.options.expect_keeps {
+        let found = config.keepfile.count_found(matching_files.iter());
+        if found != expected {
+            eprintln!(
+                "Expected {expected} numbers from the keep file to be found, but found {found}. No files were changed"
+            );
+            std::process::exit(1);
+        }
+    }
+
+    let keep_order = config.keepfile.numbers();
+    let annotations = config.keepfile.annotations();
+    let number_pattern = config.keepfile.number_pattern().clone();
+    let handler = config.action.handler();
+    let acts_on_kept = acts_on_kept(handler.selection(), config.options.invert);
+    let matching_files = matching_files.filter_by(match acts_on_kept {
+        false => config.keepfile.into_exclusion_matcher(),
+        true => config.keepfile.into_inclusion_matcher(),
+    });
+
+    if let Some((file, mut steps)) = explained {
+        let action = action_name(&config.action);
+        let kept = steps.last().is_some_and(|step| step.accepted);
+        steps.push(match acts_on_kept {
+            true => Step::new("action", kept, format!("{action} is applied to the kept files")),
+            false => Step::new(
+                "action",
+                !kept,
+                format!("{action} is applied to the files that aren't kept"),
+            ),
+        });
+        let selected = matching_files.iter().any(|found| found == &file);
+        return print!("{}", Explanation { file, steps, selected });
+    }
+
+    let selected_count = matching_files.count();
+
+    let move_or_copy = match &config.action {
+        Action::MoveOrCopyTo(handler) if handler.renumber.is_some() || handler.dedupe.is_some() => {
+            let files: Vec<_> = matching_files.iter().cloned().collect();
+            let handler = handler.clone().with_numbering(&files, &keep_order, &number_pattern);
+            match handler.and_then(|handler| handler.with_deduplication(&files)) {
+                Ok(handler) => Some(handler),
+                Err(e) => {
+                    eprintln!("{e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Action::MoveOrCopyTo(handler) => Some(handler.clone()),
+        _ => None,
+    };
+
+    // Destinations are computed before the files are transferred
+    let mut index = match (&move_or_copy, config.options.index) {
+        (Some(move_or_copy), Some(_)) if !config.options.dry_run => {
+            let ctx = ExecutionContext {
+                source_dir: matching_files.dir(),
+                walk_paths: matching_files.walk_paths(),
+                options: &config.options,
+            };
+            let mut index = TransferIndex::new(move_or_copy.dest_dir.clone())
+                .with_annotations(annotations.clone())
+                .with_number_pattern(number_pattern.clone());
+            for file in matching_files.iter() {
+                if let Ok(dest) = move_or_copy.destination(file, &ctx) {
+                    index.push(file, &dest);
+                }
+            }
+            Some(index)
+        }
+        _ => None,
+    };
+
+    let stats = config.options.stats_csv.as_ref().map(|_| {
+        let mut stats = RunStats::default();
+        let source_dir = matching_files.dir();
+        matching_files
+            .source()
+            .iter()
+            .for_each(|file| stats.record_matching(source_dir, file));
+        matching_files.iter().for_each(|file| stats.record_selected(source_dir, file));
+        stats
     });
 
     if config.options.verbose {
-        let mut kept_count = matching_files.count();
-        let matching_count = matching_files.source().count();
+        let kept_count = match handler.selection() {
+            Selection::Kept => selected_count,
+            Selection::Rejected => matching_count - selected_count,
+        };
+        print_record(
+            config.options.terminator,
+            &format!("Keeping files: {kept_count}/{matching_count}"),
+        );
+    }
+
+    let mut hook_env = vec![
+        ("DELETE_REST_SOURCE", matching_files.dir().display().to_string()),
+        ("DELETE_REST_ACTION", action_name(&config.action).to_owned()),
+        ("DELETE_REST_TOTAL", total_count.to_string()),
+        ("DELETE_REST_MATCHING", matching_count.to_string()),
+        ("DELETE_REST_SELECTED", selected_count.to_string()),
+        ("DELETE_REST_DRY_RUN", u8::from(config.options.dry_run).to_string()),
+    ];
+    if let Action::MoveOrCopyTo(handler) = &config.action {
+        hook_env.push(("DELETE_REST_DESTINATION", handler.dest_dir.display().to_string()));
+    }
 
-        if let Action::Delete = config.action {
-            kept_count = matching_count - kept_count;
+    if let Err(e) = config.hooks.run_pre(&hook_env) {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+
+    // The transferred files are recorded, so an interrupted transfer can be resumed.
+    // The session starts after the pre-hook, which may mount the destination
+    let session = match &move_or_copy {
+        Some(move_or_copy) if !config.options.dry_run => {
+            let files: Vec<_> = matching_files.iter().cloned().collect();
+            let session = CopySession::start(&move_or_copy.dest_dir, config.options.resume, &files)
+                .and_then(|session| session.with_subdir(config.dest_subdir.as_deref()));
+            match session {
+                Ok(session) => Some(Rc::new(session)),
+                Err(e) => {
+                    eprintln!("Failed to start the session: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => None,
+    };
+    let handler: Rc<dyn ActionHandler> = match (&move_or_copy, &session) {
+        (Some(move_or_copy), Some(session)) => Rc::new(ResumableHandler {
+            inner: move_or_copy.clone(),
+            session: session.clone(),
+        }),
+        (Some(move_or_copy), None) => Rc::new(move_or_copy.clone()),
+        (None, _) => handler,
+    };
+
+    let ctx = ExecutionContext {
+        source_dir: matching_files.dir(),
+        walk_paths: matching_files.walk_paths(),
+        options: &config.options,
+    };
+    let archive = config.archive.as_ref();
+    let (failed, mut errors) = handle_action(
+        handler.as_ref(),
+        &ctx,
+        &annotations,
+        &number_pattern,
+        matching_files.iter(),
+        archive,
+    );
+
+    if let Some(session) = session.filter(|_| failed.is_empty()) {
+        if let Err(e) = session.finish() {
+            eprintln!("Failed to remove the session state: {e}");
+            errors += 1;
         }
-        println!("Keeping files: {kept_count}/{matching_count}")
     }
 
-    // Step 6
-    match config.action {
-        Action::Delete => handle_delete(config.options, matching_files),
-        Action::MoveOrCopyTo(op, dir) => handle_move_or_copy(op, config.options, matching_files, dir),
+    if let (Some(index), Some(format)) = (&mut index, config.options.index) {
+        index.retain(|entry| !failed.contains(&entry.source));
+        match index.save(format) {
+            Ok(path) if config.options.verbose => print_record(
+                config.options.terminator,
+                &format!("Index written to \"{}\"", path.display()),
+            ),
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Failed to write the index: {e}");
+                errors += 1;
+            }
+        }
+    }
+
+    if let (Some(stats), Some(path)) = (stats, &config.options.stats_csv) {
+        if let Err(e) = stats.save_csv(path) {
+            eprintln!("Failed to write statistics to \"{}\": {e}", path.display());
+            errors += 1;
+        }
+    }
+
+    hook_env.push(("DELETE_REST_ERRORS", errors.to_string()));
+    if let Err(e) = config.hooks.run_post(&hook_env) {
+        eprintln!("{e}");
+        errors += 1;
+    }
+    print_scan_errors(&scan_errors);
+    if errors > 0 {
+        std::process::exit(1);
     }
 }
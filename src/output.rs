@@ -0,0 +1,71 @@
+//! Module containing the terminator of the printed records ([Terminator])
+//!
+//! Listed files and planned operations are printed one per line by default, or separated by NUL characters,
+//! so paths containing newlines or spaces survive piping to `xargs -0`. Lists of files are read the same way.
+
+use std::fmt::Display;
+use std::io::Write;
+
+/// Character ending every printed or read record
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Terminator {
+    /// Records end with a newline
+    #[default]
+    Newline,
+    /// Records end with a NUL character
+    Nul,
+}
+
+impl Terminator {
+    /// Get the terminator for the flag requesting NUL characters
+    pub fn nul_if(nul: bool) -> Self {
+        match nul {
+            true => Terminator::Nul,
+            false => Terminator::Newline,
+        }
+    }
+
+    /// Get the terminating character
+    pub fn as_char(&self) -> char {
+        match self {
+            Terminator::Newline => '\n',
+            Terminator::Nul => '\0',
+        }
+    }
+
+    /// Print the record to the standard output, followed by the terminator
+    ///
+    /// # Errors
+    /// - If the standard output can't be written to
+    pub fn print(&self, record: impl Display) -> std::io::Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        write!(stdout, "{record}{}", self.as_char())
+    }
+
+    /// Split the content into the records, skipping the empty ones
+    ///
+    /// Lines may also end with a carriage return, which isn't part of the record.
+    pub fn split<'a>(&self, content: &'a str) -> impl Iterator<Item = &'a str> {
+        let records: Box<dyn Iterator<Item = &'a str>> = match self {
+            Terminator::Newline => Box::new(content.lines()),
+            Terminator::Nul => Box::new(content.split('\0')),
+        };
+        records.filter(|record| !record.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+
+    use super::*;
+
+    #[test]
+    fn splits_records() {
+        let content = "IMG_1.jpg\r\nIMG 2.jpg\n\nnew\nline.jpg\0";
+        let lines = Terminator::Newline.split(content).collect_vec();
+        assert_eq!(lines, ["IMG_1.jpg", "IMG 2.jpg", "new", "line.jpg\0"]);
+        let records = Terminator::Nul.split(content).collect_vec();
+        assert_eq!(records, ["IMG_1.jpg\r\nIMG 2.jpg\n\nnew\nline.jpg"]);
+    }
+}
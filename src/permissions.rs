@@ -0,0 +1,255 @@
+//! Module containing declarations related to [Permissions] struct
+//!
+//! Permissions and ownership are applied to the moved or copied files,
+//! so selections copied onto shared storage don't need a follow-up `chmod -R`.
+
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// Permissions and ownership to apply to the moved or copied files, as configured
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Permissions {
+    /// Octal file mode, like `644`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chmod: Option<String>,
+    /// Owner, as `user`, `user:group` or `:group`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chown: Option<String>,
+}
+
+impl Display for Permissions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{ ")?;
+        if let Some(chmod) = &self.chmod {
+            write!(f, "chmod: {:?} ", chmod)?;
+        }
+        if let Some(chown) = &self.chown {
+            write!(f, "chown: {:?} ", chown)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl Permissions {
+    /// Check if no permissions are configured
+    pub fn is_empty(&self) -> bool {
+        self.chmod.is_none() && self.chown.is_none()
+    }
+
+    /// Override the configured permissions with the provided ones
+    pub fn with_overrides(self, chmod: Option<String>, chown: Option<String>) -> Self {
+        Permissions {
+            chmod: chmod.or(self.chmod),
+            chown: chown.or(self.chown),
+        }
+    }
+
+    /// Parse the mode and look up the owner
+    ///
+    /// Returns `None` if no permissions are configured.
+    ///
+    /// # Errors
+    /// - If the mode isn't an octal number
+    /// - If the user or the group doesn't exist
+    /// - If permissions are configured on a platform other than Unix
+    pub fn resolve(&self) -> Result<Option<ResolvedPermissions>, PermissionsError> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        if !cfg!(unix) {
+            return Err(PermissionsError::Unsupported);
+        }
+
+        let mode = match &self.chmod {
+            Some(mode) => match u32::from_str_radix(mode, 8) {
+                Ok(parsed) if parsed <= 0o7777 => Some(parsed),
+                _ => return Err(PermissionsError::InvalidMode(mode.clone())),
+            },
+            None => None,
+        };
+        let (user, group) = match self.chown.as_deref().map(|owner| owner.split_once(':')) {
+            Some(Some((user, group))) => (Some(user), Some(group)),
+            Some(None) => (self.chown.as_deref(), None),
+            None => (None, None),
+        };
+        Ok(Some(ResolvedPermissions {
+            mode,
+            uid: user.filter(|user| !user.is_empty()).map(lookup_user).transpose()?,
+            gid: group.filter(|group| !group.is_empty()).map(lookup_group).transpose()?,
+        }))
+    }
+}
+
+/// Look up the user id by its name, or parse it if it is numeric
+#[cfg(unix)]
+fn lookup_user(user: &str) -> Result<u32, PermissionsError> {
+    user.parse()
+        .ok()
+        .or_else(|| uzers::get_user_by_name(user).map(|user| user.uid()))
+        .ok_or_else(|| PermissionsError::UnknownUser(user.to_owned()))
+}
+
+/// Look up the user id by its name, or parse it if it is numeric
+#[cfg(not(unix))]
+fn lookup_user(_user: &str) -> Result<u32, PermissionsError> {
+    Err(PermissionsError::Unsupported)
+}
+
+/// Look up the group id by its name, or parse it if it is numeric
+#[cfg(unix)]
+fn lookup_group(group: &str) -> Result<u32, PermissionsError> {
+    group
+        .parse()
+        .ok()
+        .or_else(|| uzers::get_group_by_name(group).map(|group| group.gid()))
+        .ok_or_else(|| PermissionsError::UnknownGroup(group.to_owned()))
+}
+
+/// Look up the group id by its name, or parse it if it is numeric
+#[cfg(not(unix))]
+fn lookup_group(_group: &str) -> Result<u32, PermissionsError> {
+    Err(PermissionsError::Unsupported)
+}
+
+/// Permissions and ownership with the mode parsed and the owner looked up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResolvedPermissions {
+    /// File mode
+    pub mode: Option<u32>,
+    /// Owner user id
+    pub uid: Option<u32>,
+    /// Owner group id
+    pub gid: Option<u32>,
+}
+
+impl ResolvedPermissions {
+    /// Apply the permissions and ownership to the file
+    ///
+    /// # Errors
+    /// - If the mode or the owner can't be changed, e.g. if changing the owner requires privileges
+    #[cfg(unix)]
+    pub fn apply(&self, path: &Path) -> std::io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        if let Some(mode) = self.mode {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        }
+        if self.uid.is_some() || self.gid.is_some() {
+            std::os::unix::fs::chown(path, self.uid, self.gid)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the permissions and ownership to the file
+    ///
+    /// Always fails, as permissions can only be applied on Unix.
+    #[cfg(not(unix))]
+    pub fn apply(&self, _path: &Path) -> std::io::Result<()> {
+        Err(std::io::Error::other(PermissionsError::Unsupported))
+    }
+
+    /// Get the shell commands applying the permissions and ownership to the file
+    pub fn shell_commands(&self, path: &str) -> Vec<String> {
+        let mut commands = vec![];
+        if let Some(mode) = self.mode {
+            commands.push(format!("chmod {mode:o} -- {path}"));
+        }
+        // `chown UID:` would also change the group, to the login group of the user
+        match (self.uid, self.gid) {
+            (None, None) => {}
+            (Some(uid), None) => commands.push(format!("chown {uid} -- {path}")),
+            (None, Some(gid)) => commands.push(format!("chown :{gid} -- {path}")),
+            (Some(uid), Some(gid)) => commands.push(format!("chown {uid}:{gid} -- {path}")),
+        }
+        commands
+    }
+}
+
+/// Error type for resolving the [Permissions]
+#[derive(thiserror::Error, Debug)]
+pub enum PermissionsError {
+    /// The mode isn't an octal number
+    #[error("Invalid file mode \"{0}\", expected an octal number like 644")]
+    InvalidMode(String),
+    /// The user doesn't exist
+    #[error("Unknown user \"{0}\"")]
+    UnknownUser(String),
+    /// The group doesn't exist
+    #[error("Unknown group \"{0}\"")]
+    UnknownGroup(String),
+    /// Permissions can only be applied on Unix
+    #[error("File permissions and ownership can only be changed on Unix")]
+    Unsupported,
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    #[test]
+    fn resolves_and_applies_permissions() -> TestResult {
+        let permissions = Permissions {
+            chmod: Some("640".to_owned()),
+            chown: Some(":0".to_owned()),
+        }
+        .with_overrides(None, Some("0:0".to_owned()));
+        let resolved = permissions.resolve()?.unwrap();
+        assert_eq!(
+            resolved,
+            ResolvedPermissions {
+                mode: Some(0o640),
+                uid: Some(0),
+                gid: Some(0),
+            }
+        );
+        assert_eq!(
+            resolved.shell_commands("'a.jpg'"),
+            vec!["chmod 640 -- 'a.jpg'", "chown 0:0 -- 'a.jpg'"]
+        );
+
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("a.jpg");
+        std::fs::write(&file, b"content")?;
+        let chmod_only = ResolvedPermissions {
+            uid: None,
+            gid: None,
+            ..resolved
+        };
+        chmod_only.apply(&file)?;
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&file)?.permissions().mode() & 0o7777, 0o640);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn changes_only_the_given_owner_in_shell_commands() {
+        let user_only = ResolvedPermissions {
+            mode: None,
+            uid: Some(1000),
+            gid: None,
+        };
+        assert_eq!(user_only.shell_commands("'a.jpg'"), vec!["chown 1000 -- 'a.jpg'"]);
+
+        let group_only = ResolvedPermissions {
+            mode: None,
+            uid: None,
+            gid: Some(100),
+        };
+        assert_eq!(group_only.shell_commands("'a.jpg'"), vec!["chown :100 -- 'a.jpg'"]);
+    }
+
+    #[test]
+    fn rejects_invalid_mode() {
+        let permissions = Permissions {
+            chmod: Some("rw-r--r--".to_owned()),
+            chown: None,
+        };
+        assert!(matches!(permissions.resolve(), Err(PermissionsError::InvalidMode(_))));
+    }
+}
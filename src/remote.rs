@@ -0,0 +1,180 @@
+//! Module containing declarations related to fetching remote files
+//!
+//! Keepfiles and configuration files can be provided as `http://` or `https://` URLs.
+//! Fetching is only available when the crate is built with the `http` feature.
+//!
+//! The fetched files are cached, so they are still available when the server can't be reached,
+//! or when the fetching is disabled with `--offline`.
+
+use std::path::{Path, PathBuf};
+
+/// Check if the provided location is an HTTP(S) URL
+pub fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// Fetch the contents of the provided URL
+///
+/// If `sha256` is provided, the SHA-256 checksum of the downloaded content
+/// must match it, otherwise an error is returned.
+///
+/// # Errors
+/// - If the crate was built without the `http` feature
+/// - If the request fails
+/// - If the checksum doesn't match
+#[cfg(feature = "http")]
+pub fn fetch(url: &str, sha256: Option<&str>) -> Result<Vec<u8>, RemoteError> {
+    let content = ureq::get(url)
+        .call()
+        .and_then(|mut response| response.body_mut().read_to_vec())
+        .map_err(|e| RemoteError::Request(url.to_owned(), e.to_string()))?;
+    verify(url, &content, sha256)?;
+    Ok(content)
+}
+
+/// Check that the SHA-256 checksum of the content matches the expected one, if it is provided
+#[cfg(feature = "http")]
+fn verify(url: &str, content: &[u8], sha256: Option<&str>) -> Result<(), RemoteError> {
+    use sha2::{Digest, Sha256};
+
+    let Some(expected) = sha256 else {
+        return Ok(());
+    };
+    let actual = hex::encode(Sha256::digest(content));
+    match actual.eq_ignore_ascii_case(expected.trim()) {
+        true => Ok(()),
+        false => Err(RemoteError::Checksum {
+            url: url.to_owned(),
+            expected: expected.to_owned(),
+            actual,
+        }),
+    }
+}
+
+/// Get the directory the fetched files are cached in
+///
+/// It is `delete-rest` in the user's cache directory, or in the temporary directory
+/// if the home directory can't be found.
+pub fn cache_dir() -> PathBuf {
+    directories::BaseDirs::new()
+        .map_or_else(std::env::temp_dir, |dirs| dirs.cache_dir().to_path_buf())
+        .join("delete-rest")
+}
+
+/// Fetch the contents of the provided URL, falling back to the cached copy
+///
+/// The fetched content is cached in [cache_dir]. If the request fails, or `offline` is set,
+/// the cached copy is used instead. The checksum is verified for the cached copy too.
+///
+/// # Errors
+/// - If the crate was built without the `http` feature
+/// - If the request fails, and the URL isn't cached
+/// - If `offline` is set, and the URL isn't cached
+/// - If the checksum doesn't match
+pub fn fetch_cached(url: &str, sha256: Option<&str>, offline: bool) -> Result<Vec<u8>, RemoteError> {
+    fetch_cached_in(&cache_dir(), url, sha256, offline)
+}
+
+/// Fetch the contents of the provided URL, falling back to the copy cached in the `cache` directory
+#[cfg(feature = "http")]
+fn fetch_cached_in(cache: &Path, url: &str, sha256: Option<&str>, offline: bool) -> Result<Vec<u8>, RemoteError> {
+    use sha2::{Digest, Sha256};
+
+    let cached = cache.join(hex::encode(Sha256::digest(url.as_bytes())));
+    let read_cached = || {
+        let content = std::fs::read(&cached).map_err(|_| RemoteError::NotCached(url.to_owned()))?;
+        verify(url, &content, sha256)?;
+        Ok(content)
+    };
+    if offline {
+        return read_cached();
+    }
+    match fetch(url, sha256) {
+        Ok(content) => {
+            // The cache is only a fallback, so the fetch doesn't fail if it can't be written
+            let written = std::fs::create_dir_all(cache).and_then(|_| std::fs::write(&cached, &content));
+            if written.is_err() {
+                eprintln!("Warning: failed to cache \"{url}\" in \"{}\"", cache.display());
+            }
+            Ok(content)
+        }
+        Err(RemoteError::Request(_, e)) if cached.exists() => {
+            eprintln!("Warning: failed to fetch \"{url}\" ({e}), using the cached copy");
+            read_cached()
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Fetch the contents of the provided URL
+///
+/// Always fails, as the crate was built without the `http` feature.
+#[cfg(not(feature = "http"))]
+pub fn fetch(url: &str, _sha256: Option<&str>) -> Result<Vec<u8>, RemoteError> {
+    Err(RemoteError::Unsupported(url.to_owned()))
+}
+
+/// Fetch the contents of the provided URL, falling back to the cached copy
+///
+/// Always fails, as the crate was built without the `http` feature.
+#[cfg(not(feature = "http"))]
+fn fetch_cached_in(_cache: &Path, url: &str, _sha256: Option<&str>, _offline: bool) -> Result<Vec<u8>, RemoteError> {
+    Err(RemoteError::Unsupported(url.to_owned()))
+}
+
+/// Error type for fetching remote files
+#[derive(thiserror::Error, Debug)]
+pub enum RemoteError {
+    /// The crate was built without HTTP support
+    #[error("Can't fetch \"{0}\": built without the `http` feature")]
+    Unsupported(String),
+    /// The request failed
+    #[error("Failed to fetch \"{0}\": {1}")]
+    Request(String, String),
+    /// Fetching is disabled, and the URL wasn't fetched before
+    #[error("Can't fetch \"{0}\" offline: it isn't cached")]
+    NotCached(String),
+    /// The downloaded content doesn't match the pinned checksum
+    #[error("Checksum mismatch for \"{url}\": expected {expected}, got {actual}")]
+    Checksum {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "http")]
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn fetch_offline() -> TestResult {
+        use sha2::{Digest, Sha256};
+
+        let cache = tempfile::tempdir()?;
+        let url = "https://example.invalid/config.yaml";
+        let fetch = |sha256, offline| fetch_cached_in(cache.path(), url, sha256, offline);
+        assert!(matches!(fetch(None, true), Err(RemoteError::NotCached(_))));
+        assert!(matches!(fetch(None, false), Err(RemoteError::Request(..))));
+
+        let cached = cache.path().join(hex::encode(Sha256::digest(url)));
+        std::fs::write(cached, "extensions: [jpg]\n")?;
+        assert_eq!(fetch(None, true)?, b"extensions: [jpg]\n");
+        assert_eq!(fetch(None, false)?, b"extensions: [jpg]\n");
+        assert!(matches!(fetch(Some("00"), true), Err(RemoteError::Checksum { .. })));
+
+        Ok(())
+    }
+
+    #[test]
+    fn detect_url() {
+        assert!(is_url("https://example.com/keep.txt"));
+        assert!(is_url("http://example.com/keep.txt"));
+        assert!(!is_url("keep.txt"));
+        assert!(!is_url("C:\\keep.txt"));
+    }
+}
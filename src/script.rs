@@ -0,0 +1,105 @@
+//! Module containing declarations related to [ShellScript] struct
+
+use std::io::Write;
+use std::path::Path;
+
+/// A POSIX shell script, equivalent to the planned operations
+#[derive(Debug, Clone, Default)]
+pub struct ShellScript {
+    /// Lines of the script, without the header
+    lines: Vec<String>,
+}
+
+impl ShellScript {
+    /// Add the command performing the action on the provided file
+    ///
+    /// If the action can't be expressed as a shell command, a comment is added instead.
+    pub fn push(&mut self, src: &Path, command: Option<String>) {
+        let line = command.unwrap_or_else(|| format!("# No shell equivalent for {}", comment(src)));
+        self.lines.push(line);
+    }
+
+    /// Write the script
+    pub fn write<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writeln!(writer, "#!/bin/sh")?;
+        writeln!(writer, "# Generated by delete-rest")?;
+        writeln!(writer, "set -e")?;
+        for line in &self.lines {
+            writeln!(writer, "{line}")?;
+        }
+        Ok(())
+    }
+
+    /// Write the script to the file at the provided path, and make it executable on Unix
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let file = std::fs::File::create(path.as_ref())?;
+        self.write(std::io::BufWriter::new(file))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(())
+    }
+}
+
+/// Quote the path for the POSIX shell
+///
+/// The path is wrapped in single quotes, so no character inside it is interpreted by the shell.
+/// Returns `None` if the path isn't valid UTF-8, as it couldn't be written into the script exactly.
+pub fn quote(path: &Path) -> Option<String> {
+    Some(format!("'{}'", path.to_str()?.replace('\'', r"'\''")))
+}
+
+/// Describe the path in a comment of the script
+///
+/// Line breaks and other control characters are escaped, so the path can't end the comment.
+pub fn comment(path: &Path) -> String {
+    format!("{path:?}")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    #[test]
+    fn quotes_special_characters() {
+        assert_eq!(quote(Path::new("IMG_0001.jpg")).as_deref(), Some("'IMG_0001.jpg'"));
+        assert_eq!(
+            quote(Path::new("my photos/it's $HOME.jpg")).as_deref(),
+            Some(r"'my photos/it'\''s $HOME.jpg'")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn refuses_non_utf8_paths() {
+        use std::os::unix::ffi::OsStrExt;
+
+        assert_eq!(quote(Path::new(std::ffi::OsStr::from_bytes(b"IMG_\xff.jpg"))), None);
+    }
+
+    #[test]
+    fn writes_commands_and_comments() -> TestResult {
+        let mut script = ShellScript::default();
+        script.push(Path::new("a.jpg"), Some("rm -- 'a.jpg'".to_owned()));
+        script.push(Path::new("b.jpg"), None);
+        script.push(Path::new("x\nrm -rf ~ #.jpg"), None);
+
+        let mut output = Vec::new();
+        script.write(&mut output)?;
+        let output = String::from_utf8(output)?;
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines[0], "#!/bin/sh");
+        assert_eq!(lines[3], "rm -- 'a.jpg'");
+        assert_eq!(lines[4], "# No shell equivalent for \"b.jpg\"");
+        // The line break in the name doesn't end the comment
+        assert_eq!(lines[5], r#"# No shell equivalent for "x\nrm -rf ~ #.jpg""#);
+        assert_eq!(lines.len(), 6);
+
+        Ok(())
+    }
+}
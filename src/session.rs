@@ -0,0 +1,300 @@
+//! Module containing declarations related to [CopySession] struct
+//!
+//! The session state is kept in the destination directory while files are moved or copied,
+//! so an interrupted transfer can be resumed without transferring the completed files again.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::action::{ActionHandler, ExecutionContext, MoveOrCopyHandler, Outcome, Selection};
+
+/// State of a move or copy run
+///
+/// The state file lists the files planned for transfer (`pending`) and appends the ones
+/// transferred so far (`done`), one tab-separated entry per line.
+/// When the files are placed into a subdirectory unique to the run, the destination directory holds
+/// a state file naming that subdirectory (`subdir`), so the run can be resumed into it.
+#[derive(Debug)]
+pub struct CopySession {
+    /// Path of the state file
+    path: PathBuf,
+    /// Path of the state file naming the subdirectory of the run, if the files are placed into one
+    subdir_path: Option<PathBuf>,
+    /// Files transferred by the previous, interrupted run
+    completed: HashSet<PathBuf>,
+    /// Files the previous, interrupted run planned to transfer, including the transferred ones
+    planned: HashSet<PathBuf>,
+    /// The open state file
+    state: RefCell<File>,
+}
+
+impl CopySession {
+    /// Name of the state file in the destination directory
+    pub const FILE_NAME: &'static str = ".delete-rest-session";
+
+    /// Start a session transferring the files to the destination directory
+    ///
+    /// If `resume` is true, the files planned and completed by the previous session are remembered.
+    /// Otherwise, the previous session is discarded.
+    ///
+    /// # Errors
+    /// - If the state file can't be read or written
+    pub fn start(dest_dir: &Path, resume: bool, pending: &[PathBuf]) -> std::io::Result<Self> {
+        let path = dest_dir.join(Self::FILE_NAME);
+        let (completed, planned) = match File::open(&path) {
+            Ok(file) if resume => Self::read_state(BufReader::new(file))?,
+            _ => (HashSet::new(), HashSet::new()),
+        };
+
+        std::fs::create_dir_all(dest_dir)?;
+        let mut state = File::create(&path)?;
+        for file in completed.iter() {
+            writeln!(state, "done\t{}", file.display())?;
+        }
+        for file in pending.iter().filter(|file| !completed.contains(*file)) {
+            writeln!(state, "pending\t{}", file.display())?;
+        }
+        state.flush()?;
+
+        Ok(CopySession {
+            path,
+            subdir_path: None,
+            completed,
+            planned,
+            state: RefCell::new(state),
+        })
+    }
+
+    /// Record the name of the subdirectory of the destination directory the files are transferred to
+    ///
+    /// The session is started in the subdirectory, and the name is written to the destination directory,
+    /// so [CopySession::interrupted_subdir] finds the subdirectory again even if it is named after the time.
+    ///
+    /// # Errors
+    /// - If the state file can't be written
+    pub fn with_subdir(mut self, subdir: Option<&str>) -> std::io::Result<Self> {
+        let (Some(subdir), Some(dest_dir)) = (subdir, self.path.parent().and_then(Path::parent)) else {
+            return Ok(self);
+        };
+        let path = dest_dir.join(Self::FILE_NAME);
+        std::fs::write(&path, format!("subdir\t{subdir}\n"))?;
+        self.subdir_path = Some(path);
+        Ok(self)
+    }
+
+    /// Get the subdirectory of the destination directory an interrupted session transferred the files to
+    ///
+    /// Returns `None` if there is no interrupted session, or if it didn't use a subdirectory.
+    pub fn interrupted_subdir(dest_dir: &Path) -> Option<String> {
+        let content = std::fs::read_to_string(dest_dir.join(Self::FILE_NAME)).ok()?;
+        let subdir = content.lines().find_map(|line| line.strip_prefix("subdir\t"))?;
+        dest_dir.join(subdir).join(Self::FILE_NAME).exists().then(|| subdir.to_owned())
+    }
+
+    /// Read the files marked as done, and all the files marked as either done or pending, from the state file
+    fn read_state<R: BufRead>(reader: R) -> std::io::Result<(HashSet<PathBuf>, HashSet<PathBuf>)> {
+        let mut completed = HashSet::new();
+        let mut planned = HashSet::new();
+        for line in reader.lines() {
+            let line = line?;
+            if let Some(file) = line.strip_prefix("done\t") {
+                completed.insert(PathBuf::from(file));
+                planned.insert(PathBuf::from(file));
+            } else if let Some(file) = line.strip_prefix("pending\t") {
+                planned.insert(PathBuf::from(file));
+            }
+        }
+        Ok((completed, planned))
+    }
+
+    /// Check whether the file was transferred to all of its destinations by the previous session
+    ///
+    /// The transfer is verified by comparing the sizes of the source and each destination.
+    pub fn is_completed(&self, src: &Path, dests: &[PathBuf]) -> bool {
+        if !self.completed.contains(src) {
+            return false;
+        }
+        let Ok(src) = std::fs::metadata(src) else {
+            return false;
+        };
+        dests
+            .iter()
+            .all(|dest| std::fs::metadata(dest).is_ok_and(|dest| dest.len() == src.len()))
+    }
+
+    /// Check whether the previous session planned to transfer the file
+    ///
+    /// Unless [CopySession::is_completed] confirms the transfer, the destinations of such a file
+    /// may hold partial copies of it.
+    pub fn is_planned(&self, src: &Path) -> bool {
+        self.planned.contains(src)
+    }
+
+    /// Mark the file as transferred
+    pub fn record(&self, src: &Path) -> std::io::Result<()> {
+        let mut state = self.state.borrow_mut();
+        writeln!(state, "done\t{}", src.display())?;
+        state.flush()
+    }
+
+    /// Remove the state files, once all the files were transferred
+    pub fn finish(&self) -> std::io::Result<()> {
+        if let Some(subdir_path) = &self.subdir_path {
+            std::fs::remove_file(subdir_path)?;
+        }
+        std::fs::remove_file(&self.path)
+    }
+}
+
+/// Move or copy handler that records the transferred files in a [CopySession],
+/// skips the ones transferred by the previous session, and overwrites the destinations of the ones it didn't finish
+#[derive(Debug)]
+pub struct ResumableHandler {
+    /// The wrapped handler
+    pub inner: MoveOrCopyHandler,
+    /// The session the transfers are recorded in
+    pub session: Rc<CopySession>,
+}
+
+impl ActionHandler for ResumableHandler {
+    fn selection(&self) -> Selection {
+        self.inner.selection()
+    }
+
+    fn apply(&self, src: &Path, ctx: &ExecutionContext) -> Result<Outcome, std::io::Error> {
+        if let Ok(dests) = self.inner.destinations(src, ctx) {
+            if self.session.is_completed(src, &dests) {
+                self.inner.mark_transferred(src, ctx);
+                return Ok(Outcome::Skipped(format!(
+                    "Already transferred \"{}\"",
+                    ctx.display(src)
+                )));
+            }
+        }
+        let outcome = match self.session.is_planned(src) {
+            true => self.inner.resume(src, ctx)?,
+            false => self.inner.apply(src, ctx)?,
+        };
+        if let Outcome::Applied(_) = outcome {
+            self.session.record(src)?;
+        }
+        Ok(outcome)
+    }
+
+    fn extract(&self, src: &Path, content: &mut dyn Read, ctx: &ExecutionContext) -> Result<Outcome, std::io::Error> {
+        let outcome = self.inner.extract(src, content, ctx)?;
+        if let Outcome::Applied(_) = outcome {
+            self.session.record(src)?;
+        }
+        Ok(outcome)
+    }
+
+    fn shell_command(&self, src: &Path, ctx: &ExecutionContext) -> Option<String> {
+        self.inner.shell_command(src, ctx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::action::{DestinationLayout, MoveOrCopy};
+    use crate::test_utils::TestResult;
+    use crate::ExecutionOptions;
+
+    use super::*;
+
+    #[test]
+    fn resumes_interrupted_copy() -> TestResult {
+        let source = tempfile::tempdir()?;
+        let dest = tempfile::tempdir()?;
+        let files: Vec<_> = ["IMG_0001.jpg", "IMG_0002.jpg"]
+            .into_iter()
+            .map(|name| source.path().join(name))
+            .collect();
+        for file in &files {
+            std::fs::write(file, b"content")?;
+        }
+
+        let options = ExecutionOptions::default();
+        let ctx = ExecutionContext {
+            source_dir: source.path(),
+            walk_paths: None,
+            options: &options,
+        };
+        let inner = MoveOrCopyHandler::new(MoveOrCopy::Copy, dest.path().to_path_buf(), DestinationLayout::Mirror);
+
+        // The first run is interrupted after the first file
+        let handler = ResumableHandler {
+            inner: inner.clone(),
+            session: Rc::new(CopySession::start(dest.path(), false, &files)?),
+        };
+        assert!(matches!(handler.apply(&files[0], &ctx)?, Outcome::Applied(_)));
+        drop(handler);
+
+        let session = Rc::new(CopySession::start(dest.path(), true, &files)?);
+        let handler = ResumableHandler {
+            inner,
+            session: session.clone(),
+        };
+        assert!(matches!(handler.apply(&files[0], &ctx)?, Outcome::Skipped(_)));
+        assert!(matches!(handler.apply(&files[1], &ctx)?, Outcome::Applied(_)));
+
+        session.finish()?;
+        assert!(!dest.path().join(CopySession::FILE_NAME).exists());
+        assert!(dest.path().join("IMG_0002.jpg").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn resumes_over_partial_copies() -> TestResult {
+        let source = tempfile::tempdir()?;
+        let dest = tempfile::tempdir()?;
+        let mirror = tempfile::tempdir()?;
+        let files: Vec<_> = ["IMG_0001.jpg", "IMG_0002.jpg"]
+            .into_iter()
+            .map(|name| source.path().join(name))
+            .collect();
+        for file in &files {
+            std::fs::write(file, b"content")?;
+        }
+
+        let options = ExecutionOptions::default();
+        let ctx = ExecutionContext {
+            source_dir: source.path(),
+            walk_paths: None,
+            options: &options,
+        };
+        let mut inner = MoveOrCopyHandler::new(MoveOrCopy::Copy, dest.path().to_path_buf(), DestinationLayout::Mirror);
+        inner.mirrors = vec![mirror.path().to_path_buf()];
+
+        // The first run is interrupted while copying the second file,
+        // and the copy of the first one in the mirror was truncated since
+        let handler = ResumableHandler {
+            inner: inner.clone(),
+            session: Rc::new(CopySession::start(dest.path(), false, &files)?),
+        };
+        assert!(matches!(handler.apply(&files[0], &ctx)?, Outcome::Applied(_)));
+        drop(handler);
+        std::fs::write(mirror.path().join("IMG_0001.jpg"), b"con")?;
+        std::fs::write(dest.path().join("IMG_0002.jpg"), b"con")?;
+
+        // The partial copies are overwritten, although conflicts are skipped by default
+        let handler = ResumableHandler {
+            inner,
+            session: Rc::new(CopySession::start(dest.path(), true, &files)?),
+        };
+        assert!(matches!(handler.apply(&files[0], &ctx)?, Outcome::Applied(_)));
+        assert!(matches!(handler.apply(&files[1], &ctx)?, Outcome::Applied(_)));
+        for dir in [dest.path(), mirror.path()] {
+            for name in ["IMG_0001.jpg", "IMG_0002.jpg"] {
+                assert_eq!(std::fs::read(dir.join(name))?, b"content");
+            }
+        }
+
+        Ok(())
+    }
+}
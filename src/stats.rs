@@ -0,0 +1,153 @@
+//! Module containing declarations related to [RunStats] struct
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// File counts and byte totals of a group of files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatsEntry {
+    /// Number of files matching the configuration
+    pub matching: usize,
+    /// Total size of the files matching the configuration
+    pub matching_bytes: u64,
+    /// Number of files the action was applied to
+    pub selected: usize,
+    /// Total size of the files the action was applied to
+    pub selected_bytes: u64,
+}
+
+/// Statistics of a run, grouped per directory and per extension
+///
+/// Directories are relative to the searched directory.
+/// Extensions are lowercase, and files without one are grouped under an empty extension.
+#[derive(Debug, Clone, Default)]
+pub struct RunStats {
+    /// Statistics per directory
+    pub directories: BTreeMap<PathBuf, StatsEntry>,
+    /// Statistics per extension
+    pub extensions: BTreeMap<String, StatsEntry>,
+}
+
+impl RunStats {
+    /// Get the groups the file belongs to
+    fn groups(&mut self, source_dir: &Path, path: &Path) -> [&mut StatsEntry; 2] {
+        let dir = path
+            .parent()
+            .and_then(|p| p.strip_prefix(source_dir).ok())
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase())
+            .unwrap_or_default();
+        [
+            self.directories.entry(dir).or_default(),
+            self.extensions.entry(ext).or_default(),
+        ]
+    }
+
+    /// Record a file matching the configuration
+    pub fn record_matching(&mut self, source_dir: &Path, path: &Path) {
+        let size = std::fs::metadata(path).map_or(0, |m| m.len());
+        for entry in self.groups(source_dir, path) {
+            entry.matching += 1;
+            entry.matching_bytes += size;
+        }
+    }
+
+    /// Record a file the action is applied to
+    pub fn record_selected(&mut self, source_dir: &Path, path: &Path) {
+        let size = std::fs::metadata(path).map_or(0, |m| m.len());
+        for entry in self.groups(source_dir, path) {
+            entry.selected += 1;
+            entry.selected_bytes += size;
+        }
+    }
+
+    /// Write the statistics as CSV
+    ///
+    /// The first column tells if the row describes a `directory` or an `extension`.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writeln!(writer, "kind,name,matching,matching_bytes,selected,selected_bytes")?;
+        let directories = self
+            .directories
+            .iter()
+            .map(|(dir, entry)| ("directory", dir.display().to_string(), entry));
+        let extensions = self.extensions.iter().map(|(ext, entry)| ("extension", ext.clone(), entry));
+        for (kind, name, entry) in directories.chain(extensions) {
+            writeln!(
+                writer,
+                "{kind},{},{},{},{},{}",
+                escape_csv(&name),
+                entry.matching,
+                entry.matching_bytes,
+                entry.selected,
+                entry.selected_bytes
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write the statistics as CSV to the file at the provided path
+    pub fn save_csv<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        self.write_csv(std::io::BufWriter::new(file))
+    }
+}
+
+/// Quote the CSV field if needed
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    #[test]
+    fn groups_by_directory_and_extension() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir(dir.path().join("a,b"))?;
+        let files = [
+            (dir.path().join("IMG_0001.jpg"), 10),
+            (dir.path().join("IMG_0002.JPG"), 20),
+            (dir.path().join("a,b").join("IMG_0003.cr2"), 30),
+        ];
+        for (file, size) in &files {
+            std::fs::write(file, vec![0u8; *size])?;
+        }
+
+        let mut stats = RunStats::default();
+        for (file, _) in &files {
+            stats.record_matching(dir.path(), file);
+        }
+        stats.record_selected(dir.path(), &files[1].0);
+
+        let jpg = stats.extensions["jpg"];
+        assert_eq!(
+            (jpg.matching, jpg.matching_bytes, jpg.selected, jpg.selected_bytes),
+            (2, 30, 1, 20)
+        );
+        let root = stats.directories[Path::new("")];
+        assert_eq!((root.matching, root.selected), (2, 1));
+
+        let mut csv = Vec::new();
+        stats.write_csv(&mut csv)?;
+        let csv = String::from_utf8(csv)?;
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines[0], "kind,name,matching,matching_bytes,selected,selected_bytes");
+        assert!(lines.contains(&"directory,,2,30,1,20"));
+        assert!(lines.contains(&"directory,\"a,b\",1,30,0,0"));
+        assert!(lines.contains(&"extension,cr2,1,30,0,0"));
+
+        Ok(())
+    }
+}
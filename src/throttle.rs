@@ -0,0 +1,108 @@
+//! Module containing declarations related to [RateLimiter] struct
+//!
+//! Rate limiting is used to keep large copies from saturating the storage
+//! while other work is happening.
+
+use std::cell::Cell;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Limits the throughput of all the writes it is shared by
+///
+/// The rate is averaged over the whole run, starting with the first transferred bytes.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Maximum number of bytes per second
+    bytes_per_second: f64,
+    /// Time of the first transfer
+    start: Cell<Option<Instant>>,
+    /// Number of bytes transferred so far
+    transferred: Cell<u64>,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter allowing the provided number of megabytes (10^6 bytes) per second
+    pub fn from_megabytes_per_second(megabytes: f64) -> Self {
+        RateLimiter {
+            bytes_per_second: megabytes * 1_000_000.0,
+            start: Cell::new(None),
+            transferred: Cell::new(0),
+        }
+    }
+
+    /// Account for the transferred bytes, sleeping while the transfer is ahead of the allowed rate
+    pub fn consume(&self, bytes: usize) {
+        let start = self.start.get().unwrap_or_else(Instant::now);
+        self.start.set(Some(start));
+        self.transferred.set(self.transferred.get() + bytes as u64);
+
+        let expected = Duration::from_secs_f64(self.transferred.get() as f64 / self.bytes_per_second);
+        if let Some(ahead) = expected.checked_sub(start.elapsed()) {
+            std::thread::sleep(ahead);
+        }
+    }
+
+    /// Copy the file, limiting the throughput
+    ///
+    /// Like [std::fs::copy], the permissions of the file are copied too.
+    ///
+    /// Returns the number of copied bytes.
+    pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> std::io::Result<u64> {
+        let mut reader = File::open(from.as_ref())?;
+        let mut writer = ThrottledWriter {
+            inner: File::create(to.as_ref())?,
+            limiter: self,
+        };
+        let copied = std::io::copy(&mut reader, &mut writer)?;
+        writer.flush()?;
+        std::fs::set_permissions(to, reader.metadata()?.permissions())?;
+        Ok(copied)
+    }
+}
+
+/// Writer whose throughput is limited by a [RateLimiter]
+pub struct ThrottledWriter<'a, W> {
+    /// The underlying writer
+    pub inner: W,
+    /// The rate limiter
+    pub limiter: &'a RateLimiter,
+}
+
+impl<W: Write> Write for ThrottledWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.limiter.consume(written);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::TestResult;
+
+    use super::*;
+
+    #[test]
+    fn limits_throughput() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        let from = dir.path().join("from.bin");
+        let to = dir.path().join("to.bin");
+        std::fs::write(&from, vec![7u8; 200_000])?;
+
+        // 1 MB/s, so 200 kB should take at least 0.2s
+        let limiter = RateLimiter::from_megabytes_per_second(1.0);
+        let start = Instant::now();
+        assert_eq!(limiter.copy(&from, &to)?, 200_000);
+
+        assert!(start.elapsed() >= Duration::from_millis(180));
+        assert_eq!(std::fs::read(&to)?, std::fs::read(&from)?);
+
+        Ok(())
+    }
+}